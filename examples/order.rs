@@ -13,15 +13,11 @@ pub enum Args {
   Cancel{id: Option<String>},
 }
 
-#[derive(Debug, StructOpt)]
+#[derive(Debug, Default, StructOpt)]
 pub enum OrderStatus {
+  #[default]
   All, Open, Closed
 }
-impl Default for OrderStatus {
-  fn default() -> Self {
-    Self::All
-  }
-}
 impl FromStr for OrderStatus {
     type Err = String;
 
@@ -134,7 +130,11 @@ async fn cancel(client: &Client, id: Option<String>) -> Result<()> {
   } else {
     let canceled = client.cancel_all_orders().await?;
     for data in canceled {
-      println!("CANCELED -- {} -- {:?}", data.id, data.status);
+      if data.is_success() {
+        println!("CANCELED -- {} ", data.id);
+      } else {
+        println!("COULD NOT CANCEL -- {} -- {:?} {:?}", data.id, data.status, data.error);
+      }
     }
   }
   Ok(())
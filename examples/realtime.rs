@@ -31,7 +31,7 @@ async fn main() -> Result<()> {
     // process message
     client.stream().for_each_concurrent(1000, |r| async move {
         match r {
-            Response::Error(e) => println!("ERROR {:?}", e),
+            Response::Error(e) => println!("ERROR {:?} {}", e.code, e.message),
             Response::Trade(t) => println!("Trade {:?}", t),
             Response::Bar(b)   => println!("Bar   {:?}", b),
             Response::Quote(q) => 
@@ -19,7 +19,7 @@ async fn main() -> Result<()> {
     // process message
     client.stream().for_each_concurrent(1000, |r| async move {
         match r {
-            Response::TradeUpdates { data } => 
+            Ok(Response::TradeUpdates { data }) => 
               match data {
                 OrderUpdate::New { order }                  => summarize(&order),
                 OrderUpdate::Fill { order, .. }             => summarize(&order),
@@ -37,7 +37,10 @@ async fn main() -> Result<()> {
                 OrderUpdate::Suspended { order }            => summarize(&order),
                 OrderUpdate::OrderReplaceRejected { order } => summarize(&order),
                 OrderUpdate::OrderCancelRejected { order }  => summarize(&order),
+                _ => /* event added after this example was written */ (),
             },
+            Ok(Response::Reconnected) => println!("-- reconnected --"),
+            Err(e) => eprintln!("-- {} --", e),
             _ => /* ignore */ (),
         }
     }).await;
@@ -0,0 +1,490 @@
+//! This module provides an access to the account API: the status of the
+//! account, its cash and buying power, its equity and the restrictions which
+//! apply to it (pattern day trader flag, blocked trading...).
+//!
+//! The amounts are expressed in the currency of the account (USD).
+//!
+//! The configurations of the account (day trading checks, shorting, 
+//! fractional trading...) can be read and updated as well:
+//!
+//! ```no_run
+//! # async fn example(client: apca_datav2::rest::Client) -> Result<(), apca_datav2::errors::Error> {
+//! use apca_datav2::account::AccountConfigurationsUpdateBuilder;
+//!
+//! let update = AccountConfigurationsUpdateBuilder::default()
+//!     .no_shorting(true)
+//!     .build().unwrap();
+//! let configurations = client.update_account_configurations(&update).await?;
+//! assert!(configurations.no_shorting);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ```no_run
+//! # async fn example(client: apca_datav2::rest::Client) -> Result<(), apca_datav2::errors::Error> {
+//! let account = client.account().await?;
+//! if account.can_trade() {
+//!     println!("buying power: {}", account.buying_power);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The portfolio history gives the evolution of the equity of the account
+//! over a period (e.g. to draw a performance chart):
+//!
+//! ```no_run
+//! # async fn example(client: apca_datav2::rest::Client) -> Result<(), apca_datav2::errors::Error> {
+//! use apca_datav2::account::{HistoryPeriod, HistoryTimeFrame, PortfolioHistory, PortfolioHistoryRequestBuilder};
+//!
+//! let request = PortfolioHistoryRequestBuilder::default()
+//!     .period(HistoryPeriod::Months(1))
+//!     .timeframe(HistoryTimeFrame::OneDay)
+//!     .build().unwrap();
+//! for point in client.portfolio_history(&request).await?.points() {
+//!     println!("{}: {:?}", point.timestamp, point.equity);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use derive_builder::Builder;
+use serde::{Serialize, Deserialize};
+
+use crate::{endpoints::Endpoint, errors::{Error, status_code_to_api_error}, rest::Client};
+
+impl Client {
+    /// Retrieves the account associated with the credentials of the client
+    pub async fn account(&self) -> Result<AccountData, Error> {
+        let url = Endpoint::Account.url(self.env_url());
+        let rsp = self.get_authenticated(&url)
+            .send().await?;
+
+        status_code_to_api_error(rsp).await
+    }
+    /// Retrieves the current configurations of the account
+    pub async fn account_configurations(&self) -> Result<AccountConfigurations, Error> {
+        let url = Endpoint::AccountConfigurations.url(self.env_url());
+        let rsp = self.get_authenticated(&url)
+            .send().await?;
+
+        status_code_to_api_error(rsp).await
+    }
+    /// Updates the given configurations of the account (the others are left
+    /// unchanged) and returns the resulting configurations
+    pub async fn update_account_configurations(&self, update: &AccountConfigurationsUpdate) -> Result<AccountConfigurations, Error> {
+        let url = Endpoint::AccountConfigurations.url(self.env_url());
+        let rsp = self.patch_authenticated(&url)
+            .json(update)
+            .send().await?;
+
+        status_code_to_api_error(rsp).await
+    }
+    /// Retrieves the history of the equity and profit/loss of the account
+    pub async fn portfolio_history(&self, request: &PortfolioHistoryRequest) -> Result<PortfolioHistory, Error> {
+        let url = Endpoint::PortfolioHistory.url(self.env_url());
+        let rsp = self.get_authenticated(&url)
+            .query(request)
+            .send().await?;
+
+        status_code_to_api_error(rsp).await
+    }
+}
+
+/// The status of an account
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all="SCREAMING_SNAKE_CASE")]
+#[non_exhaustive]
+pub enum AccountStatus {
+    /// The account is onboarding
+    Onboarding,
+    /// The account application submission failed for some reason
+    SubmissionFailed,
+    /// The account application has been submitted for review
+    Submitted,
+    /// The account information is being updated
+    AccountUpdated,
+    /// The final account approval is pending
+    ApprovalPending,
+    /// The account is active for trading
+    Active,
+    /// The account application has been rejected
+    Rejected,
+    /// Any status this crate does not know (yet)
+    #[serde(other)]
+    Other,
+}
+
+/// The description of a trading account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct AccountData {
+    /// Account ID
+    pub id: String,
+    /// Account number
+    pub account_number: String,
+    /// The status of the account
+    pub status: AccountStatus,
+    /// The currency of the account (e.g. USD)
+    pub currency: String,
+    /// Cash balance
+    #[serde(deserialize_with="crate::utils::number_as_f64")]
+    pub cash: f64,
+    /// Total value of cash + holding positions (same as `equity`)
+    #[serde(deserialize_with="crate::utils::number_as_f64")]
+    pub portfolio_value: f64,
+    /// Cash + long_market_value + short_market_value
+    #[serde(deserialize_with="crate::utils::number_as_f64")]
+    pub equity: f64,
+    /// Equity as of the previous trading day at 16:00:00 ET
+    #[serde(deserialize_with="crate::utils::number_as_f64")]
+    pub last_equity: f64,
+    /// Current available buying power. With a margin account, it is the
+    /// `daytrading_buying_power` of a pattern day trader and the
+    /// `regt_buying_power` otherwise (see `multiplier`).
+    #[serde(deserialize_with="crate::utils::number_as_f64")]
+    pub buying_power: f64,
+    /// The buying power for the day trades (4x the excess equity of the
+    /// previous day, for a pattern day trader)
+    #[serde(deserialize_with="crate::utils::number_as_f64")]
+    pub daytrading_buying_power: f64,
+    /// The buying power under Regulation T (2x the excess equity)
+    #[serde(deserialize_with="crate::utils::number_as_f64")]
+    pub regt_buying_power: f64,
+    /// The buying power available to the non marginable assets (e.g. crypto)
+    #[serde(default, deserialize_with="crate::utils::option_as_f64")]
+    pub non_marginable_buying_power: Option<f64>,
+    /// The buying power available to the options
+    #[serde(default, deserialize_with="crate::utils::option_as_f64")]
+    pub options_buying_power: Option<f64>,
+    /// Buying power multiplier: 1 for a cash account, 2 for a margin account
+    /// and 4 for a pattern day trader margin account
+    #[serde(deserialize_with="crate::utils::number_as_f64")]
+    pub multiplier: f64,
+    /// Real-time market value of the long positions
+    #[serde(deserialize_with="crate::utils::number_as_f64")]
+    pub long_market_value: f64,
+    /// Real-time market value of the short positions (negative)
+    #[serde(deserialize_with="crate::utils::number_as_f64")]
+    pub short_market_value: f64,
+    /// Reg T initial margin requirement
+    #[serde(deserialize_with="crate::utils::number_as_f64")]
+    pub initial_margin: f64,
+    /// Maintenance margin requirement
+    #[serde(deserialize_with="crate::utils::number_as_f64")]
+    pub maintenance_margin: f64,
+    /// Maintenance margin requirement on the previous trading day
+    #[serde(deserialize_with="crate::utils::number_as_f64")]
+    pub last_maintenance_margin: f64,
+    /// Value of the special memorandum account
+    #[serde(default, deserialize_with="crate::utils::option_as_f64")]
+    pub sma: Option<f64>,
+    /// The fees collected
+    #[serde(default, deserialize_with="crate::utils::option_as_f64")]
+    pub accrued_fees: Option<f64>,
+    /// The cash which is being transferred in
+    #[serde(default, deserialize_with="crate::utils::option_as_f64")]
+    pub pending_transfer_in: Option<f64>,
+    /// The number of day trades made in the last 5 trading days (including
+    /// today)
+    pub daytrade_count: u32,
+    /// Whether the account is flagged as a pattern day trader
+    pub pattern_day_trader: bool,
+    /// Whether the account is not allowed to place orders
+    pub trading_blocked: bool,
+    /// Whether the account is not allowed to request money transfers
+    pub transfers_blocked: bool,
+    /// Whether the account activity is restricted by Alpaca
+    pub account_blocked: bool,
+    /// Whether the trading was suspended by the user
+    pub trade_suspended_by_user: bool,
+    /// Whether the account is allowed to short
+    pub shorting_enabled: bool,
+    /// The options trading level approved for the account (if any)
+    #[serde(default)]
+    pub options_approved_level: Option<u8>,
+    /// Timestamp this account was created at
+    pub created_at: DateTime<Utc>,
+}
+impl AccountData {
+    /// Returns true iff the account is active and allowed to place orders
+    pub fn can_trade(&self) -> bool {
+        self.status == AccountStatus::Active
+            && !self.trading_blocked
+            && !self.account_blocked
+            && !self.trade_suspended_by_user
+    }
+}
+
+/// The side of the trades to which a check applies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all="lowercase")]
+pub enum CheckSide {
+    /// The check applies to both the entry and exit of the positions
+    Both,
+    /// The check only applies to the entry of the positions
+    Entry,
+    /// The check only applies to the exit of the positions
+    Exit,
+}
+
+/// Which trades trigger a confirmation email
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all="lowercase")]
+pub enum TradeConfirmEmail {
+    /// An email is sent for every trade
+    All,
+    /// No email is ever sent
+    None,
+}
+
+/// The configurations of an account
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct AccountConfigurations {
+    /// The side of the trades subject to the day trading buying power check
+    pub dtbp_check: CheckSide,
+    /// The side of the trades subject to the pattern day trader check
+    #[serde(default)]
+    pub pdt_check: Option<CheckSide>,
+    /// Whether the trades are confirmed by email
+    pub trade_confirm_email: TradeConfirmEmail,
+    /// If true, new orders are blocked
+    pub suspend_trade: bool,
+    /// If true, the account becomes long only
+    pub no_shorting: bool,
+    /// If true, the account is allowed to trade fractional shares
+    #[serde(default)]
+    pub fractional_trading: bool,
+    /// The maximum margin multiplier of the account ("1", "2" or "4")
+    #[serde(default)]
+    pub max_margin_multiplier: Option<String>,
+}
+
+/// An update of the configurations of an account. Only the fields which are
+/// set are changed.
+#[derive(Builder, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AccountConfigurationsUpdate {
+    /// The side of the trades subject to the day trading buying power check
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dtbp_check: Option<CheckSide>,
+    /// The side of the trades subject to the pattern day trader check
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pdt_check: Option<CheckSide>,
+    /// Whether the trades are confirmed by email
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trade_confirm_email: Option<TradeConfirmEmail>,
+    /// If true, new orders are blocked
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suspend_trade: Option<bool>,
+    /// If true, the account becomes long only
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub no_shorting: Option<bool>,
+    /// If true, the account is allowed to trade fractional shares
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fractional_trading: Option<bool>,
+    /// The maximum margin multiplier of the account ("1", "2" or "4")
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_margin_multiplier: Option<String>,
+}
+
+/// The period covered by a portfolio history
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HistoryPeriod {
+    /// N days (1D, 2D...)
+    Days(u32),
+    /// N weeks (1W, 2W...)
+    Weeks(u32),
+    /// N months (1M, 3M...)
+    Months(u32),
+    /// N years (1A, 2A...)
+    Years(u32),
+}
+impl std::fmt::Display for HistoryPeriod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Days(n)   => write!(f, "{}D", n),
+            Self::Weeks(n)  => write!(f, "{}W", n),
+            Self::Months(n) => write!(f, "{}M", n),
+            Self::Years(n)  => write!(f, "{}A", n),
+        }
+    }
+}
+impl Serialize for HistoryPeriod {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+/// The resolution of a portfolio history
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum HistoryTimeFrame {
+    /// One point per minute
+    #[serde(rename="1Min")]
+    OneMinute,
+    /// One point every five minutes
+    #[serde(rename="5Min")]
+    FiveMinutes,
+    /// One point every fifteen minutes
+    #[serde(rename="15Min")]
+    FifteenMinutes,
+    /// One point per hour
+    #[serde(rename="1H")]
+    OneHour,
+    /// One point per day
+    #[serde(rename="1D")]
+    OneDay,
+}
+
+/// Portfolio history requests
+#[derive(Builder, Debug, Clone, Default, Serialize)]
+pub struct PortfolioHistoryRequest {
+    /// The period covered by the history (by default, one month)
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub period: Option<HistoryPeriod>,
+    /// The resolution of the history. By default, it is one minute for a
+    /// period shorter than 7 days, fifteen minutes for a period shorter than
+    /// 30 days and one day otherwise.
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeframe: Option<HistoryTimeFrame>,
+    /// The last day of the history (by default, the current market date)
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date_end: Option<NaiveDate>,
+    /// If true, the extended hours are included (intraday timeframes only)
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extended_hours: Option<bool>,
+}
+
+/// The history of the equity and profit/loss of the account. The arrays all
+/// have one item per timestamp; their items are None for the timestamps at
+/// which the account had no value (e.g. before it was opened).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct PortfolioHistory {
+    /// The timestamps (unix epoch seconds) of the points of the history
+    pub timestamp: Vec<i64>,
+    /// The equity of the account at each timestamp
+    pub equity: Vec<Option<f64>>,
+    /// The profit/loss (in dollars) relative to the base value
+    pub profit_loss: Vec<Option<f64>>,
+    /// The profit/loss (as a ratio) relative to the base value
+    pub profit_loss_pct: Vec<Option<f64>>,
+    /// The equity of the account at the start of the period
+    #[serde(default)]
+    pub base_value: Option<f64>,
+    /// The resolution of the history
+    pub timeframe: HistoryTimeFrame,
+}
+impl PortfolioHistory {
+    /// The points of the history, in chronological order. The points whose
+    /// timestamp is out of the range of a `DateTime` are skipped.
+    pub fn points(&self) -> impl Iterator<Item=HistoryPoint> + '_ {
+        self.timestamp.iter().enumerate().filter_map(move |(i, t)| Some(HistoryPoint {
+            timestamp      : Utc.timestamp_opt(*t, 0).single()?,
+            equity         : self.equity.get(i).copied().flatten(),
+            profit_loss    : self.profit_loss.get(i).copied().flatten(),
+            profit_loss_pct: self.profit_loss_pct.get(i).copied().flatten(),
+        }))
+    }
+}
+
+/// One point of a portfolio history
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HistoryPoint {
+    /// The time of the point
+    pub timestamp: DateTime<Utc>,
+    /// The equity of the account
+    pub equity: Option<f64>,
+    /// The profit/loss (in dollars) relative to the base value
+    pub profit_loss: Option<f64>,
+    /// The profit/loss (as a ratio) relative to the base value
+    pub profit_loss_pct: Option<f64>,
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use crate::account::{AccountConfigurationsUpdateBuilder, AccountStatus, CheckSide, HistoryPeriod, HistoryTimeFrame, PortfolioHistory, PortfolioHistoryRequestBuilder};
+    use crate::testing::fake_client;
+    use crate::transport::FakeTransport;
+
+    const ACCOUNT: &str = r#"{"id":"e6fe16f3-64a4-4921-8928-cadf02f92f98","account_number":"010203ABCD",
+        "status":"ACTIVE","crypto_status":"ACTIVE","currency":"USD","buying_power":"262113.632","regt_buying_power":"262113.632",
+        "daytrading_buying_power":"262113.632","non_marginable_buying_power":"131056.82","options_buying_power":"131056.82",
+        "cash":"-23140.2","accrued_fees":"0","pending_transfer_in":"0","portfolio_value":"103820.56",
+        "pattern_day_trader":false,"trading_blocked":false,"transfers_blocked":false,"account_blocked":false,
+        "created_at":"2019-06-12T22:47:07.99658Z","trade_suspended_by_user":false,"multiplier":"4",
+        "shorting_enabled":true,"equity":"103820.56","last_equity":"103529.24","long_market_value":"126960.76",
+        "short_market_value":"0","initial_margin":"63480.38","maintenance_margin":"38088.228",
+        "last_maintenance_margin":"38000.832","sma":"0","daytrade_count":0,"options_approved_level":2}"#;
+
+    #[tokio::test]
+    async fn test_account() {
+        let transport = FakeTransport::new().respond("GET", "/v2/account", 200, ACCOUNT);
+        let client    = fake_client(&transport);
+
+        let account = client.account().await.unwrap();
+        assert_eq!(AccountStatus::Active, account.status);
+        assert_eq!(262113.632, account.buying_power);
+        assert_eq!(4.0, account.multiplier);
+        assert_eq!(Some(2), account.options_approved_level);
+        assert!(account.can_trade());
+    }
+    #[tokio::test]
+    async fn test_update_account_configurations() {
+        let configurations = r#"{"dtbp_check":"entry","trade_confirm_email":"all","pdt_check":"entry",
+            "suspend_trade":false,"no_shorting":true,"fractional_trading":true,"max_margin_multiplier":"4"}"#;
+        let transport = FakeTransport::new().respond("PATCH", "/v2/account/configurations", 200, configurations);
+        let client    = fake_client(&transport);
+        let update    = AccountConfigurationsUpdateBuilder::default()
+            .no_shorting(true)
+            .dtbp_check(CheckSide::Entry)
+            .build().unwrap();
+
+        let configurations = client.update_account_configurations(&update).await.unwrap();
+        assert!(configurations.no_shorting);
+        assert_eq!(Some(r#"{"dtbp_check":"entry","no_shorting":true}"#.to_string()), transport.requests()[0].body);
+    }
+    #[tokio::test]
+    async fn test_portfolio_history() {
+        let history = r#"{"timestamp":[1580826600,1580913000,1580999400],"equity":[null,27423.73,28028.59],
+            "profit_loss":[null,11.8,604.86],"profit_loss_pct":[null,0.000430469,0.0220557],"base_value":27411.93,"timeframe":"1D"}"#;
+        let transport = FakeTransport::new().respond("GET", "/v2/account/portfolio/history", 200, history);
+        let client    = fake_client(&transport);
+        let request   = PortfolioHistoryRequestBuilder::default()
+            .period(HistoryPeriod::Weeks(1))
+            .timeframe(HistoryTimeFrame::OneDay)
+            .build().unwrap();
+
+        let history = client.portfolio_history(&request).await.unwrap();
+        let points  = history.points().collect::<Vec<_>>();
+        assert_eq!(3, points.len());
+        assert_eq!(None, points[0].equity);
+        assert_eq!(Some(604.86), points[2].profit_loss);
+        assert!(transport.requests()[0].url.contains("period=1W&timeframe=1D"));
+    }
+    #[test]
+    fn test_out_of_range_points_are_skipped() {
+        let history = r#"{"timestamp":[1580826600,9223372036854775807],"equity":[27423.73,28028.59],
+            "profit_loss":[11.8,604.86],"profit_loss_pct":[0.000430469,0.0220557],"timeframe":"1D"}"#;
+        let history = serde_json::from_str::<PortfolioHistory>(history).unwrap();
+        let points  = history.points().collect::<Vec<_>>();
+        assert_eq!(1, points.len());
+        assert_eq!(Some(27423.73), points[0].equity);
+    }
+}
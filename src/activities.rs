@@ -0,0 +1,405 @@
+//! This module provides an access to the account activities API: the history
+//! of the fills (trade activities) and of the other events which affected the
+//! cash balance or the positions of the account, such as the dividends, the
+//! fees or the transfers (non-trade activities).
+//!
+//! The activities are served by pages of at most `ACTIVITIES_PAGE_SIZE`
+//! items. The `activities` stream goes through all of them transparently.
+//!
+//! ```no_run
+//! # async fn example(client: apca_datav2::rest::Client) -> Result<(), apca_datav2::errors::Error> {
+//! use futures::StreamExt;
+//! use apca_datav2::activities::{Activity, ActivitiesRequestBuilder, ActivityType};
+//!
+//! let request = ActivitiesRequestBuilder::default()
+//!     .activity_types(vec![ActivityType::Fill, ActivityType::Div])
+//!     .build().unwrap();
+//! let mut activities = Box::pin(client.activities(&request));
+//! while let Some(activity) = activities.next().await {
+//!     match activity? {
+//!         Activity::Trade(fill)   => println!("{} {} @ {}", fill.symbol, fill.qty, fill.price),
+//!         Activity::NonTrade(div) => println!("{:?}: {}", div.activity_type, div.net_amount),
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::pin::Pin;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use derive_builder::Builder;
+use futures::{Future, Stream};
+use serde::{Serialize, Deserialize};
+
+use crate::{endpoints::Endpoint, entities::Direction, errors::{Error, status_code_to_api_error}, rest::{Client, FetchNextPage, Paged, PagedStream}};
+
+/// The maximum number of activities per page
+pub const ACTIVITIES_PAGE_SIZE: u32 = 100;
+
+impl Client {
+    /// This stream returns all the activities matching the request, going
+    /// through the several pages asynchronously; upon request. The page size
+    /// of the request is used (`ACTIVITIES_PAGE_SIZE` when none is set).
+    pub fn activities<'a>(&'a self, request: &ActivitiesRequest) -> impl Stream<Item=Result<Activity, Error>> + 'a {
+        let mut request = request.clone();
+        request.page_size = Some(request.page_size.unwrap_or(ACTIVITIES_PAGE_SIZE));
+        PagedStream::new(FetchNextActivities { client: self, request }).prefetch(self.prefetches())
+    }
+    /// Retrieves one page of the activities matching the request. The next
+    /// page is obtained by setting the `page_token` of the request to the id
+    /// of the last activity of this page.
+    pub async fn activities_paged(&self, request: &ActivitiesRequest) -> Result<Vec<Activity>, Error> {
+        let url = match request.activity_types.as_deref() {
+            Some([single]) => Endpoint::ActivitiesOfType(&single.to_string()).url(self.env_url()),
+            _              => Endpoint::Activities.url(self.env_url()),
+        };
+        let types = request.activity_types.as_ref()
+            .filter(|types| types.len() > 1)
+            .map(|types| types.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(","));
+        let rsp = self.get_authenticated(&url)
+            .query(&request)
+            .query(&[("activity_types", types)])
+            .send().await?;
+
+        status_code_to_api_error(rsp).await
+    }
+}
+
+/// The types of activities
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all="UPPERCASE")]
+#[non_exhaustive]
+pub enum ActivityType {
+    /// Order fills (both partial and full fills)
+    Fill,
+    /// Cash transactions (both CSD and CSW)
+    Trans,
+    /// Miscellaneous or rarely used activity types
+    Misc,
+    /// ACATS IN/OUT (Cash)
+    Acatc,
+    /// ACATS IN/OUT (Securities)
+    Acats,
+    /// Crypto fee
+    Cfee,
+    /// Cash in lieu of stock
+    Cil,
+    /// Cash deposit (+)
+    Csd,
+    /// Cash withdrawal (-)
+    Csw,
+    /// Dividends
+    Div,
+    /// Dividend (capital gain long term)
+    Divcgl,
+    /// Dividend (capital gain short term)
+    Divcgs,
+    /// Dividend fee
+    Divfee,
+    /// Dividend adjusted (foreign tax withheld)
+    Divft,
+    /// Dividend adjusted (NRA withheld)
+    Divnra,
+    /// Dividend return of capital
+    Divroc,
+    /// Dividend adjusted (tefra withheld)
+    Divtw,
+    /// Dividend (tax exempt)
+    Divtxex,
+    /// Fee denominated in USD
+    Fee,
+    /// Interest (credit/margin)
+    Int,
+    /// Interest adjusted (NRA withheld)
+    Intnra,
+    /// Interest adjusted (tefra withheld)
+    Inttw,
+    /// Journal entry
+    Jnl,
+    /// Journal entry (cash)
+    Jnlc,
+    /// Journal entry (stock)
+    Jnls,
+    /// Merger/Acquisition
+    Ma,
+    /// Name change
+    Nc,
+    /// Option assignment
+    Opasn,
+    /// Option corporate action
+    Opca,
+    /// Option expiration
+    Opexp,
+    /// Option trade
+    Optrd,
+    /// Option exercise
+    Opxrc,
+    /// Pass thru charge
+    Ptc,
+    /// Pass thru rebate
+    Ptr,
+    /// Reorg CA
+    Reorg,
+    /// Symbol change
+    Sc,
+    /// Stock spinoff
+    Sso,
+    /// Stock split
+    Ssp,
+    /// Voluntary offering
+    Vof,
+    /// Any activity type which is not known to this crate (never sent as a
+    /// filter)
+    #[serde(other)]
+    Other,
+}
+impl std::fmt::Display for ActivityType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let txt = serde_json::to_string(self).map_err(|_| std::fmt::Error)?;
+        write!(f, "{}", txt.trim_matches('"'))
+    }
+}
+
+/// One activity of the account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Activity {
+    /// A fill of an order
+    Trade(TradeActivity),
+    /// Any other activity (dividend, fee, transfer...)
+    NonTrade(NonTradeActivity),
+}
+impl Activity {
+    /// The id of the activity (which is used as page token)
+    pub fn id(&self) -> &str {
+        match self {
+            Activity::Trade(a)    => &a.id,
+            Activity::NonTrade(a) => &a.id,
+        }
+    }
+    /// The type of the activity
+    pub fn activity_type(&self) -> ActivityType {
+        match self {
+            Activity::Trade(a)    => a.activity_type,
+            Activity::NonTrade(a) => a.activity_type,
+        }
+    }
+}
+
+/// Whether a fill completed its order or not
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub enum FillType {
+    /// The order is completely filled
+    Fill,
+    /// The order is partially filled
+    PartialFill,
+}
+
+/// The side of a fill
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub enum FillSide {
+    /// Bought shares
+    Buy,
+    /// Sold shares
+    Sell,
+    /// Sold shares short
+    SellShort,
+}
+
+/// The fill of an order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct TradeActivity {
+    /// The id of the activity
+    pub id: String,
+    /// Always `ActivityType::Fill`
+    pub activity_type: ActivityType,
+    /// The time at which the execution occurred
+    pub transaction_time: DateTime<Utc>,
+    /// Whether the order was partially or completely filled
+    #[serde(rename="type")]
+    pub fill_type: FillType,
+    /// The per-share price at which the shares were filled
+    #[serde(deserialize_with="crate::utils::number_as_f64")]
+    pub price: f64,
+    /// The number of shares filled by this execution
+    #[serde(deserialize_with="crate::utils::number_as_f64")]
+    pub qty: f64,
+    /// The side of the fill
+    pub side: FillSide,
+    /// The symbol of the security
+    pub symbol: String,
+    /// The number of shares of the order which remain to be filled
+    #[serde(deserialize_with="crate::utils::number_as_f64")]
+    pub leaves_qty: f64,
+    /// The id of the order which was filled
+    pub order_id: String,
+    /// The cumulative number of shares filled for the order
+    #[serde(deserialize_with="crate::utils::number_as_f64")]
+    pub cum_qty: f64,
+    /// The status of the order after this fill
+    #[serde(default)]
+    pub order_status: Option<String>,
+}
+
+/// An activity which is not a fill (dividend, fee, transfer...)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct NonTradeActivity {
+    /// The id of the activity
+    pub id: String,
+    /// The type of the activity
+    pub activity_type: ActivityType,
+    /// The date on which the activity occurred or on which the transaction
+    /// associated with the activity settled
+    pub date: NaiveDate,
+    /// The net amount of money (positive or negative) associated with the
+    /// activity
+    #[serde(deserialize_with="crate::utils::number_as_f64")]
+    pub net_amount: f64,
+    /// The symbol of the security involved with the activity (if any)
+    #[serde(default)]
+    pub symbol: Option<String>,
+    /// For the dividend activities, the number of shares that contributed to
+    /// the payment
+    #[serde(default, deserialize_with="crate::utils::option_as_f64")]
+    pub qty: Option<f64>,
+    /// For the dividend activities, the average amount paid per share
+    #[serde(default, deserialize_with="crate::utils::option_as_f64")]
+    pub per_share_amount: Option<f64>,
+    /// A description of the activity
+    #[serde(default)]
+    pub description: Option<String>,
+    /// The status of the activity (e.g. executed)
+    #[serde(default)]
+    pub status: Option<String>,
+}
+
+/// Account activities requests
+#[derive(Builder, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ActivitiesRequest {
+    /// Only the activities of these types are returned (by default, all the
+    /// types are)
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip)]
+    pub activity_types: Option<Vec<ActivityType>>,
+    /// Only the activities of this day are returned
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date: Option<NaiveDate>,
+    /// Only the activities up to this time are returned
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub until: Option<DateTime<Utc>>,
+    /// Only the activities after this time are returned
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<DateTime<Utc>>,
+    /// The chronological order of the activities (by default, descending)
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub direction: Option<Direction>,
+    /// The maximum number of activities per page (at most 100)
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_size: Option<u32>,
+    /// The id of the last activity of the previous page
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_token: Option<String>,
+}
+
+/// One page of the activities
+struct ActivitiesPage {
+    activities: Vec<Activity>,
+    /// The page size; a shorter page is the last one
+    page_size : u32,
+}
+impl Paged for ActivitiesPage {
+    type Item = Activity;
+    fn split(self) -> (Vec<Self::Item>, Option<String>) {
+        let token = if self.activities.len() < self.page_size as usize {
+            None
+        } else {
+            self.activities.last().map(|a| a.id().to_string())
+        };
+        (self.activities, token)
+    }
+}
+/// This structure encapsulates a call to `activities_paged` and yields a
+/// future that can be used to asychronously fetch the next page
+struct FetchNextActivities<'a> {
+    client : &'a Client,
+    request: ActivitiesRequest,
+}
+impl <'a> FetchNextPage<'a, ActivitiesPage> for FetchNextActivities<'a> {
+    fn fetch(self: Pin<&Self>, token: Option<String>) -> Pin<Box<dyn Future<Output=Result<ActivitiesPage, Error>> + 'a >> {
+        let client      = self.client;
+        let mut request = self.request.clone();
+        if token.is_some() {
+            request.page_token = token;
+        }
+        Box::pin(async move {
+            let activities = client.activities_paged(&request).await?;
+            Ok(ActivitiesPage { activities, page_size: request.page_size.unwrap_or(ACTIVITIES_PAGE_SIZE) })
+        })
+    }
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use futures::{StreamExt, TryStreamExt};
+
+    use crate::activities::{ActivitiesRequestBuilder, Activity, ActivityType, FillSide};
+    use crate::testing::fake_client;
+    use crate::transport::FakeTransport;
+
+    #[tokio::test]
+    async fn test_activities_are_paged() {
+        let page = r#"[
+            {"id":"20210301000000000::8e92b95a","activity_type":"FILL","transaction_time":"2021-03-01T14:30:01.5Z",
+             "type":"fill","price":"120.5","qty":"2","side":"sell_short","symbol":"AAPL","leaves_qty":"0",
+             "order_id":"904837e3","cum_qty":"2","order_status":"filled"},
+            {"id":"20210226000000000::3a3b0b5c","activity_type":"DIV","date":"2021-02-26","net_amount":"1.02",
+             "symbol":"T","qty":"2","per_share_amount":"0.51","status":"executed"}
+        ]"#;
+        let transport = FakeTransport::new().respond("GET", "/v2/account/activities", 200, page);
+        let client    = fake_client(&transport);
+        let request   = ActivitiesRequestBuilder::default()
+            .activity_types(vec![ActivityType::Fill, ActivityType::Div])
+            .page_size(2)
+            .build().unwrap();
+
+        let activities = client.activities(&request).take(3).try_collect::<Vec<_>>().await.unwrap();
+        assert!(matches!(&activities[0], Activity::Trade(fill) if fill.side == FillSide::SellShort && fill.price == 120.5));
+        assert!(matches!(&activities[1], Activity::NonTrade(div) if div.activity_type == ActivityType::Div));
+
+        // the second page starts after the last activity of the first one
+        let requests = transport.requests();
+        assert!(requests[0].url.contains("activity_types=FILL%2CDIV"));
+        assert!(requests[1].url.contains("page_token=20210226000000000%3A%3A3a3b0b5c"));
+    }
+    #[tokio::test]
+    async fn test_unknown_activity_types_are_tolerated() {
+        let page = r#"[
+            {"id":"20210301000000000::8e92b95a","activity_type":"BRANDNEW","date":"2021-03-01","net_amount":"-0.5",
+             "description":"some new kind of fee","status":"executed"},
+            {"id":"20210226000000000::3a3b0b5c","activity_type":"CIL","date":"2021-02-26","net_amount":"12.3",
+             "symbol":"T","qty":"0.3","status":"executed"}
+        ]"#;
+        let transport = FakeTransport::new().respond("GET", "/v2/account/activities", 200, page);
+        let client    = fake_client(&transport);
+        let request   = ActivitiesRequestBuilder::default().build().unwrap();
+
+        let activities = client.activities(&request).try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(ActivityType::Other, activities[0].activity_type());
+        assert_eq!(ActivityType::Cil, activities[1].activity_type());
+    }
+}
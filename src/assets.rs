@@ -5,9 +5,7 @@
 //! via Polygon, and are not tradable with Alpaca. These assets will be 
 //! marked with the flag tradable=false.
 
-use crate::{entities::{AssetData, AssetStatus}, errors::{Error, maybe_convert_to_asset_error, status_code_to_asset_error}, rest::Client};
-
-static ENDPOINT: &str = "/v2/assets";
+use crate::{entities::{AssetClass, AssetData, AssetStatus}, errors::{Error, maybe_convert_to_asset_error, status_code_to_asset_error}, rest::Client, endpoints::Endpoint, symbols::SymbolOrAssetId};
 
 impl Client {
   /// Get a list of assets
@@ -15,14 +13,14 @@ impl Client {
   /// # Parameters
   /// - status: .g. “active”. By default, all statuses are included.
   /// - asset_class: Defaults to us_equity.
-  pub async fn list_assets(&self, status: Option<AssetStatus>, asset_class: Option<&str>) -> Result<Vec<AssetData>, Error> {
-    let url = format!("{}/{}", self.env_url(), ENDPOINT);
+  pub async fn list_assets(&self, status: Option<AssetStatus>, asset_class: Option<AssetClass>) -> Result<Vec<AssetData>, Error> {
+    let url = Endpoint::Assets.url(self.env_url());
     let mut params = vec![];
     if let Some(status) = status {
       params.push(("status", status.to_str()));
     }
-    if let Some(asset_class) = asset_class {
-      params.push(("asset_class", asset_class));
+    if let Some(asset_class) = asset_class.as_ref() {
+      params.push(("asset_class", asset_class.as_str()));
     }
     let rsp = self.get_authenticated(&url)
       .query(&params)
@@ -31,9 +29,9 @@ impl Client {
     status_code_to_asset_error(rsp).await
   }
 
-  /// Get an asset for the given symbol
-  pub async fn get_asset(&self, symbol: &str) -> Result<AssetData, Error> {
-    let url = format!("{}/{}/{}", self.env_url(), ENDPOINT, symbol);
+  /// Get an asset for the given symbol or asset id
+  pub async fn get_asset<S: Into<SymbolOrAssetId>>(&self, symbol: S) -> Result<AssetData, Error> {
+    let url = Endpoint::Asset(&self.symbols().normalize_target(&symbol.into())).url(self.env_url());
     let rsp = self.get_authenticated(&url)
       .send().await
       .map_err(maybe_convert_to_asset_error)?;
@@ -0,0 +1,265 @@
+//! This module provides a persistent on-disk cache of the historical bars.
+//!
+//! Backtests tend to ask for the same bars over and over again. With a
+//! `BarCache`, the bars fetched from the API are stored in a directory and
+//! the subsequent requests which overlap them are served locally: only the
+//! ranges which have never been fetched hit the API.
+//!
+//! # Layout
+//! The cache is a directory of files keyed by symbol, timeframe, feed,
+//! adjustment and asof date:
+//!
+//! ```text
+//! <root>/<symbol>/<timeframe>/<feed>/<adjustment>/<asof>/<start>-<end>.json
+//! ```
+//!
+//! where `start` and `end` are the bounds (in nanoseconds since the epoch) of
+//! the range covered by the file. The bars are fetched with the feed and
+//! currency of the client (`default` when none is set), with its asof date
+//! (`latest` when none is set) and without any adjustment (`raw`, the
+//! default of the API). The ranges whose last bar is
+//! not entirely in the past are served but never stored, since the bars of
+//! the ongoing period may still change.
+//!
+//! ```no_run
+//! # async fn example(client: apca_datav2::rest::Client) -> Result<(), apca_datav2::errors::Error> {
+//! use chrono::{TimeZone, Utc};
+//! use apca_datav2::barcache::BarCache;
+//! use apca_datav2::historical::TimeFrame;
+//!
+//! let cache = BarCache::new("bars")?;
+//! let start = Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap();
+//! let end   = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+//! // the first call hits the API, the second one is served from disk
+//! let bars  = cache.bars(&client, "AAPL", start, end, TimeFrame::Day).await?;
+//! let again = cache.bars(&client, "AAPL", start, end, TimeFrame::Day).await?;
+//! assert_eq!(bars.len(), again.len());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{fs::{self, File}, path::{Path, PathBuf}};
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use futures::TryStreamExt;
+
+use crate::{entities::BarData, errors::Error, historical::{MAX_LIMIT, TimeFrame}, rest::Client};
+
+/// The adjustment of the cached bars (the bars are fetched as they traded)
+pub const ADJUSTMENT: &str = "raw";
+
+/// A range of time (both bounds included)
+pub type Range = (DateTime<Utc>, DateTime<Utc>);
+
+/// A persistent cache of the historical bars
+#[derive(Debug, Clone)]
+pub struct BarCache {
+    root: PathBuf,
+}
+impl BarCache {
+    /// Opens (or creates) the cache stored in the given directory
+    #[allow(clippy::result_large_err)]
+    pub fn new<P: AsRef<Path>>(root: P) -> Result<Self, Error> {
+        fs::create_dir_all(root.as_ref())?;
+        Ok(Self { root: root.as_ref().to_path_buf() })
+    }
+    /// The directory where the cache is stored
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+    /// Retrieves the bars of the given symbol between start and end
+    /// (inclusive), in chronological order. Only the ranges which are not in
+    /// the cache yet are fetched through the given client.
+    pub async fn bars(&self, client: &Client, symbol: &str, start: DateTime<Utc>, end: DateTime<Utc>, timeframe: TimeFrame) -> Result<Vec<BarData>, Error> {
+        let dir = self.dir(client, symbol, timeframe);
+        let now = Utc::now();
+        let mut bars = vec![];
+        for (from, to) in missing(&covered(&dir)?, start, end) {
+            let fetched = client.bars(symbol, from, to, timeframe, Some(MAX_LIMIT)).try_collect::<Vec<_>>().await?;
+            // the bar starting at the end of the range must be complete
+            if to + timeframe.duration() < now {
+                fs::create_dir_all(&dir)?;
+                let file = File::create(dir.join(file_name(from, to)))?;
+                serde_json::to_writer(file, &fetched)?;
+            }
+            bars.extend(fetched);
+        }
+        for (from, to) in covered(&dir)? {
+            if from <= end && to >= start {
+                let file = File::open(dir.join(file_name(from, to)))?;
+                bars.extend(serde_json::from_reader::<_, Vec<BarData>>(file)?);
+            }
+        }
+        bars.retain(|b| b.timestamp >= start && b.timestamp <= end);
+        bars.sort_by_key(|b| b.timestamp);
+        bars.dedup_by_key(|b| b.timestamp);
+        Ok(bars)
+    }
+    /// The ranges between start and end which are not in the cache yet
+    #[allow(clippy::result_large_err)]
+    pub fn missing(&self, client: &Client, symbol: &str, start: DateTime<Utc>, end: DateTime<Utc>, timeframe: TimeFrame) -> Result<Vec<Range>, Error> {
+        Ok(missing(&covered(&self.dir(client, symbol, timeframe))?, start, end))
+    }
+    /// Removes all the bars of the cache
+    #[allow(clippy::result_large_err)]
+    pub fn clear(&self) -> Result<(), Error> {
+        fs::remove_dir_all(&self.root)?;
+        fs::create_dir_all(&self.root)?;
+        Ok(())
+    }
+    /// Private: the directory holding the bars of the given key. The key
+    /// comprises every parameter of the request which changes the bars.
+    fn dir(&self, client: &Client, symbol: &str, timeframe: TimeFrame) -> PathBuf {
+        let mut feed = client.feed().map(|f| f.to_string()).unwrap_or_else(|| "default".to_string());
+        if let Some(currency) = client.currency() {
+            feed = format!("{}.{}", feed, currency);
+        }
+        let asof = client.asof().map(|d| d.to_string()).unwrap_or_else(|| "latest".to_string());
+        self.root
+            .join(symbol.replace('/', "_"))
+            .join(timeframe.to_string())
+            .join(feed)
+            .join(ADJUSTMENT)
+            .join(asof)
+    }
+}
+
+/// Private: the ranges covered by the files of the given directory, sorted
+/// by start
+#[allow(clippy::result_large_err)]
+fn covered(dir: &Path) -> Result<Vec<Range>, Error> {
+    let mut ranges = vec![];
+    if !dir.exists() {
+        return Ok(ranges);
+    }
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        if let Some(range) = name.to_str().and_then(parse_file_name) {
+            ranges.push(range);
+        }
+    }
+    ranges.sort();
+    Ok(ranges)
+}
+
+/// Private: the sub ranges of [start, end] which are not covered by the
+/// given (sorted) ranges
+fn missing(covered: &[Range], start: DateTime<Utc>, end: DateTime<Utc>) -> Vec<Range> {
+    let mut gaps   = vec![];
+    let mut cursor = start;
+    for (from, to) in covered.iter().copied() {
+        if cursor > end {
+            break;
+        }
+        if to < cursor {
+            continue;
+        }
+        if from > cursor {
+            gaps.push((cursor, (from - Duration::nanoseconds(1)).min(end)));
+        }
+        cursor = cursor.max(to + Duration::nanoseconds(1));
+    }
+    if cursor <= end {
+        gaps.push((cursor, end));
+    }
+    gaps
+}
+
+/// Private: the name of the file covering the given range
+fn file_name(start: DateTime<Utc>, end: DateTime<Utc>) -> String {
+    format!("{}-{}.json", nanos(start), nanos(end))
+}
+/// Private: the range covered by the file having the given name
+fn parse_file_name(name: &str) -> Option<Range> {
+    let (start, end) = name.strip_suffix(".json")?.split_once('-')?;
+    Some((Utc.timestamp_nanos(start.parse().ok()?), Utc.timestamp_nanos(end.parse().ok()?)))
+}
+/// Private: the number of nanoseconds since the epoch
+fn nanos(time: DateTime<Utc>) -> i64 {
+    time.timestamp_nanos_opt().unwrap_or_default()
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, TimeZone, Utc};
+
+    use crate::barcache::BarCache;
+    use crate::historical::TimeFrame;
+    use crate::testing::fake_client;
+    use crate::transport::FakeTransport;
+
+    #[tokio::test]
+    async fn test_only_missing_ranges_are_fetched() {
+        let bars = r#"{"bars":[
+            {"t":"2021-08-02T04:00:00Z","o":1,"h":2,"l":0.5,"c":1.5,"v":100},
+            {"t":"2021-08-04T04:00:00Z","o":1,"h":2,"l":0.5,"c":1.5,"v":100}
+        ],"symbol":"AAPL","next_page_token":null}"#;
+        let transport = FakeTransport::new()
+            .respond("GET", "/v2/stocks/AAPL/bars", 200, bars);
+        let client = fake_client(&transport);
+
+        let root  = std::env::temp_dir().join(format!("apca_barcache_{}", std::process::id()));
+        let cache = BarCache::new(&root).unwrap();
+        let aug1  = Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap();
+        let aug3  = Utc.with_ymd_and_hms(2021, 8, 3, 0, 0, 0).unwrap();
+        let aug5  = Utc.with_ymd_and_hms(2021, 8, 5, 0, 0, 0).unwrap();
+
+        let first = cache.bars(&client, "AAPL", aug1, aug3, TimeFrame::Day).await.unwrap();
+        assert_eq!(1, first.len());
+        let again = cache.bars(&client, "AAPL", aug1, aug3, TimeFrame::Day).await.unwrap();
+        assert_eq!(1, again.len());
+        assert_eq!(1, transport.requests().len());
+
+        let missing = cache.missing(&client, "AAPL", aug1, aug5, TimeFrame::Day).unwrap();
+        assert_eq!(vec![(aug3 + chrono::Duration::nanoseconds(1), aug5)], missing);
+        let wider = cache.bars(&client, "AAPL", aug1, aug5, TimeFrame::Day).await.unwrap();
+        assert_eq!(2, wider.len());
+        assert_eq!(2, transport.requests().len());
+        assert!(cache.missing(&client, "AAPL", aug1, aug5, TimeFrame::Day).unwrap().is_empty());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+    #[tokio::test]
+    async fn test_asof_is_part_of_the_key() {
+        let transport = FakeTransport::new()
+            .respond("GET", "/v2/stocks/FB/bars", 200, r#"{"bars":[],"symbol":"FB","next_page_token":null}"#);
+        let client = fake_client(&transport);
+        let asof   = fake_client(&transport).with_asof(NaiveDate::from_ymd_opt(2022, 6, 8).unwrap());
+
+        let root  = std::env::temp_dir().join(format!("apca_barcache_asof_{}", std::process::id()));
+        let cache = BarCache::new(&root).unwrap();
+        let aug1  = Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap();
+        let aug3  = Utc.with_ymd_and_hms(2021, 8, 3, 0, 0, 0).unwrap();
+
+        cache.bars(&client, "FB", aug1, aug3, TimeFrame::Day).await.unwrap();
+        assert!(cache.missing(&client, "FB", aug1, aug3, TimeFrame::Day).unwrap().is_empty());
+        // the symbols resolved as of another date may have other bars
+        assert_eq!(vec![(aug1, aug3)], cache.missing(&asof, "FB", aug1, aug3, TimeFrame::Day).unwrap());
+        cache.bars(&asof, "FB", aug1, aug3, TimeFrame::Day).await.unwrap();
+        assert_eq!(2, transport.requests().len());
+        assert!(transport.requests()[1].url.contains("asof=2022-06-08"));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+    #[tokio::test]
+    async fn test_bars_in_progress_are_not_stored() {
+        let transport = FakeTransport::new()
+            .respond("GET", "/v2/stocks/AAPL/bars", 200, r#"{"bars":[],"symbol":"AAPL","next_page_token":null}"#);
+        let client = fake_client(&transport);
+
+        let root  = std::env::temp_dir().join(format!("apca_barcache_progress_{}", std::process::id()));
+        let cache = BarCache::new(&root).unwrap();
+        // the range is in the past, but the daily bar it ends with is not
+        let end   = Utc::now() - chrono::Duration::hours(1);
+        let start = end - chrono::Duration::days(3);
+
+        cache.bars(&client, "AAPL", start, end, TimeFrame::Day).await.unwrap();
+        assert_eq!(vec![(start, end)], cache.missing(&client, "AAPL", start, end, TimeFrame::Day).unwrap());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
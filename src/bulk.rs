@@ -0,0 +1,172 @@
+//! This module downloads the history of many symbols concurrently.
+//!
+//! Fetching the bars of a whole universe one symbol after the other spends
+//! most of its time waiting on the network. The `BulkDownloader` fans the
+//! requests out with a bounded concurrency instead; while the requests still
+//! go through the rate limiter of the client (if any), so that the downloader
+//! never exceeds the rate Alpaca allows. The pages which fail for a transient
+//! reason (connection lost, rate limit exceeded, server error...) are
+//! retried according to a `RetryPolicy`. The results are yielded per symbol,
+//! as soon as the whole history of a symbol has been fetched.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), apca_datav2::errors::Error> {
+//! use std::time::Duration;
+//! use chrono::{TimeZone, Utc};
+//! use futures::StreamExt;
+//! use apca_datav2::bulk::BulkDownloader;
+//! use apca_datav2::historical::TimeFrame;
+//! use apca_datav2::rest::{ALPACA_RATE_LIMIT, Client};
+//!
+//! let client = Client::from_env()?
+//!     .with_rate_limit(ALPACA_RATE_LIMIT, Duration::from_secs(60));
+//! let start  = Utc.with_ymd_and_hms(2018, 1, 1, 0, 0, 0).unwrap();
+//! let end    = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+//! let symbols = ["AAPL", "MSFT", "AMZN", "GOOG"];
+//!
+//! let downloader = BulkDownloader::new(&client).concurrency(8);
+//! let results    = downloader.bars(&symbols, start, end, TimeFrame::Day);
+//! futures::pin_mut!(results);
+//! while let Some((symbol, bars)) = results.next().await {
+//!     match bars {
+//!         Ok(bars)   => println!("{}: {} bars", symbol, bars.len()),
+//!         Err(error) => println!("{}: {}", symbol, error),
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::pin::Pin;
+
+use chrono::{DateTime, Utc};
+use futures::{Stream, StreamExt, TryStreamExt};
+
+use crate::{entities::BarData, errors::{Error, HistoryError}, historical::{MAX_LIMIT, MultiBars, TimeFrame}, rest::{Client, FailibleFuture, FetchNextPage, PagedStream, RetryPolicy}};
+
+/// The default number of symbols which are downloaded at the same time
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+/// Downloads the history of several symbols concurrently
+pub struct BulkDownloader<'a> {
+    client     : &'a Client,
+    concurrency: usize,
+    retry      : RetryPolicy,
+}
+impl<'a> BulkDownloader<'a> {
+    /// Creates a downloader which sends its requests with the given client
+    pub fn new(client: &'a Client) -> Self {
+        Self { client, concurrency: DEFAULT_CONCURRENCY, retry: RetryPolicy::default() }
+    }
+    /// Sets the maximum number of symbols which are downloaded at the same
+    /// time
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+    /// Sets the policy used to retry the pages which fail for a transient
+    /// reason (only the `max_attempts` and backoff settings are used)
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+    /// Downloads the bars of the given symbols. The results are yielded in
+    /// the order in which the downloads complete.
+    pub fn bars<'b>(&'b self, symbols: &'b [&'b str], start: DateTime<Utc>, end: DateTime<Utc>, timeframe: TimeFrame) -> impl Stream<Item=(String, Result<Vec<BarData>, Error>)> + 'b {
+        futures::stream::iter(symbols.iter())
+            .map(move |symbol| async move {
+                (symbol.to_string(), self.download_bars(symbol, start, end, timeframe).await)
+            })
+            .buffer_unordered(self.concurrency)
+    }
+    /// Private: downloads all the pages of the bars of one symbol
+    async fn download_bars(&self, symbol: &str, start: DateTime<Utc>, end: DateTime<Utc>, timeframe: TimeFrame) -> Result<Vec<BarData>, Error> {
+        PagedStream::new(FetchBarsWithRetry { downloader: self, symbol, start, end, timeframe })
+            .prefetch(self.client.prefetches())
+            .try_collect().await
+    }
+}
+
+/// Private: fetches the pages of the bars of one symbol, retrying the pages
+/// which fail for a transient reason
+struct FetchBarsWithRetry<'a> {
+    downloader: &'a BulkDownloader<'a>,
+    symbol    : &'a str,
+    start     : DateTime<Utc>,
+    end       : DateTime<Utc>,
+    timeframe : TimeFrame,
+}
+impl<'a> FetchNextPage<'a, MultiBars> for FetchBarsWithRetry<'a> {
+    fn fetch(self: Pin<&Self>, token: Option<String>) -> Pin<Box<FailibleFuture<'a, MultiBars>>> {
+        let Self { downloader, symbol, start, end, timeframe } = *self.get_ref();
+        Box::pin(async move {
+            let mut attempt = 1;
+            loop {
+                match downloader.client.bars_paged(symbol, start, end, timeframe, Some(MAX_LIMIT), token.clone()).await {
+                    Err(error) if is_transient(&error) && attempt < downloader.retry.max_attempts => {
+                        tokio::time::sleep(downloader.retry.backoff(attempt)).await;
+                        attempt += 1;
+                    },
+                    result => return result,
+                }
+            }
+        })
+    }
+}
+
+/// Private: returns true iff the given error may vanish when the request is
+/// sent again
+fn is_transient(error: &Error) -> bool {
+    match error {
+        Error::HttpError(e)                                        => e.is_timeout() || e.is_connect() || e.is_request(),
+        Error::History { kind: HistoryError::TooManyRequests, .. } => true,
+        Error::Unexpected { status, .. }                           => *status >= 500,
+        _                                                          => false,
+    }
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{TimeZone, Utc};
+    use futures::StreamExt;
+
+    use crate::bulk::BulkDownloader;
+    use crate::historical::TimeFrame;
+    use crate::rest::RetryPolicyBuilder;
+    use crate::testing::fake_client;
+    use crate::transport::FakeTransport;
+
+    #[tokio::test]
+    async fn test_bulk_download() {
+        let bars = |symbol: &str| format!(r#"{{"bars":[{{"t":"2021-08-02T04:00:00Z","o":1,"h":2,"l":0.5,"c":1.5,"v":100}}],"symbol":"{}","next_page_token":null}}"#, symbol);
+        let transport = FakeTransport::new()
+            .respond("GET", "/v2/stocks/AAPL/bars", 200, &bars("AAPL"))
+            .respond("GET", "/v2/stocks/MSFT/bars", 200, &bars("MSFT"))
+            .respond("GET", "/v2/stocks/BUSY/bars", 503, "");
+        let client = fake_client(&transport);
+        let policy = RetryPolicyBuilder::default()
+            .max_attempts(3)
+            .initial_backoff(Duration::from_millis(1))
+            .build().unwrap();
+
+        let start   = Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap();
+        let end     = Utc.with_ymd_and_hms(2021, 8, 3, 0, 0, 0).unwrap();
+        let symbols = ["AAPL", "BUSY", "MSFT"];
+        let downloader  = BulkDownloader::new(&client).concurrency(2).retry(policy);
+        let mut results = downloader.bars(&symbols, start, end, TimeFrame::Day).collect::<Vec<_>>().await;
+        results.sort_by(|a, b| a.0.cmp(&b.0));
+
+        assert_eq!(1, results[0].1.as_ref().unwrap().len());
+        assert!(results[1].1.is_err());
+        assert_eq!(1, results[2].1.as_ref().unwrap().len());
+        // the transient failure was attempted three times
+        let busy = transport.requests().iter().filter(|r| r.url.contains("BUSY")).count();
+        assert_eq!(3, busy);
+    }
+}
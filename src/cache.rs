@@ -0,0 +1,144 @@
+//! This module provides the in-memory cache of the responses served by the
+//! endpoints whose content rarely changes (e.g. the list of assets).
+//!
+//! Code which resolves the symbols into asset ids before placing each order
+//! would otherwise burn a significant share of the rate limit on requests
+//! whose answer is always the same. When the cache is enabled on a client,
+//! the successful responses of the static endpoints (assets, calendar...) are
+//! kept for a given time to live and served from memory in the meantime. The
+//! responses are cached per api key, so that a client whose credentials are
+//! replaced never serves the responses obtained with the previous ones. The
+//! other endpoints are never cached.
+//!
+//! ```
+//! use std::time::Duration;
+//! use apca_datav2::rest::Client;
+//!
+//! let client = Client::paper("key".to_string(), "secret".to_string())
+//!     .with_cache(Duration::from_secs(3600));
+//! // ... later on, when the assets are known to have changed
+//! client.clear_cache();
+//! ```
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use reqwest::{Response, header::HeaderMap};
+
+use crate::endpoints::STATIC_PATHS;
+
+/// Private: a response kept in the cache
+struct Entry {
+    stored_at: Instant,
+    status   : u16,
+    headers  : HeaderMap,
+    body     : Vec<u8>,
+}
+
+/// The responses of the static endpoints, indexed by key (the credentials
+/// and the url, query included)
+pub struct ResponseCache {
+    ttl    : Duration,
+    entries: Mutex<HashMap<String, Entry>>,
+}
+impl ResponseCache {
+    /// Creates a cache whose entries expire after the given time to live
+    pub fn new(ttl: Duration) -> Self {
+        Self { ttl, entries: Mutex::new(HashMap::new()) }
+    }
+    /// Returns true iff the responses of the endpoint at the given path
+    /// can be cached
+    pub fn is_cacheable(path: &str) -> bool {
+        STATIC_PATHS.iter().any(|p| path.starts_with(p))
+    }
+    /// Returns a copy of the cached response with the given key (if it has
+    /// not expired yet)
+    pub fn get(&self, key: &str) -> Option<Response> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some(entry) if entry.stored_at.elapsed() < self.ttl => Some(rebuild(entry)),
+            Some(_) => {
+                entries.remove(key);
+                None
+            },
+            None => None,
+        }
+    }
+    /// Keeps the given response (if it is successful) under the given key
+    /// and returns it
+    pub async fn store(&self, key: &str, rsp: Response) -> Result<Response, reqwest::Error> {
+        if !rsp.status().is_success() {
+            return Ok(rsp);
+        }
+        let entry = Entry {
+            stored_at: Instant::now(),
+            status   : rsp.status().as_u16(),
+            headers  : rsp.headers().clone(),
+            body     : rsp.bytes().await?.to_vec(),
+        };
+        let rsp = rebuild(&entry);
+        self.entries.lock().unwrap().insert(key.to_string(), entry);
+        Ok(rsp)
+    }
+    /// Drops all the cached responses
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+/// Private: turns a cache entry back into a response
+fn rebuild(entry: &Entry) -> Response {
+    let mut response = http::Response::new(entry.body.clone());
+    *response.status_mut()  = http::StatusCode::from_u16(entry.status).unwrap();
+    *response.headers_mut() = entry.headers.clone();
+    Response::from(response)
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::testing::fake_client;
+    use crate::transport::FakeTransport;
+
+    #[tokio::test]
+    async fn test_static_endpoints_are_cached() {
+        let transport = FakeTransport::new()
+            .respond("GET", "/v2/assets", 200, "[]")
+            .respond("GET", "/v2/positions", 200, "[]");
+        let client = fake_client(&transport)
+            .with_cache(Duration::from_secs(60));
+
+        for _ in 0..3 {
+            assert!(client.list_assets(None, None).await.unwrap().is_empty());
+            assert!(client.list_open_positions().await.unwrap().is_empty());
+        }
+        // the assets were only fetched once; the positions every time
+        assert_eq!(4, transport.requests().len());
+
+        client.clear_cache();
+        client.list_assets(None, None).await.unwrap();
+        assert_eq!(5, transport.requests().len());
+
+        // the responses obtained with other credentials are not shared
+        client.set_credentials("other".to_string(), "secret".to_string());
+        client.list_assets(None, None).await.unwrap();
+        assert_eq!(6, transport.requests().len());
+    }
+    #[tokio::test]
+    async fn test_calendar_is_cached() {
+        let transport = FakeTransport::new()
+            .respond("GET", "/v2/calendar", 200, "[]");
+        let client = fake_client(&transport)
+            .with_cache(Duration::from_secs(60));
+
+        let day = chrono::NaiveDate::from_ymd_opt(2021, 11, 24).unwrap();
+        client.calendar(day, day).await.unwrap();
+        client.calendar(day, day).await.unwrap();
+        assert_eq!(1, transport.requests().len());
+    }
+}
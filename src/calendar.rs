@@ -0,0 +1,232 @@
+//! This module provides an access to the market calendar: the days on which
+//! the market is open along with their opening and closing times (which
+//! account for the early closes).
+//!
+//! The times returned by the API are expressed in the local time of the
+//! market (New York); `TradingDay::open_at` and `TradingDay::close_at` convert
+//! them into UTC instants.
+//!
+//! The market clock tells whether the market is currently open along with the
+//! next opening and closing instants. Based on it (and on the calendar),
+//! `await_market_open` and `await_market_close` let a strategy sleep until
+//! the next session starts or ends (possibly shifted by an offset). The
+//! instants which have already passed are skipped: the wait always ends in
+//! the future, hence these can be called in a loop.
+//!
+//! ```no_run
+//! # async fn example(client: apca_datav2::rest::Client) -> Result<(), apca_datav2::errors::Error> {
+//! use chrono::Duration;
+//!
+//! loop {
+//!     // five minutes after the open
+//!     client.await_market_open(Duration::minutes(5)).await?;
+//!     println!("trading...");
+//!     // one minute before the close
+//!     client.await_market_close(Duration::minutes(-1)).await?;
+//!     println!("flattening...");
+//! }
+//! # }
+//! ```
+//!
+//! ```no_run
+//! # async fn example(client: apca_datav2::rest::Client) -> Result<(), apca_datav2::errors::Error> {
+//! use chrono::NaiveDate;
+//!
+//! let start = NaiveDate::from_ymd_opt(2021, 11, 1).unwrap();
+//! let end   = NaiveDate::from_ymd_opt(2021, 11, 30).unwrap();
+//! for day in client.calendar(start, end).await? {
+//!     println!("{}: {} -> {}", day.date, day.open_at(), day.close_at());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::America::New_York;
+use serde::{Serialize, Deserialize};
+
+use crate::{endpoints::Endpoint, errors::{Error, status_code_to_api_error}, rest::Client};
+
+impl Client {
+    /// Retrieves the trading days between start and end (inclusive)
+    pub async fn calendar(&self, start: NaiveDate, end: NaiveDate) -> Result<Vec<TradingDay>, Error> {
+        let url = Endpoint::Calendar.url(self.env_url());
+        let rsp = self.get_authenticated(&url)
+            .query(&[("start", start), ("end", end)])
+            .send().await?;
+
+        status_code_to_api_error(rsp).await
+    }
+    /// Retrieves the market clock
+    pub async fn clock(&self) -> Result<MarketClock, Error> {
+        let url = Endpoint::Clock.url(self.env_url());
+        let rsp = self.get_authenticated(&url)
+            .send().await?;
+
+        status_code_to_api_error(rsp).await
+    }
+    /// Sleeps until the market opens, shifted by the given offset (which can
+    /// be negative), then returns the clock which was used to plan the wait.
+    /// When that instant has already passed for the current session (e.g.
+    /// the market opened more than `offset` ago), this waits for the next
+    /// session.
+    pub async fn await_market_open(&self, offset: Duration) -> Result<MarketClock, Error> {
+        self.await_session(TradingDay::open_at, |clock| clock.next_open, offset).await
+    }
+    /// Sleeps until the market closes, shifted by the given offset (which can
+    /// be negative), then returns the clock which was used to plan the wait.
+    /// When that instant has already passed for the current session (or when
+    /// the market is closed), this waits for the next session.
+    pub async fn await_market_close(&self, offset: Duration) -> Result<MarketClock, Error> {
+        self.await_session(TradingDay::close_at, |clock| clock.next_close, offset).await
+    }
+    /// Private: sleeps until the first instant after now (as told by the
+    /// server) at which the given point of a session, shifted by the offset,
+    /// occurs. The clock is used as a fallback when the calendar does not
+    /// reach far enough.
+    async fn await_session(&self, point: fn(&TradingDay) -> DateTime<Utc>, next: fn(&MarketClock) -> DateTime<Utc>, offset: Duration) -> Result<MarketClock, Error> {
+        let clock = self.clock().await?;
+        let today = clock.timestamp.with_timezone(&New_York).date_naive();
+        let days  = self.calendar(today, today + Duration::days(CALENDAR_LOOKAHEAD)).await?;
+        let until = next_instant(&days, clock.timestamp, point, offset)
+            .unwrap_or_else(|| next(&clock) + offset);
+        sleep_until(clock.timestamp, until).await;
+        Ok(clock)
+    }
+}
+
+/// The number of days of the calendar which are looked at when planning a
+/// wait for the open or the close of the market
+const CALENDAR_LOOKAHEAD: i64 = 14;
+
+/// Private: the first instant strictly after now at which the given point of
+/// the sessions of the given days (e.g. their open), shifted by the offset,
+/// occurs
+fn next_instant(days: &[TradingDay], now: DateTime<Utc>, point: fn(&TradingDay) -> DateTime<Utc>, offset: Duration) -> Option<DateTime<Utc>> {
+    days.iter().map(|day| point(day) + offset).filter(|t| *t > now).min()
+}
+
+/// Private: sleeps from now (as told by the server) until the given instant
+async fn sleep_until(now: DateTime<Utc>, until: DateTime<Utc>) {
+    if let Ok(wait) = (until - now).to_std() {
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// The state of the market at a given instant
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MarketClock {
+    /// The current time (as told by the server)
+    pub timestamp : DateTime<Utc>,
+    /// Whether the market is open
+    pub is_open   : bool,
+    /// The instant at which the market opens next
+    pub next_open : DateTime<Utc>,
+    /// The instant at which the market closes next
+    pub next_close: DateTime<Utc>,
+}
+
+/// One day on which the market is open
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TradingDay {
+    /// The date of the trading day
+    pub date : NaiveDate,
+    /// The time at which the regular session opens (New York time)
+    #[serde(deserialize_with="crate::utils::hours_minutes")]
+    pub open : NaiveTime,
+    /// The time at which the regular session closes (New York time)
+    #[serde(deserialize_with="crate::utils::hours_minutes")]
+    pub close: NaiveTime,
+}
+impl TradingDay {
+    /// The instant at which the regular session opens
+    pub fn open_at(&self) -> DateTime<Utc> {
+        market_time(self.date, self.open)
+    }
+    /// The instant at which the regular session closes
+    pub fn close_at(&self) -> DateTime<Utc> {
+        market_time(self.date, self.close)
+    }
+    /// The instant at which the day starts (midnight, New York time). This
+    /// is the timestamp of the daily bars.
+    pub fn start_at(&self) -> DateTime<Utc> {
+        market_time(self.date, NaiveTime::MIN)
+    }
+}
+
+/// Converts the given date and time of the market (New York) into UTC
+pub fn market_time(date: NaiveDate, time: NaiveTime) -> DateTime<Utc> {
+    let local = date.and_time(time);
+    New_York.from_local_datetime(&local)
+        .earliest()
+        .map(|t| t.with_timezone(&Utc))
+        .unwrap_or_else(|| Utc.from_utc_datetime(&local))
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+
+    use crate::calendar::{TradingDay, next_instant};
+    use crate::testing::fake_client;
+    use crate::transport::FakeTransport;
+
+    #[tokio::test]
+    async fn test_calendar() {
+        let calendar = r#"[
+            {"date":"2021-11-24","open":"09:30","close":"16:00","session_open":"0400","session_close":"2000"},
+            {"date":"2021-11-26","open":"09:30","close":"13:00","session_open":"0400","session_close":"1700"}
+        ]"#;
+        let transport = FakeTransport::new()
+            .respond("GET", "/v2/calendar", 200, calendar);
+        let client = fake_client(&transport);
+
+        let start = NaiveDate::from_ymd_opt(2021, 11, 24).unwrap();
+        let end   = NaiveDate::from_ymd_opt(2021, 11, 26).unwrap();
+        let days  = client.calendar(start, end).await.unwrap();
+        assert_eq!(2, days.len());
+        assert_eq!(Utc.with_ymd_and_hms(2021, 11, 24, 14, 30, 0).unwrap(), days[0].open_at());
+        assert_eq!(Utc.with_ymd_and_hms(2021, 11, 26, 18, 0, 0).unwrap(), days[1].close_at());
+        assert!(transport.requests()[0].url.ends_with("/v2/calendar?start=2021-11-24&end=2021-11-26"));
+    }
+    #[test]
+    fn test_passed_instants_move_to_the_next_session() {
+        let day = |d, close| TradingDay {
+            date : NaiveDate::from_ymd_opt(2021, 11, d).unwrap(),
+            open : NaiveTime::from_hms_opt(9, 30, 0).unwrap(),
+            close: NaiveTime::from_hms_opt(close, 0, 0).unwrap(),
+        };
+        let days = [day(24, 16), day(26, 13)];
+        // 10:31 (New York): the market opened more than five minutes ago
+        let now  = Utc.with_ymd_and_hms(2021, 11, 24, 15, 31, 0).unwrap();
+
+        let open = next_instant(&days, now, TradingDay::open_at, Duration::minutes(5));
+        assert_eq!(Some(Utc.with_ymd_and_hms(2021, 11, 26, 14, 35, 0).unwrap()), open);
+        let close = next_instant(&days, now, TradingDay::close_at, Duration::minutes(-1));
+        assert_eq!(Some(Utc.with_ymd_and_hms(2021, 11, 24, 20, 59, 0).unwrap()), close);
+        // right at the instant, the next session is awaited (no busy loop)
+        let close = next_instant(&days, close.unwrap(), TradingDay::close_at, Duration::minutes(-1));
+        assert_eq!(Some(Utc.with_ymd_and_hms(2021, 11, 26, 17, 59, 0).unwrap()), close);
+    }
+    #[tokio::test]
+    async fn test_await_market_open() {
+        let clock = r#"{"timestamp":"2021-11-24T09:29:59.9-05:00","is_open":false,
+            "next_open":"2021-11-24T09:30:00-05:00","next_close":"2021-11-24T16:00:00-05:00"}"#;
+        let calendar = r#"[{"date":"2021-11-24","open":"09:30","close":"16:00","session_open":"0400","session_close":"2000"}]"#;
+        let transport = FakeTransport::new()
+            .respond("GET", "/v2/clock", 200, clock)
+            .respond("GET", "/v2/calendar", 200, calendar);
+        let client = fake_client(&transport);
+
+        // the wait is planned with the time of the server (100ms here)
+        let start = std::time::Instant::now();
+        let clock = client.await_market_open(Duration::zero()).await.unwrap();
+        assert!(start.elapsed() >= std::time::Duration::from_millis(100));
+        assert_eq!(Utc.with_ymd_and_hms(2021, 11, 24, 21, 0, 0).unwrap(), clock.next_close);
+        assert!(transport.requests()[1].url.ends_with("/v2/calendar?start=2021-11-24&end=2021-12-08"));
+    }
+}
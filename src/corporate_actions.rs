@@ -0,0 +1,540 @@
+//! This module provides an access to the corporate actions (splits,
+//! dividends, mergers and spin-offs) which alter the positions and the
+//! prices of the assets.
+//!
+//! Two APIs are covered:
+//! * the announcements of the trading API (`/v2/corporate_actions`), which
+//!   are published as soon as the actions are declared; with one type of
+//!   date (declaration, ex, record or payable date) to filter them by.
+//! * the corporate actions of the market data API (`/v1beta1/corporate-actions`),
+//!   which are typed by kind of action (e.g. forward split vs reverse split;
+//!   cash vs stock merger).
+//!
+//! ```no_run
+//! # async fn example(client: apca_datav2::rest::Client) -> Result<(), apca_datav2::errors::Error> {
+//! use chrono::NaiveDate;
+//! use apca_datav2::corporate_actions::{AnnouncementType, AnnouncementsRequestBuilder, CorporateActionsRequestBuilder};
+//!
+//! let request = AnnouncementsRequestBuilder::default()
+//!     .ca_types(vec![AnnouncementType::Split, AnnouncementType::Dividend])
+//!     .since(NaiveDate::from_ymd_opt(2022, 5, 1).unwrap())
+//!     .until(NaiveDate::from_ymd_opt(2022, 6, 30).unwrap())
+//!     .build().unwrap();
+//! for announcement in client.list_announcements(&request).await? {
+//!     println!("{} {:?} ex-date {:?}", announcement.initiating_symbol, announcement.ca_type, announcement.ex_date);
+//! }
+//!
+//! let request = CorporateActionsRequestBuilder::default()
+//!     .symbols("AAPL,TSLA".to_string())
+//!     .start(NaiveDate::from_ymd_opt(2020, 1, 1).unwrap())
+//!     .build().unwrap();
+//! for split in client.corporate_actions(&request).await?.forward_splits {
+//!     println!("{} split {}:{} on {}", split.symbol, split.new_rate, split.old_rate, split.ex_date);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt::Display;
+
+use chrono::NaiveDate;
+use serde::{Serialize, Deserialize};
+use derive_builder::Builder;
+use crate::{entities::Direction, errors::{Error, maybe_convert_to_hist_error, status_code_to_api_error, status_code_to_hist_error}, rest::Client, endpoints::Endpoint};
+
+impl Client {
+    /// Retrieves the corporate action announcements matching the request.
+    /// The range between `since` and `until` may not exceed 90 days.
+    pub async fn list_announcements(&self, request: &AnnouncementsRequest) -> Result<Vec<Announcement>, Error> {
+        let url = Endpoint::Announcements.url(self.env_url());
+        let mut request = request.clone();
+        request.symbol  = request.symbol.map(|s| self.normalize(&s));
+        let rsp = self.get_authenticated(&url)
+            .query(&request)
+            .send().await?;
+        status_code_to_api_error(rsp).await
+    }
+    /// Retrieves one corporate action announcement given its id
+    pub async fn get_announcement(&self, id: &str) -> Result<Announcement, Error> {
+        let url = Endpoint::Announcement(id).url(self.env_url());
+        let rsp = self.get_authenticated(&url)
+            .send().await?;
+        status_code_to_api_error(rsp).await
+    }
+    /// Retrieves all the corporate actions matching the request (going
+    /// through all the pages of the result).
+    pub async fn corporate_actions(&self, request: &CorporateActionsRequest) -> Result<CorporateActions, Error> {
+        let mut request = request.clone();
+        let mut actions = CorporateActions::default();
+        loop {
+            let page = self.corporate_actions_paged(&request).await?;
+            actions.extend(page.corporate_actions);
+            match page.token {
+                Some(token) => request.page_token = Some(token),
+                None        => return Ok(actions),
+            }
+        }
+    }
+    /// This endpoint returns one page of the corporate actions matching the
+    /// request. The next page is fetched by setting the `page_token` of the
+    /// request to the token of this page.
+    pub async fn corporate_actions_paged(&self, request: &CorporateActionsRequest) -> Result<CorporateActionsPage, Error> {
+        let url = Endpoint::CorporateActions.url(self.data_url());
+        let mut request = request.clone();
+        request.symbols = request.symbols.map(|s| self.symbols().normalize_list(&s));
+        let rsp = self.get_authenticated(&url)
+            .query(&request)
+            .send().await
+            .map_err(maybe_convert_to_hist_error)?;
+
+        status_code_to_hist_error(rsp).await
+    }
+}
+
+/******************************************************************************
+ * ANNOUNCEMENTS **************************************************************
+ ******************************************************************************/
+
+/// The type of a corporate action announcement
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AnnouncementType {
+    #[serde(rename="dividend", alias="Dividend")]
+    Dividend,
+    #[serde(rename="merger", alias="Merger")]
+    Merger,
+    #[serde(rename="spinoff", alias="Spinoff")]
+    Spinoff,
+    #[serde(rename="split", alias="Split")]
+    Split,
+}
+impl Display for AnnouncementType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Dividend => write!(f, "Dividend"),
+            Self::Merger   => write!(f, "Merger"),
+            Self::Spinoff  => write!(f, "Spinoff"),
+            Self::Split    => write!(f, "Split"),
+        }
+    }
+}
+
+/// The date used to filter the announcements
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AnnouncementDate {
+    /// The date at which the action was declared
+    #[serde(rename="declaration_date")]
+    Declaration,
+    /// The first date at which the buyers of the asset are no longer entitled
+    /// to the action
+    #[serde(rename="ex_date")]
+    Ex,
+    /// The date at which the holders of the asset are recorded
+    #[serde(rename="record_date")]
+    Record,
+    /// The date at which the action is paid
+    #[serde(rename="payable_date")]
+    Payable,
+}
+
+/// The filters of the corporate action announcements
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+pub struct AnnouncementsRequest {
+    /// The types of announcements to return
+    #[serde(serialize_with="crate::utils::comma_separated")]
+    pub ca_types: Vec<AnnouncementType>,
+    /// The start (inclusive) of the range of dates to search
+    pub since: NaiveDate,
+    /// The end (inclusive) of the range of dates to search. At most 90 days
+    /// after `since`.
+    pub until: NaiveDate,
+    /// Only the announcements about this symbol
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<String>,
+    /// Only the announcements about this CUSIP
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cusip: Option<String>,
+    /// The date which is compared to the range. Any of them by default.
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date_type: Option<AnnouncementDate>,
+}
+
+/// A corporate action announcement
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Announcement {
+    /// The id of the announcement
+    pub id: String,
+    /// The id of the corporate action (shared by the announcements of the
+    /// same action)
+    pub corporate_action_id: String,
+    /// The type of the action
+    pub ca_type: AnnouncementType,
+    /// The subtype of the action (e.g. cash or stock dividend, reverse split)
+    pub ca_sub_type: String,
+    /// The symbol of the company initiating the action
+    pub initiating_symbol: String,
+    /// The CUSIP of the company initiating the action
+    #[serde(default)]
+    #[builder(default)]
+    pub initiating_original_cusip: String,
+    /// The symbol of the target of the action (the acquiree of a merger, the
+    /// new company of a spin-off)
+    #[serde(default)]
+    #[builder(default)]
+    pub target_symbol: Option<String>,
+    /// The CUSIP of the target of the action
+    #[serde(default)]
+    #[builder(default)]
+    pub target_original_cusip: Option<String>,
+    /// The date at which the action was declared
+    #[serde(default)]
+    #[builder(default)]
+    pub declaration_date: Option<NaiveDate>,
+    /// The first date at which the buyers are no longer entitled to the action
+    #[serde(default)]
+    #[builder(default)]
+    pub ex_date: Option<NaiveDate>,
+    /// The date at which the holders are recorded
+    #[serde(default)]
+    #[builder(default)]
+    pub record_date: Option<NaiveDate>,
+    /// The date at which the action is paid
+    #[serde(default)]
+    #[builder(default)]
+    pub payable_date: Option<NaiveDate>,
+    /// The amount of cash paid per share
+    #[serde(default, deserialize_with="crate::utils::option_as_f64")]
+    #[builder(default)]
+    pub cash: Option<f64>,
+    /// The number of shares before the action (e.g. 1 in a 4:1 split)
+    #[serde(default, deserialize_with="crate::utils::option_as_f64")]
+    #[builder(default)]
+    pub old_rate: Option<f64>,
+    /// The number of shares after the action (e.g. 4 in a 4:1 split)
+    #[serde(default, deserialize_with="crate::utils::option_as_f64")]
+    #[builder(default)]
+    pub new_rate: Option<f64>,
+}
+
+/******************************************************************************
+ * CORPORATE ACTIONS (MARKET DATA) ********************************************
+ ******************************************************************************/
+
+/// The types of corporate actions of the market data API
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CorporateActionType {
+    #[serde(rename="forward_split")]
+    ForwardSplit,
+    #[serde(rename="reverse_split")]
+    ReverseSplit,
+    #[serde(rename="cash_dividend")]
+    CashDividend,
+    #[serde(rename="stock_dividend")]
+    StockDividend,
+    #[serde(rename="spin_off")]
+    SpinOff,
+    #[serde(rename="cash_merger")]
+    CashMerger,
+    #[serde(rename="stock_merger")]
+    StockMerger,
+}
+impl Display for CorporateActionType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ForwardSplit  => write!(f, "forward_split"),
+            Self::ReverseSplit  => write!(f, "reverse_split"),
+            Self::CashDividend  => write!(f, "cash_dividend"),
+            Self::StockDividend => write!(f, "stock_dividend"),
+            Self::SpinOff       => write!(f, "spin_off"),
+            Self::CashMerger    => write!(f, "cash_merger"),
+            Self::StockMerger   => write!(f, "stock_merger"),
+        }
+    }
+}
+
+/// The filters of the corporate actions
+#[derive(Builder, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CorporateActionsRequest {
+    /// A comma-separated list of symbols to filter by (ex. “AAPL,TSLA”)
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symbols: Option<String>,
+    /// The types of actions to return. All of them by default.
+    #[builder(default)]
+    #[serde(serialize_with="crate::utils::comma_separated", skip_serializing_if = "Vec::is_empty")]
+    pub types: Vec<CorporateActionType>,
+    /// The start (inclusive) of the range of dates to search
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<NaiveDate>,
+    /// The end (inclusive) of the range of dates to search
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<NaiveDate>,
+    /// The maximum number of actions in one page
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+    /// The chronological order of the actions. Defaults to asc.
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<Direction>,
+    /// The token of the page to fetch
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_token: Option<String>,
+}
+
+/// A (forward or reverse) split
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct SplitData {
+    /// The symbol of the split asset
+    pub symbol: String,
+    /// The number of shares after the split (e.g. 4 in a 4:1 split)
+    pub new_rate: f64,
+    /// The number of shares before the split (e.g. 1 in a 4:1 split)
+    pub old_rate: f64,
+    /// The date at which the split was processed
+    pub process_date: NaiveDate,
+    /// The first date at which the shares trade at the split price
+    pub ex_date: NaiveDate,
+    /// The date at which the holders are recorded
+    #[serde(default)]
+    #[builder(default)]
+    pub record_date: Option<NaiveDate>,
+    /// The date at which the new shares are distributed
+    #[serde(default)]
+    #[builder(default)]
+    pub payable_date: Option<NaiveDate>,
+}
+
+/// A dividend paid in cash
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct CashDividendData {
+    /// The symbol of the asset paying the dividend
+    pub symbol: String,
+    /// The amount paid per share
+    pub rate: f64,
+    /// True iff this is a special (non-recurring) dividend
+    #[serde(default)]
+    #[builder(default)]
+    pub special: bool,
+    /// True iff the dividend is paid in a foreign currency
+    #[serde(default)]
+    #[builder(default)]
+    pub foreign: bool,
+    /// The date at which the dividend was processed
+    pub process_date: NaiveDate,
+    /// The first date at which the buyers are no longer entitled to the dividend
+    pub ex_date: NaiveDate,
+    /// The date at which the holders are recorded
+    #[serde(default)]
+    #[builder(default)]
+    pub record_date: Option<NaiveDate>,
+    /// The date at which the dividend is paid
+    #[serde(default)]
+    #[builder(default)]
+    pub payable_date: Option<NaiveDate>,
+}
+
+/// A dividend paid in shares
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct StockDividendData {
+    /// The symbol of the asset paying the dividend
+    pub symbol: String,
+    /// The number of new shares per share
+    pub rate: f64,
+    /// The date at which the dividend was processed
+    pub process_date: NaiveDate,
+    /// The first date at which the buyers are no longer entitled to the dividend
+    pub ex_date: NaiveDate,
+    /// The date at which the holders are recorded
+    #[serde(default)]
+    #[builder(default)]
+    pub record_date: Option<NaiveDate>,
+    /// The date at which the dividend is paid
+    #[serde(default)]
+    #[builder(default)]
+    pub payable_date: Option<NaiveDate>,
+}
+
+/// A spin-off: the holders of the source asset receive shares of a new one
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct SpinOffData {
+    /// The symbol of the source asset
+    pub source_symbol: String,
+    /// The number of source shares
+    pub source_rate: f64,
+    /// The symbol of the new asset
+    pub new_symbol: String,
+    /// The number of new shares received for `source_rate` source shares
+    pub new_rate: f64,
+    /// The date at which the spin-off was processed
+    pub process_date: NaiveDate,
+    /// The first date at which the buyers are no longer entitled to the new shares
+    pub ex_date: NaiveDate,
+    /// The date at which the holders are recorded
+    #[serde(default)]
+    #[builder(default)]
+    pub record_date: Option<NaiveDate>,
+    /// The date at which the new shares are distributed
+    #[serde(default)]
+    #[builder(default)]
+    pub payable_date: Option<NaiveDate>,
+}
+
+/// A merger where the shares of the acquiree are bought in cash
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct CashMergerData {
+    /// The symbol of the acquirer (when it is listed)
+    #[serde(default)]
+    #[builder(default)]
+    pub acquirer_symbol: Option<String>,
+    /// The symbol of the acquiree
+    pub acquiree_symbol: String,
+    /// The amount paid per share of the acquiree
+    pub rate: f64,
+    /// The date at which the merger was processed
+    pub process_date: NaiveDate,
+    /// The date at which the merger takes effect
+    pub effective_date: NaiveDate,
+    /// The date at which the cash is paid
+    #[serde(default)]
+    #[builder(default)]
+    pub payable_date: Option<NaiveDate>,
+}
+
+/// A merger where the shares of the acquiree are exchanged for shares of the
+/// acquirer
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct StockMergerData {
+    /// The symbol of the acquirer
+    pub acquirer_symbol: String,
+    /// The number of shares of the acquirer
+    pub acquirer_rate: f64,
+    /// The symbol of the acquiree
+    pub acquiree_symbol: String,
+    /// The number of shares of the acquiree exchanged for `acquirer_rate`
+    /// shares of the acquirer
+    pub acquiree_rate: f64,
+    /// The date at which the merger was processed
+    pub process_date: NaiveDate,
+    /// The date at which the merger takes effect
+    pub effective_date: NaiveDate,
+    /// The date at which the new shares are distributed
+    #[serde(default)]
+    #[builder(default)]
+    pub payable_date: Option<NaiveDate>,
+}
+
+/// The corporate actions, grouped by type
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct CorporateActions {
+    #[serde(default)]
+    pub forward_splits: Vec<SplitData>,
+    #[serde(default)]
+    pub reverse_splits: Vec<SplitData>,
+    #[serde(default)]
+    pub cash_dividends: Vec<CashDividendData>,
+    #[serde(default)]
+    pub stock_dividends: Vec<StockDividendData>,
+    #[serde(default)]
+    pub spin_offs: Vec<SpinOffData>,
+    #[serde(default)]
+    pub cash_mergers: Vec<CashMergerData>,
+    #[serde(default)]
+    pub stock_mergers: Vec<StockMergerData>,
+}
+impl CorporateActions {
+    /// Appends the actions of another page
+    pub fn extend(&mut self, other: CorporateActions) {
+        self.forward_splits.extend(other.forward_splits);
+        self.reverse_splits.extend(other.reverse_splits);
+        self.cash_dividends.extend(other.cash_dividends);
+        self.stock_dividends.extend(other.stock_dividends);
+        self.spin_offs.extend(other.spin_offs);
+        self.cash_mergers.extend(other.cash_mergers);
+        self.stock_mergers.extend(other.stock_mergers);
+    }
+}
+
+/// A page of corporate actions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct CorporateActionsPage {
+    /// The actual payload
+    #[serde(default)]
+    pub corporate_actions: CorporateActions,
+    #[serde(rename="next_page_token", default)]
+    pub token: Option<String>,
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use crate::corporate_actions::{AnnouncementDate, AnnouncementType, AnnouncementsRequestBuilder, CorporateActionType, CorporateActionsRequestBuilder};
+    use crate::testing::fake_client;
+    use crate::transport::FakeTransport;
+
+    #[tokio::test]
+    async fn test_announcements() {
+        let announcements = r#"[{"id":"be3c368a-4c7c-4384-808e-f02c9f5a8afe","corporate_action_id":"F58684224_XY37",
+            "ca_type":"dividend","ca_sub_type":"cash","initiating_symbol":"MLLAX","initiating_original_cusip":"55275E101",
+            "target_symbol":null,"target_original_cusip":null,"declaration_date":"2021-01-05","ex_date":"2021-01-12",
+            "record_date":"2021-01-13","payable_date":"2021-01-14","cash":"0.018","old_rate":"1","new_rate":"1"}]"#;
+        let transport = FakeTransport::new()
+            .respond("GET", "/v2/corporate_actions/announcements", 200, announcements);
+        let client = fake_client(&transport);
+
+        let request = AnnouncementsRequestBuilder::default()
+            .ca_types(vec![AnnouncementType::Dividend, AnnouncementType::Split])
+            .since(NaiveDate::from_ymd_opt(2021, 1, 1).unwrap())
+            .until(NaiveDate::from_ymd_opt(2021, 1, 31).unwrap())
+            .date_type(AnnouncementDate::Ex)
+            .build().unwrap();
+        let announcements = client.list_announcements(&request).await.unwrap();
+        assert_eq!(AnnouncementType::Dividend, announcements[0].ca_type);
+        assert_eq!(Some(0.018), announcements[0].cash);
+        assert!(announcements[0].target_symbol.is_none());
+        assert!(transport.requests()[0].url.ends_with(
+            "?ca_types=Dividend%2CSplit&since=2021-01-01&until=2021-01-31&date_type=ex_date"));
+    }
+
+    #[tokio::test]
+    async fn test_corporate_actions() {
+        let actions = r#"{"corporate_actions":{
+            "forward_splits":[{"symbol":"AAPL","new_rate":4,"old_rate":1,"process_date":"2020-08-31","ex_date":"2020-08-31","record_date":"2020-08-24","payable_date":"2020-08-28"}],
+            "cash_dividends":[{"symbol":"AAPL","rate":0.205,"special":false,"foreign":false,"process_date":"2020-08-14","ex_date":"2020-08-07","record_date":"2020-08-10","payable_date":"2020-08-13"}],
+            "name_changes":[{"old_symbol":"FB","new_symbol":"META","process_date":"2022-06-09"}]
+            },"next_page_token":null}"#;
+        let transport = FakeTransport::new()
+            .respond("GET", "/v1beta1/corporate-actions", 200, actions);
+        let client = fake_client(&transport);
+
+        let request = CorporateActionsRequestBuilder::default()
+            .symbols("AAPL".to_string())
+            .types(vec![CorporateActionType::ForwardSplit, CorporateActionType::CashDividend])
+            .build().unwrap();
+        let actions = client.corporate_actions(&request).await.unwrap();
+        assert_eq!(1, actions.forward_splits.len());
+        assert!((actions.forward_splits[0].new_rate - 4.0).abs() < 1e-9);
+        assert_eq!(1, actions.cash_dividends.len());
+        assert!(actions.spin_offs.is_empty());
+        assert!(transport.requests()[0].url.contains("types=forward_split%2Ccash_dividend"));
+    }
+}
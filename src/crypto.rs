@@ -0,0 +1,457 @@
+//! This module provides an easy access to the historical crypto market data
+//! offered by Alpaca (`v1beta3/crypto/us` endpoints).
+//!
+//! Unlike the stock endpoints, the crypto endpoints always accept several
+//! symbols at once (e.g. `BTC/USD,ETH/USD`) and return the data indexed by
+//! symbol. Hence, the streams of this module yield `(symbol, data)` pairs;
+//! the data of each symbol being ordered by time. Sizes and volumes are
+//! fractional since the coins are divisible.
+//!
+//! The crypto symbols are never normalized (the slash between the coin and
+//! the currency is part of the symbol), and the `feed`/`asof` options of the
+//! client do not apply to crypto data.
+//!
+//! ```no_run
+//! # async fn example(client: apca_datav2::rest::Client) -> Result<(), apca_datav2::errors::Error> {
+//! use chrono::{Duration, Utc};
+//! use futures::StreamExt;
+//! use apca_datav2::historical::TimeFrame;
+//!
+//! let end   = Utc::now();
+//! let start = end - Duration::days(1);
+//! let bars  = client.crypto_bars(&["BTC/USD", "ETH/USD"], start, end, TimeFrame::Hours(1), None);
+//! futures::pin_mut!(bars);
+//! while let Some((symbol, bar)) = bars.next().await.transpose()? {
+//!     println!("{} closed at {} ({} traded)", symbol, bar.close_price, bar.volume);
+//! }
+//!
+//! let latest = client.crypto_latest_quotes(&["BTC/USD"]).await?;
+//! println!("BTC/USD ask: {}", latest["BTC/USD"].ask_price);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{collections::HashMap, pin::Pin};
+
+use chrono::{DateTime, Utc};
+use futures::{Future, Stream};
+use itertools::Itertools;
+use serde::{Serialize, Deserialize};
+use derive_builder::Builder;
+use crate::{errors::{Error, maybe_convert_to_hist_error, status_code_to_hist_error}, historical::TimeFrame, rest::{Client, FetchNextPage, Paged, PagedStream}, endpoints::Endpoint};
+
+impl Client {
+    /// This stream returns the desired crypto trades history of the given
+    /// symbols going through the several "pages" of the history
+    /// asynchronously; upon request.
+    pub fn crypto_trades<'a>(&'a self, symbols: &'a [&'a str], start: DateTime<Utc>, end: DateTime<Utc>, limit: Option<usize>) -> impl Stream<Item=Result<(String, CryptoTradeData), Error>> + 'a {
+        PagedStream::new(FetchNextBySymbol {
+            client: self,
+            symbols,
+            start, end,
+            timeframe: None,
+            limit,
+            fetch: |client, params, token| Box::pin(client.crypto_trades_paged(params.symbols, params.start, params.end, params.limit, token)),
+        }).prefetch(self.prefetches())
+    }
+    /// This stream returns the desired crypto quotes history of the given
+    /// symbols going through the several "pages" of the history
+    /// asynchronously; upon request.
+    pub fn crypto_quotes<'a>(&'a self, symbols: &'a [&'a str], start: DateTime<Utc>, end: DateTime<Utc>, limit: Option<usize>) -> impl Stream<Item=Result<(String, CryptoQuoteData), Error>> + 'a {
+        PagedStream::new(FetchNextBySymbol {
+            client: self,
+            symbols,
+            start, end,
+            timeframe: None,
+            limit,
+            fetch: |client, params, token| Box::pin(client.crypto_quotes_paged(params.symbols, params.start, params.end, params.limit, token)),
+        }).prefetch(self.prefetches())
+    }
+    /// This stream returns the desired crypto bars history of the given
+    /// symbols going through the several "pages" of the history
+    /// asynchronously; upon request.
+    pub fn crypto_bars<'a>(&'a self, symbols: &'a [&'a str], start: DateTime<Utc>, end: DateTime<Utc>, timeframe: TimeFrame, limit: Option<usize>) -> impl Stream<Item=Result<(String, CryptoBarData), Error>> + 'a {
+        PagedStream::new(FetchNextBySymbol {
+            client: self,
+            symbols,
+            start, end,
+            timeframe: Some(timeframe),
+            limit,
+            fetch: |client, params, token| Box::pin(client.crypto_bars_paged(params.symbols, params.start, params.end, params.timeframe.unwrap_or(TimeFrame::Day), params.limit, token)),
+        }).prefetch(self.prefetches())
+    }
+
+    /// This endpoint returns the crypto trades historical data for the
+    /// requested symbols
+    pub async fn crypto_trades_paged(&self, symbols: &[&str], start: DateTime<Utc>, end: DateTime<Utc>, limit: Option<usize>, page_token: Option<String>) -> Result<MultiCryptoTrades, Error> {
+        self.history_by_symbol(Endpoint::CryptoTrades, symbols, start, end, None, limit, page_token).await
+    }
+    /// This endpoint returns the crypto quotes historical data for the
+    /// requested symbols
+    pub async fn crypto_quotes_paged(&self, symbols: &[&str], start: DateTime<Utc>, end: DateTime<Utc>, limit: Option<usize>, page_token: Option<String>) -> Result<MultiCryptoQuotes, Error> {
+        self.history_by_symbol(Endpoint::CryptoQuotes, symbols, start, end, None, limit, page_token).await
+    }
+    /// This endpoint returns the aggregate crypto historical data for the
+    /// requested symbols
+    pub async fn crypto_bars_paged(&self, symbols: &[&str], start: DateTime<Utc>, end: DateTime<Utc>, timeframe: TimeFrame, limit: Option<usize>, page_token: Option<String>) -> Result<MultiCryptoBars, Error> {
+        self.history_by_symbol(Endpoint::CryptoBars, symbols, start, end, Some(timeframe), limit, page_token).await
+    }
+    /// This endpoint returns the latest trade of each requested symbol
+    pub async fn crypto_latest_trades(&self, symbols: &[&str]) -> Result<HashMap<String, CryptoTradeData>, Error> {
+        let latest: LatestCryptoTrades = self.latest_by_symbol(Endpoint::CryptoLatestTrades, symbols).await?;
+        Ok(latest.trades)
+    }
+    /// This endpoint returns the latest quote of each requested symbol
+    pub async fn crypto_latest_quotes(&self, symbols: &[&str]) -> Result<HashMap<String, CryptoQuoteData>, Error> {
+        let latest: LatestCryptoQuotes = self.latest_by_symbol(Endpoint::CryptoLatestQuotes, symbols).await?;
+        Ok(latest.quotes)
+    }
+    /// This endpoint returns the latest minute bar of each requested symbol
+    pub async fn crypto_latest_bars(&self, symbols: &[&str]) -> Result<HashMap<String, CryptoBarData>, Error> {
+        let latest: LatestCryptoBars = self.latest_by_symbol(Endpoint::CryptoLatestBars, symbols).await?;
+        Ok(latest.bars)
+    }
+    /// The snapshot of each requested symbol: its latest trade, latest quote,
+    /// minute bar, daily bar and previous daily bar.
+    pub async fn crypto_snapshots(&self, symbols: &[&str]) -> Result<HashMap<String, CryptoSnapshotData>, Error> {
+        let snapshots: CryptoSnapshots = self.latest_by_symbol(Endpoint::CryptoSnapshots, symbols).await?;
+        Ok(snapshots.snapshots)
+    }
+
+    /// Fetches one page of the history of the given endpoint which serves
+    /// several symbols at once (crypto, options)
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) async fn history_by_symbol<T>(&self, endpoint: Endpoint<'_>, symbols: &[&str], start: DateTime<Utc>, end: DateTime<Utc>, timeframe: Option<TimeFrame>, limit: Option<usize>, page_token: Option<String>) -> Result<T, Error>
+        where T: for<'de> Deserialize<'de>
+    {
+        let url = endpoint.url(self.data_url());
+        let mut query = vec![
+            ("symbols", symbols.iter().join(",")),
+            ("start",   start.to_rfc3339()),
+            ("end",     end.to_rfc3339()),
+            ];
+        if let Some(timeframe) = timeframe {
+            query.push(("timeframe", timeframe.validate()?.to_string()));
+        }
+        if let Some(limit) = limit {
+            query.push(("limit", limit.to_string()))
+        }
+        if let Some(sort) = self.sort() {
+            query.push(("sort", sort.to_string()));
+        }
+        if let Some(token) = page_token {
+            query.push(("page_token", token));
+        }
+        let rsp = self.get_authenticated(&url)
+                .query(&query)
+                .send().await
+                .map_err(maybe_convert_to_hist_error)?;
+
+        status_code_to_hist_error(rsp).await
+    }
+    /// Fetches the latest data of the given symbols from an endpoint which
+    /// serves several symbols at once (crypto, options)
+    pub(crate) async fn latest_by_symbol<T>(&self, endpoint: Endpoint<'_>, symbols: &[&str]) -> Result<T, Error>
+        where T: for<'de> Deserialize<'de>
+    {
+        let url = endpoint.url(self.data_url());
+        let rsp = self.get_authenticated(&url)
+                .query(&[("symbols", symbols.iter().join(","))])
+                .send().await
+                .map_err(maybe_convert_to_hist_error)?;
+
+        status_code_to_hist_error(rsp).await
+    }
+}
+
+/******************************************************************************
+ * DATA POINTS ****************************************************************
+ ******************************************************************************/
+
+/// The side of the taker of a crypto trade
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TakerSide {
+    /// The taker was buying
+    #[serde(rename="B")]
+    Buy,
+    /// The taker was selling
+    #[serde(rename="S")]
+    Sell,
+    /// The side of the taker is not known
+    #[serde(other, rename="-")]
+    Unknown,
+}
+
+/// Datapoint encapsulating informations about a given crypto trade
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct CryptoTradeData {
+    /// Trade identifier
+    #[serde(rename="i")]
+    pub trade_id: u64,
+    /// trade price
+    #[serde(rename="p")]
+    pub trade_price: f64,
+    /// trade size
+    #[serde(rename="s")]
+    pub trade_size: f64,
+    /// side of the taker
+    #[serde(rename="tks")]
+    pub taker_side: TakerSide,
+    /// RFC-3339 formatted timestamp with nanosecond precision.
+    #[serde(rename="t")]
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Datapoint encapsulating a crypto quote
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct CryptoQuoteData {
+    /// ask price
+    #[serde(rename="ap")]
+    pub ask_price: f64,
+    /// ask size
+    #[serde(rename="as")]
+    pub ask_size: f64,
+    /// bid price
+    #[serde(rename="bp")]
+    pub bid_price: f64,
+    /// bid size
+    #[serde(rename="bs")]
+    pub bid_size: f64,
+    /// RFC-3339 formatted timestamp with nanosecond precision.
+    #[serde(rename="t")]
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Datapoint encapsulating a crypto 'bar' (a.k.a. OHLC)
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct CryptoBarData {
+    /// open price
+    #[serde(rename="o")]
+    pub open_price: f64,
+    /// high price
+    #[serde(rename="h")]
+    pub high_price: f64,
+    /// low price
+    #[serde(rename="l")]
+    pub low_price: f64,
+    /// close price
+    #[serde(rename="c")]
+    pub close_price: f64,
+    /// volume
+    #[serde(rename="v")]
+    pub volume: f64,
+    /// number of trades
+    #[serde(rename="n", default)]
+    #[builder(default)]
+    pub trade_count: u64,
+    /// volume weighted average price
+    #[serde(rename="vw", default)]
+    #[builder(default)]
+    pub vwap: f64,
+    /// RFC-3339 formatted timestamp with nanosecond precision.
+    #[serde(rename="t")]
+    pub timestamp: DateTime<Utc>,
+}
+
+/// The latest trade, latest quote, minute bar, daily bar and previous daily
+/// bar of a crypto symbol. Any of these may be missing for a pair that
+/// rarely trades.
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct CryptoSnapshotData {
+    /// Latest trade object.
+    #[serde(rename = "latestTrade")]
+    #[builder(default)]
+    pub latest_trade: Option<CryptoTradeData>,
+    /// Latest quote object
+    #[serde(rename = "latestQuote")]
+    #[builder(default)]
+    pub latest_quote: Option<CryptoQuoteData>,
+    /// Minute bar object.
+    #[serde(rename = "minuteBar")]
+    #[builder(default)]
+    pub minute_bar: Option<CryptoBarData>,
+    /// Daily bar object.
+    #[serde(rename = "dailyBar")]
+    #[builder(default)]
+    pub daily_bar: Option<CryptoBarData>,
+    /// Previous daily close bar object
+    #[serde(rename = "prevDailyBar")]
+    #[builder(default)]
+    pub prev_daily_bar: Option<CryptoBarData>,
+}
+
+/// A page of crypto trades, indexed by symbol
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct MultiCryptoTrades {
+    /// The actual payload
+    #[serde(default)]
+    #[builder(default)]
+    pub trades: HashMap<String, Vec<CryptoTradeData>>,
+    #[serde(rename="next_page_token")]
+    #[builder(default)]
+    pub token : Option<String>,
+}
+/// A page of crypto quotes, indexed by symbol
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct MultiCryptoQuotes {
+    /// The actual payload
+    #[serde(default)]
+    #[builder(default)]
+    pub quotes: HashMap<String, Vec<CryptoQuoteData>>,
+    #[serde(rename="next_page_token")]
+    #[builder(default)]
+    pub token : Option<String>,
+}
+/// A page of crypto bars, indexed by symbol
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct MultiCryptoBars {
+    /// The actual payload
+    #[serde(default)]
+    #[builder(default)]
+    pub bars : HashMap<String, Vec<CryptoBarData>>,
+    #[serde(rename="next_page_token")]
+    #[builder(default)]
+    pub token: Option<String>,
+}
+
+/// Private: the payload of the latest trades endpoint
+#[derive(Debug, Deserialize)]
+struct LatestCryptoTrades {
+    trades: HashMap<String, CryptoTradeData>,
+}
+/// Private: the payload of the latest quotes endpoint
+#[derive(Debug, Deserialize)]
+struct LatestCryptoQuotes {
+    quotes: HashMap<String, CryptoQuoteData>,
+}
+/// Private: the payload of the latest bars endpoint
+#[derive(Debug, Deserialize)]
+struct LatestCryptoBars {
+    bars: HashMap<String, CryptoBarData>,
+}
+/// Private: the payload of the snapshots endpoint
+#[derive(Debug, Deserialize)]
+struct CryptoSnapshots {
+    snapshots: HashMap<String, CryptoSnapshotData>,
+}
+
+/*----------------------------------------------------------------------------*/
+/* THE MULTI-* DATA POINTS ARE STRUCTURES THAT EMBODY THE PAGING MECHANISM    */
+/*----------------------------------------------------------------------------*/
+
+/// Flattens a page indexed by symbol (ordered by symbol then time)
+pub(crate) fn by_symbol<T>(data: HashMap<String, Vec<T>>) -> Vec<(String, T)> {
+    data.into_iter()
+        .sorted_by(|a, b| a.0.cmp(&b.0))
+        .flat_map(|(symbol, items)| items.into_iter().map(move |item| (symbol.clone(), item)))
+        .collect()
+}
+impl Paged for MultiCryptoTrades {
+    type Item = (String, CryptoTradeData);
+    fn split(self) -> (Vec<Self::Item>, Option<String>) {
+        (by_symbol(self.trades), self.token)
+    }
+}
+impl Paged for MultiCryptoQuotes {
+    type Item = (String, CryptoQuoteData);
+    fn split(self) -> (Vec<Self::Item>, Option<String>) {
+        (by_symbol(self.quotes), self.token)
+    }
+}
+impl Paged for MultiCryptoBars {
+    type Item = (String, CryptoBarData);
+    fn split(self) -> (Vec<Self::Item>, Option<String>) {
+        (by_symbol(self.bars), self.token)
+    }
+}
+
+/// A future fetching the next page of data
+pub(crate) type PageFuture<'a, T> = Pin<Box<dyn Future<Output=Result<T, Error>> + 'a>>;
+
+/// This structure encapsulates a call to one of the `*_paged` methods of the
+/// endpoints serving several symbols at once (e.g. `crypto_bars_paged`) and
+/// yields a future that can be used to asychronously fetch the next page
+pub(crate) struct FetchNextBySymbol<'a, T> {
+    pub(crate) client: &'a Client,
+    // params
+    pub(crate) symbols: &'a [&'a str],
+    pub(crate) start: DateTime<Utc>,
+    pub(crate) end: DateTime<Utc>,
+    pub(crate) timeframe: Option<TimeFrame>,
+    pub(crate) limit: Option<usize>,
+    // the method fetching the page
+    pub(crate) fetch: fn(&'a Client, &FetchNextBySymbol<'a, T>, Option<String>) -> PageFuture<'a, T>,
+}
+impl <'a, T: Paged> FetchNextPage<'a, T> for FetchNextBySymbol<'a, T> {
+    fn fetch(self: Pin<&Self>, token: Option<String>) -> PageFuture<'a, T> {
+        (self.fetch)(self.client, self.get_ref(), token)
+    }
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use futures::TryStreamExt;
+
+    use crate::crypto::TakerSide;
+    use crate::historical::TimeFrame;
+    use crate::testing::fake_client;
+    use crate::transport::FakeTransport;
+
+    #[tokio::test]
+    async fn test_crypto_bars_stream() {
+        let bars = r#"{"bars":{
+            "ETH/USD":[{"t":"2022-05-27T10:00:00Z","o":1794.3,"h":1795.5,"l":1790.1,"c":1792.2,"v":12.5,"n":40,"vw":1793.1}],
+            "BTC/USD":[{"t":"2022-05-27T10:00:00Z","o":28999,"h":29035,"l":28955,"c":29001,"v":0.1234,"n":12,"vw":29000.5},
+                       {"t":"2022-05-27T11:00:00Z","o":29001,"h":29050,"l":28990,"c":29020,"v":1.5,"n":30,"vw":29010}]
+            },"next_page_token":null}"#;
+        let transport = FakeTransport::new()
+            .respond("GET", "/v1beta3/crypto/us/bars", 200, bars);
+        let client = fake_client(&transport);
+
+        let start = Utc.with_ymd_and_hms(2022, 5, 27, 10, 0, 0).unwrap();
+        let end   = Utc.with_ymd_and_hms(2022, 5, 27, 12, 0, 0).unwrap();
+        let bars  = client.crypto_bars(&["BTC/USD", "ETH/USD"], start, end, TimeFrame::Hours(1), None)
+            .try_collect::<Vec<_>>().await.unwrap();
+
+        let symbols = bars.iter().map(|(s, _)| s.as_str()).collect::<Vec<_>>();
+        assert_eq!(vec!["BTC/USD", "BTC/USD", "ETH/USD"], symbols);
+        assert!((bars[0].1.volume - 0.1234).abs() < 1e-9);
+        let url = &transport.requests()[0].url;
+        assert!(url.contains("symbols=BTC%2FUSD%2CETH%2FUSD"));
+        assert!(url.contains("timeframe=1Hour"));
+    }
+
+    #[tokio::test]
+    async fn test_crypto_latest_and_snapshots() {
+        let trades = r#"{"trades":{"BTC/USD":{"t":"2022-05-27T10:18:00.123Z","p":29000.5,"s":0.0021,"tks":"S","i":71223}}}"#;
+        let snapshots = r#"{"snapshots":{"BTC/USD":{
+            "latestQuote":{"t":"2022-05-27T10:18:00Z","bp":28999,"bs":0.5,"ap":29001,"as":0.25},
+            "dailyBar":null}}}"#;
+        let transport = FakeTransport::new()
+            .respond("GET", "/v1beta3/crypto/us/latest/trades", 200, trades)
+            .respond("GET", "/v1beta3/crypto/us/snapshots", 200, snapshots);
+        let client = fake_client(&transport);
+
+        let trades = client.crypto_latest_trades(&["BTC/USD"]).await.unwrap();
+        assert_eq!(TakerSide::Sell, trades["BTC/USD"].taker_side);
+        assert!((trades["BTC/USD"].trade_size - 0.0021).abs() < 1e-9);
+
+        let snapshots = client.crypto_snapshots(&["BTC/USD"]).await.unwrap();
+        let snapshot  = &snapshots["BTC/USD"];
+        assert!((snapshot.latest_quote.as_ref().unwrap().ask_size - 0.25).abs() < 1e-9);
+        assert!(snapshot.daily_bar.is_none());
+        assert!(snapshot.latest_trade.is_none());
+    }
+}
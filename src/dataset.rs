@@ -0,0 +1,308 @@
+//! This module helps exporting the data fetched through this crate as datasets
+//! that can be shared with other teams.
+//!
+//! # Datasets
+//! A dataset is a directory comprising one file per (symbol, kind of data)
+//! along with a `manifest.json` file. The manifest documents the provenance
+//! of the dataset: which symbols and time ranges it covers, how many rows each
+//! file contains, the SHA-256 checksum of each file and the versions of the
+//! crate and data feed that were used to produce it.
+//!
+//! Thanks to the manifest, anyone receiving a dataset can [verify](Manifest::verify)
+//! that none of its files has been silently altered or corrupted.
+
+use std::{fs::{self, File}, io::{BufWriter, Write}, path::{Path, PathBuf}};
+
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+
+use crate::{entities::{BarData, QuoteData, TradeData}, errors::Error};
+
+/// Name of the manifest file which is written at the root of each dataset
+pub const MANIFEST: &str = "manifest.json";
+
+/// The kind of data stored in some dataset file
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum DataKind {
+    #[serde(rename="bars")]
+    Bars,
+    #[serde(rename="trades")]
+    Trades,
+    #[serde(rename="quotes")]
+    Quotes,
+}
+impl DataKind {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            DataKind::Bars   => "bars",
+            DataKind::Trades => "trades",
+            DataKind::Quotes => "quotes",
+        }
+    }
+}
+
+/// The manifest documents the provenance of a dataset
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Version of this crate that was used to produce the dataset
+    pub crate_version: String,
+    /// Data feed the data was fetched from (e.g. iex or sip)
+    pub feed: Option<String>,
+    /// Time when the dataset was produced
+    pub created_at: DateTime<Utc>,
+    /// Description of each file comprised in the dataset
+    pub files: Vec<FileEntry>,
+}
+
+/// The description of one single file from a dataset
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileEntry {
+    /// Path of the file, relative to the dataset directory
+    pub path: String,
+    /// The symbol whose data is stored in the file
+    pub symbol: String,
+    /// The kind of data stored in the file
+    pub kind: DataKind,
+    /// Timestamp of the earliest row in the file (if any)
+    pub start: Option<DateTime<Utc>>,
+    /// Timestamp of the latest row in the file (if any)
+    pub end: Option<DateTime<Utc>>,
+    /// Number of rows (header excluded)
+    pub rows: usize,
+    /// Hex encoded SHA-256 checksum of the file content
+    pub sha256: String,
+}
+
+/// A problem detected while verifying a dataset against its manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Discrepancy {
+    /// The file is listed in the manifest but could not be found
+    Missing { path: String },
+    /// The file content does not match the checksum recorded in the manifest
+    Corrupted { path: String, expected: String, actual: String },
+}
+
+impl Manifest {
+    /// Creates an empty manifest
+    pub fn new(feed: Option<String>) -> Self {
+        Self {
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            feed,
+            created_at: Utc::now(),
+            files: vec![],
+        }
+    }
+    /// Loads the manifest of the dataset stored in the given directory
+    #[allow(clippy::result_large_err)]
+    pub fn load<P: AsRef<Path>>(dir: P) -> Result<Self, Error> {
+        let file = File::open(dir.as_ref().join(MANIFEST))?;
+        Ok(serde_json::from_reader(file)?)
+    }
+    /// Saves the manifest at the root of the given dataset directory
+    #[allow(clippy::result_large_err)]
+    pub fn save<P: AsRef<Path>>(&self, dir: P) -> Result<(), Error> {
+        let file = File::create(dir.as_ref().join(MANIFEST))?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+    /// Checks each file of the dataset stored in `dir` against the checksum
+    /// recorded in this manifest. An empty result means the dataset is intact.
+    #[allow(clippy::result_large_err)]
+    pub fn verify<P: AsRef<Path>>(&self, dir: P) -> Result<Vec<Discrepancy>, Error> {
+        let mut discrepancies = vec![];
+        for entry in self.files.iter() {
+            let path = dir.as_ref().join(&entry.path);
+            if !path.exists() {
+                discrepancies.push(Discrepancy::Missing { path: entry.path.clone() });
+                continue;
+            }
+            let actual = sha256_file(&path)?;
+            if actual != entry.sha256 {
+                discrepancies.push(Discrepancy::Corrupted {
+                    path: entry.path.clone(),
+                    expected: entry.sha256.clone(),
+                    actual,
+                });
+            }
+        }
+        Ok(discrepancies)
+    }
+}
+
+/// Writes the files of a dataset and keeps track of them in a manifest
+pub struct DatasetWriter {
+    dir: PathBuf,
+    manifest: Manifest,
+}
+impl DatasetWriter {
+    /// Creates a writer which stores the dataset files in the given directory
+    /// (the directory is created when it does not exist yet).
+    #[allow(clippy::result_large_err)]
+    pub fn new<P: AsRef<Path>>(dir: P, feed: Option<String>) -> Result<Self, Error> {
+        fs::create_dir_all(dir.as_ref())?;
+        Ok(Self { dir: dir.as_ref().to_path_buf(), manifest: Manifest::new(feed) })
+    }
+    /// Writes the given bars in a csv file
+    #[allow(clippy::result_large_err)]
+    pub fn write_bars(&mut self, symbol: &str, bars: &[BarData]) -> Result<&FileEntry, Error> {
+        self.write_csv(symbol, DataKind::Bars, bars)
+    }
+    /// Writes the given trades in a csv file
+    #[allow(clippy::result_large_err)]
+    pub fn write_trades(&mut self, symbol: &str, trades: &[TradeData]) -> Result<&FileEntry, Error> {
+        self.write_csv(symbol, DataKind::Trades, trades)
+    }
+    /// Writes the given quotes in a csv file
+    #[allow(clippy::result_large_err)]
+    pub fn write_quotes(&mut self, symbol: &str, quotes: &[QuoteData]) -> Result<&FileEntry, Error> {
+        self.write_csv(symbol, DataKind::Quotes, quotes)
+    }
+    /// Records a file which has been written to the dataset directory by
+    /// some other means.
+    #[allow(clippy::result_large_err)]
+    pub fn record(&mut self, path: &str, symbol: &str, kind: DataKind,
+        start: Option<DateTime<Utc>>, end: Option<DateTime<Utc>>, rows: usize) -> Result<&FileEntry, Error>
+    {
+        let sha256 = sha256_file(self.dir.join(path))?;
+        self.manifest.files.push(FileEntry {
+            path: path.to_string(),
+            symbol: symbol.to_string(),
+            kind, start, end, rows, sha256
+        });
+        Ok(self.manifest.files.last().unwrap())
+    }
+    /// The directory where the dataset is stored
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+    /// Writes the manifest and returns it
+    #[allow(clippy::result_large_err)]
+    pub fn finish(self) -> Result<Manifest, Error> {
+        self.manifest.save(&self.dir)?;
+        Ok(self.manifest)
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn write_csv<T: CsvRow>(&mut self, symbol: &str, kind: DataKind, rows: &[T]) -> Result<&FileEntry, Error> {
+        // the crypto pairs (e.g. BTC/USD) must not create a subdirectory
+        let path = format!("{}.{}.csv", symbol.replace('/', "_"), kind.to_str());
+        let mut out = BufWriter::new(File::create(self.dir.join(&path))?);
+        writeln!(out, "{}", T::HEADER)?;
+        for row in rows {
+            writeln!(out, "{}", row.to_csv())?;
+        }
+        out.flush()?;
+        drop(out);
+
+        let start = rows.iter().map(|r| r.timestamp()).min();
+        let end   = rows.iter().map(|r| r.timestamp()).max();
+        self.record(&path, symbol, kind, start, end, rows.len())
+    }
+}
+
+/// Computes the hex encoded SHA-256 checksum of the given file
+#[allow(clippy::result_large_err)]
+pub fn sha256_file<P: AsRef<Path>>(path: P) -> Result<String, Error> {
+    let mut file   = File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/******************************************************************************
+ * CSV ENCODING ***************************************************************
+ ******************************************************************************/
+
+/// Private: the datapoints which can be written as a csv row
+trait CsvRow {
+    const HEADER: &'static str;
+    fn timestamp(&self) -> DateTime<Utc>;
+    fn to_csv(&self) -> String;
+}
+impl CsvRow for BarData {
+    const HEADER: &'static str = "timestamp,open,high,low,close,volume";
+    fn timestamp(&self) -> DateTime<Utc> { self.timestamp }
+    fn to_csv(&self) -> String {
+        format!("{},{},{},{},{},{}", self.timestamp.to_rfc3339(),
+            self.open_price, self.high_price, self.low_price, self.close_price,
+            self.volume)
+    }
+}
+impl CsvRow for TradeData {
+    const HEADER: &'static str = "timestamp,trade_id,exchange,price,size,conditions,tape";
+    fn timestamp(&self) -> DateTime<Utc> { self.timestamp }
+    fn to_csv(&self) -> String {
+        format!("{},{},{},{},{},{},{}", self.timestamp.to_rfc3339(),
+            self.trade_id, plain(&self.exchange_code), self.trade_price,
+            self.trade_size, self.conditions.join("|"), self.tape)
+    }
+}
+impl CsvRow for QuoteData {
+    const HEADER: &'static str = "timestamp,ask_exchange,ask_price,ask_size,bid_exchange,bid_price,bid_size,conditions,tape";
+    fn timestamp(&self) -> DateTime<Utc> { self.timestamp }
+    fn to_csv(&self) -> String {
+        format!("{},{},{},{},{},{},{},{},{}", self.timestamp.to_rfc3339(),
+            plain(&self.ask_exchange), self.ask_price, self.ask_size,
+            plain(&self.bid_exchange), self.bid_price, self.bid_size,
+            self.conditions.join("|"), self.tape)
+    }
+}
+/// Private: the wire representation of some value without its json quotes
+fn plain<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(s)) => s,
+        Ok(other) => other.to_string(),
+        Err(_) => String::new(),
+    }
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use chrono::{TimeZone, Utc};
+
+    use crate::{dataset::{DatasetWriter, Discrepancy, Manifest}, entities::BarData};
+
+    #[test]
+    fn manifest_detects_corruption() {
+        let dir = std::env::temp_dir().join(format!("apca_dataset_{}", std::process::id()));
+        let bar = BarData {
+            open_price: 1.0, high_price: 2.0, low_price: 0.5, close_price: 1.5,
+            volume: 100, timestamp: Utc.with_ymd_and_hms(2021, 8, 2, 4, 0, 0).unwrap()
+        };
+        let mut writer = DatasetWriter::new(&dir, Some("iex".to_string())).unwrap();
+        let entry = writer.write_bars("AAPL", &[bar.clone(), bar]).unwrap();
+        assert_eq!(2, entry.rows);
+        writer.finish().unwrap();
+
+        let manifest = Manifest::load(&dir).unwrap();
+        assert!(manifest.verify(&dir).unwrap().is_empty());
+
+        fs::write(dir.join("AAPL.bars.csv"), "tampered").unwrap();
+        let problems = manifest.verify(&dir).unwrap();
+        assert!(matches!(problems[0], Discrepancy::Corrupted{..}));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn crypto_pairs_are_written_flat() {
+        let dir = std::env::temp_dir().join(format!("apca_dataset_crypto_{}", std::process::id()));
+        let bar = BarData {
+            open_price: 1.0, high_price: 2.0, low_price: 0.5, close_price: 1.5,
+            volume: 100, timestamp: Utc.with_ymd_and_hms(2021, 8, 2, 4, 0, 0).unwrap()
+        };
+        let mut writer = DatasetWriter::new(&dir, None).unwrap();
+        writer.write_bars("BTC/USD", &[bar]).unwrap();
+        writer.finish().unwrap();
+
+        assert!(dir.join("BTC_USD.bars.csv").is_file());
+        assert!(Manifest::load(&dir).unwrap().verify(&dir).unwrap().is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,227 @@
+//! This module centralizes the construction of the URLs of all the REST
+//! endpoints offered by Alpaca.
+//!
+//! Each endpoint is identified by an `Endpoint` which knows its own path. The
+//! full URL is obtained by joining that path with the base URL of the API
+//! (either the trading API, paper or live, or the market data API).
+//!
+//! ```
+//! use apca_datav2::endpoints::{Endpoint, DATA_URL};
+//! use apca_datav2::rest::PAPER_TRADING_URL;
+//!
+//! assert_eq!("https://paper-api.alpaca.markets/v2/orders/1234",
+//!     Endpoint::Order("1234").url(PAPER_TRADING_URL));
+//! assert_eq!("https://data.alpaca.markets/v2/stocks/AAPL/bars",
+//!     Endpoint::Bars("AAPL").url(DATA_URL));
+//! ```
+
+use std::fmt::Display;
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, PercentEncode, NON_ALPHANUMERIC};
+
+/// Base URL of the market data API
+pub const DATA_URL: &str = "https://data.alpaca.markets";
+/// The paths (prefixes) of the endpoints whose content rarely changes
+pub const STATIC_PATHS: &[&str] = &["/v2/assets", "/v2/calendar", "/v2/stocks/meta"];
+
+/// The REST endpoints of Alpaca's API
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endpoint<'a> {
+    // --- TRADING API --------------------------------------------------------
+    /// All the orders of the account
+    Orders,
+    /// One order identified by its id
+    Order(&'a str),
+    /// One order identified by its client order id (passed as query param)
+    OrderByClientId,
+    /// All the open positions of the account
+    Positions,
+    /// The open position on one symbol (or asset id)
+    Position(&'a str),
+    /// The exercise of the options position on one contract (symbol or id)
+    ExercisePosition(&'a str),
+    /// All the assets
+    Assets,
+    /// One asset identified by its symbol (or asset id)
+    Asset(&'a str),
+    /// All the watchlists of the account
+    Watchlists,
+    /// One watchlist identified by its id
+    Watchlist(&'a str),
+    /// One symbol (second field) of a watchlist (first field)
+    WatchlistSymbol(&'a str, &'a str),
+    /// The corporate action announcements
+    Announcements,
+    /// One corporate action announcement identified by its id
+    Announcement(&'a str),
+    /// The trading days of the market
+    Calendar,
+    /// The market clock
+    Clock,
+    /// The account of the authenticated user
+    Account,
+    /// The configurations of the account
+    AccountConfigurations,
+    /// The history of the equity of the account
+    PortfolioHistory,
+    /// The activities of the account
+    Activities,
+    /// The activities of one type (e.g. FILL)
+    ActivitiesOfType(&'a str),
+
+    // --- MARKET DATA API ----------------------------------------------------
+    /// The historical trades of one symbol
+    Trades(&'a str),
+    /// The latest trade of one symbol
+    LatestTrade(&'a str),
+    /// The historical quotes of one symbol
+    Quotes(&'a str),
+    /// The latest quote of one symbol
+    LatestQuote(&'a str),
+    /// The historical bars of one symbol
+    Bars(&'a str),
+    /// The latest bar of one symbol
+    LatestBar(&'a str),
+    /// The snapshot of one symbol
+    Snapshot(&'a str),
+    /// The snapshots of several symbols (passed as query param)
+    Snapshots,
+    /// The names of the exchanges
+    Exchanges,
+    /// The descriptions of the trade or quote conditions
+    Conditions(&'a str),
+
+    // --- CRYPTO MARKET DATA API ---------------------------------------------
+    /// The historical trades of several crypto symbols (passed as query param)
+    CryptoTrades,
+    /// The latest trade of several crypto symbols
+    CryptoLatestTrades,
+    /// The historical quotes of several crypto symbols
+    CryptoQuotes,
+    /// The latest quote of several crypto symbols
+    CryptoLatestQuotes,
+    /// The historical bars of several crypto symbols
+    CryptoBars,
+    /// The latest bar of several crypto symbols
+    CryptoLatestBars,
+    /// The snapshots of several crypto symbols
+    CryptoSnapshots,
+    /// The news articles
+    News,
+    /// The corporate actions (splits, dividends, mergers...)
+    CorporateActions,
+
+    // --- OPTIONS MARKET DATA API --------------------------------------------
+    /// The historical trades of several contracts (passed as query param)
+    OptionTrades,
+    /// The latest trade of several contracts
+    OptionLatestTrades,
+    /// The latest quote of several contracts
+    OptionLatestQuotes,
+    /// The historical bars of several contracts
+    OptionBars,
+    /// The snapshots of several contracts
+    OptionSnapshots,
+    /// The snapshots of all the contracts of one underlying symbol
+    OptionChain(&'a str),
+}
+impl Endpoint<'_> {
+    /// The path of this endpoint (always starting with a slash). The symbols
+    /// and ids it contains are percent-encoded (e.g. `BTC/USD` -> `BTC%2FUSD`)
+    pub fn path(&self) -> String {
+        match self {
+            Endpoint::Orders                  => "/v2/orders".to_string(),
+            Endpoint::Order(id)               => format!("/v2/orders/{}", segment(id)),
+            Endpoint::OrderByClientId         => "/v2/orders:by_client_order_id".to_string(),
+            Endpoint::Positions               => "/v2/positions".to_string(),
+            Endpoint::Position(symbol)        => format!("/v2/positions/{}", segment(symbol)),
+            Endpoint::ExercisePosition(s)     => format!("/v2/positions/{}/exercise", segment(s)),
+            Endpoint::Assets                  => "/v2/assets".to_string(),
+            Endpoint::Asset(symbol)           => format!("/v2/assets/{}", segment(symbol)),
+            Endpoint::Watchlists              => "/v2/watchlists".to_string(),
+            Endpoint::Watchlist(id)           => format!("/v2/watchlists/{}", segment(id)),
+            Endpoint::WatchlistSymbol(id, s)  => format!("/v2/watchlists/{}/{}", segment(id), segment(s)),
+            Endpoint::Announcements           => "/v2/corporate_actions/announcements".to_string(),
+            Endpoint::Announcement(id)        => format!("/v2/corporate_actions/announcements/{}", segment(id)),
+            Endpoint::Calendar                => "/v2/calendar".to_string(),
+            Endpoint::Clock                   => "/v2/clock".to_string(),
+            Endpoint::Account                 => "/v2/account".to_string(),
+            Endpoint::AccountConfigurations   => "/v2/account/configurations".to_string(),
+            Endpoint::PortfolioHistory        => "/v2/account/portfolio/history".to_string(),
+            Endpoint::Activities              => "/v2/account/activities".to_string(),
+            Endpoint::ActivitiesOfType(t)     => format!("/v2/account/activities/{}", segment(t)),
+            Endpoint::Trades(symbol)          => format!("/v2/stocks/{}/trades", segment(symbol)),
+            Endpoint::LatestTrade(symbol)     => format!("/v2/stocks/{}/trades/latest", segment(symbol)),
+            Endpoint::Quotes(symbol)          => format!("/v2/stocks/{}/quotes", segment(symbol)),
+            Endpoint::LatestQuote(symbol)     => format!("/v2/stocks/{}/quotes/latest", segment(symbol)),
+            Endpoint::Bars(symbol)            => format!("/v2/stocks/{}/bars", segment(symbol)),
+            Endpoint::LatestBar(symbol)       => format!("/v2/stocks/{}/bars/latest", segment(symbol)),
+            Endpoint::Snapshot(symbol)        => format!("/v2/stocks/{}/snapshot", segment(symbol)),
+            Endpoint::Snapshots               => "/v2/stocks/snapshots".to_string(),
+            Endpoint::Exchanges               => "/v2/stocks/meta/exchanges".to_string(),
+            Endpoint::Conditions(tick_type)   => format!("/v2/stocks/meta/conditions/{}", segment(tick_type)),
+            Endpoint::CryptoTrades            => "/v1beta3/crypto/us/trades".to_string(),
+            Endpoint::CryptoLatestTrades      => "/v1beta3/crypto/us/latest/trades".to_string(),
+            Endpoint::CryptoQuotes            => "/v1beta3/crypto/us/quotes".to_string(),
+            Endpoint::CryptoLatestQuotes      => "/v1beta3/crypto/us/latest/quotes".to_string(),
+            Endpoint::CryptoBars              => "/v1beta3/crypto/us/bars".to_string(),
+            Endpoint::CryptoLatestBars        => "/v1beta3/crypto/us/latest/bars".to_string(),
+            Endpoint::CryptoSnapshots         => "/v1beta3/crypto/us/snapshots".to_string(),
+            Endpoint::News                    => "/v1beta1/news".to_string(),
+            Endpoint::CorporateActions        => "/v1beta1/corporate-actions".to_string(),
+            Endpoint::OptionTrades            => "/v1beta1/options/trades".to_string(),
+            Endpoint::OptionLatestTrades      => "/v1beta1/options/trades/latest".to_string(),
+            Endpoint::OptionLatestQuotes      => "/v1beta1/options/quotes/latest".to_string(),
+            Endpoint::OptionBars              => "/v1beta1/options/bars".to_string(),
+            Endpoint::OptionSnapshots         => "/v1beta1/options/snapshots".to_string(),
+            Endpoint::OptionChain(underlying) => format!("/v1beta1/options/snapshots/{}", segment(underlying)),
+        }
+    }
+    /// The full URL of this endpoint given the base URL of the API. A
+    /// trailing slash in the base URL is tolerated.
+    pub fn url(&self, base: &str) -> String {
+        format!("{}{}", base.trim_end_matches('/'), self.path())
+    }
+}
+
+/// The characters which may appear unencoded in a path segment (the
+/// unreserved characters of RFC 3986)
+const SEGMENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+/// Percent-encodes one segment of a path
+fn segment(s: &str) -> PercentEncode<'_> {
+    utf8_percent_encode(s, SEGMENT)
+}
+impl Display for Endpoint<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.path())
+    }
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use crate::endpoints::Endpoint;
+
+    #[test]
+    fn test_no_double_slashes() {
+        assert_eq!("https://api.alpaca.markets/v2/positions/AAPL",
+            Endpoint::Position("AAPL").url("https://api.alpaca.markets/"));
+        assert_eq!("https://api.alpaca.markets/v2/assets",
+            Endpoint::Assets.url("https://api.alpaca.markets"));
+        assert_eq!("https://api.alpaca.markets/v2/watchlists/42/AAPL",
+            Endpoint::WatchlistSymbol("42", "AAPL").url("https://api.alpaca.markets"));
+    }
+
+    #[test]
+    fn test_path_segments_are_encoded() {
+        assert_eq!("https://api.alpaca.markets/v2/positions/BTC%2FUSD",
+            Endpoint::Position("BTC/USD").url("https://api.alpaca.markets"));
+        assert_eq!("https://api.alpaca.markets/v2/watchlists/my%20list/BRK.B",
+            Endpoint::WatchlistSymbol("my list", "BRK.B").url("https://api.alpaca.markets"));
+        assert_eq!("/v2/orders/810f77c9-1c2e%3F",
+            Endpoint::Order("810f77c9-1c2e?").path());
+    }
+}
@@ -1,16 +1,37 @@
 //! This module provides the definition of the entity objects used in 
 //! Alpaca's API v2.
+//!
+//! Alpaca regularly adds new fields and new values to its API. Therefore, 
+//! the entities are marked `#[non_exhaustive]` so that these can be added 
+//! without breaking your code: the entities are constructed using their
+//! builders (e.g. `BarDataBuilder`) and the matches on the enumerations must
+//! have a wildcard arm.
+//!
+//! ```
+//! use chrono::Utc;
+//! use apca_datav2::entities::BarDataBuilder;
+//!
+//! let bar = BarDataBuilder::default()
+//!     .open_price(1.0).high_price(2.0).low_price(0.5).close_price(1.5)
+//!     .volume(100)
+//!     .timestamp(Utc::now())
+//!     .build().unwrap();
+//! assert_eq!(1.5, bar.close_price);
+//! ```
 
 extern crate serde;
 use chrono::{DateTime, Utc};
 use serde::{Serialize, Deserialize};
-use serde_repr::{Deserialize_repr, Serialize_repr};
+use derive_builder::Builder;
+
+use crate::errors::ApiError;
 
 /******************************************************************************
  * DATA POINTS ****************************************************************
  ******************************************************************************/
  /// Datapoint encapsulating informations about a given trade
- #[derive(Debug, Clone, Serialize, Deserialize)]
+ #[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+ #[non_exhaustive]
  pub struct TradeData {
      /// Trade identifier
      #[serde(rename="i")]
@@ -38,14 +59,30 @@ use serde_repr::{Deserialize_repr, Serialize_repr};
      /// and 
      /// <https://alpaca.markets/docs/api-documentation/api-v2/market-data/alpaca-data-api-v2/#quote-conditions>
      #[serde(rename="c")]
+     #[builder(default)]
      pub conditions: Vec<String>,
      /// Tape
      #[serde(rename="z")]
      pub tape: String,
  }
+ impl TradeData {
+    /// True iff this trade is a regular sale (no special condition)
+    pub fn is_regular_sale(&self) -> bool {
+        crate::meta::is_regular_sale(&self.conditions)
+    }
+    /// True iff this trade updates the open, high, low and close of a bar
+    pub fn updates_ohlc(&self) -> bool {
+        crate::meta::updates_ohlc(&self.conditions)
+    }
+    /// True iff this trade counts in the volume of a bar
+    pub fn updates_volume(&self) -> bool {
+        crate::meta::updates_volume(&self.conditions)
+    }
+ }
 
  /// Datapoint encapsulating a quote
- #[derive(Debug, Clone, Serialize, Deserialize)]
+ #[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+ #[non_exhaustive]
  pub struct QuoteData {
      /// ask exchange code
      #[serde(rename="ax")]
@@ -79,6 +116,7 @@ use serde_repr::{Deserialize_repr, Serialize_repr};
      /// and 
      /// <https://alpaca.markets/docs/api-documentation/api-v2/market-data/alpaca-data-api-v2/#quote-conditions>
      #[serde(rename="c")]
+     #[builder(default)]
      pub conditions: Vec<String>,
      /// Tape
      #[serde(rename="z")]
@@ -86,7 +124,8 @@ use serde_repr::{Deserialize_repr, Serialize_repr};
  }
 
 /// Datapoint encapsulating a 'bar' (a.k.a. OHLC)
- #[derive(Debug, Clone, Serialize, Deserialize)]
+ #[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+ #[non_exhaustive]
  pub struct BarData {
     // open price
     #[serde(rename="o")]
@@ -108,10 +147,198 @@ use serde_repr::{Deserialize_repr, Serialize_repr};
     pub timestamp: DateTime<Utc>,
 }
 
+/// Datapoint encapsulating a trading status change (e.g. a halt or a
+/// resumption) of a given symbol
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct StatusData {
+    /// Status code (e.g. "H" for a trading halt, "T" for a resumption)
+    #[serde(rename="sc")]
+    pub status_code: String,
+    /// Status message
+    #[serde(rename="sm")]
+    pub status_message: String,
+    /// Reason code (e.g. "T12" for a halt pending additional information)
+    #[serde(rename="rc")]
+    pub reason_code: String,
+    /// Reason message
+    #[serde(rename="rm")]
+    pub reason_message: String,
+    /// RFC-3339 formatted timestamp with nanosecond precision.
+    #[serde(rename="t")]
+    pub timestamp: DateTime<Utc>,
+    /// Tape
+    #[serde(rename="z")]
+    pub tape: String,
+}
+impl StatusData {
+    /// True iff this status stops the trading of the symbol (halt or 
+    /// volatility pause)
+    pub fn is_halted(&self) -> bool {
+        matches!(self.status_code.as_str(), "2" | "H" | "M" | "P")
+    }
+    /// True iff this status resumes the trading of the symbol
+    pub fn is_resumed(&self) -> bool {
+        matches!(self.status_code.as_str(), "3" | "Q" | "T")
+    }
+}
+
+/// Datapoint encapsulating the limit up - limit down price band of a given
+/// symbol
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct LuldData {
+    /// Limit up price
+    #[serde(rename="u")]
+    pub limit_up_price: f64,
+    /// Limit down price
+    #[serde(rename="d")]
+    pub limit_down_price: f64,
+    /// Indicator (e.g. "B" when both bands are executable)
+    #[serde(rename="i")]
+    pub indicator: String,
+    /// RFC-3339 formatted timestamp with nanosecond precision.
+    #[serde(rename="t")]
+    pub timestamp: DateTime<Utc>,
+    /// Tape
+    #[serde(rename="z")]
+    pub tape: String,
+}
+
+/// Datapoint encapsulating the correction of a trade which was previously
+/// sent
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct CorrectionData {
+    /// exchange code where the trade occurred
+    #[serde(rename="x")]
+    pub exchange_code: Exchange,
+    /// Identifier of the original trade
+    #[serde(rename="oi")]
+    pub original_id: i64,
+    /// Price of the original trade
+    #[serde(rename="op")]
+    pub original_price: f64,
+    /// Size of the original trade
+    #[serde(rename="os")]
+    pub original_size: u64,
+    /// Conditions of the original trade
+    #[serde(rename="oc", default)]
+    #[builder(default)]
+    pub original_conditions: Vec<String>,
+    /// Identifier of the corrected trade
+    #[serde(rename="ci")]
+    pub corrected_id: i64,
+    /// Price of the corrected trade
+    #[serde(rename="cp")]
+    pub corrected_price: f64,
+    /// Size of the corrected trade
+    #[serde(rename="cs")]
+    pub corrected_size: u64,
+    /// Conditions of the corrected trade
+    #[serde(rename="cc", default)]
+    #[builder(default)]
+    pub corrected_conditions: Vec<String>,
+    /// RFC-3339 formatted timestamp with nanosecond precision.
+    #[serde(rename="t")]
+    pub timestamp: DateTime<Utc>,
+    /// Tape
+    #[serde(rename="z")]
+    pub tape: String,
+}
+
+/// Datapoint encapsulating the cancelation (or error) of a trade which was
+/// previously sent
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct CancelErrorData {
+    /// Identifier of the canceled trade
+    #[serde(rename="i")]
+    pub trade_id: i64,
+    /// exchange code where the trade occurred
+    #[serde(rename="x")]
+    pub exchange_code: Exchange,
+    /// Price of the canceled trade
+    #[serde(rename="p")]
+    pub trade_price: f64,
+    /// Size of the canceled trade
+    #[serde(rename="s")]
+    pub trade_size: u64,
+    /// Action ("C" for a cancelation, "E" for an error)
+    #[serde(rename="a")]
+    pub action: String,
+    /// RFC-3339 formatted timestamp with nanosecond precision.
+    #[serde(rename="t")]
+    pub timestamp: DateTime<Utc>,
+    /// Tape
+    #[serde(rename="z")]
+    pub tape: String,
+}
+
+/// Datapoint encapsulating (an update of) the order book of a crypto pair
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct OrderbookData {
+    /// The bid levels
+    #[serde(rename="b", default)]
+    #[builder(default)]
+    pub bids: Vec<OrderbookLevel>,
+    /// The ask levels
+    #[serde(rename="a", default)]
+    #[builder(default)]
+    pub asks: Vec<OrderbookLevel>,
+    /// True iff this is a full snapshot of the book (rather than an update)
+    #[serde(rename="r", default)]
+    #[builder(default)]
+    pub reset: bool,
+    /// RFC-3339 formatted timestamp with nanosecond precision.
+    #[serde(rename="t")]
+    pub timestamp: DateTime<Utc>,
+}
+
+/// One price level of an order book. A level whose size is zero has been
+/// removed from the book.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct OrderbookLevel {
+    /// Price of the level
+    #[serde(rename="p")]
+    pub price: f64,
+    /// Size available at that price
+    #[serde(rename="s")]
+    pub size: f64,
+}
+
+/// Common interface of all the bars (a.k.a. OHLC) regardless of the API 
+/// they originate from. This lets you write analytics code that is generic 
+/// over the source of the data (historical or realtime).
+pub trait Ohlcv {
+    /// open price
+    fn open(&self) -> f64;
+    /// high price
+    fn high(&self) -> f64;
+    /// low price
+    fn low(&self) -> f64;
+    /// close price
+    fn close(&self) -> f64;
+    /// volume
+    fn volume(&self) -> u64;
+    /// time at which the bar starts
+    fn timestamp(&self) -> DateTime<Utc>;
+}
+impl Ohlcv for BarData {
+    fn open(&self) -> f64 { self.open_price }
+    fn high(&self) -> f64 { self.high_price }
+    fn low(&self) -> f64 { self.low_price }
+    fn close(&self) -> f64 { self.close_price }
+    fn volume(&self) -> u64 { self.volume }
+    fn timestamp(&self) -> DateTime<Utc> { self.timestamp }
+}
+
 /// List of stock exchanges which are supported by Alpaca.
 /// The tape id of each exchange is returned in all market data requests. 
 /// You can use this table to map the code to an exchange.
  #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+ #[non_exhaustive]
  pub enum Exchange {
     /// A     NYSE American (AMEX)     
     #[serde(rename="A")]
@@ -186,6 +413,7 @@ use serde_repr::{Deserialize_repr, Serialize_repr};
  ******************************************************************************/
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub enum OrderClass {
     /// Class of the orders that are generated when closing a position
     #[serde(rename="")]
@@ -201,6 +429,7 @@ pub enum OrderClass {
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
 pub enum OrderType {
     #[serde(rename="market")]
     Market,
@@ -230,6 +459,14 @@ pub enum Direction {
     #[serde(rename="desc")]
     Descending
 }
+impl std::fmt::Display for Direction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ascending  => write!(f, "asc"),
+            Self::Descending => write!(f, "desc"),
+        }
+    }
+}
 
 /// # Time in Force
 /// 
@@ -282,6 +519,7 @@ pub enum Direction {
 ///     quantity can be filled, otherwise the order is canceled. 
 ///     Only available with API v2.
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
 pub enum TimeInForce {
     /// A day order is eligible for execution only on the day it is live. 
     /// By default, the order is only valid during Regular Trading Hours 
@@ -296,7 +534,7 @@ pub enum TimeInForce {
     /// subject to price adjustments to offset corporate actions affecting the 
     /// issue. We do not currently support Do Not Reduce(DNR) orders to opt out 
     /// of such price adjustments.
-    #[serde(rename="day")]
+    #[serde(rename="gtc")]
     GoodUntilCanceled,
     /// Use this TIF with a market/limit order type to submit “market on open” 
     /// (MOO) and “limit on open” (LOO) orders. This order is eligible to execute 
@@ -307,7 +545,7 @@ pub enum TimeInForce {
     /// are routed to the primary exchange. Such orders do not necessarily 
     /// execute exactly at 9:30am / 4:00pm ET but execute per the exchange’s 
     /// auction rules.
-    #[serde(rename="day")]
+    #[serde(rename="opg")]
     OpeningAuction,
     /// Use this TIF with a market/limit order type to submit 
     /// “market on close” (MOC) and “limit on close” (LOC) orders. This order is 
@@ -316,7 +554,7 @@ pub enum TimeInForce {
     /// 3:50pm but before 7:00pm ET will be rejected. CLS orders submitted after 
     /// 7:00pm will be queued and routed to the following day’s closing auction. 
     /// Only available with API v2.
-    #[serde(rename="day")]
+    #[serde(rename="cls")]
     ClosingAuction,
     /// An Immediate Or Cancel (IOC) order requires all or part of the order 
     /// to be executed immediately. Any unfilled portion of the order is 
@@ -325,12 +563,12 @@ pub enum TimeInForce {
     /// cancel any unfilled balance. On occasion, this can result in the entire 
     /// order being cancelled if the market maker does not have any existing 
     /// inventory of the security in question.
-    #[serde(rename="day")]
+    #[serde(rename="ioc")]
     ImmediateOrCancel,
     /// A Fill or Kill (FOK) order is only executed if the entire order 
     /// quantity can be filled, otherwise the order is canceled. 
     /// Only available with API v2.
-    #[serde(rename="day")]
+    #[serde(rename="fok")]
     FillOrKill,
 }
 
@@ -394,6 +632,7 @@ pub enum TimeInForce {
 /// An order may be canceled through the API up until the point it reaches a state of either filled, canceled, or expired.
 /// 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
 pub enum OrderStatus {
     /// The order has been received by Alpaca, and routed to exchanges for 
     /// execution. This is the usual initial state of an order.
@@ -462,10 +701,20 @@ pub enum OrderStatus {
     #[serde(rename="calculated")]
     Calculated
 }
+impl OrderStatus {
+    /// Returns true iff the order may still be (partially) executed
+    pub fn is_open(self) -> bool {
+        !matches!(self, 
+            OrderStatus::Filled   | OrderStatus::Canceled | OrderStatus::Expired | 
+            OrderStatus::Replaced | OrderStatus::Rejected | OrderStatus::DoneForDay |
+            OrderStatus::Calculated)
+    }
+}
 
 /// The Snapshot API for one ticker provides the latest trade, latest quote, 
 /// minute bar daily bar and previous daily bar data for a given ticker symbol.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct OrderData {
     /// Order ID
     pub id: String,
@@ -473,35 +722,47 @@ pub struct OrderData {
     pub client_order_id: String,
     /// Timestamp ot the order creation
     pub created_at: DateTime<Utc>,
+    #[builder(default)]
     pub updated_at: Option<DateTime<Utc>>,
+    #[builder(default)]
     pub submitted_at: Option<DateTime<Utc>>,
+    #[builder(default)]
     pub filled_at: Option<DateTime<Utc>>,
+    #[builder(default)]
     pub expired_at: Option<DateTime<Utc>>,
+    #[builder(default)]
     pub canceled_at: Option<DateTime<Utc>>,
+    #[builder(default)]
     pub failed_at: Option<DateTime<Utc>>,
+    #[builder(default)]
     pub replaced_at: Option<DateTime<Utc>>,
     /// The order ID that this order was replaced by
+    #[builder(default)]
     pub replaced_by: Option<String>,
     /// The order ID that this order replaces
+    #[builder(default)]
     pub replaces: Option<String>,
     /// Asset uuid
     pub asset_id: String,
     /// Asset symbol
     pub symbol: String,
     /// Asset class
-    pub asset_class: String,
+    pub asset_class: AssetClass,
     /// Ordered notional amount. If entered, qty will be null. 
     /// Can take up to 9 decimal points.
+    #[builder(default)]
     pub notional: Option<f64>,
     /// Ordered quantity. If entered, notional will be null. 
     /// Can take up to 9 decimal points.
     #[serde(deserialize_with="crate::utils::option_as_f64")]
+    #[builder(default)]
     pub qty: Option<f64>,
     /// Filled quantity
     #[serde(deserialize_with="crate::utils::number_as_f64")]
     pub filled_qty: f64,
     /// Filled average price
     #[serde(deserialize_with="crate::utils::option_as_f64")]
+    #[builder(default)]
     pub filled_avg_price: Option<f64>,
     /// simple, bracket, oco or oto. For details of non-simple order 
     /// classes, please see ![bracket](https://alpaca.markets/docs/trading-on-alpaca/orders#bracket-orders "Bracket Order Overview")
@@ -515,9 +776,11 @@ pub struct OrderData {
     pub time_in_force: TimeInForce,
     /// Limit price
     #[serde(deserialize_with="crate::utils::option_as_f64")]
+    #[builder(default)]
     pub limit_price: Option<f64>,
     /// Stop price
     #[serde(deserialize_with="crate::utils::option_as_f64")]
+    #[builder(default)]
     pub stop_price: Option<f64>,
     /// The current status of the order in its lifecycle
     pub status: OrderStatus,
@@ -525,40 +788,153 @@ pub struct OrderData {
     pub extended_hours: bool,
     /// When querying non-simple order_class orders in a nested style, an array 
     /// of Order entities associated with this order. Otherwise, null.
+    #[builder(default)]
     pub legs: Option<Vec<OrderData>>,
     /// The percent value away from the high water mark for trailing stop orders.
     #[serde(deserialize_with="crate::utils::option_as_f64")]
+    #[builder(default)]
     pub trail_percent: Option<f64>,
     /// The dollar value away from the high water mark for trailing stop orders.
     #[serde(deserialize_with="crate::utils::option_as_f64")]
+    #[builder(default)]
     pub trail_price: Option<f64>,
     /// The highest (lowest) market price seen since the trailing stop order was 
     /// submitted.
     #[serde(deserialize_with="crate::utils::option_as_f64")]
+    #[builder(default)]
     pub hwm: Option<f64>,
 }
+impl OrderData {
+    /// The legs of this multi-leg order (empty unless the order was fetched
+    /// with `nested` set to true)
+    pub fn leg_orders(&self) -> &[OrderData] {
+        self.legs.as_deref().unwrap_or_default()
+    }
+    /// The leg of this multi-leg order having the given type (e.g. the
+    /// stop loss leg of a bracket order)
+    pub fn leg_of_type(&self, order_type: OrderType) -> Option<&OrderData> {
+        self.leg_orders().iter().find(|leg| leg.order_type == order_type)
+    }
+}
 
-/// A notification wrt the status of a cancelation request
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The outcome of the cancelation of one order among those requested by 
+/// `cancel_all_orders`
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[serde(from="MultiStatus<OrderKey, CancelationStatus>", into="MultiStatus<OrderKey, CancelationStatus>")]
+#[non_exhaustive]
 pub struct CancellationData {
   /// The order whose cancelation has been requested.
   pub id: String,
   /// The cancelation status
-  pub status: CancelationStatus 
+  pub status: CancelationStatus,
+  /// The order as it was after the cancelation request (when the server 
+  /// has sent it back)
+  #[builder(default)]
+  pub order: Option<OrderData>,
+  /// The reason why the cancelation failed (when the server has given one)
+  #[builder(default)]
+  pub error: Option<ApiError>,
+}
+impl CancellationData {
+  /// Returns true iff the cancelation was accepted
+  pub fn is_success(&self) -> bool {
+    matches!(self.status, CancelationStatus::Success | CancelationStatus::NoContent)
+  }
+}
+impl From<MultiStatus<OrderKey, CancelationStatus>> for CancellationData {
+  fn from(raw: MultiStatus<OrderKey, CancelationStatus>) -> Self {
+    let (key, status, order, error) = raw.into_parts();
+    Self { id: key.id, status, order, error }
+  }
+}
+impl From<CancellationData> for MultiStatus<OrderKey, CancelationStatus> {
+  fn from(data: CancellationData) -> Self {
+    MultiStatus::new(OrderKey { id: data.id }, data.status, data.order, data.error)
+  }
+}
+/// Private: one entry of a multi-status reply (such as those of 
+/// `cancel_all_orders` and `close_all_positions`) as it is encoded on the 
+/// wire. The body is either the order or the reason of the failure.
+#[derive(Serialize, Deserialize)]
+struct MultiStatus<K, S> {
+  #[serde(flatten)]
+  key   : K,
+  status: S,
+  #[serde(default)]
+  body  : Option<serde_json::Value>,
+}
+impl <K, S> MultiStatus<K, S> {
+  fn new(key: K, status: S, order: Option<OrderData>, error: Option<ApiError>) -> Self {
+    let body = match (order, error) {
+      (Some(order), _) => serde_json::to_value(order).ok(),
+      (_, Some(error)) => serde_json::to_value(error).ok(),
+      _                => None,
+    };
+    Self { key, status, body }
+  }
+  fn into_parts(self) -> (K, S, Option<OrderData>, Option<ApiError>) {
+    let body  = self.body.filter(|b| !b.is_null());
+    let order = body.clone().and_then(|b| serde_json::from_value::<OrderData>(b).ok());
+    let error = body.filter(|_| order.is_none()).and_then(|b| serde_json::from_value::<ApiError>(b).ok());
+    (self.key, self.status, order, error)
+  }
+}
+/// Private: the key of a canceled order in a multi-status reply
+#[derive(Serialize, Deserialize)]
+struct OrderKey {
+  id: String,
+}
+/// Private: the key of a closed position in a multi-status reply
+#[derive(Serialize, Deserialize)]
+struct SymbolKey {
+  symbol: String,
 }
 /// Basically an http status code which is interpreted in the context of an 
 /// order cancelation request
-#[derive(Debug, Clone, Serialize_repr, Deserialize_repr)]
- #[repr(u16)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from="u16", into="u16")]
+#[non_exhaustive]
 pub enum CancelationStatus {
   /// Cancelation succeeded
-  Success = 200,
+  Success,
   /// The request has been sucessfully processed but there is no reply info.
-  NoContent = 204,
+  NoContent,
+  /// The action is forbidden
+  Forbidden,
   /// The order was not found
-  NotFound = 404,
+  NotFound,
   /// The order cannot be canceled
-  Unprocessable = 422
+  Unprocessable,
+  /// The cancelation was rejected
+  InternalError,
+  /// Any other http status
+  Other(u16),
+}
+impl From<u16> for CancelationStatus {
+  fn from(status: u16) -> Self {
+    match status {
+      200    => CancelationStatus::Success,
+      204    => CancelationStatus::NoContent,
+      403    => CancelationStatus::Forbidden,
+      404    => CancelationStatus::NotFound,
+      422    => CancelationStatus::Unprocessable,
+      500    => CancelationStatus::InternalError,
+      status => CancelationStatus::Other(status),
+    }
+  }
+}
+impl From<CancelationStatus> for u16 {
+  fn from(status: CancelationStatus) -> Self {
+    match status {
+      CancelationStatus::Success       => 200,
+      CancelationStatus::NoContent     => 204,
+      CancelationStatus::Forbidden     => 403,
+      CancelationStatus::NotFound      => 404,
+      CancelationStatus::Unprocessable => 422,
+      CancelationStatus::InternalError => 500,
+      CancelationStatus::Other(status) => status,
+    }
+  }
 }
 
 /// The side of a position (is it a long position or a short one ?)
@@ -573,7 +949,8 @@ pub enum PositionSide {
 }
 
 /// The description of a position
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct PositionData {
     /// Asset ID
     pub asset_id: String,
@@ -581,8 +958,8 @@ pub struct PositionData {
     pub symbol: String,
     /// Exchange name of the asset (ErisX for crypto)
     pub exchange: String,
-    /// Asset class name
-    pub asset_class: String,
+    /// Asset class
+    pub asset_class: AssetClass,
     /// Average entry price of the position
     #[serde(deserialize_with="crate::utils::number_as_f64")]
     pub avg_entry_price: f64,
@@ -622,34 +999,91 @@ pub struct PositionData {
 }
 
 /// A notification wrt the status of a position closure
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[serde(from="MultiStatus<SymbolKey, ClosureStatus>", into="MultiStatus<SymbolKey, ClosureStatus>")]
+#[non_exhaustive]
 pub struct ClosureData {
   /// The symbol whose position is being closed
   pub symbol: String,
   /// The closure status
-  pub status: ClosureStatus 
+  pub status: ClosureStatus,
+  /// The order liquidating the position (when the server has sent it back)
+  #[builder(default)]
+  pub order: Option<OrderData>,
+  /// The reason why the closure failed (when the server has given one)
+  #[builder(default)]
+  pub error: Option<ApiError>,
+}
+impl ClosureData {
+  /// Returns true iff the liquidation order was placed
+  pub fn is_success(&self) -> bool {
+    matches!(self.status, ClosureStatus::Success | ClosureStatus::NoContent)
+  }
+}
+impl From<MultiStatus<SymbolKey, ClosureStatus>> for ClosureData {
+  fn from(raw: MultiStatus<SymbolKey, ClosureStatus>) -> Self {
+    let (key, status, order, error) = raw.into_parts();
+    Self { symbol: key.symbol, status, order, error }
+  }
+}
+impl From<ClosureData> for MultiStatus<SymbolKey, ClosureStatus> {
+  fn from(data: ClosureData) -> Self {
+    MultiStatus::new(SymbolKey { symbol: data.symbol }, data.status, data.order, data.error)
+  }
 }
 /// Basically an http status code which is interpreted in the context of an 
 /// position closure
-#[derive(Debug, Clone, Serialize_repr, Deserialize_repr)]
- #[repr(u16)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from="u16", into="u16")]
+#[non_exhaustive]
 pub enum ClosureStatus {
   /// Position closure succeeded
-  Success = 200,
+  Success,
   /// The request has been sucessfully processed but there is no reply info.
-  NoContent = 204,
+  NoContent,
   /// The action is forbidden
-  Forbidden = 403,
+  Forbidden,
   /// The position was not found
-  NotFound = 404,
+  NotFound,
   /// The position cannot be closed
-  Unprocessable = 422
+  Unprocessable,
+  /// The liquidation order was rejected
+  InternalError,
+  /// Any other http status
+  Other(u16),
+}
+impl From<u16> for ClosureStatus {
+  fn from(status: u16) -> Self {
+    match status {
+      200    => ClosureStatus::Success,
+      204    => ClosureStatus::NoContent,
+      403    => ClosureStatus::Forbidden,
+      404    => ClosureStatus::NotFound,
+      422    => ClosureStatus::Unprocessable,
+      500    => ClosureStatus::InternalError,
+      status => ClosureStatus::Other(status),
+    }
+  }
+}
+impl From<ClosureStatus> for u16 {
+  fn from(status: ClosureStatus) -> Self {
+    match status {
+      ClosureStatus::Success       => 200,
+      ClosureStatus::NoContent     => 204,
+      ClosureStatus::Forbidden     => 403,
+      ClosureStatus::NotFound      => 404,
+      ClosureStatus::Unprocessable => 422,
+      ClosureStatus::InternalError => 500,
+      ClosureStatus::Other(status) => status,
+    }
+  }
 }
 
 /*******************************************************************************
  * ASSET API SPECIFIC STUFFS
  ******************************************************************************/
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
 pub enum AssetStatus {
     #[serde(rename="active")]
     Active,
@@ -664,12 +1098,57 @@ impl AssetStatus {
         }
     }
 }
-#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+/// The class of an asset
+#[derive(Debug, Clone, Eq, PartialEq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(from="String", into="String")]
+pub enum AssetClass {
+    /// us_equity
+    UsEquity,
+    /// crypto
+    Crypto,
+    /// us_option
+    UsOption,
+    /// Any class which is not known to this crate
+    Other(String),
+}
+impl AssetClass {
+    /// The name of the class (as used by the API)
+    pub fn as_str(&self) -> &str {
+        match self {
+            AssetClass::UsEquity => "us_equity",
+            AssetClass::Crypto   => "crypto",
+            AssetClass::UsOption => "us_option",
+            AssetClass::Other(c) => c,
+        }
+    }
+}
+impl From<String> for AssetClass {
+    fn from(class: String) -> Self {
+        match class.as_str() {
+            "us_equity" => AssetClass::UsEquity,
+            "crypto"    => AssetClass::Crypto,
+            "us_option" => AssetClass::UsOption,
+            _           => AssetClass::Other(class),
+        }
+    }
+}
+impl From<AssetClass> for String {
+    fn from(class: AssetClass) -> Self {
+        class.as_str().to_string()
+    }
+}
+impl std::fmt::Display for AssetClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+#[derive(Builder, Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct AssetData {
     /// Asset ID.
     pub id: String,
-    /// “us_equity”
-    pub class: String,
+    /// us_equity, crypto or us_option
+    pub class: AssetClass,
     /// AMEX, ARCA, BATS, NYSE, NASDAQ or NYSEARCA
     pub exchange: String,
     /// Symbol of the asset
@@ -698,7 +1177,8 @@ pub struct AssetData {
 /*******************************************************************************
  * WATCHLIST API SPECIFIC STUFFS
  ******************************************************************************/
-#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[derive(Builder, Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct WatchlistData {
     /// watchlist id
     #[serde(rename="id")]
@@ -717,6 +1197,7 @@ pub struct WatchlistData {
     pub updated_at: DateTime<Utc>,
     /// the content of this watchlist, in the order as registered by the client
     #[serde(rename="assets", default)]
+    #[builder(default)]
     pub assets: Vec<AssetData>
 }
 
@@ -726,7 +1207,7 @@ pub struct WatchlistData {
 
 #[cfg(test)]
 mod tests {
-   use crate::entities::{AssetData, OrderData, PositionData};
+   use crate::entities::{AssetClass, AssetData, OrderData, PositionData, TimeInForce};
 
 use super::WatchlistData;
 
@@ -868,4 +1349,34 @@ use super::WatchlistData;
        assert!(rsp.is_ok())
    }
 
+   #[test]
+   fn test_time_in_force_round_trip() {
+       let wire = [
+           (TimeInForce::Day,               "\"day\""),
+           (TimeInForce::GoodUntilCanceled, "\"gtc\""),
+           (TimeInForce::OpeningAuction,    "\"opg\""),
+           (TimeInForce::ClosingAuction,    "\"cls\""),
+           (TimeInForce::ImmediateOrCancel, "\"ioc\""),
+           (TimeInForce::FillOrKill,        "\"fok\""),
+       ];
+       for (tif, txt) in wire.iter() {
+           assert_eq!(*txt, serde_json::to_string(tif).unwrap());
+           assert_eq!(*tif, serde_json::from_str::<TimeInForce>(txt).unwrap());
+       }
+   }
+
+   #[test]
+   fn test_asset_class_round_trip() {
+       let wire = [
+           (AssetClass::UsEquity,                 "\"us_equity\""),
+           (AssetClass::Crypto,                   "\"crypto\""),
+           (AssetClass::UsOption,                 "\"us_option\""),
+           (AssetClass::Other("fx".to_string()),  "\"fx\""),
+       ];
+       for (class, txt) in wire.iter() {
+           assert_eq!(*txt, serde_json::to_string(class).unwrap());
+           assert_eq!(*class, serde_json::from_str::<AssetClass>(txt).unwrap());
+       }
+   }
+
 }
@@ -4,6 +4,7 @@ use reqwest::Response;
 use tokio_tungstenite::tungstenite as tungstenite;
 use serde::{Deserialize, Serialize};
 use serde_repr::{Serialize_repr, Deserialize_repr};
+use crate::entities::OrderDataBuilderError;
 use crate::realtime::{AuthDataBuilderError, SubscriptionDataBuilderError};
 
 /*******************************************************************************
@@ -13,31 +14,264 @@ use crate::realtime::{AuthDataBuilderError, SubscriptionDataBuilderError};
 /// Error types that can occur while working with this crate
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
+    // held by value (which makes `Error` 136 bytes large): the sync fns
+    // returning an `Error` allow `clippy::result_large_err`
     #[error("error with the websocket {0}")]
     Websocket(#[from] tungstenite::Error),
     #[error("error with Alpaca's realtime API {0}")]
     Realtime(#[from] RealtimeError),
-    #[error("error with Alpaca's history API {0}")]
-    History(#[from] HistoryError),
-    #[error("error with Alpaca's order API {0}")]
-    Order(#[from] OrderError),
-    #[error("error with Alpaca's position API {0}")]
-    Position(#[from] PositionError),
-    #[error("error with Alpaca's asset API {0}")]
-    Asset(#[from] AssetError),
-    #[error("error with Alpaca's watchlist API {0}")]
-    Watchlist(#[from] WatchlistError),
+    #[error("error with Alpaca's history API {kind}{}{}", details(api), origin(request))]
+    History { kind: HistoryError, api: Option<ApiError>, request: Option<RequestInfo> },
+    #[error("error with Alpaca's order API {kind}{}{}", details(api), origin(request))]
+    Order { kind: OrderError, api: Option<ApiError>, request: Option<RequestInfo> },
+    #[error("error with Alpaca's position API {kind}{}{}", details(api), origin(request))]
+    Position { kind: PositionError, api: Option<ApiError>, request: Option<RequestInfo> },
+    #[error("error with Alpaca's asset API {kind}{}{}", details(api), origin(request))]
+    Asset { kind: AssetError, api: Option<ApiError>, request: Option<RequestInfo> },
+    #[error("error with Alpaca's watchlist API {kind}{}{}", details(api), origin(request))]
+    Watchlist { kind: WatchlistError, api: Option<ApiError>, request: Option<RequestInfo> },
+    #[error("error with Alpaca's API (status {status}){}{}", details(api), origin(request))]
+    Api { status: u16, api: Option<ApiError>, request: Option<RequestInfo> },
+    #[error("order rejected by the risk guard: {0}")]
+    Risk(#[from] RiskViolation),
     #[error("error in the conversion from/to JSON")]
     Json(#[from] serde_json::Error),
+    #[error("no frame received for {0:?}")]
+    Timeout(std::time::Duration),
+    #[error("the credentials were rejected by the server")]
+    Unauthorized,
+    #[error("invalid timeframe {0:?}")]
+    InvalidTimeFrame(String),
+    #[error("invalid option symbol {0:?}")]
+    InvalidOptionSymbol(String),
+    #[error("invalid order: {0}")]
+    InvalidOrder(String),
+    #[error("invalid span {0}")]
+    InvalidSpan(chrono::Duration),
+    #[error("invalid replay speed {0}")]
+    InvalidSpeed(f64),
+    #[error("order {0} is still open after {1:?}")]
+    OrderTimeout(String, std::time::Duration),
+    #[error("the environment variable {0} is not set")]
+    MissingEnv(&'static str),
+    #[error("invalid value {1:?} for the environment variable {0}")]
+    InvalidEnv(&'static str, String),
+    #[error("i/o error {0}")]
+    Io(#[from] std::io::Error),
     #[error("BUG: {0}")]
     AuthDataBuilder(#[from] AuthDataBuilderError),
     #[error("BUG: {0}")]
     SubscriptionDataBuilder(#[from] SubscriptionDataBuilderError),
+    #[error("BUG: {0}")]
+    OrderDataBuilder(#[from] OrderDataBuilderError),
     #[error("http error {0}")]
     HttpError(#[from] reqwest::Error),
+    #[cfg(feature = "parquet-sink")]
+    #[error("arrow error {0}")]
+    Arrow(#[from] arrow::error::ArrowError),
+    #[cfg(feature = "parquet-sink")]
+    #[error("parquet error {0}")]
+    Parquet(#[from] parquet::errors::ParquetError),
     /// Should never occur
-    #[error("BUG: Unexpected http status ({0})")]
-    Unexpected(u16),
+    #[error("BUG: Unexpected http status ({status}){}{}", details(api), origin(request))]
+    Unexpected { status: u16, api: Option<ApiError>, request: Option<RequestInfo> },
+}
+
+impl From<HistoryError> for Error {
+    fn from(e: HistoryError) -> Self {
+        Error::History { kind: e, api: None, request: None }
+    }
+}
+impl From<OrderError> for Error {
+    fn from(e: OrderError) -> Self {
+        Error::Order { kind: e, api: None, request: None }
+    }
+}
+impl From<PositionError> for Error {
+    fn from(e: PositionError) -> Self {
+        Error::Position { kind: e, api: None, request: None }
+    }
+}
+impl From<AssetError> for Error {
+    fn from(e: AssetError) -> Self {
+        Error::Asset { kind: e, api: None, request: None }
+    }
+}
+impl From<WatchlistError> for Error {
+    fn from(e: WatchlistError) -> Self {
+        Error::Watchlist { kind: e, api: None, request: None }
+    }
+}
+
+/// The explanation sent by Alpaca's trading API along with an error status.
+/// For instance: `{"code":40310000,"message":"insufficient buying power"}`
+///
+/// The rejections of the orders often carry some more details, such as the
+/// buying power of the account and the cost of the order. These are kept in
+/// the `details`, and `reason` tells the usual rejections apart.
+///
+/// ```
+/// use apca_datav2::errors::{ApiError, RejectionReason};
+///
+/// let body  = r#"{"buying_power":"100.5","code":40310000,"cost_basis":"330.2","message":"insufficient buying power"}"#;
+/// let error = serde_json::from_str::<ApiError>(body).unwrap();
+/// assert_eq!(RejectionReason::InsufficientBuyingPower, error.reason());
+/// assert_eq!(Some(100.5), error.details.buying_power);
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, thiserror::Error)]
+#[error("{message} (code {code})")]
+pub struct ApiError {
+    /// Code identifying the problem
+    pub code: u64,
+    /// Human readable explanation of the failure
+    pub message: String,
+    /// The details of the rejection of an order (if any)
+    #[serde(flatten)]
+    pub details: Box<RejectionDetails>,
+}
+
+/// The details which come along with the rejection of an order (depending on
+/// the reason of the rejection)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RejectionDetails {
+    /// The buying power of the account (when it does not cover the order)
+    #[serde(default, deserialize_with="crate::utils::option_as_f64", skip_serializing_if="Option::is_none")]
+    pub buying_power: Option<f64>,
+    /// The cost of the rejected order
+    #[serde(default, deserialize_with="crate::utils::option_as_f64", skip_serializing_if="Option::is_none")]
+    pub cost_basis: Option<f64>,
+    /// The quantity which is available to the order (when it is too large)
+    #[serde(default, deserialize_with="crate::utils::option_as_f64", skip_serializing_if="Option::is_none")]
+    pub available: Option<f64>,
+    /// The quantity held in the position
+    #[serde(default, deserialize_with="crate::utils::option_as_f64", skip_serializing_if="Option::is_none")]
+    pub existing_qty: Option<f64>,
+    /// The quantity already held by the other open orders
+    #[serde(default, deserialize_with="crate::utils::option_as_f64", skip_serializing_if="Option::is_none")]
+    pub held_for_orders: Option<f64>,
+    /// The symbol of the rejected order
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub symbol: Option<String>,
+    /// The ids of the open orders which hold the quantity
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub related_orders: Option<Vec<String>>,
+}
+impl ApiError {
+    /// Tells the usual reasons why an order is rejected apart
+    pub fn reason(&self) -> RejectionReason {
+        let message = self.message.to_lowercase();
+        if message.contains("buying power") {
+            RejectionReason::InsufficientBuyingPower
+        } else if message.contains("insufficient qty") {
+            RejectionReason::InsufficientQty
+        } else if message.contains("shortable") || message.contains("sold short") {
+            RejectionReason::NotShortable
+        } else if message.contains("not tradable") || message.contains("not active") {
+            RejectionReason::NotTradable
+        } else if message.contains("pattern day trad") {
+            RejectionReason::PatternDayTrading
+        } else {
+            RejectionReason::Other
+        }
+    }
+}
+
+/// The usual reasons why an order is rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RejectionReason {
+    /// The buying power does not cover the cost of the order
+    InsufficientBuyingPower,
+    /// The quantity to sell exceeds the available quantity
+    InsufficientQty,
+    /// The asset cannot be sold short
+    NotShortable,
+    /// The asset is not tradable (or no longer active)
+    NotTradable,
+    /// The order would flag the account as a pattern day trader
+    PatternDayTrading,
+    /// Any other reason (see the message)
+    Other,
+}
+/// Private: formats the explanation of an error (if any)
+fn details(api: &Option<ApiError>) -> String {
+    api.as_ref().map(|a| format!(": {}", a)).unwrap_or_default()
+}
+/// Reads the explanation of an error from the body of the response (if any)
+pub(crate) async fn api_error(rsp: Response) -> Option<ApiError> {
+    rsp.json::<ApiError>().await.ok()
+}
+
+/// Identifies the request which caused an error. Alpaca's support asks for
+/// the request id when investigating a failed call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestInfo {
+    /// The http method (GET, POST...)
+    pub method: String,
+    /// The path of the endpoint
+    pub path: String,
+    /// The id assigned by the server (`X-Request-ID` header), if any
+    pub request_id: Option<String>,
+}
+impl std::fmt::Display for RequestInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.method, self.path)?;
+        match &self.request_id {
+            Some(id) => write!(f, ", request id {}", id),
+            None     => Ok(()),
+        }
+    }
+}
+/// Private: formats the request which caused an error (if known)
+fn origin(request: &Option<RequestInfo>) -> String {
+    request.as_ref().map(|r| format!(" [{}]", r)).unwrap_or_default()
+}
+/// The request that caused the given response (recorded by the rest client)
+pub(crate) fn request_info(rsp: &Response) -> Option<RequestInfo> {
+    rsp.extensions().get::<RequestInfo>().cloned()
+}
+impl Error {
+    /// The explanation sent by the server along with the error status (if
+    /// any); e.g. the details of a rejected order
+    pub fn api_error(&self) -> Option<&ApiError> {
+        match self {
+            Error::History    { api, .. } |
+            Error::Order      { api, .. } |
+            Error::Position   { api, .. } |
+            Error::Asset      { api, .. } |
+            Error::Watchlist  { api, .. } |
+            Error::Api        { api, .. } |
+            Error::Unexpected { api, .. } => api.as_ref(),
+            _ => None,
+        }
+    }
+    /// The request which caused this error, when it was answered by the 
+    /// server with an error status
+    pub fn request(&self) -> Option<&RequestInfo> {
+        match self {
+            Error::History    { request, .. } |
+            Error::Order      { request, .. } |
+            Error::Position   { request, .. } |
+            Error::Asset      { request, .. } |
+            Error::Watchlist  { request, .. } |
+            Error::Api        { request, .. } |
+            Error::Unexpected { request, .. } => request.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+/// Converts the response of an endpoint which has no specific error type: 
+/// any unsuccessful status is reported as an `Error::Api`
+pub(crate) async fn status_code_to_api_error<T>(rsp: Response) -> Result<T, Error> 
+    where T: for<'de> Deserialize<'de>
+{
+    let request = request_info(&rsp);
+    let status  = rsp.status().as_u16();
+    if rsp.status().is_success() {
+        Ok(rsp.json::<T>().await?)
+    } else {
+        Err(Error::Api { status, api: api_error(rsp).await, request })
+    }
 }
 
 /*******************************************************************************
@@ -53,10 +287,10 @@ pub struct RealtimeError {
     pub code: RealtimeErrorCode,
     /// Human readable explanation of the failure
     #[serde(rename="msg")]
-    message: String,
+    pub message: String,
 }
 /// Encapsulates the protocol errors codes
-#[derive(Debug, Clone, Copy, Serialize_repr, Deserialize_repr)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize_repr, Deserialize_repr)]
 #[repr(u16)]
 pub enum RealtimeErrorCode {
     /// The message you sent to the server did not follow the specification
@@ -151,11 +385,11 @@ pub enum HistoryError {
 pub(crate) fn maybe_convert_to_hist_error(e: reqwest::Error) -> Error {
     if let Some(status) = e.status() {
         match status.as_u16() {
-            400 => Error::History(HistoryError::BadRequest),
-            403 => Error::History(HistoryError::Forbidden),
-            404 => Error::History(HistoryError::NotFound),
-            422 => Error::History(HistoryError::Unprocessable),
-            429 => Error::History(HistoryError::TooManyRequests),
+            400 => Error::History { kind: HistoryError::BadRequest, api: None, request: None },
+            403 => Error::History { kind: HistoryError::Forbidden, api: None, request: None },
+            404 => Error::History { kind: HistoryError::NotFound, api: None, request: None },
+            422 => Error::History { kind: HistoryError::Unprocessable, api: None, request: None },
+            429 => Error::History { kind: HistoryError::TooManyRequests, api: None, request: None },
             _   => Error::HttpError(e)
         }
     } else {
@@ -165,14 +399,15 @@ pub(crate) fn maybe_convert_to_hist_error(e: reqwest::Error) -> Error {
 pub(crate) async fn status_code_to_hist_error<T>(rsp: Response) -> Result<T, Error> 
     where T: for<'de> Deserialize<'de>
 {
+    let request = request_info(&rsp);
     match rsp.status().as_u16() {
         200 => Ok(rsp.json::<T>().await?),
-        400 => Err(Error::History(HistoryError::BadRequest)),
-        403 => Err(Error::History(HistoryError::Forbidden)),
-        404 => Err(Error::History(HistoryError::NotFound)),
-        422 => Err(Error::History(HistoryError::Unprocessable)),
-        429 => Err(Error::History(HistoryError::TooManyRequests)),
-        s   => Err(Error::Unexpected(s))
+        400 => Err(Error::History { kind: HistoryError::BadRequest, api: api_error(rsp).await, request }),
+        403 => Err(Error::History { kind: HistoryError::Forbidden, api: api_error(rsp).await, request }),
+        404 => Err(Error::History { kind: HistoryError::NotFound, api: api_error(rsp).await, request }),
+        422 => Err(Error::History { kind: HistoryError::Unprocessable, api: api_error(rsp).await, request }),
+        429 => Err(Error::History { kind: HistoryError::TooManyRequests, api: api_error(rsp).await, request }),
+        s   => Err(Error::Unexpected { status: s, api: api_error(rsp).await, request })
     }
 }
 
@@ -209,10 +444,10 @@ pub enum OrderError {
 pub(crate) fn maybe_convert_to_order_error(e: reqwest::Error) -> Error {
     if let Some(status) = e.status() {
         match status.as_u16() {
-            403 => Error::Order(OrderError::Forbidden),
-            404 => Error::Order(OrderError::NotFound),
-            422 => Error::Order(OrderError::Unprocessable),
-            500 => Error::Order(OrderError::InternalError),
+            403 => Error::Order { kind: OrderError::Forbidden, api: None, request: None },
+            404 => Error::Order { kind: OrderError::NotFound, api: None, request: None },
+            422 => Error::Order { kind: OrderError::Unprocessable, api: None, request: None },
+            500 => Error::Order { kind: OrderError::InternalError, api: None, request: None },
             _   => Error::HttpError(e)
         }
     } else {
@@ -222,15 +457,16 @@ pub(crate) fn maybe_convert_to_order_error(e: reqwest::Error) -> Error {
 pub(crate) async fn status_code_to_order_error<T>(rsp: Response) -> Result<T, Error> 
     where T: for<'de> Deserialize<'de>
 {
+    let request = request_info(&rsp);
     match rsp.status().as_u16() {
         200 => Ok(rsp.json::<T>().await?),
         204 => Ok(rsp.json::<T>().await?),
         207 => Ok(rsp.json::<T>().await?),
-        403 => Err(Error::Order(OrderError::Forbidden)),
-        404 => Err(Error::Order(OrderError::NotFound)),
-        422 => Err(Error::Order(OrderError::Unprocessable)),
-        500 => Err(Error::Order(OrderError::InternalError)),
-        s   => Err(Error::Unexpected(s)),
+        403 => Err(Error::Order { kind: OrderError::Forbidden, api: api_error(rsp).await, request }),
+        404 => Err(Error::Order { kind: OrderError::NotFound, api: api_error(rsp).await, request }),
+        422 => Err(Error::Order { kind: OrderError::Unprocessable, api: api_error(rsp).await, request }),
+        500 => Err(Error::Order { kind: OrderError::InternalError, api: api_error(rsp).await, request }),
+        s   => Err(Error::Unexpected { status: s, api: api_error(rsp).await, request }),
     }
 }
 
@@ -264,9 +500,9 @@ pub enum PositionError {
 pub(crate) fn maybe_convert_to_position_error(e: reqwest::Error) -> Error {
    if let Some(status) = e.status() {
        match status.as_u16() {
-           403 => Error::Position(PositionError::Forbidden),
-           404 => Error::Position(PositionError::NotFound),
-           500 => Error::Position(PositionError::InternalError),
+           403 => Error::Position { kind: PositionError::Forbidden, api: None, request: None },
+           404 => Error::Position { kind: PositionError::NotFound, api: None, request: None },
+           500 => Error::Position { kind: PositionError::InternalError, api: None, request: None },
            _   => Error::HttpError(e)
        }
    } else {
@@ -276,14 +512,15 @@ pub(crate) fn maybe_convert_to_position_error(e: reqwest::Error) -> Error {
 pub(crate) async fn status_code_to_position_error<T>(rsp: Response) -> Result<T, Error> 
    where T: for<'de> Deserialize<'de>
 {
+   let request = request_info(&rsp);
    match rsp.status().as_u16() {
        200 => Ok(rsp.json::<T>().await?),
        204 => Ok(rsp.json::<T>().await?),
        207 => Ok(rsp.json::<T>().await?),
-       403 => Err(Error::Position(PositionError::Forbidden)),
-       404 => Err(Error::Position(PositionError::NotFound)),
-       500 => Err(Error::Position(PositionError::InternalError)),
-       s   => Err(Error::Unexpected(s)),
+       403 => Err(Error::Position { kind: PositionError::Forbidden, api: api_error(rsp).await, request }),
+       404 => Err(Error::Position { kind: PositionError::NotFound, api: api_error(rsp).await, request }),
+       500 => Err(Error::Position { kind: PositionError::InternalError, api: api_error(rsp).await, request }),
+       s   => Err(Error::Unexpected { status: s, api: api_error(rsp).await, request }),
    }
 }
 
@@ -316,9 +553,9 @@ pub enum AssetError {
 pub(crate) fn maybe_convert_to_asset_error(e: reqwest::Error) -> Error {
     if let Some(status) = e.status() {
         match status.as_u16() {
-            403 => Error::Asset(AssetError::Forbidden),
-            404 => Error::Asset(AssetError::NotFound),
-            500 => Error::Asset(AssetError::InternalError),
+            403 => Error::Asset { kind: AssetError::Forbidden, api: None, request: None },
+            404 => Error::Asset { kind: AssetError::NotFound, api: None, request: None },
+            500 => Error::Asset { kind: AssetError::InternalError, api: None, request: None },
             _   => Error::HttpError(e)
         }
     } else {
@@ -328,14 +565,15 @@ pub(crate) fn maybe_convert_to_asset_error(e: reqwest::Error) -> Error {
  pub(crate) async fn status_code_to_asset_error<T>(rsp: Response) -> Result<T, Error> 
     where T: for<'de> Deserialize<'de>
  {
+    let request = request_info(&rsp);
     match rsp.status().as_u16() {
         200 => Ok(rsp.json::<T>().await?),
         204 => Ok(rsp.json::<T>().await?),
         207 => Ok(rsp.json::<T>().await?),
-        403 => Err(Error::Asset(AssetError::Forbidden)),
-        404 => Err(Error::Asset(AssetError::NotFound)),
-        500 => Err(Error::Asset(AssetError::InternalError)),
-        s   => Err(Error::Unexpected(s)),
+        403 => Err(Error::Asset { kind: AssetError::Forbidden, api: api_error(rsp).await, request }),
+        404 => Err(Error::Asset { kind: AssetError::NotFound, api: api_error(rsp).await, request }),
+        500 => Err(Error::Asset { kind: AssetError::InternalError, api: api_error(rsp).await, request }),
+        s   => Err(Error::Unexpected { status: s, api: api_error(rsp).await, request }),
     }
  }
 
@@ -364,8 +602,8 @@ pub enum WatchlistError {
 pub(crate) fn maybe_convert_to_watchlist_error(e: reqwest::Error) -> Error {
     if let Some(status) = e.status() {
         match status.as_u16() {
-            404 => Error::Watchlist(WatchlistError::NotFound),
-            422 => Error::Watchlist(WatchlistError::Unprocessable),
+            404 => Error::Watchlist { kind: WatchlistError::NotFound, api: None, request: None },
+            422 => Error::Watchlist { kind: WatchlistError::Unprocessable, api: None, request: None },
             _   => Error::HttpError(e)
         }
     } else {
@@ -375,23 +613,117 @@ pub(crate) fn maybe_convert_to_watchlist_error(e: reqwest::Error) -> Error {
  pub(crate) async fn status_code_to_watchlist_error<T>(rsp: Response) -> Result<T, Error> 
     where T: for<'de> Deserialize<'de>
  {
+    let request = request_info(&rsp);
     match rsp.status().as_u16() {
         200 => Ok(rsp.json::<T>().await?),
         204 => Ok(rsp.json::<T>().await?),
         207 => Ok(rsp.json::<T>().await?),
-        404 => Err(Error::Watchlist(WatchlistError::NotFound)),
-        422 => Err(Error::Watchlist(WatchlistError::Unprocessable)),
-        s   => Err(Error::Unexpected(s)),
+        404 => Err(Error::Watchlist { kind: WatchlistError::NotFound, api: api_error(rsp).await, request }),
+        422 => Err(Error::Watchlist { kind: WatchlistError::Unprocessable, api: api_error(rsp).await, request }),
+        s   => Err(Error::Unexpected { status: s, api: api_error(rsp).await, request }),
     }
  }
  pub(crate) async fn status_code_to_watchlist_error_noparse(rsp: Response) -> Result<(), Error> 
  {
+    let request = request_info(&rsp);
     match rsp.status().as_u16() {
         200 => Ok(()),
         204 => Ok(()),
         207 => Ok(()),
-        404 => Err(Error::Watchlist(WatchlistError::NotFound)),
-        422 => Err(Error::Watchlist(WatchlistError::Unprocessable)),
-        s   => Err(Error::Unexpected(s)),
+        404 => Err(Error::Watchlist { kind: WatchlistError::NotFound, api: api_error(rsp).await, request }),
+        422 => Err(Error::Watchlist { kind: WatchlistError::Unprocessable, api: api_error(rsp).await, request }),
+        s   => Err(Error::Unexpected { status: s, api: api_error(rsp).await, request }),
     }
  }
+
+/*******************************************************************************
+ * RISK GUARD SPECIFIC STUFFS
+ ******************************************************************************/
+
+/// The reason why an order was rejected by the risk guard
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum RiskViolation {
+    /// The notional value of the order exceeds the per-order limit
+    #[error("order notional {notional} exceeds the limit of {limit}")]
+    OrderNotional { notional: f64, limit: f64 },
+    /// The order would bring the gross (long + short) exposure above its limit
+    #[error("gross exposure {exposure} would exceed the limit of {limit}")]
+    GrossExposure { exposure: f64, limit: f64 },
+    /// The order would bring the short exposure above its limit
+    #[error("short exposure {exposure} would exceed the limit of {limit}")]
+    ShortExposure { exposure: f64, limit: f64 },
+    /// The order would bring the leverage (gross exposure / equity) above 
+    /// the allowed multiplier
+    #[error("leverage {leverage} would exceed the limit of {limit}")]
+    Leverage { leverage: f64, limit: f64 },
+    /// The notional value of the order cannot be estimated because no price 
+    /// is known for the symbol
+    #[error("no reference price is known for {0}")]
+    UnknownPrice(String),
+    /// The trading of the symbol is currently halted
+    #[error("trading is halted on {0}")]
+    Halted(String),
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use crate::entities::OrderSide;
+    use crate::errors::{ApiError, Error, OrderError, RejectionReason};
+    use crate::orders::PlaceOrderRequestBuilder;
+    use crate::testing::fake_client;
+    use crate::transport::FakeTransport;
+
+    #[tokio::test]
+    async fn test_api_error_is_attached() {
+        let body      = r#"{"code":40310000,"message":"insufficient buying power"}"#;
+        let transport = FakeTransport::new().respond("POST", "/v2/orders", 403, body);
+        let client    = fake_client(&transport);
+        let order     = PlaceOrderRequestBuilder::default()
+            .symbol("AAPL".to_string()).qty(1.0).side(OrderSide::Buy)
+            .build().unwrap();
+
+        match client.place_order(&order).await {
+            Err(e@Error::Order { kind: OrderError::Forbidden, api: Some(ApiError { code: 40310000, .. }), .. }) => {
+                assert_eq!("fake-0", e.request().unwrap().request_id.as_deref().unwrap());
+                assert!(e.to_string().ends_with(": insufficient buying power (code 40310000) [POST /v2/orders, request id fake-0]"));
+            },
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+    #[tokio::test]
+    async fn test_api_error_is_attached_to_unexpected_statuses() {
+        let body      = r#"{"code":40110000,"message":"request is not authorized"}"#;
+        let transport = FakeTransport::new().respond("POST", "/v2/orders", 401, body);
+        let client    = fake_client(&transport);
+        let order     = PlaceOrderRequestBuilder::default()
+            .symbol("AAPL".to_string()).qty(1.0).side(OrderSide::Buy)
+            .build().unwrap();
+
+        let error = client.place_order(&order).await.unwrap_err();
+        assert!(matches!(error, Error::Unexpected { status: 401, api: Some(ApiError { code: 40110000, .. }), .. }));
+        assert!(error.to_string().ends_with(": request is not authorized (code 40110000) [POST /v2/orders, request id fake-0]"));
+    }
+    #[tokio::test]
+    async fn test_rejection_details_are_attached() {
+        let body      = r#"{"available":"0","code":40310000,"existing_qty":"10","held_for_orders":"10",
+            "message":"insufficient qty available for order (requested: 10, available: 0)",
+            "related_orders":["810f77c9"],"symbol":"AAPL"}"#;
+        let transport = FakeTransport::new().respond("POST", "/v2/orders", 403, body);
+        let client    = fake_client(&transport);
+        let order     = PlaceOrderRequestBuilder::default()
+            .symbol("AAPL".to_string()).qty(10.0).side(OrderSide::Sell)
+            .build().unwrap();
+
+        let error = client.place_order(&order).await.unwrap_err();
+        let api   = error.api_error().unwrap();
+        assert_eq!(RejectionReason::InsufficientQty, api.reason());
+        assert_eq!(Some(0.0), api.details.available);
+        assert_eq!(Some(10.0), api.details.held_for_orders);
+        assert_eq!(Some(vec!["810f77c9".to_string()]), api.details.related_orders);
+        assert_eq!(None, api.details.buying_power);
+    }
+}
@@ -0,0 +1,141 @@
+//! This module provides a source agnostic view on the market data.
+//!
+//! Both the realtime and historical APIs convey trades, quotes and bars; but
+//! they do so using different message types. The `MarketEvent` unifies these
+//! so that strategy code can be written once and be fed either by the
+//! realtime or the historical data.
+
+use std::convert::TryFrom;
+
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::entities::{BarData, QuoteData, TradeData};
+use crate::historical::{MultiBars, MultiQuotes, MultiTrades, SingleBar, SingleQuote, SingleTrade};
+use crate::realtime::{DataPoint, Response};
+
+/// A market event regardless of the API it originates from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum MarketEvent {
+    /// A trade was executed
+    Trade(DataPoint<TradeData>),
+    /// A quote (NBBO) was published
+    Quote(DataPoint<QuoteData>),
+    /// A bar (OHLC) was published
+    Bar(DataPoint<BarData>),
+}
+impl MarketEvent {
+    /// The symbol this event relates to
+    pub fn symbol(&self) -> &str {
+        match self {
+            MarketEvent::Trade(x) => &x.symbol,
+            MarketEvent::Quote(x) => &x.symbol,
+            MarketEvent::Bar(x)   => &x.symbol,
+        }
+    }
+    /// The time at which this event occurred
+    pub fn timestamp(&self) -> DateTime<Utc> {
+        match self {
+            MarketEvent::Trade(x) => x.data.timestamp,
+            MarketEvent::Quote(x) => x.data.timestamp,
+            MarketEvent::Bar(x)   => x.data.timestamp,
+        }
+    }
+}
+
+// --- FROM THE REALTIME API --------------------------------------------------
+impl From<DataPoint<TradeData>> for MarketEvent {
+    fn from(x: DataPoint<TradeData>) -> Self {
+        MarketEvent::Trade(x)
+    }
+}
+impl From<DataPoint<QuoteData>> for MarketEvent {
+    fn from(x: DataPoint<QuoteData>) -> Self {
+        MarketEvent::Quote(x)
+    }
+}
+impl From<DataPoint<BarData>> for MarketEvent {
+    fn from(x: DataPoint<BarData>) -> Self {
+        MarketEvent::Bar(x)
+    }
+}
+/// Only the data points are market events. The control messages are given
+/// back as is.
+impl TryFrom<Response> for MarketEvent {
+    type Error = Response;
+    fn try_from(r: Response) -> Result<Self, Response> {
+        match r {
+            Response::Trade(x) => Ok(MarketEvent::Trade(x)),
+            Response::Quote(x) => Ok(MarketEvent::Quote(x)),
+            Response::Bar(x)   => Ok(MarketEvent::Bar(x)),
+            other              => Err(other),
+        }
+    }
+}
+
+// --- FROM THE HISTORICAL API ------------------------------------------------
+impl From<SingleTrade> for MarketEvent {
+    fn from(x: SingleTrade) -> Self {
+        MarketEvent::Trade(x.into())
+    }
+}
+impl From<SingleQuote> for MarketEvent {
+    fn from(x: SingleQuote) -> Self {
+        MarketEvent::Quote(x.into())
+    }
+}
+impl From<SingleBar> for MarketEvent {
+    fn from(x: SingleBar) -> Self {
+        MarketEvent::Bar(x.into())
+    }
+}
+impl From<MultiTrades> for Vec<MarketEvent> {
+    fn from(page: MultiTrades) -> Self {
+        let symbol = page.symbol;
+        page.trades.into_iter()
+            .map(|data| MarketEvent::Trade(DataPoint { symbol: symbol.clone(), data }))
+            .collect()
+    }
+}
+impl From<MultiQuotes> for Vec<MarketEvent> {
+    fn from(page: MultiQuotes) -> Self {
+        let symbol = page.symbol;
+        page.quotes.into_iter()
+            .map(|data| MarketEvent::Quote(DataPoint { symbol: symbol.clone(), data }))
+            .collect()
+    }
+}
+impl From<MultiBars> for Vec<MarketEvent> {
+    fn from(page: MultiBars) -> Self {
+        let symbol = page.symbol;
+        page.bars.into_iter()
+            .map(|data| MarketEvent::Bar(DataPoint { symbol: symbol.clone(), data }))
+            .collect()
+    }
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use crate::events::MarketEvent;
+    use crate::historical::MultiBars;
+    use crate::realtime::Response;
+
+    #[test]
+    fn test_same_event_from_both_sources() {
+        let txt = r#"{"T":"b","S":"SPY","o":388.985,"h":389.13,"l":388.975,"c":389.12,"v":49378,"t":"2021-02-22T19:15:00Z"}"#;
+        let live = MarketEvent::try_from(serde_json::from_str::<Response>(txt).unwrap()).unwrap();
+
+        let txt = r#"{"bars":[{"t":"2021-02-22T19:15:00Z","o":388.985,"h":389.13,"l":388.975,"c":389.12,"v":49378}],"symbol":"SPY","next_page_token":null}"#;
+        let hist: Vec<MarketEvent> = serde_json::from_str::<MultiBars>(txt).unwrap().into();
+
+        assert_eq!(1, hist.len());
+        assert_eq!(live.symbol(), hist[0].symbol());
+        assert_eq!(live.timestamp(), hist[0].timestamp());
+    }
+}
@@ -0,0 +1,200 @@
+//! This module is a development tool which helps you generate realistic test
+//! data for your own symbols.
+//!
+//! It downloads a small sample of historical data and scrubs it into fixture
+//! files which you can use in your unit tests (or serve from a mock server)
+//! without committing the licensed raw data. The scrubbing consists in:
+//! * replacing the symbol with an alias,
+//! * shifting all timestamps so that the sample starts at a fixed origin
+//!   (the intervals between data points are preserved),
+//! * rescaling all prices so that the reference price becomes 100.0 (the
+//!   relative moves are preserved),
+//! * renumbering the trade identifiers.
+//!
+//! The fixture files hold one page of data each (`{alias}.bars.json`,
+//! `{alias}.trades.json` and `{alias}.quotes.json`) in the very format of the
+//! historical API responses; so they can be deserialized as `MultiBars`,
+//! `MultiTrades` and `MultiQuotes`.
+//!
+//! ```no_run
+//! # async fn example(client: apca_datav2::rest::Client) -> Result<(), apca_datav2::errors::Error> {
+//! use chrono::{TimeZone, Utc};
+//! use apca_datav2::fixtures::FixtureGenerator;
+//! use apca_datav2::historical::TimeFrame;
+//!
+//! let start = Utc.with_ymd_and_hms(2021, 8, 2, 14, 30, 0).unwrap();
+//! let end   = Utc.with_ymd_and_hms(2021, 8, 2, 15, 30, 0).unwrap();
+//! FixtureGenerator::new(&client, "tests/fixtures")
+//!     .generate("AAPL", "TEST", start, end, TimeFrame::Minutes(1), 50)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{fs::{self, File}, path::{Path, PathBuf}};
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use serde::Serialize;
+
+use crate::entities::{BarData, QuoteData, TradeData};
+use crate::errors::Error;
+use crate::historical::{MultiBars, MultiQuotes, MultiTrades, TimeFrame};
+use crate::rest::Client;
+
+/// The price of the reference data point once it has been rescaled
+pub const BASE_PRICE: f64 = 100.0;
+
+/// Scrubs the data so that it can be shared without disclosing the raw data
+#[derive(Debug, Clone)]
+pub struct Scrubber {
+    /// The symbol replacing the actual one
+    pub alias: String,
+    /// The duration that is added to each timestamp
+    pub shift: Duration,
+    /// The factor by which each price is multiplied
+    pub factor: f64,
+}
+impl Scrubber {
+    /// Creates a scrubber which moves the reference time to the default
+    /// origin (2000-01-03 14:30 UTC) and rescales the reference price to
+    /// `BASE_PRICE`
+    pub fn new(alias: &str, reference_time: DateTime<Utc>, reference_price: f64) -> Self {
+        let origin = Utc.with_ymd_and_hms(2000, 1, 3, 14, 30, 0).unwrap();
+        Self {
+            alias : alias.to_string(),
+            shift : origin - reference_time,
+            factor: if reference_price > 0.0 { BASE_PRICE / reference_price } else { 1.0 },
+        }
+    }
+    /// Scrubs a page of bars
+    pub fn bars(&self, page: MultiBars) -> MultiBars {
+        MultiBars {
+            bars  : page.bars.into_iter().map(|b| self.bar(b)).collect(),
+            symbol: self.alias.clone(),
+            token : None,
+        }
+    }
+    /// Scrubs a page of trades
+    pub fn trades(&self, page: MultiTrades) -> MultiTrades {
+        MultiTrades {
+            trades: page.trades.into_iter().enumerate().map(|(i, t)| self.trade(i, t)).collect(),
+            symbol: self.alias.clone(),
+            token : None,
+        }
+    }
+    /// Scrubs a page of quotes
+    pub fn quotes(&self, page: MultiQuotes) -> MultiQuotes {
+        MultiQuotes {
+            quotes: page.quotes.into_iter().map(|q| self.quote(q)).collect(),
+            symbol: self.alias.clone(),
+            token : None,
+        }
+    }
+
+    fn bar(&self, bar: BarData) -> BarData {
+        BarData {
+            open_price : self.price(bar.open_price),
+            high_price : self.price(bar.high_price),
+            low_price  : self.price(bar.low_price),
+            close_price: self.price(bar.close_price),
+            volume     : bar.volume,
+            timestamp  : bar.timestamp + self.shift,
+        }
+    }
+    fn trade(&self, id: usize, trade: TradeData) -> TradeData {
+        TradeData {
+            trade_id   : id as i64 + 1,
+            trade_price: self.price(trade.trade_price),
+            timestamp  : trade.timestamp + self.shift,
+            ..trade
+        }
+    }
+    fn quote(&self, quote: QuoteData) -> QuoteData {
+        QuoteData {
+            ask_price: self.price(quote.ask_price),
+            bid_price: self.price(quote.bid_price),
+            timestamp: quote.timestamp + self.shift,
+            ..quote
+        }
+    }
+    /// Rescales a price (rounded to the cent)
+    fn price(&self, price: f64) -> f64 {
+        (price * self.factor * 100.0).round() / 100.0
+    }
+}
+
+/// Downloads samples of historical data and turns them into fixtures
+pub struct FixtureGenerator<'a> {
+    client: &'a Client,
+    dir   : PathBuf,
+}
+impl <'a> FixtureGenerator<'a> {
+    /// Creates a generator which writes the fixtures in the given directory
+    pub fn new<P: AsRef<Path>>(client: &'a Client, dir: P) -> Self {
+        Self { client, dir: dir.as_ref().to_path_buf() }
+    }
+    /// Downloads (at most `limit`) bars, trades and quotes of `symbol` and
+    /// writes them as fixtures for the symbol `alias`. The reference price
+    /// used for the rescaling is the first trade price (or the first bar open
+    /// price when there is no trade).
+    pub async fn generate(&self, symbol: &str, alias: &str, start: DateTime<Utc>, end: DateTime<Utc>, timeframe: TimeFrame, limit: usize)
+        -> Result<Vec<PathBuf>, Error>
+    {
+        let bars   = self.client.bars_paged(symbol, start, end, timeframe, Some(limit), None).await?;
+        let trades = self.client.trades_paged(symbol, start, end, Some(limit), None).await?;
+        let quotes = self.client.quotes_paged(symbol, start, end, Some(limit), None).await?;
+
+        let reference = trades.trades.first().map(|t| t.trade_price)
+            .or_else(|| bars.bars.first().map(|b| b.open_price))
+            .unwrap_or(BASE_PRICE);
+        let scrubber  = Scrubber::new(alias, start, reference);
+
+        fs::create_dir_all(&self.dir)?;
+        Ok(vec![
+            self.write(alias, "bars",   &scrubber.bars(bars))?,
+            self.write(alias, "trades", &scrubber.trades(trades))?,
+            self.write(alias, "quotes", &scrubber.quotes(quotes))?,
+        ])
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn write<T: Serialize>(&self, alias: &str, kind: &str, page: &T) -> Result<PathBuf, Error> {
+        let path = self.dir.join(format!("{}.{}.json", alias, kind));
+        serde_json::to_writer_pretty(File::create(&path)?, page)?;
+        Ok(path)
+    }
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use crate::fixtures::Scrubber;
+    use crate::historical::MultiTrades;
+
+    #[test]
+    fn test_scrub_trades() {
+        let txt = r#"{"trades":[
+            {"t":"2021-02-06T13:04:56.334320128Z","x":"C","p":387.62,"s":100,"c":[" ","T"],"i":52983525029461,"z":"B"},
+            {"t":"2021-02-06T13:09:42.325484032Z","x":"C","p":387.69,"s":100,"c":[" ","T"],"i":52983525033813,"z":"B"}],
+            "symbol":"SPY","next_page_token":"MjAyMS0wMi0wNlQxMzowOTo0Mlo7MQ=="}"#;
+        let page     = serde_json::from_str::<MultiTrades>(txt).unwrap();
+        let start    = Utc.with_ymd_and_hms(2021, 2, 6, 13, 0, 0).unwrap();
+        let scrubbed = Scrubber::new("TEST", start, 387.62).trades(page);
+
+        assert_eq!("TEST", scrubbed.symbol);
+        assert!(scrubbed.token.is_none());
+        assert_eq!(1,      scrubbed.trades[0].trade_id);
+        assert_eq!(100.0,  scrubbed.trades[0].trade_price);
+        assert_eq!(100.02, scrubbed.trades[1].trade_price);
+        assert_eq!(Utc.with_ymd_and_hms(2000, 1, 3, 14, 34, 56).unwrap() + Duration::nanoseconds(334320128), scrubbed.trades[0].timestamp);
+
+        // the scrubbed data can be read back
+        let txt = serde_json::to_string(&scrubbed).unwrap();
+        assert!(serde_json::from_str::<MultiTrades>(&txt).is_ok());
+    }
+}
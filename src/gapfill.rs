@@ -0,0 +1,226 @@
+//! This module aligns the bars of a symbol on the sessions of the market
+//! calendar.
+//!
+//! Alpaca only returns a bar for the intervals during which the symbol has
+//! traded. Hence, the bars of two symbols seldom line up; which gets in the
+//! way of any backtest involving several symbols. The `GapFill` iterator
+//! yields exactly one `Slot` per interval of the regular sessions of the
+//! given trading days: either the bar which was received for that interval,
+//! or a filler. Depending on the `FillPolicy`, the filler is a flat bar
+//! carrying the last close forward (with a zero volume), or an explicit
+//! `Missing` marker.
+//!
+//! The intraday timeframes yield one slot per interval overlapping the
+//! regular session of each day (the bars of the extended hours are skipped).
+//! The daily bars yield one slot per trading day, the weekly (resp. monthly)
+//! bars yield one slot per first trading day of each week (resp. month).
+//!
+//! ```no_run
+//! # async fn example(client: apca_datav2::rest::Client) -> Result<(), apca_datav2::errors::Error> {
+//! use chrono::{NaiveDate, Utc};
+//! use futures::TryStreamExt;
+//! use apca_datav2::gapfill::{FillPolicy, GapFill};
+//! use apca_datav2::historical::TimeFrame;
+//!
+//! let first = NaiveDate::from_ymd_opt(2021, 11, 1).unwrap();
+//! let last  = NaiveDate::from_ymd_opt(2021, 11, 30).unwrap();
+//! let days  = client.calendar(first, last).await?;
+//! let start = days[0].open_at();
+//! let end   = days[days.len() - 1].close_at();
+//!
+//! let bars  = client.bars("AAPL", start, end, TimeFrame::Minutes(1), None).try_collect::<Vec<_>>().await?;
+//! for slot in GapFill::new(bars, &days, TimeFrame::Minutes(1), FillPolicy::CarryForward)? {
+//!     println!("{:?}", slot);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::iter::Peekable;
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc, Weekday};
+
+use crate::{calendar::TradingDay, entities::BarData, errors::Error, historical::TimeFrame};
+
+/// What to yield for the intervals without any bar
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FillPolicy {
+    /// A flat bar at the last close (with a zero volume); the intervals
+    /// preceding the first bar are `Missing` nonetheless
+    CarryForward,
+    /// A `Missing` marker
+    Missing,
+}
+
+/// One expected interval of the sessions
+#[derive(Debug, Clone)]
+pub enum Slot {
+    /// The bar which was received for this interval
+    Bar(BarData),
+    /// A flat bar carrying the last close forward
+    Filled(BarData),
+    /// No bar was received for the interval starting at the given time
+    Missing(DateTime<Utc>),
+}
+impl Slot {
+    /// The time at which the interval starts
+    pub fn timestamp(&self) -> DateTime<Utc> {
+        match self {
+            Slot::Bar(bar)    => bar.timestamp,
+            Slot::Filled(bar) => bar.timestamp,
+            Slot::Missing(t)  => *t,
+        }
+    }
+    /// The bar of this slot (filled or not), if any
+    pub fn bar(&self) -> Option<&BarData> {
+        match self {
+            Slot::Bar(bar) | Slot::Filled(bar) => Some(bar),
+            Slot::Missing(_)                   => None,
+        }
+    }
+    /// Returns true iff no bar was received for this interval
+    pub fn is_gap(&self) -> bool {
+        !matches!(self, Slot::Bar(_))
+    }
+}
+
+/// Iterator yielding one slot per expected interval of the sessions
+pub struct GapFill<I: Iterator<Item=BarData>> {
+    bars  : Peekable<I>,
+    slots : std::vec::IntoIter<DateTime<Utc>>,
+    policy: FillPolicy,
+    last  : Option<f64>,
+}
+impl<I: Iterator<Item=BarData>> GapFill<I> {
+    /// Aligns the given bars (in chronological order) on the sessions of the
+    /// given trading days. This fails when the timeframe is not valid.
+    #[allow(clippy::result_large_err)]
+    pub fn new<B: IntoIterator<IntoIter=I>>(bars: B, days: &[TradingDay], timeframe: TimeFrame, policy: FillPolicy) -> Result<Self, Error> {
+        Ok(Self {
+            bars  : bars.into_iter().peekable(),
+            slots : expected_slots(days, timeframe)?.into_iter(),
+            policy,
+            last  : None,
+        })
+    }
+}
+impl<I: Iterator<Item=BarData>> Iterator for GapFill<I> {
+    type Item = Slot;
+
+    fn next(&mut self) -> Option<Slot> {
+        let slot = self.slots.next()?;
+        while self.bars.next_if(|b| b.timestamp < slot).is_some() {}
+
+        if let Some(bar) = self.bars.next_if(|b| b.timestamp == slot) {
+            self.last = Some(bar.close_price);
+            return Some(Slot::Bar(bar));
+        }
+        match (self.policy, self.last) {
+            (FillPolicy::CarryForward, Some(close)) => Some(Slot::Filled(BarData {
+                open_price: close, high_price: close, low_price: close, close_price: close,
+                volume: 0, timestamp: slot
+            })),
+            _ => Some(Slot::Missing(slot)),
+        }
+    }
+}
+
+/// The start of every interval of the given timeframe during the regular
+/// sessions of the given trading days. This fails when the timeframe is not
+/// valid (e.g. `Minutes(0)`).
+#[allow(clippy::result_large_err)]
+pub fn expected_slots(days: &[TradingDay], timeframe: TimeFrame) -> Result<Vec<DateTime<Utc>>, Error> {
+    let timeframe = timeframe.validate()?;
+    let mut slots = vec![];
+    let mut previous: Option<&TradingDay> = None;
+    for day in days {
+        match timeframe {
+            TimeFrame::Minutes(_) | TimeFrame::Hours(_) => {
+                // the bars are aligned on the multiples of the timeframe
+                let step  = timeframe.duration();
+                let close = day.close_at();
+                let open  = day.open_at();
+                let mut t = open - Duration::seconds(open.timestamp().rem_euclid(step.num_seconds()));
+                while t < close {
+                    slots.push(t);
+                    t += step;
+                }
+            },
+            TimeFrame::Day    => slots.push(day.start_at()),
+            TimeFrame::Week | TimeFrame::Months(_) => if previous.is_none_or(|p| period(p.date, timeframe) != period(day.date, timeframe)) {
+                slots.push(day.start_at());
+            },
+        }
+        previous = Some(day);
+    }
+    Ok(slots)
+}
+
+/// The index of the week (or of the group of n months, counted from january)
+/// which contains the given date
+fn period(date: NaiveDate, timeframe: TimeFrame) -> i64 {
+    match timeframe {
+        TimeFrame::Months(n) => (date.year() as i64 * 12 + date.month0() as i64).div_euclid(n as i64),
+        _ => date.week(Weekday::Mon).first_day().num_days_from_ce() as i64,
+    }
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveTime, TimeZone, Utc};
+
+    use crate::calendar::TradingDay;
+    use crate::entities::BarData;
+    use crate::gapfill::{FillPolicy, GapFill, Slot, expected_slots};
+    use crate::historical::TimeFrame;
+
+    #[test]
+    fn test_gaps_are_filled() {
+        let day = TradingDay {
+            date : NaiveDate::from_ymd_opt(2021, 11, 26).unwrap(),
+            open : NaiveTime::from_hms_opt(9, 30, 0).unwrap(),
+            close: NaiveTime::from_hms_opt(13, 0, 0).unwrap(),
+        };
+        let bar = |h, m, close| BarData {
+            open_price: close, high_price: close, low_price: close, close_price: close,
+            volume: 10, timestamp: Utc.with_ymd_and_hms(2021, 11, 26, h, m, 0).unwrap()
+        };
+        // one pre-market bar, then the bars of 15:00 and 17:00 (UTC)
+        let bars = vec![bar(13, 0, 1.0), bar(15, 0, 2.0), bar(17, 0, 3.0)];
+
+        let slots = GapFill::new(bars.clone(), std::slice::from_ref(&day), TimeFrame::Hours(1), FillPolicy::CarryForward).unwrap().collect::<Vec<_>>();
+        assert_eq!(4, slots.len());
+        assert!(matches!(slots[0], Slot::Missing(_)));
+        assert!(matches!(slots[1], Slot::Bar(_)));
+        assert!(matches!(&slots[2], Slot::Filled(b) if b.close_price == 2.0 && b.volume == 0));
+        assert_eq!(Utc.with_ymd_and_hms(2021, 11, 26, 17, 0, 0).unwrap(), slots[3].timestamp());
+        assert!(!slots[3].is_gap());
+
+        let slots = GapFill::new(bars.clone(), std::slice::from_ref(&day), TimeFrame::Hours(1), FillPolicy::Missing).unwrap().collect::<Vec<_>>();
+        assert_eq!(2, slots.iter().filter(|s| s.is_gap()).count());
+
+        // an empty interval would never move past the close
+        assert!(GapFill::new(bars, &[day], TimeFrame::Minutes(0), FillPolicy::Missing).is_err());
+    }
+    #[test]
+    fn test_multi_month_slots() {
+        let day = |y, m, d| TradingDay {
+            date : NaiveDate::from_ymd_opt(y, m, d).unwrap(),
+            open : NaiveTime::from_hms_opt(9, 30, 0).unwrap(),
+            close: NaiveTime::from_hms_opt(16, 0, 0).unwrap(),
+        };
+        let days = [day(2021, 1, 4), day(2021, 2, 1), day(2021, 3, 1), day(2021, 4, 1), day(2022, 4, 1)];
+
+        // one slot per quarter, the same month of the next year included
+        let slots = expected_slots(&days, TimeFrame::Months(3)).unwrap();
+        assert_eq!(vec![days[0].start_at(), days[3].start_at(), days[4].start_at()], slots);
+        // the weeks of different years are not merged either
+        let days  = [day(2021, 1, 4), day(2021, 1, 8), day(2022, 1, 7)];
+        let slots = expected_slots(&days, TimeFrame::Week).unwrap();
+        assert_eq!(vec![days[0].start_at(), days[2].start_at()], slots);
+    }
+}
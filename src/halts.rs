@@ -0,0 +1,192 @@
+//! This module makes the order pipeline aware of the trading halts.
+//!
+//! The `HaltGate` sits in front of the [`RiskGuard`](crate::risk::RiskGuard)
+//! and follows the messages of the realtime `statuses` channel. As long as
+//! the trading of a symbol is halted (or paused), the orders for that symbol
+//! are not sent to Alpaca. Depending on the `HaltPolicy`, they are either
+//! held locally until trading resumes, or rejected right away.
+//!
+//! ```no_run
+//! # async fn example(client: apca_datav2::rest::Client, guard: apca_datav2::risk::RiskGuard, order: apca_datav2::orders::PlaceOrderRequest) -> Result<(), apca_datav2::errors::Error> {
+//! use futures::StreamExt;
+//! use apca_datav2::halts::{HaltGate, HaltPolicy, Submission};
+//! use apca_datav2::realtime::{Client, Response, Source};
+//!
+//! let mut gate = HaltGate::new(guard, HaltPolicy::Queue);
+//! if let Submission::Held = gate.place_order(&client, order).await? {
+//!     println!("the order is held until trading resumes");
+//! }
+//!
+//! let stream = Client::new(Source::SIP).await?.stream();
+//! futures::pin_mut!(stream);
+//! while let Some(response) = stream.next().await {
+//!     if let Response::Status(status) = response {
+//!         for event in gate.on_status(&client, &status).await {
+//!             println!("{:?}", event);
+//!         }
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+
+use crate::entities::{OrderData, StatusData};
+use crate::errors::{Error, RiskViolation};
+use crate::orders::PlaceOrderRequest;
+use crate::realtime::DataPoint;
+use crate::rest::Client;
+use crate::risk::RiskGuard;
+
+/// What to do with the orders placed while the trading of their symbol is 
+/// halted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HaltPolicy {
+    /// Hold the orders locally and send them when trading resumes
+    Queue,
+    /// Reject the orders (with a `RiskViolation::Halted` error)
+    Reject,
+}
+
+/// The outcome of an order submission
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+pub enum Submission {
+    /// The order has been sent to Alpaca
+    Placed(OrderData),
+    /// The order is held until the trading of its symbol resumes
+    Held,
+}
+
+/// The events informing the strategy of the holds
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+pub enum HaltEvent {
+    /// The trading of the symbol has been halted
+    Halted { symbol: String, status: StatusData },
+    /// The trading of the symbol has resumed
+    Resumed { symbol: String, status: StatusData },
+    /// An order which was held has been submitted (the result tells whether 
+    /// it was accepted by the risk guard and by Alpaca)
+    Released { order: PlaceOrderRequest, result: Result<OrderData, Error> },
+}
+
+/// Holds (or rejects) the orders of the symbols whose trading is halted
+#[derive(Debug)]
+pub struct HaltGate {
+    guard : RiskGuard,
+    policy: HaltPolicy,
+    /// The symbols which are currently halted (along with the halt status)
+    halted: HashMap<String, StatusData>,
+    /// The orders held for each symbol (in the order of their submission)
+    held  : HashMap<String, Vec<PlaceOrderRequest>>,
+}
+impl HaltGate {
+    /// Creates a gate in front of the given risk guard
+    pub fn new(guard: RiskGuard, policy: HaltPolicy) -> Self {
+        Self { guard, policy, halted: HashMap::new(), held: HashMap::new() }
+    }
+    /// The underlying risk guard
+    pub fn guard(&self) -> &RiskGuard {
+        &self.guard
+    }
+    /// Gives access to the underlying risk guard (e.g. to update the exposure)
+    pub fn guard_mut(&mut self) -> &mut RiskGuard {
+        &mut self.guard
+    }
+    /// The halt status of the given symbol (if it is halted)
+    pub fn halted(&self, symbol: &str) -> Option<&StatusData> {
+        self.halted.get(symbol)
+    }
+    /// The orders which are held for the given symbol
+    pub fn held(&self, symbol: &str) -> &[PlaceOrderRequest] {
+        self.held.get(symbol).map(Vec::as_slice).unwrap_or(&[])
+    }
+    /// Withdraws the orders which are held for the given symbol; these will
+    /// not be sent when trading resumes.
+    pub fn withdraw(&mut self, symbol: &str) -> Vec<PlaceOrderRequest> {
+        self.held.remove(symbol).unwrap_or_default()
+    }
+    /// Checks the order against the risk limits and, unless its symbol is
+    /// halted, places it.
+    pub async fn place_order(&mut self, client: &Client, order: PlaceOrderRequest) -> Result<Submission, Error> {
+        self.guard.check(&order)?;
+        if self.halted.contains_key(&order.symbol) {
+            return match self.policy {
+                HaltPolicy::Reject => Err(RiskViolation::Halted(order.symbol).into()),
+                HaltPolicy::Queue  => {
+                    self.held.entry(order.symbol.clone()).or_default().push(order);
+                    Ok(Submission::Held)
+                },
+            };
+        }
+        Ok(Submission::Placed(self.guard.place_order(client, &order).await?))
+    }
+    /// Processes a message of the statuses channel. When the trading of a
+    /// symbol resumes, the orders held for that symbol are checked again
+    /// against the risk limits and sent.
+    pub async fn on_status(&mut self, client: &Client, status: &DataPoint<StatusData>) -> Vec<HaltEvent> {
+        let symbol = status.symbol.clone();
+        let mut events = vec![];
+        if status.data.is_halted() {
+            self.halted.insert(symbol.clone(), status.data.clone());
+            events.push(HaltEvent::Halted { symbol, status: status.data.clone() });
+        } else if status.data.is_resumed() && self.halted.remove(&symbol).is_some() {
+            events.push(HaltEvent::Resumed { symbol: symbol.clone(), status: status.data.clone() });
+            for order in self.withdraw(&symbol) {
+                let result = self.guard.place_order(client, &order).await;
+                events.push(HaltEvent::Released { order, result });
+            }
+        }
+        events
+    }
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use crate::entities::{OrderSide, StatusData};
+    use crate::errors::{Error, RiskViolation};
+    use crate::halts::{HaltEvent, HaltGate, HaltPolicy, Submission};
+    use crate::orders::PlaceOrderRequestBuilder;
+    use crate::realtime::{DataPoint, Response};
+    use crate::rest::Client;
+    use crate::risk::{Exposure, RiskGuard, RiskLimits};
+
+    fn status(txt: &str) -> DataPoint<StatusData> {
+        match serde_json::from_str::<Response>(txt).unwrap() {
+            Response::Status(status) => status,
+            other => panic!("unexpected response {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_orders_are_held_while_halted() {
+        let client = Client::new("key".to_string(), "secret".to_string(), false);
+        let guard  = RiskGuard::new(RiskLimits::default(), Exposure::new(10_000.0));
+        let order  = PlaceOrderRequestBuilder::default()
+            .symbol("AAPL".to_string()).qty(1.0).limit_price(100.0).side(OrderSide::Buy)
+            .build().unwrap();
+
+        let halt   = status(r#"{"T":"s","S":"AAPL","sc":"H","sm":"Trading Halt","rc":"T12","rm":"Trading Halted; For information requested by NASDAQ","t":"2021-02-22T19:15:00Z","z":"C"}"#);
+        let resume = status(r#"{"T":"s","S":"AAPL","sc":"T","sm":"Trading Resumption","rc":"","rm":"","t":"2021-02-22T19:45:00Z","z":"C"}"#);
+
+        let mut gate = HaltGate::new(guard.clone(), HaltPolicy::Queue);
+        let events   = gate.on_status(&client, &halt).await;
+        assert!(matches!(events[..], [HaltEvent::Halted{..}]));
+        assert!(matches!(gate.place_order(&client, order.clone()).await, Ok(Submission::Held)));
+        assert_eq!(1, gate.held("AAPL").len());
+        assert_eq!(1, gate.withdraw("AAPL").len());
+        let events   = gate.on_status(&client, &resume).await;
+        assert!(matches!(events[..], [HaltEvent::Resumed{..}]));
+        assert!(gate.halted("AAPL").is_none());
+
+        let mut gate = HaltGate::new(guard, HaltPolicy::Reject);
+        gate.on_status(&client, &halt).await;
+        assert!(matches!(gate.place_order(&client, order).await, Err(Error::Risk(RiskViolation::Halted(_)))));
+    }
+}
@@ -0,0 +1,158 @@
+//! This module lets you react to the order updates through callbacks rather
+//! than by matching each and every kind of `OrderUpdate`.
+//!
+//! You implement the `OrderUpdateHandler` methods for the events you care
+//! about (all of them do nothing by default) and let the streaming client
+//! dispatch the updates into your handler.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), apca_datav2::errors::Error> {
+//! use chrono::{DateTime, Utc};
+//! use apca_datav2::entities::OrderData;
+//! use apca_datav2::handlers::OrderUpdateHandler;
+//! use apca_datav2::streaming::{Client, MessageStream};
+//!
+//! struct Fills;
+//! impl OrderUpdateHandler for Fills {
+//!     fn on_fill(&mut self, order: &OrderData, _: DateTime<Utc>, price: f64, _: f64) {
+//!         println!("{} filled at {}", order.symbol, price);
+//!     }
+//! }
+//!
+//! let mut client = Client::paper().await?;
+//! client.authenticate("key".to_string(), "secret".to_string()).await?;
+//! client.listen(vec![MessageStream::TradeUpdates]).await?;
+//! client.run(&mut Fills).await;
+//! # Ok(())
+//! # }
+//! ```
+
+use chrono::{DateTime, Utc};
+use futures::{Stream, StreamExt};
+
+use crate::entities::OrderData;
+use crate::errors::Error;
+use crate::streaming::{OrderUpdate, Response};
+
+/// Callbacks invoked for each kind of order update. All the methods do
+/// nothing by default.
+#[allow(unused_variables)]
+pub trait OrderUpdateHandler {
+    /// The order has been routed to exchanges for execution
+    fn on_new(&mut self, order: &OrderData) {}
+    /// The order has been completely filled
+    fn on_fill(&mut self, order: &OrderData, timestamp: DateTime<Utc>, price: f64, position_qty: f64) {}
+    /// A part of the order has been filled
+    fn on_partial_fill(&mut self, order: &OrderData, timestamp: DateTime<Utc>, price: f64, position_qty: f64) {}
+    /// The requested cancelation of the order has been processed
+    fn on_canceled(&mut self, order: &OrderData, timestamp: DateTime<Utc>) {}
+    /// The order has reached the end of its lifespan
+    fn on_expired(&mut self, order: &OrderData, timestamp: DateTime<Utc>) {}
+    /// The order is done executing for the day
+    fn on_done_for_day(&mut self, order: &OrderData) {}
+    /// The requested replacement of the order has been processed
+    fn on_replaced(&mut self, order: &OrderData, timestamp: DateTime<Utc>) {}
+    /// The order has been rejected
+    fn on_rejected(&mut self, order: &OrderData, timestamp: DateTime<Utc>) {}
+    /// The order has been received but not yet accepted for execution
+    fn on_pending_new(&mut self, order: &OrderData) {}
+    /// The order has been stopped (a trade is guaranteed but has not occurred)
+    fn on_stopped(&mut self, order: &OrderData) {}
+    /// The order is awaiting cancelation
+    fn on_pending_cancel(&mut self, order: &OrderData) {}
+    /// The order is awaiting replacement
+    fn on_pending_replace(&mut self, order: &OrderData) {}
+    /// The order is completed for the day but settlement calculations are pending
+    fn on_calculated(&mut self, order: &OrderData) {}
+    /// The order has been suspended and is not eligible for trading
+    fn on_suspended(&mut self, order: &OrderData) {}
+    /// The replacement of the order has been rejected
+    fn on_order_replace_rejected(&mut self, order: &OrderData) {}
+    /// The cancelation of the order has been rejected
+    fn on_order_cancel_rejected(&mut self, order: &OrderData) {}
+    /// The stream has reconnected (the updates sent while it was down are lost)
+    fn on_reconnected(&mut self) {}
+    /// The stream yielded an error (e.g. a frame that could not be decoded)
+    fn on_error(&mut self, error: &Error) {}
+    /// Any other response from the server (authorization, listening...)
+    fn on_other(&mut self, response: &Response) {}
+
+    /// Invokes the callback matching the given update
+    fn on_update(&mut self, update: &OrderUpdate) {
+        match update {
+            OrderUpdate::New { order }                  => self.on_new(order),
+            OrderUpdate::Fill { order, timestamp, price, position_qty }
+                => self.on_fill(order, *timestamp, *price, *position_qty),
+            OrderUpdate::PartialFill { order, timestamp, price, position_qty }
+                => self.on_partial_fill(order, *timestamp, *price, *position_qty),
+            OrderUpdate::Canceled { order, timestamp }  => self.on_canceled(order, *timestamp),
+            OrderUpdate::Expired { order, timestamp }   => self.on_expired(order, *timestamp),
+            OrderUpdate::DoneForDay { order }           => self.on_done_for_day(order),
+            OrderUpdate::Replaced { order, timestamp }  => self.on_replaced(order, *timestamp),
+            OrderUpdate::Rejected { order, timestamp }  => self.on_rejected(order, *timestamp),
+            OrderUpdate::PendingNew { order }           => self.on_pending_new(order),
+            OrderUpdate::Stopped { order }              => self.on_stopped(order),
+            OrderUpdate::PendingCancel { order }        => self.on_pending_cancel(order),
+            OrderUpdate::PendingReplace { order }       => self.on_pending_replace(order),
+            OrderUpdate::Calculated { order }           => self.on_calculated(order),
+            OrderUpdate::Suspended { order }            => self.on_suspended(order),
+            OrderUpdate::OrderReplaceRejected { order } => self.on_order_replace_rejected(order),
+            OrderUpdate::OrderCancelRejected { order }  => self.on_order_cancel_rejected(order),
+        }
+    }
+}
+
+/// Dispatches each item of the given stream into the handler. This returns
+/// when the stream ends.
+pub async fn dispatch<S, H>(stream: S, handler: &mut H)
+    where S: Stream<Item=Result<Response, Error>>,
+          H: OrderUpdateHandler + ?Sized
+{
+    futures::pin_mut!(stream);
+    while let Some(item) = stream.next().await {
+        match item {
+            Ok(Response::TradeUpdates { data }) => handler.on_update(&data),
+            Ok(Response::Reconnected)           => handler.on_reconnected(),
+            Ok(other)                           => handler.on_other(&other),
+            Err(error)                          => handler.on_error(&error),
+        }
+    }
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Utc};
+
+    use crate::entities::OrderData;
+    use crate::errors::Error;
+    use crate::handlers::{dispatch, OrderUpdateHandler};
+    use crate::streaming::Response;
+    use crate::testing::ORDER;
+
+    #[derive(Default)]
+    struct Counter { fills: usize, errors: usize, reconnections: usize }
+    impl OrderUpdateHandler for Counter {
+        fn on_fill(&mut self, _: &OrderData, _: DateTime<Utc>, _: f64, _: f64) { self.fills += 1 }
+        fn on_error(&mut self, _: &Error) { self.errors += 1 }
+        fn on_reconnected(&mut self) { self.reconnections += 1 }
+    }
+
+    #[tokio::test]
+    async fn test_dispatch() {
+        let fill  = format!(r#"{{"stream":"trade_updates","data":{{"event":"fill","price":"333.16","qty":"1","position_qty":"1",
+            "timestamp":"2021-11-11T17:11:17.557793708Z","order":{}}}}}"#, ORDER);
+        let fill  = serde_json::from_str::<Response>(&fill).unwrap();
+        let error = serde_json::from_str::<Response>("{").unwrap_err();
+        let items = vec![Ok(fill), Ok(Response::Reconnected), Err(Error::from(error))];
+
+        let mut counter = Counter::default();
+        dispatch(futures::stream::iter(items), &mut counter).await;
+        assert_eq!(1, counter.fills);
+        assert_eq!(1, counter.errors);
+        assert_eq!(1, counter.reconnections);
+    }
+}
@@ -22,6 +22,13 @@
 //! ### Limiting
 //! Use the limit query parameter. The value should be in the range 1 - 10000 
 //! (endpoints included) with 1000 being the default if unspecified.
+//!
+//! ### Chunking
+//! The `*_chunked` streams split a long time range (e.g. five years of minute
+//! bars) into consecutive chunks which are fetched one after the other, each
+//! with the largest possible pages. The bars are chunked so that each chunk
+//! fits in one single page; the trades and quotes are chunked with the span
+//! of your choice. The results are stitched into one single stream.
 //! 
 //! ### Paging
 //! To support querying long timespans continuously we support paging in our 
@@ -32,7 +39,28 @@
 //! continue where the previous response left off.
 //! 
 //! ### Ordering
-//! The results are ordered in ascending order by time.
+//! The results are ordered in ascending order by time; unless the client
+//! sorts them otherwise (`Client::with_sort`). With a descending sort, the
+//! first page holds the most recent data.
+//!
+//! ### Feed
+//! By default, the data comes from the best feed your subscription gives 
+//! access to. A client can be pinned to one feed with `Client::with_feed` 
+//! (e.g. `Feed::SIP` for the unlimited plan, or `Feed::IEX`); which then
+//! applies to all its historical requests.
+//!
+//! ### Currency
+//! The prices are expressed in USD unless the client converts them into 
+//! another currency (`Client::with_currency`). The conversion applies to 
+//! the trades, quotes, bars and snapshots; and is only available to some 
+//! accounts.
+//!
+//! ### Symbol mapping
+//! The symbols are resolved as of the current day: when a ticker was renamed
+//! (e.g. FB became META), the history of the company is returned under its
+//! new name, while its former name only yields a truncated history. The 
+//! `asof` date set with `Client::with_asof` resolves the symbols as they 
+//! were on that day instead.
 //! 
 //! ### Timestamps
 //! The timestamps for trades, quotes, and bars are based on market participant 
@@ -46,17 +74,21 @@
 //! participant timestamps may have broader resolution such as milliseconds 
 //! or seconds.
 
-use std::{collections::HashMap, fmt::Display, pin::Pin};
+use std::{collections::HashMap, convert::TryFrom, fmt::Display, pin::Pin, str::FromStr};
 
-use chrono::{DateTime, Utc};
-use futures::{Future, Stream};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use futures::{Future, Stream, StreamExt};
 use itertools::Itertools;
 use serde::{Serialize, Deserialize};
-use crate::{entities::{BarData, QuoteData, TradeData}, errors::{Error, maybe_convert_to_hist_error, status_code_to_hist_error}, rest::{Client, FetchNextPage, Paged, PagedStream}};
+use derive_builder::Builder;
+use crate::{entities::{BarData, Direction, Ohlcv, QuoteData, TradeData}, errors::{Error, maybe_convert_to_hist_error, status_code_to_hist_error}, rest::{Client, FetchNextPage, Paged, PagedStream}, endpoints::Endpoint};
 
 /// Base URL to access historical data
 pub const BASE_URL: &str = "https://data.alpaca.markets/v2";
 
+/// The maximum number of data points in one page
+pub const MAX_LIMIT: usize = 10_000;
+
 /// Path to access historical trades
 pub const TRADES: &str = "/v2/stocks/{symbol}/trades";
 /// Latest trade path
@@ -67,43 +99,105 @@ pub const QUOTES: &str = "/v2/stocks/{symbol}/quotes";
 pub const LATEST_QUOTE: &str = "/v2/stocks/{symbol}/quotes/latest";
 /// Path to access the historical bars (OHLC)
 pub const BARS: &str = "/v2/stocks/{symbol}/bars";
+/// Path to access the latest minute bar (OHLC)
+pub const LATEST_BAR: &str = "/v2/stocks/{symbol}/bars/latest";
 
 impl Client {
+    /// Private: the given options, completed with the defaults of the client
+    fn options(&self, options: &HistoricalOptions) -> HistoricalOptions {
+        HistoricalOptions {
+            feed    : options.feed.or_else(|| self.feed()),
+            sort    : options.sort.or_else(|| self.sort()),
+            currency: options.currency.clone().or_else(|| self.currency().map(str::to_string)),
+        }
+    }
+    /// Private: the query parameters shared by all the historical requests
+    fn common_query<'a>(&'a self, options: &'a HistoricalOptions) -> CommonQuery<'a> {
+        CommonQuery { feed: options.feed, asof: self.asof(), currency: options.currency.as_deref() }
+    }
     /// This stream returns the desired trades history going through the several 
     /// "pages" of the history asynchoronously; upon request.
-    pub fn trades<'a>(&'a self, symbol: &'a str, start: DateTime<Utc>, end: DateTime<Utc>, limit: Option<usize>) -> impl Stream<Item=TradeData> + 'a {
+    pub fn trades<'a>(&'a self, symbol: &'a str, start: DateTime<Utc>, end: DateTime<Utc>, limit: Option<usize>) -> impl Stream<Item=Result<TradeData, Error>> + 'a {
+        self.trades_with(symbol, start, end, limit, HistoricalOptions::default())
+    }
+    /// Same as `trades`, with options which override those of the client
+    pub fn trades_with<'a>(&'a self, symbol: &'a str, start: DateTime<Utc>, end: DateTime<Utc>, limit: Option<usize>, options: HistoricalOptions) -> impl Stream<Item=Result<TradeData, Error>> + 'a {
         PagedStream::new(FetchNextTrades {
             client: self,
             symbol,
             start, end,
-            limit
-        })
+            limit,
+            options
+        }).prefetch(self.prefetches())
     }
     /// This stream returns the desired quotes history going through the several 
     /// "pages" of the history asynchoronously; upon request.
-    pub fn quotes<'a>(&'a self, symbol: &'a str, start: DateTime<Utc>, end: DateTime<Utc>, limit: Option<usize>) -> impl Stream<Item=QuoteData> + 'a {
+    pub fn quotes<'a>(&'a self, symbol: &'a str, start: DateTime<Utc>, end: DateTime<Utc>, limit: Option<usize>) -> impl Stream<Item=Result<QuoteData, Error>> + 'a {
+        self.quotes_with(symbol, start, end, limit, HistoricalOptions::default())
+    }
+    /// Same as `quotes`, with options which override those of the client
+    pub fn quotes_with<'a>(&'a self, symbol: &'a str, start: DateTime<Utc>, end: DateTime<Utc>, limit: Option<usize>, options: HistoricalOptions) -> impl Stream<Item=Result<QuoteData, Error>> + 'a {
         PagedStream::new(FetchNextQuotes {
             client: self,
             symbol,
             start, end,
-            limit
-        })
+            limit,
+            options
+        }).prefetch(self.prefetches())
     }
     /// This stream returns the desired trades history going through the several 
     /// "pages" of the history asynchoronously; upon request.
-    pub fn bars<'a>(&'a self, symbol: &'a str, start: DateTime<Utc>, end: DateTime<Utc>, timeframe: TimeFrame ,limit: Option<usize>) -> impl Stream<Item=BarData> + 'a {
+    pub fn bars<'a>(&'a self, symbol: &'a str, start: DateTime<Utc>, end: DateTime<Utc>, timeframe: TimeFrame ,limit: Option<usize>) -> impl Stream<Item=Result<BarData, Error>> + 'a {
+        self.bars_with(symbol, start, end, timeframe, limit, HistoricalOptions::default())
+    }
+    /// Same as `bars`, with options which override those of the client
+    pub fn bars_with<'a>(&'a self, symbol: &'a str, start: DateTime<Utc>, end: DateTime<Utc>, timeframe: TimeFrame, limit: Option<usize>, options: HistoricalOptions) -> impl Stream<Item=Result<BarData, Error>> + 'a {
         PagedStream::new(FetchNextBars {
             client: self,
             symbol,
             start, end,
             timeframe,
-            limit
-        })
+            limit,
+            options
+        }).prefetch(self.prefetches())
+    }
+    /// This stream returns the trades history of the given range, which is
+    /// fetched in consecutive chunks of the given span (see `split_range`)
+    pub fn trades_chunked<'a>(&'a self, symbol: &'a str, start: DateTime<Utc>, end: DateTime<Utc>, span: Duration) -> impl Stream<Item=Result<TradeData, Error>> + 'a {
+        chunked(self.chunks(start, end, span), move |start, end| self.trades(symbol, start, end, Some(MAX_LIMIT)))
+    }
+    /// This stream returns the quotes history of the given range, which is
+    /// fetched in consecutive chunks of the given span (see `split_range`)
+    pub fn quotes_chunked<'a>(&'a self, symbol: &'a str, start: DateTime<Utc>, end: DateTime<Utc>, span: Duration) -> impl Stream<Item=Result<QuoteData, Error>> + 'a {
+        chunked(self.chunks(start, end, span), move |start, end| self.quotes(symbol, start, end, Some(MAX_LIMIT)))
+    }
+    /// This stream returns the bars history of the given range, which is
+    /// fetched in consecutive chunks of at most `MAX_LIMIT` bars (hence, 
+    /// one request per chunk).
+    #[allow(clippy::result_large_err)]
+    pub fn bars_chunked<'a>(&'a self, symbol: &'a str, start: DateTime<Utc>, end: DateTime<Utc>, timeframe: TimeFrame) -> impl Stream<Item=Result<BarData, Error>> + 'a {
+        let chunks = timeframe.validate()
+            .and_then(|tf| self.chunks(start, end, tf.duration() * MAX_LIMIT as i32));
+        chunked(chunks, move |start, end| self.bars(symbol, start, end, timeframe, Some(MAX_LIMIT)))
+    }
+    /// Private: the chunks of the given range, in the order of the sort
+    #[allow(clippy::result_large_err)]
+    fn chunks(&self, start: DateTime<Utc>, end: DateTime<Utc>, span: Duration) -> Result<Vec<Chunk>, Error> {
+        let mut chunks = split_range(start, end, span)?;
+        if self.sort() == Some(Direction::Descending) {
+            chunks.reverse();
+        }
+        Ok(chunks)
     }
 
     /// This endpoint returns trade historical data for the requested security
     pub async fn trades_paged(&self, symbol: &str, start: DateTime<Utc>, end: DateTime<Utc>, limit: Option<usize>, page_token: Option<String>) -> Result<MultiTrades, Error> {
-        let url = format!("https://data.alpaca.markets/v2/stocks/{symbol}/trades", symbol=symbol);
+        self.trades_paged_with(symbol, start, end, limit, page_token, &HistoricalOptions::default()).await
+    }
+    /// Same as `trades_paged`, with options which override those of the client
+    pub async fn trades_paged_with(&self, symbol: &str, start: DateTime<Utc>, end: DateTime<Utc>, limit: Option<usize>, page_token: Option<String>, options: &HistoricalOptions) -> Result<MultiTrades, Error> {
+        let options = self.options(options);
+        let url = Endpoint::Trades(&self.normalize(symbol)).url(self.data_url());
         let mut query = vec![
             ("start", start.to_rfc3339()),
             ("end",   end.to_rfc3339()),
@@ -111,10 +205,14 @@ impl Client {
         if let Some(limit) = limit {
             query.push(("limit", limit.to_string()))
         }
+        if let Some(sort) = options.sort {
+            query.push(("sort", sort.to_string()));
+        }
         if let Some(token) = page_token {
             query.push(("page_token", token));
         }
         let rsp = self.get_authenticated(&url)
+                .query(&self.common_query(&options))
                 .query(&query)
                 .send().await
                 .map_err(maybe_convert_to_hist_error)?;
@@ -123,8 +221,29 @@ impl Client {
     }
     /// This endpoint returns latest trade for the requested security.
     pub async fn latest_trade(&self, symbol: &str) -> Result<SingleTrade, Error> {
-        let url = format!("https://data.alpaca.markets/v2/stocks/{symbol}/trades/latest", symbol=symbol);
+        self.latest_trade_with(symbol, &HistoricalOptions::default()).await
+    }
+    /// Same as `latest_trade`, with options which override those of the client
+    pub async fn latest_trade_with(&self, symbol: &str, options: &HistoricalOptions) -> Result<SingleTrade, Error> {
+        let options = self.options(options);
+        let url = Endpoint::LatestTrade(&self.normalize(symbol)).url(self.data_url());
         let rsp = self.get_authenticated(&url)
+                .query(&self.common_query(&options))
+                .send().await
+                .map_err(maybe_convert_to_hist_error)?;
+
+        status_code_to_hist_error(rsp).await
+    }
+    /// This endpoint returns the latest minute bar for the requested security.
+    pub async fn latest_bar(&self, symbol: &str) -> Result<SingleBar, Error> {
+        self.latest_bar_with(symbol, &HistoricalOptions::default()).await
+    }
+    /// Same as `latest_bar`, with options which override those of the client
+    pub async fn latest_bar_with(&self, symbol: &str, options: &HistoricalOptions) -> Result<SingleBar, Error> {
+        let options = self.options(options);
+        let url = Endpoint::LatestBar(&self.normalize(symbol)).url(self.data_url());
+        let rsp = self.get_authenticated(&url)
+                .query(&self.common_query(&options))
                 .send().await
                 .map_err(maybe_convert_to_hist_error)?;
 
@@ -132,7 +251,12 @@ impl Client {
     }
     /// This endpoint returns quote (NBBO) historical data for the requested security.
     pub async fn quotes_paged(&self, symbol: &str, start: DateTime<Utc>, end: DateTime<Utc>, limit: Option<usize>, page_token: Option<String>) -> Result<MultiQuotes, Error> {
-        let url = format!("https://data.alpaca.markets/v2/stocks/{symbol}/quotes", symbol=symbol);
+        self.quotes_paged_with(symbol, start, end, limit, page_token, &HistoricalOptions::default()).await
+    }
+    /// Same as `quotes_paged`, with options which override those of the client
+    pub async fn quotes_paged_with(&self, symbol: &str, start: DateTime<Utc>, end: DateTime<Utc>, limit: Option<usize>, page_token: Option<String>, options: &HistoricalOptions) -> Result<MultiQuotes, Error> {
+        let options = self.options(options);
+        let url = Endpoint::Quotes(&self.normalize(symbol)).url(self.data_url());
         let mut query = vec![
             ("start", start.to_rfc3339()),
             ("end",   end.to_rfc3339()),
@@ -140,10 +264,14 @@ impl Client {
         if let Some(limit) = limit {
             query.push(("limit", limit.to_string()))
         }
+        if let Some(sort) = options.sort {
+            query.push(("sort", sort.to_string()));
+        }
         if let Some(token) = page_token {
             query.push(("page_token", token));
         }
         let rsp   = self.get_authenticated(&url)
+                .query(&self.common_query(&options))
                 .query(&query)
                 .send().await
                 .map_err(maybe_convert_to_hist_error)?;
@@ -152,8 +280,14 @@ impl Client {
     }
     /// This endpoint returns latest quote for the requested security.
     pub async fn latest_quote(&self, symbol: &str) -> Result<SingleQuote, Error> {
-        let url = format!("https://data.alpaca.markets/v2/stocks/{symbol}/quotes/latest", symbol=symbol);
+        self.latest_quote_with(symbol, &HistoricalOptions::default()).await
+    }
+    /// Same as `latest_quote`, with options which override those of the client
+    pub async fn latest_quote_with(&self, symbol: &str, options: &HistoricalOptions) -> Result<SingleQuote, Error> {
+        let options = self.options(options);
+        let url = Endpoint::LatestQuote(&self.normalize(symbol)).url(self.data_url());
         let rsp = self.get_authenticated(&url)
+                .query(&self.common_query(&options))
                 .send().await
                 .map_err(maybe_convert_to_hist_error)?;
 
@@ -161,19 +295,29 @@ impl Client {
     }
     /// This endpoint returns aggregate historical data for the requested security.
     pub async fn bars_paged(&self, symbol: &str, start: DateTime<Utc>, end: DateTime<Utc>, timeframe: TimeFrame ,limit: Option<usize>, page_token: Option<String>) -> Result<MultiBars, Error> {
-        let url = format!("https://data.alpaca.markets/v2/stocks/{symbol}/bars", symbol=symbol);
+        self.bars_paged_with(symbol, start, end, timeframe, limit, page_token, &HistoricalOptions::default()).await
+    }
+    /// Same as `bars_paged`, with options which override those of the client
+    #[allow(clippy::too_many_arguments)]
+    pub async fn bars_paged_with(&self, symbol: &str, start: DateTime<Utc>, end: DateTime<Utc>, timeframe: TimeFrame, limit: Option<usize>, page_token: Option<String>, options: &HistoricalOptions) -> Result<MultiBars, Error> {
+        let options = self.options(options);
+        let url = Endpoint::Bars(&self.normalize(symbol)).url(self.data_url());
         let mut query = vec![
             ("start",     start.to_rfc3339()),
             ("end",       end.to_rfc3339()),
-            ("timeframe", timeframe.to_string())
+            ("timeframe", timeframe.validate()?.to_string())
             ];
         if let Some(limit) = limit {
             query.push(("limit", limit.to_string()))
         }
+        if let Some(sort) = options.sort {
+            query.push(("sort", sort.to_string()));
+        }
         if let Some(token) = page_token {
             query.push(("page_token", token));
         }
         let rsp   = self.get_authenticated(&url)
+                .query(&self.common_query(&options))
                 .query(&query)
                 .send().await
                 .map_err(maybe_convert_to_hist_error)?;
@@ -183,8 +327,14 @@ impl Client {
     /// The Snapshot API for one ticker provides the latest trade, latest quote, 
     /// minute bar daily bar and previous daily bar data for a given ticker symbol.
     pub async fn snapshot(&self, symbol: &str) -> Result<SingleSnapshot, Error> {
-        let url = format!("https://data.alpaca.markets/v2/stocks/{symbol}/snapshot", symbol=symbol);
+        self.snapshot_with(symbol, &HistoricalOptions::default()).await
+    }
+    /// Same as `snapshot`, with options which override those of the client
+    pub async fn snapshot_with(&self, symbol: &str, options: &HistoricalOptions) -> Result<SingleSnapshot, Error> {
+        let options = self.options(options);
+        let url = Endpoint::Snapshot(&self.normalize(symbol)).url(self.data_url());
         let rsp = self.get_authenticated(&url)
+            .query(&self.common_query(&options))
             .send().await
             .map_err(maybe_convert_to_hist_error)?;
 
@@ -192,28 +342,77 @@ impl Client {
     }
     /// The Snapshot API for multiple tickers provides the latest trade, 
     /// latest quote, minute bar daily bar and previous daily bar data for 
-    /// the given ticker symbols.
+    /// the given ticker symbols (separated by commas). As with `snapshots`,
+    /// the symbols for which no snapshot is available are absent.
     pub async fn snapshots_multi(&self, symbols: &str) -> Result<HashMap<String, SnapshotData>, Error> {
-        let url = "https://data.alpaca.markets/v2/stocks/snapshots";
-        let rsp = self.get_authenticated(url)
-            .query(&[("symbols", symbols)])
-            .send().await
-            .map_err(maybe_convert_to_hist_error)?;
-
-        status_code_to_hist_error(rsp).await
+        self.snapshots(&symbols.split(',').collect::<Vec<_>>()).await
     }
     /// The Snapshot API for multiple tickers provides the latest trade, 
     /// latest quote, minute bar daily bar and previous daily bar data for 
-    /// the given ticker symbols.
+    /// the given ticker symbols. As with `snapshots`, the symbols for which
+    /// no snapshot is available are absent.
     pub async fn snapshots_multi_vec(&self, symbols: &[&str]) -> Result<HashMap<String, SnapshotData>, Error> {
-        let url = "https://data.alpaca.markets/v2/stocks/snapshots";
+        self.snapshots(symbols).await
+    }
+    /// Fetches the snapshots of all the given symbols in one single call 
+    /// (e.g. to refresh a whole watchlist). The symbols for which no snapshot
+    /// is available (unknown or inactive symbols) are absent from the result.
+    pub async fn snapshots(&self, symbols: &[&str]) -> Result<HashMap<String, SnapshotData>, Error> {
+        self.snapshots_with(symbols, &HistoricalOptions::default()).await
+    }
+    /// Same as `snapshots`, with options which override those of the client
+    pub async fn snapshots_with(&self, symbols: &[&str], options: &HistoricalOptions) -> Result<HashMap<String, SnapshotData>, Error> {
+        let options = self.options(options);
+        let url = Endpoint::Snapshots.url(self.data_url());
         let symbols = symbols.iter().join(",");
-        let rsp = self.get_authenticated(url)
-            .query(&[("symbols", symbols)])
+        let rsp = self.get_authenticated(&url)
+            .query(&self.common_query(&options))
+            .query(&[("symbols", self.symbols().normalize_list(&symbols))])
             .send().await
             .map_err(maybe_convert_to_hist_error)?;
 
-        status_code_to_hist_error(rsp).await
+        let snapshots: HashMap<String, Option<SnapshotData>> = status_code_to_hist_error(rsp).await?;
+        Ok(snapshots.into_iter()
+            .filter_map(|(symbol, snapshot)| snapshot.map(|s| (symbol, s)))
+            .collect())
+    }
+}
+
+/// The [start, end] bounds of one chunk of a range
+pub type Chunk = (DateTime<Utc>, DateTime<Utc>);
+
+/// Splits the [start, end] range into consecutive chunks spanning at most 
+/// `span` each. Since the bounds of the requests are inclusive, each chunk 
+/// ends one nanosecond before the start of the next one. The span must be
+/// positive.
+#[allow(clippy::result_large_err)]
+pub fn split_range(start: DateTime<Utc>, end: DateTime<Utc>, span: Duration) -> Result<Vec<Chunk>, Error> {
+    if span <= Duration::zero() {
+        return Err(Error::InvalidSpan(span));
+    }
+    let mut chunks = vec![];
+    let mut from   = start;
+    while from <= end {
+        let next = from + span;
+        if next > end {
+            chunks.push((from, end));
+            break;
+        }
+        chunks.push((from, next - Duration::nanoseconds(1)));
+        from = next;
+    }
+    Ok(chunks)
+}
+/// Private: fetches the given chunks one after the other (or yields the 
+/// error when the range could not be split)
+fn chunked<'a, T, S, F>(chunks: Result<Vec<Chunk>, Error>, mut fetch: F) -> impl Stream<Item=Result<T, Error>> + 'a
+    where T: 'a,
+          S: Stream<Item=Result<T, Error>> + 'a,
+          F: FnMut(DateTime<Utc>, DateTime<Utc>) -> S + 'a
+{
+    match chunks {
+        Ok(chunks) => futures::stream::iter(chunks).flat_map(move |(start, end)| fetch(start, end)).left_stream(),
+        Err(e)     => futures::stream::once(async { Err(e) }).right_stream(),
     }
 }
 
@@ -221,28 +420,162 @@ impl Client {
  * HISTORY DATA POINTS ********************************************************
  ******************************************************************************/
 
- /// Timeframe for the aggregation. Available values are: 1Min, 1Hour, 1Day.
+ /// Timeframe for the aggregation. The server accepts 1 to 59 minutes (e.g.
+ /// 5Min, 15Min), 1 to 23 hours, one day, one week and 1, 2, 3, 4, 6 or 12 
+ /// months.
  #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+ #[serde(try_from="String", into="String")]
  pub enum TimeFrame {
-    #[serde(rename="1Min")]
-    Minute, 
-    #[serde(rename="1Hour")]
-    Hour,
-    #[serde(rename="1Day")]
-    Day
+    /// N minutes (1Min, 5Min, 15Min...)
+    Minutes(u32),
+    /// N hours (1Hour, 2Hour...)
+    Hours(u32),
+    /// One day
+    Day,
+    /// One week
+    Week,
+    /// N months (1Month, 3Month...)
+    Months(u32),
+ }
+ impl TimeFrame {
+    /// Returns true iff this timeframe is accepted by the server
+    pub fn is_valid(&self) -> bool {
+        match self {
+            Self::Minutes(n) => (1..=59).contains(n),
+            Self::Hours(n)   => (1..=23).contains(n),
+            Self::Months(n)  => [1, 2, 3, 4, 6, 12].contains(n),
+            Self::Day | Self::Week => true,
+        }
+    }
+    /// Returns this timeframe if it is accepted by the server, an error
+    /// otherwise (e.g. `Minutes(0)`)
+    #[allow(clippy::result_large_err)]
+    pub fn validate(self) -> Result<Self, Error> {
+        if self.is_valid() {
+            Ok(self)
+        } else {
+            Err(Error::InvalidTimeFrame(self.to_string()))
+        }
+    }
+ }
+ impl TimeFrame {
+    /// The (maximum) duration of one bar; counting 31 days in a month
+    pub fn duration(&self) -> Duration {
+        match self {
+            Self::Minutes(n) => Duration::minutes(*n as i64),
+            Self::Hours(n)   => Duration::hours(*n as i64),
+            Self::Day        => Duration::days(1),
+            Self::Week       => Duration::weeks(1),
+            Self::Months(n)  => Duration::days(31 * *n as i64),
+        }
+    }
  }
  impl Display for TimeFrame {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Minute => write!(f, "1Min"),
-            Self::Hour   => write!(f, "1Hour"),
-            Self::Day    => write!(f, "1Day"),
+            Self::Minutes(n) => write!(f, "{}Min", n),
+            Self::Hours(n)   => write!(f, "{}Hour", n),
+            Self::Day        => write!(f, "1Day"),
+            Self::Week       => write!(f, "1Week"),
+            Self::Months(n)  => write!(f, "{}Month", n),
+        }
+    }
+}
+impl FromStr for TimeFrame {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (n, unit) = s.split_at(split);
+        let n = if n.is_empty() { Ok(1) } else { n.parse::<u32>() };
+        let timeframe = match (n, unit) {
+            (Ok(n), "Min" | "T")   => Self::Minutes(n),
+            (Ok(n), "Hour" | "H")  => Self::Hours(n),
+            (Ok(1), "Day" | "D")   => Self::Day,
+            (Ok(1), "Week" | "W")  => Self::Week,
+            (Ok(n), "Month" | "M") => Self::Months(n),
+            _ => return Err(Error::InvalidTimeFrame(s.to_string())),
+        };
+        if timeframe.is_valid() {
+            Ok(timeframe)
+        } else {
+            Err(Error::InvalidTimeFrame(s.to_string()))
+        }
+    }
+}
+impl TryFrom<String> for TimeFrame {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+impl From<TimeFrame> for String {
+    fn from(timeframe: TimeFrame) -> Self {
+        timeframe.to_string()
+    }
+}
+
+/// The source of the historical data
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum Feed {
+    /// Investor's Exchange (the only feed of the free plan)
+    #[serde(rename="iex")]
+    IEX,
+    /// All US exchanges (unlimited plan only)
+    #[serde(rename="sip")]
+    SIP,
+    /// Over-the-counter exchanges
+    #[serde(rename="otc")]
+    OTC,
+}
+impl Display for Feed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IEX => write!(f, "iex"),
+            Self::SIP => write!(f, "sip"),
+            Self::OTC => write!(f, "otc"),
         }
     }
 }
+/// The options of the historical requests. When an option is not set, the
+/// value the client was configured with (`Client::with_feed`, `with_sort`, 
+/// `with_currency`) is used instead.
+///
+/// ```no_run
+/// # async fn example(client: apca_datav2::rest::Client) -> Result<(), apca_datav2::errors::Error> {
+/// use apca_datav2::historical::{Feed, HistoricalOptionsBuilder};
+///
+/// let options = HistoricalOptionsBuilder::default().feed(Feed::SIP).build().unwrap();
+/// let trade   = client.latest_trade_with("AAPL", &options).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Builder)]
+#[builder(default, setter(strip_option, into))]
+pub struct HistoricalOptions {
+    /// The feed the data comes from
+    pub feed: Option<Feed>,
+    /// The chronological order of the trades, quotes and bars
+    pub sort: Option<Direction>,
+    /// The currency of the prices (ISO 4217 code, e.g. "EUR")
+    pub currency: Option<String>,
+}
+
+/// Private: the query parameters shared by all the historical requests
+#[derive(Debug, Clone, Copy, Serialize)]
+struct CommonQuery<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    feed: Option<Feed>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    asof: Option<NaiveDate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    currency: Option<&'a str>,
+}
 
 /// A datapoint that holds one single quote
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct SingleQuote {
     /// The symbol
     pub symbol: String,
@@ -250,18 +583,22 @@ pub struct SingleQuote {
     pub quote  : QuoteData,
 }
 /// A datapoint that holds one single quote
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct MultiQuotes {
     /// The actual payload
     #[serde(deserialize_with="crate::utils::null_as_emptyvec")]
+    #[builder(default)]
     pub quotes : Vec<QuoteData>,
     /// The symbol
     pub symbol: String,
     #[serde(rename="next_page_token")]
+    #[builder(default)]
     pub token : Option<String>,
 }
 /// A datapoint that holds one single trade
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct SingleTrade {
     /// The symbol
     pub symbol: String,
@@ -269,33 +606,48 @@ pub struct SingleTrade {
     pub trade  : TradeData,
 }
 /// A datapoint that holds one single trade
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct MultiTrades {
     /// The actual payload
     #[serde(deserialize_with="crate::utils::null_as_emptyvec")]
+    #[builder(default)]
     pub trades : Vec<TradeData>,
     /// The symbol
     pub symbol: String,
     #[serde(rename="next_page_token")]
+    #[builder(default)]
     pub token : Option<String>,
 }
 /// A datapoint that holds one single bar
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct SingleBar {
     /// The actual payload
     pub bar  : BarData,
     /// The symbol
     pub symbol: String,
 }
+impl Ohlcv for SingleBar {
+    fn open(&self) -> f64 { self.bar.open() }
+    fn high(&self) -> f64 { self.bar.high() }
+    fn low(&self) -> f64 { self.bar.low() }
+    fn close(&self) -> f64 { self.bar.close() }
+    fn volume(&self) -> u64 { self.bar.volume() }
+    fn timestamp(&self) -> DateTime<Utc> { self.bar.timestamp() }
+}
 /// A datapoint that holds one single trade
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct MultiBars {
     /// The actual payload
     #[serde(deserialize_with="crate::utils::null_as_emptyvec")]
+    #[builder(default)]
     pub bars  : Vec<BarData>,
     /// The symbol
     pub symbol: String,
     #[serde(rename="next_page_token")]
+    #[builder(default)]
     pub token : Option<String>,
 }
 
@@ -305,7 +657,8 @@ pub struct MultiBars {
 
 /// The Snapshot API for one ticker provides the latest trade, latest quote, 
 /// minute bar daily bar and previous daily bar data for a given ticker symbol.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct SnapshotData {
     /// Latest trade object.
     #[serde(rename = "latestTrade")]
@@ -326,7 +679,8 @@ pub struct SnapshotData {
 
 /// The Snapshot API for one ticker provides the latest trade, latest quote, 
 /// minute bar daily bar and previous daily bar data for a given ticker symbol.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct SingleSnapshot {
     /// The symbol
     pub symbol: String, 
@@ -366,13 +720,13 @@ struct FetchNextTrades<'a> {
     start: DateTime<Utc>, 
     end: DateTime<Utc>, 
     limit: Option<usize>, 
+    options: HistoricalOptions,
 }
 impl <'a> FetchNextPage<'a, MultiTrades> for FetchNextTrades<'a> {
     fn fetch(self: Pin<&Self>, token: Option<String>) -> Pin<Box<dyn Future<Output=Result<MultiTrades, Error>> + 'a >> {
-        Box::pin(
-            self.client.trades_paged(
-                self.symbol, self.start, self.end, self.limit, token)
-        )
+        let Self { client, symbol, start, end, limit, .. } = *self.get_ref();
+        let options = self.options.clone();
+        Box::pin(async move { client.trades_paged_with(symbol, start, end, limit, token, &options).await })
     }
 }
 
@@ -385,13 +739,13 @@ struct FetchNextQuotes<'a> {
     start: DateTime<Utc>, 
     end: DateTime<Utc>, 
     limit: Option<usize>, 
+    options: HistoricalOptions,
 }
 impl <'a> FetchNextPage<'a, MultiQuotes> for FetchNextQuotes<'a> {
     fn fetch(self: Pin<&Self>, token: Option<String>) -> Pin<Box<dyn Future<Output=Result<MultiQuotes, Error>> + 'a >> {
-        Box::pin(
-            self.client.quotes_paged(
-                self.symbol, self.start, self.end, self.limit, token)
-        )
+        let Self { client, symbol, start, end, limit, .. } = *self.get_ref();
+        let options = self.options.clone();
+        Box::pin(async move { client.quotes_paged_with(symbol, start, end, limit, token, &options).await })
     }
 }
 
@@ -405,13 +759,13 @@ struct FetchNextBars<'a> {
     end: DateTime<Utc>, 
     timeframe: TimeFrame ,
     limit: Option<usize>, 
+    options: HistoricalOptions,
 }
 impl <'a> FetchNextPage<'a, MultiBars> for FetchNextBars<'a> {
     fn fetch(self: Pin<&Self>, token: Option<String>) -> Pin<Box<dyn Future<Output=Result<MultiBars, Error>> + 'a >> {
-        Box::pin(
-            self.client.bars_paged(
-                self.symbol, self.start, self.end, self.timeframe, self.limit, token)
-        )
+        let Self { client, symbol, start, end, timeframe, limit, .. } = *self.get_ref();
+        let options = self.options.clone();
+        Box::pin(async move { client.bars_paged_with(symbol, start, end, timeframe, limit, token, &options).await })
     }
 }
 
@@ -422,10 +776,10 @@ impl <'a> FetchNextPage<'a, MultiBars> for FetchNextBars<'a> {
 #[cfg(test)]
 mod test {
     use dotenv_codegen::dotenv;
-    use chrono::{TimeZone, Utc};
-    use futures::StreamExt;
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use futures::{StreamExt, TryStreamExt};
 
-    use crate::{historical::MultiBars, rest::Client};
+    use crate::{entities::Direction, errors::Error, historical::{Feed, HistoricalOptionsBuilder, MultiBars, TimeFrame, split_range}, rest::Client, testing::fake_client, transport::FakeTransport};
 
     #[tokio::test]
     async fn test_bars_stream() -> Result<(), anyhow::Error> {
@@ -436,14 +790,14 @@ mod test {
 
         let mut stream = client.bars(
             "AAPL", 
-            Utc.ymd(2021,  8,  1).and_hms(0, 0, 0),
-            Utc.ymd(2021,  8, 15).and_hms(0, 0, 0),
+            Utc.with_ymd_and_hms(2021,  8,  1, 0, 0, 0).unwrap(),
+            Utc.with_ymd_and_hms(2021,  8, 15, 0, 0, 0).unwrap(),
             crate::historical::TimeFrame::Day,
             Some(3)
         );
 
         while let Some(x) = stream.next().await {
-            println!("{:?}", x.timestamp);
+            println!("{:?}", x?.timestamp);
         }
 
         Ok(())
@@ -458,13 +812,13 @@ mod test {
 
         let mut stream = client.quotes(
             "AAPL", 
-            Utc.ymd(2021,  8,  2).and_hms(16, 0, 0),
-            Utc.ymd(2021,  8,  2).and_hms(16, 3, 0),
+            Utc.with_ymd_and_hms(2021,  8,  2, 16, 0, 0).unwrap(),
+            Utc.with_ymd_and_hms(2021,  8,  2, 16, 3, 0).unwrap(),
             None
         );
 
         while let Some(x) = stream.next().await {
-            println!("{:?}", x.timestamp);
+            println!("{:?}", x?.timestamp);
         }
 
         Ok(())
@@ -480,13 +834,13 @@ mod test {
         
         let mut stream = client.trades(
             "AAPL", 
-            Utc.ymd(2021,  8,  1).and_hms(16, 0, 0),
-            Utc.ymd(2021,  8,  1).and_hms(16, 5, 0),
+            Utc.with_ymd_and_hms(2021,  8,  1, 16, 0, 0).unwrap(),
+            Utc.with_ymd_and_hms(2021,  8,  1, 16, 5, 0).unwrap(),
             None
         );
 
         while let Some(x) = stream.next().await {
-            println!("{:?}", x.timestamp);
+            println!("{:?}", x?.timestamp);
         }
 
         Ok(())
@@ -557,4 +911,151 @@ mod test {
         let parsed = serde_json::from_str::<MultiBars>(txt).unwrap();
         println!("{:?}", parsed)
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_null_snapshots_are_skipped() {
+        let bar  = r#"{"t":"2021-08-02T16:00:00Z","o":146.23,"h":146.3,"l":146.2,"c":146.29,"v":1200,"n":12,"vw":146.25}"#;
+        let aapl = format!(r#"{{"latestTrade":{{"t":"2021-08-02T16:00:00Z","x":"V","p":146.29,"s":100,"c":["@"],"i":1,"z":"C"}},
+            "latestQuote":{{"t":"2021-08-02T16:00:00Z","ax":"V","ap":146.3,"as":1,"bx":"V","bp":146.28,"bs":2,"c":["R"],"z":"C"}},
+            "minuteBar":{0},"dailyBar":{0},"prevDailyBar":{0}}}"#, bar);
+        let body = format!(r#"{{"AAPL":{},"DEAD":null}}"#, aapl);
+        let transport = FakeTransport::new().respond("GET", "/v2/stocks/snapshots", 200, &body);
+        let client    = fake_client(&transport);
+
+        let snapshots = client.snapshots(&["AAPL", "DEAD"]).await.unwrap();
+        assert_eq!(vec!["AAPL"], snapshots.keys().collect::<Vec<_>>());
+        assert!(transport.requests()[0].url.ends_with("symbols=AAPL%2CDEAD"));
+
+        let snapshots = client.snapshots_multi("AAPL,DEAD").await.unwrap();
+        assert_eq!(vec!["AAPL"], snapshots.keys().collect::<Vec<_>>());
+        let snapshots = client.snapshots_multi_vec(&["AAPL", "DEAD"]).await.unwrap();
+        assert_eq!(vec!["AAPL"], snapshots.keys().collect::<Vec<_>>());
+        assert!(transport.requests().iter().all(|r| r.url.ends_with("symbols=AAPL%2CDEAD")));
+    }
+
+    #[tokio::test]
+    async fn test_feed_is_sent() {
+        let transport = FakeTransport::new();
+        let client    = fake_client(&transport);
+        let _ = client.latest_trade("AAPL").await;
+        let client    = client.with_feed(Feed::SIP);
+        let _ = client.latest_trade("AAPL").await;
+
+        let requests = transport.requests();
+        assert!(requests[0].url.ends_with("/v2/stocks/AAPL/trades/latest"));
+        assert!(requests[1].url.ends_with("/v2/stocks/AAPL/trades/latest?feed=sip"));
+    }
+    #[tokio::test]
+    async fn test_asof_is_sent() {
+        let transport = FakeTransport::new();
+        let client    = fake_client(&transport)
+            .with_asof(NaiveDate::from_ymd_opt(2022, 6, 8).unwrap());
+        let start     = Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap();
+        let _ = client.bars_paged("FB", start, start, TimeFrame::Day, None, None).await;
+
+        assert!(transport.requests()[0].url.contains("asof=2022-06-08"));
+    }
+
+    #[tokio::test]
+    async fn test_currency_is_sent() {
+        let transport = FakeTransport::new();
+        let client    = fake_client(&transport)
+            .with_currency("EUR");
+        let _ = client.snapshots(&["AAPL"]).await;
+
+        assert!(transport.requests()[0].url.contains("currency=EUR"));
+    }
+
+    #[tokio::test]
+    async fn test_sort_is_sent() {
+        let transport = FakeTransport::new();
+        let client    = fake_client(&transport)
+            .with_sort(Direction::Descending);
+        let start     = Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap();
+        let _ = client.trades_paged("AAPL", start, start, Some(10), None).await;
+        let _ = client.latest_trade("AAPL").await;
+
+        assert!(transport.requests()[0].url.contains("&limit=10&sort=desc"));
+        assert!(!transport.requests()[1].url.contains("sort"));
+    }
+
+    #[tokio::test]
+    async fn test_options_override_the_client() {
+        let transport = FakeTransport::new();
+        let client    = fake_client(&transport)
+            .with_feed(Feed::IEX)
+            .with_currency("EUR");
+        let options   = HistoricalOptionsBuilder::default()
+            .feed(Feed::SIP)
+            .sort(Direction::Descending)
+            .build().unwrap();
+        let start     = Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap();
+        let _ = client.trades_paged_with("AAPL", start, start, None, None, &options).await;
+        let _ = client.trades_paged("AAPL", start, start, None, None).await;
+
+        let requests = transport.requests();
+        assert!(requests[0].url.contains("feed=sip&currency=EUR"));
+        assert!(requests[0].url.contains("sort=desc"));
+        assert!(requests[1].url.contains("feed=iex&currency=EUR"));
+        assert!(!requests[1].url.contains("sort"));
+    }
+
+    #[test]
+    fn test_timeframes() {
+        for txt in ["1Min", "15Min", "2Hour", "1Day", "1Week", "3Month"].iter() {
+            assert_eq!(*txt, txt.parse::<TimeFrame>().unwrap().to_string());
+        }
+        assert_eq!(TimeFrame::Minutes(5), "5T".parse().unwrap());
+        assert!("60Min".parse::<TimeFrame>().is_err());
+        assert!("5Month".parse::<TimeFrame>().is_err());
+        assert!("2Day".parse::<TimeFrame>().is_err());
+        assert_eq!(r#""30Min""#, serde_json::to_string(&TimeFrame::Minutes(30)).unwrap());
+        assert_eq!(TimeFrame::Week, serde_json::from_str(r#""1Week""#).unwrap());
+    }
+
+    #[test]
+    fn test_split_range() {
+        let start  = Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap();
+        let end    = Utc.with_ymd_and_hms(2021, 8, 3, 12, 0, 0).unwrap();
+        let chunks = split_range(start, end, chrono::Duration::days(1)).unwrap();
+        assert_eq!(3, chunks.len());
+        assert_eq!(Utc.with_ymd_and_hms(2021, 8, 2, 0, 0, 0).unwrap() - chrono::Duration::nanoseconds(1), chunks[0].1);
+        assert_eq!(Utc.with_ymd_and_hms(2021, 8, 2, 0, 0, 0).unwrap(), chunks[1].0);
+        assert_eq!((Utc.with_ymd_and_hms(2021, 8, 3, 0, 0, 0).unwrap(), end), chunks[2]);
+        assert!(split_range(end, start, chrono::Duration::days(1)).unwrap().is_empty());
+        assert!(matches!(split_range(start, end, chrono::Duration::zero()), Err(Error::InvalidSpan(_))));
+    }
+
+    #[tokio::test]
+    async fn test_bars_chunked() {
+        let transport = FakeTransport::new()
+            .respond("GET", "/v2/stocks/AAPL/bars", 200, r#"{"bars":[],"symbol":"AAPL","next_page_token":null}"#);
+        let client    = fake_client(&transport);
+        let start     = Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap();
+        let end       = Utc.with_ymd_and_hms(2021, 8, 21, 0, 0, 0).unwrap();
+        let bars      = client.bars_chunked("AAPL", start, end, TimeFrame::Minutes(1)).try_collect::<Vec<_>>().await.unwrap();
+
+        // 10000 minutes is a little less than 7 days
+        assert!(bars.is_empty());
+        let requests = transport.requests();
+        assert_eq!(3, requests.len());
+        assert!(requests.iter().all(|r| r.url.contains("limit=10000")));
+        assert!(requests[1].url.contains("start=2021-08-07T22%3A40%3A00%2B00%3A00"));
+    }
+
+    #[tokio::test]
+    async fn test_zero_timeframes_are_rejected() {
+        let transport = FakeTransport::new();
+        let client    = fake_client(&transport);
+        let start     = Utc.with_ymd_and_hms(2021, 8, 1, 0, 0, 0).unwrap();
+        let end       = Utc.with_ymd_and_hms(2021, 8, 21, 0, 0, 0).unwrap();
+
+        let page = client.bars_paged("AAPL", start, end, TimeFrame::Minutes(0), None, None).await;
+        assert!(matches!(page, Err(Error::InvalidTimeFrame(_))));
+        let bars = client.bars_chunked("AAPL", start, end, TimeFrame::Hours(0)).collect::<Vec<_>>().await;
+        assert!(matches!(bars[..], [Err(Error::InvalidTimeFrame(_))]));
+        let trades = client.trades_chunked("AAPL", start, end, chrono::Duration::zero()).collect::<Vec<_>>().await;
+        assert!(matches!(trades[..], [Err(Error::InvalidSpan(_))]));
+        assert!(transport.requests().is_empty());
+    }
+}
@@ -0,0 +1,261 @@
+//! This module provides a few simple technical indicators which are computed
+//! incrementally, one bar at a time.
+//!
+//! The indicators accept any `Ohlcv` bar. Hence, they can be fed with the
+//! bars of the historical pages (`MultiBars`) as well as with the bars
+//! received in realtime (`DataPoint<BarData>`) without any conversion.
+//! Each indicator yields `None` until it has seen enough bars to produce a
+//! meaningful value.
+//!
+//! ```
+//! use apca_datav2::entities::BarData;
+//! use apca_datav2::indicators::{Indicator, Sma};
+//!
+//! # fn example(bars: Vec<BarData>) {
+//! let mut sma = Sma::new(20);
+//! for bar in bars.iter() {
+//!     if let Some(value) = sma.update(bar) {
+//!         println!("{}: {}", bar.timestamp, value);
+//!     }
+//! }
+//! # }
+//! ```
+
+use std::collections::VecDeque;
+
+use crate::entities::Ohlcv;
+
+/// An indicator which is updated one bar at a time
+pub trait Indicator {
+    /// Feeds the indicator with the next bar and returns its new value
+    fn update<B: Ohlcv>(&mut self, bar: &B) -> Option<f64>;
+    /// The current value of the indicator
+    fn value(&self) -> Option<f64>;
+    /// Forgets all the bars seen so far
+    fn reset(&mut self);
+    /// Feeds the indicator with several bars (e.g. a page of `MultiBars`)
+    /// and returns its value after the last one
+    fn update_all<'a, B: Ohlcv + 'a, I: IntoIterator<Item=&'a B>>(&mut self, bars: I) -> Option<f64> {
+        for bar in bars {
+            self.update(bar);
+        }
+        self.value()
+    }
+}
+
+/// The simple moving average of the close prices over the last `period` bars
+#[derive(Debug, Clone)]
+pub struct Sma {
+    window: Window,
+    sum   : f64,
+}
+impl Sma {
+    /// Creates an average over the given number of bars
+    pub fn new(period: usize) -> Self {
+        Self { window: Window::new(period), sum: 0.0 }
+    }
+}
+impl Indicator for Sma {
+    fn update<B: Ohlcv>(&mut self, bar: &B) -> Option<f64> {
+        self.sum += bar.close();
+        if let Some(evicted) = self.window.push(bar.close()) {
+            self.sum -= evicted;
+        }
+        self.value()
+    }
+    fn value(&self) -> Option<f64> {
+        if self.window.is_full() {
+            Some(self.sum / self.window.period as f64)
+        } else {
+            None
+        }
+    }
+    fn reset(&mut self) {
+        self.window.clear();
+        self.sum = 0.0;
+    }
+}
+
+/// The exponential moving average of the close prices, with a smoothing
+/// factor of `2 / (period + 1)`. It is seeded with the simple moving average
+/// of the first `period` bars.
+#[derive(Debug, Clone)]
+pub struct Ema {
+    seed : Sma,
+    alpha: f64,
+    value: Option<f64>,
+}
+impl Ema {
+    /// Creates an average over the given number of bars
+    pub fn new(period: usize) -> Self {
+        Self { seed: Sma::new(period), alpha: 2.0 / (period.max(1) as f64 + 1.0), value: None }
+    }
+}
+impl Indicator for Ema {
+    fn update<B: Ohlcv>(&mut self, bar: &B) -> Option<f64> {
+        self.value = match self.value {
+            Some(previous) => Some(previous + self.alpha * (bar.close() - previous)),
+            None           => self.seed.update(bar),
+        };
+        self.value
+    }
+    fn value(&self) -> Option<f64> {
+        self.value
+    }
+    fn reset(&mut self) {
+        self.seed.reset();
+        self.value = None;
+    }
+}
+
+/// The volume weighted average price of the bars seen since the last reset,
+/// using the typical price `(high + low + close) / 3` of each bar. The VWAP
+/// of a session is obtained by calling `reset` at the start of the session.
+#[derive(Debug, Clone, Default)]
+pub struct Vwap {
+    notional: f64,
+    volume  : u64,
+}
+impl Vwap {
+    /// Creates an empty vwap
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+impl Indicator for Vwap {
+    fn update<B: Ohlcv>(&mut self, bar: &B) -> Option<f64> {
+        let typical = (bar.high() + bar.low() + bar.close()) / 3.0;
+        self.notional += typical * bar.volume() as f64;
+        self.volume   += bar.volume();
+        self.value()
+    }
+    fn value(&self) -> Option<f64> {
+        if self.volume > 0 {
+            Some(self.notional / self.volume as f64)
+        } else {
+            None
+        }
+    }
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// The highest high of the last `period` bars
+#[derive(Debug, Clone)]
+pub struct RollingHigh {
+    window: Window,
+}
+impl RollingHigh {
+    /// Creates a rolling high over the given number of bars
+    pub fn new(period: usize) -> Self {
+        Self { window: Window::new(period) }
+    }
+}
+impl Indicator for RollingHigh {
+    fn update<B: Ohlcv>(&mut self, bar: &B) -> Option<f64> {
+        self.window.push(bar.high());
+        self.value()
+    }
+    fn value(&self) -> Option<f64> {
+        self.window.values.iter().copied().reduce(f64::max).filter(|_| self.window.is_full())
+    }
+    fn reset(&mut self) {
+        self.window.clear();
+    }
+}
+
+/// The lowest low of the last `period` bars
+#[derive(Debug, Clone)]
+pub struct RollingLow {
+    window: Window,
+}
+impl RollingLow {
+    /// Creates a rolling low over the given number of bars
+    pub fn new(period: usize) -> Self {
+        Self { window: Window::new(period) }
+    }
+}
+impl Indicator for RollingLow {
+    fn update<B: Ohlcv>(&mut self, bar: &B) -> Option<f64> {
+        self.window.push(bar.low());
+        self.value()
+    }
+    fn value(&self) -> Option<f64> {
+        self.window.values.iter().copied().reduce(f64::min).filter(|_| self.window.is_full())
+    }
+    fn reset(&mut self) {
+        self.window.clear();
+    }
+}
+
+/// Private: the values of the last `period` bars
+#[derive(Debug, Clone)]
+struct Window {
+    period: usize,
+    values: VecDeque<f64>,
+}
+impl Window {
+    fn new(period: usize) -> Self {
+        let period = period.max(1);
+        Self { period, values: VecDeque::with_capacity(period) }
+    }
+    /// Appends a value and returns the one which left the window (if any)
+    fn push(&mut self, value: f64) -> Option<f64> {
+        self.values.push_back(value);
+        if self.values.len() > self.period {
+            self.values.pop_front()
+        } else {
+            None
+        }
+    }
+    fn is_full(&self) -> bool {
+        self.values.len() == self.period
+    }
+    fn clear(&mut self) {
+        self.values.clear();
+    }
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use crate::entities::BarData;
+    use crate::indicators::{Ema, Indicator, RollingHigh, RollingLow, Sma, Vwap};
+    use crate::realtime::DataPoint;
+
+    fn bar(close: f64, volume: u64) -> BarData {
+        BarData {
+            open_price: close, high_price: close + 1.0, low_price: close - 1.0, close_price: close,
+            volume, timestamp: Utc.with_ymd_and_hms(2021, 8, 2, 4, 0, 0).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_indicators() {
+        let bars = vec![bar(1.0, 10), bar(2.0, 10), bar(3.0, 20), bar(4.0, 0)];
+
+        let mut sma = Sma::new(3);
+        assert_eq!(None, sma.update(&bars[0]));
+        assert_eq!(Some(3.0), sma.update_all(&bars[1..]));
+
+        let mut ema = Ema::new(3);
+        assert_eq!(Some(2.0), ema.update_all(&bars[..3]));
+        assert_eq!(Some(3.0), ema.update(&bars[3]));
+
+        let mut vwap = Vwap::new();
+        assert_eq!(Some(2.25), vwap.update_all(&bars));
+        vwap.reset();
+        assert_eq!(None, vwap.value());
+
+        // the realtime bars feed the very same indicators
+        let realtime = bars.iter().map(|b| DataPoint::new("AAPL".to_string(), b.clone())).collect::<Vec<_>>();
+        assert_eq!(Some(5.0), RollingHigh::new(2).update_all(&realtime));
+        assert_eq!(Some(2.0), RollingLow::new(2).update_all(&realtime));
+    }
+}
@@ -0,0 +1,312 @@
+//! This module provides an order journal: a log of the orders you submit and
+//! of their executions, which can later be used to perform a transaction cost
+//! analysis (TCA).
+//!
+//! The journal is written to a file (one JSON object per line). When the
+//! quote capture is enabled (it is disabled by default), the journal fetches
+//! the NBBO at the time each order is submitted and at the time each fill
+//! event is recorded; and stores it along with the entry. This way, the
+//! slippage of each execution can be computed without having to query the
+//! historical quotes afterwards. The capture is best-effort: should the quote
+//! be unavailable, the entry is recorded without it.
+//!
+//! Once an order has been accepted, `place_order` never fails: an entry 
+//! which cannot be written is kept aside (see `OrderJournal::unwritten`) and
+//! written along with the next one.
+//!
+//! # Note
+//! The NBBO captured for a fill is the latest quote at the time the fill
+//! event is *processed*, which may be slightly later than the execution.
+//!
+//! ```no_run
+//! # async fn example(client: apca_datav2::rest::Client) -> Result<(), apca_datav2::errors::Error> {
+//! use futures::StreamExt;
+//! use apca_datav2::entities::OrderSide;
+//! use apca_datav2::journal::OrderJournal;
+//! use apca_datav2::orders::PlaceOrderRequestBuilder;
+//! use apca_datav2::streaming::{self, MessageStream, Response};
+//!
+//! let mut journal = OrderJournal::create("orders.jsonl")?.capture_quotes(true);
+//! let order = PlaceOrderRequestBuilder::default()
+//!     .symbol("AAPL".to_string())
+//!     .qty(10.0)
+//!     .side(OrderSide::Buy)
+//!     .build().unwrap();
+//! journal.place_order(&client, &order).await?;
+//!
+//! let mut updates = streaming::Client::paper().await?;
+//! updates.authenticate("key".to_string(), "secret".to_string()).await?;
+//! updates.listen(vec![MessageStream::TradeUpdates]).await?;
+//! let mut updates = Box::pin(updates.stream());
+//! while let Some(response) = updates.next().await {
+//!     if let Ok(Response::TradeUpdates { data }) = response {
+//!         journal.record_update(&client, &data).await?;
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{fs::{File, OpenOptions}, io::{BufRead, BufWriter, Write}, path::Path};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::entities::{OrderData, OrderSide, QuoteData};
+use crate::errors::Error;
+use crate::orders::PlaceOrderRequest;
+use crate::rest::Client;
+use crate::streaming::OrderUpdate;
+
+/// What happened to the order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum JournalEvent {
+    /// The order has been submitted
+    Submitted {
+        /// The number of shares to trade (if any)
+        qty: Option<f64>,
+        /// The dollar amount to trade (if any)
+        notional: Option<f64>,
+        /// The limit price of the order (if any)
+        limit_price: Option<f64>,
+    },
+    /// The order has been partially filled
+    PartialFill {
+        /// The time at which the shares were filled
+        timestamp: DateTime<Utc>,
+        /// The average price per share at which the shares were filled
+        price: f64,
+        /// The total number of shares filled so far
+        filled_qty: f64,
+    },
+    /// The order has been completely filled
+    Fill {
+        /// The time at which the order was filled
+        timestamp: DateTime<Utc>,
+        /// The average price per share at which the order was filled
+        price: f64,
+        /// The total number of shares filled
+        filled_qty: f64,
+    },
+}
+
+/// One line of the order journal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// The time when this entry was recorded
+    pub recorded_at: DateTime<Utc>,
+    /// The id of the order
+    pub order_id: String,
+    /// The client order id of the order
+    pub client_order_id: String,
+    /// The symbol being traded
+    pub symbol: String,
+    /// The side of the order
+    pub side: OrderSide,
+    /// What happened to the order
+    #[serde(flatten)]
+    pub event: JournalEvent,
+    /// The NBBO at the time the entry was recorded (when captured)
+    pub nbbo: Option<QuoteData>,
+}
+impl JournalEntry {
+    /// The mid price of the captured NBBO (if any)
+    pub fn mid(&self) -> Option<f64> {
+        self.nbbo.as_ref().map(|q| (q.ask_price + q.bid_price) / 2.0)
+    }
+    /// The slippage (per share) of a fill with respect to the mid price of
+    /// the captured NBBO. It is positive when the execution was worse than
+    /// the mid price (i.e. a cost) and negative when it was better.
+    pub fn slippage(&self) -> Option<f64> {
+        let price = match self.event {
+            JournalEvent::Submitted { .. }            => return None,
+            JournalEvent::PartialFill { price, .. }   => price,
+            JournalEvent::Fill { price, .. }          => price,
+        };
+        self.mid().map(|mid| match self.side {
+            OrderSide::Buy  => price - mid,
+            OrderSide::Sell => mid - price,
+        })
+    }
+}
+
+/// Writes the order journal to a JSONL output
+pub struct OrderJournal<W: Write> {
+    out: W,
+    capture_quotes: bool,
+    /// The entries which could not be written yet
+    unwritten: Vec<JournalEntry>,
+}
+impl OrderJournal<BufWriter<File>> {
+    /// Creates a journal appending to the file at the given path
+    #[allow(clippy::result_large_err)]
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::new(BufWriter::new(file)))
+    }
+}
+impl<W: Write> OrderJournal<W> {
+    /// Creates a journal writing to the given output. The quote capture is
+    /// disabled by default.
+    pub fn new(out: W) -> Self {
+        Self { out, capture_quotes: false, unwritten: vec![] }
+    }
+    /// Enables (or disables) the capture of the NBBO
+    pub fn capture_quotes(mut self, enabled: bool) -> Self {
+        self.capture_quotes = enabled;
+        self
+    }
+    /// Places the order and records its submission in the journal. This only
+    /// fails when the order could not be placed.
+    pub async fn place_order(&mut self, client: &Client, request: &PlaceOrderRequest) -> Result<OrderData, Error> {
+        let nbbo  = self.nbbo(client, &request.symbol).await;
+        let order = client.place_order(request).await?;
+        let event = JournalEvent::Submitted {
+            qty        : request.qty,
+            notional   : request.notional,
+            limit_price: request.limit_price,
+        };
+        // the order is live: failing now would invite a duplicate submission
+        let entry = entry(&order, event, nbbo);
+        if self.write(&entry).is_err() {
+            self.unwritten.push(entry);
+        }
+        Ok(order)
+    }
+    /// Records an update received on the trade_updates stream. Only the fill
+    /// and partial fill events are recorded; the other ones are ignored.
+    pub async fn record_update(&mut self, client: &Client, update: &OrderUpdate) -> Result<(), Error> {
+        let (order, event) = match update {
+            OrderUpdate::Fill { order, timestamp, price, .. } =>
+                (order, JournalEvent::Fill { timestamp: *timestamp, price: *price, filled_qty: order.filled_qty }),
+            OrderUpdate::PartialFill { order, timestamp, price, .. } =>
+                (order, JournalEvent::PartialFill { timestamp: *timestamp, price: *price, filled_qty: order.filled_qty }),
+            _ => return Ok(()),
+        };
+        let nbbo = self.nbbo(client, &order.symbol).await;
+        self.write(&entry(order, event, nbbo))
+    }
+    /// Writes one entry to the journal (after the ones which could not be 
+    /// written so far)
+    #[allow(clippy::result_large_err)]
+    pub fn write(&mut self, entry: &JournalEntry) -> Result<(), Error> {
+        while let Some(pending) = self.unwritten.first() {
+            Self::write_line(&mut self.out, pending)?;
+            self.unwritten.remove(0);
+        }
+        Self::write_line(&mut self.out, entry)
+    }
+    /// The entries of the placed orders which could not be written yet
+    pub fn unwritten(&self) -> &[JournalEntry] {
+        &self.unwritten
+    }
+    /// Private: writes one line to the output
+    #[allow(clippy::result_large_err)]
+    fn write_line(out: &mut W, entry: &JournalEntry) -> Result<(), Error> {
+        serde_json::to_writer(&mut *out, entry)?;
+        writeln!(out)?;
+        out.flush()?;
+        Ok(())
+    }
+    /// Fetches the NBBO of the given symbol if the capture is enabled (and
+    /// the quote is available)
+    async fn nbbo(&self, client: &Client, symbol: &str) -> Option<QuoteData> {
+        if self.capture_quotes {
+            client.latest_quote(symbol).await.ok().map(|q| q.quote)
+        } else {
+            None
+        }
+    }
+}
+
+/// Reads all the entries of a journal
+#[allow(clippy::result_large_err)]
+pub fn read<R: BufRead>(input: R) -> Result<Vec<JournalEntry>, Error> {
+    let mut entries = vec![];
+    for line in input.lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            entries.push(serde_json::from_str(&line)?);
+        }
+    }
+    Ok(entries)
+}
+
+/// Private: creates the journal entry of the given order
+fn entry(order: &OrderData, event: JournalEvent, nbbo: Option<QuoteData>) -> JournalEntry {
+    JournalEntry {
+        recorded_at    : Utc::now(),
+        order_id       : order.id.clone(),
+        client_order_id: order.client_order_id.clone(),
+        symbol         : order.symbol.clone(),
+        side           : order.side,
+        event,
+        nbbo,
+    }
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use chrono::Utc;
+
+    use crate::entities::{OrderSide, QuoteData};
+    use crate::journal::{self, JournalEntry, JournalEvent, OrderJournal};
+    use crate::orders::PlaceOrderRequestBuilder;
+    use crate::testing::{ORDER, fake_client};
+    use crate::transport::FakeTransport;
+
+    #[test]
+    fn test_slippage_of_captured_fill() {
+        let txt  = r#"{"t":"2021-02-06T13:04:56.334320128Z","ax":"C","ap":387.62,"as":1,"bx":"C","bp":387.58,"bs":3,"c":["R"],"z":"B"}"#;
+        let nbbo = serde_json::from_str::<QuoteData>(txt).unwrap();
+        let fill = JournalEntry {
+            recorded_at    : Utc::now(),
+            order_id       : "1".to_string(),
+            client_order_id: "2".to_string(),
+            symbol         : "SPY".to_string(),
+            side           : OrderSide::Buy,
+            event          : JournalEvent::Fill { timestamp: Utc::now(), price: 387.63, filled_qty: 10.0 },
+            nbbo           : Some(nbbo),
+        };
+        let mut out = vec![];
+        OrderJournal::new(&mut out).write(&fill).unwrap();
+        let entries = journal::read(out.as_slice()).unwrap();
+
+        assert_eq!(1, entries.len());
+        assert_eq!(fill.event, entries[0].event);
+        assert!((entries[0].slippage().unwrap() - 0.03).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_accepted_orders_never_fail() {
+        struct Full;
+        impl Write for Full {
+            fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::StorageFull.into())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        // the quote is not available (404) and the journal cannot be written
+        let transport = FakeTransport::new().respond("POST", "/v2/orders", 200, ORDER);
+        let client    = fake_client(&transport);
+        let request   = PlaceOrderRequestBuilder::default()
+            .symbol("MSFT".to_string())
+            .qty(2.0)
+            .side(OrderSide::Buy)
+            .build().unwrap();
+        let mut journal = OrderJournal::new(Full).capture_quotes(true);
+
+        assert_eq!("810f77c9", journal.place_order(&client, &request).await.unwrap().id);
+        assert_eq!(1, journal.unwritten().len());
+        assert!(journal.unwritten()[0].nbbo.is_none());
+    }
+}
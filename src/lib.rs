@@ -12,16 +12,51 @@
 //! Nasdaq) SIPs. These 2 feeds combined offer 100% market volume.
 
 mod utils;
+#[cfg(test)]
+mod testing;
 pub mod errors;
 pub mod entities;
 
 pub mod rest;
+pub mod transport;
+pub mod cache;
+pub mod symbols;
+pub mod endpoints;
 
 pub mod historical;
+pub mod crypto;
+pub mod news;
+pub mod options;
+pub mod corporate_actions;
+pub mod meta;
+pub mod calendar;
+pub mod bulk;
+pub mod indicators;
+pub mod gapfill;
+pub mod scheduler;
 pub mod orders;
 pub mod positions;
 pub mod assets;
 pub mod watchlist;
+pub mod account;
+pub mod activities;
 
 pub mod realtime;
 pub mod streaming;
+pub mod handlers;
+pub mod tracker;
+pub mod orderbook;
+pub mod websocket;
+pub mod events;
+pub mod risk;
+pub mod halts;
+pub mod journal;
+pub mod sharding;
+pub mod watchdog;
+pub mod replay;
+pub mod fixtures;
+
+pub mod dataset;
+pub mod barcache;
+#[cfg(feature = "parquet-sink")]
+pub mod columnar;
@@ -0,0 +1,215 @@
+//! This module provides an access to the metadata of the market data API:
+//! the reference tables which give a meaning to the codes found in the
+//! trades and quotes.
+//!
+//! These tables rarely change. Hence, a client with a cache (see
+//! `Client::with_cache`) fetches them only once per time to live.
+//!
+//! The condition codes also tell which trades count when computing the
+//! official OHLC and volume (e.g. an odd lot or an average price trade
+//! must not set the high of a bar). The `is_regular_sale`, `updates_ohlc` 
+//! and `updates_volume` helpers implement these rules.
+//!
+//! ```no_run
+//! # async fn example(client: apca_datav2::rest::Client) -> Result<(), apca_datav2::errors::Error> {
+//! use apca_datav2::meta::{Tape, TickType};
+//!
+//! let exchanges = client.exchanges().await?;
+//! println!("V stands for {:?}", exchanges.name("V"));
+//!
+//! let conditions = client.conditions(TickType::Trade, Tape::C).await?;
+//! println!("I stands for {:?}", conditions.description("I"));
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+
+use serde::{Serialize, Deserialize};
+use crate::{entities::{Exchange, TradeData}, errors::{Error, maybe_convert_to_hist_error, status_code_to_hist_error}, rest::Client, endpoints::Endpoint};
+
+impl Client {
+    /// Retrieves the names of the exchanges, indexed by code. Unlike the
+    /// `Exchange` enumeration, this table also knows the venues added by
+    /// Alpaca after the release of this crate.
+    pub async fn exchanges(&self) -> Result<ExchangeTable, Error> {
+        let url = Endpoint::Exchanges.url(self.data_url());
+        let rsp = self.get_authenticated(&url)
+            .send().await
+            .map_err(maybe_convert_to_hist_error)?;
+
+        status_code_to_hist_error(rsp).await
+    }
+    /// Retrieves the descriptions of the trade or quote conditions used on
+    /// the given tape, indexed by code
+    pub async fn conditions(&self, tick_type: TickType, tape: Tape) -> Result<ConditionTable, Error> {
+        let url = Endpoint::Conditions(tick_type.as_str()).url(self.data_url());
+        let rsp = self.get_authenticated(&url)
+            .query(&[("tape", tape)])
+            .send().await
+            .map_err(maybe_convert_to_hist_error)?;
+
+        status_code_to_hist_error(rsp).await
+    }
+}
+
+/// The names of the exchanges, indexed by code (e.g. "V" -> "IEX")
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ExchangeTable {
+    names: HashMap<String, String>,
+}
+impl ExchangeTable {
+    /// The name of the exchange having the given code
+    pub fn name(&self, code: &str) -> Option<&str> {
+        self.names.get(code).map(|n| n.as_str())
+    }
+    /// The name of the given exchange
+    pub fn name_of(&self, exchange: Exchange) -> Option<&str> {
+        match serde_json::to_value(exchange) {
+            Ok(serde_json::Value::String(code)) => self.name(&code),
+            _ => None,
+        }
+    }
+    /// Iterates over the (code, name) pairs of the table
+    pub fn iter(&self) -> impl Iterator<Item=(&str, &str)> {
+        self.names.iter().map(|(c, n)| (c.as_str(), n.as_str()))
+    }
+    /// The number of exchanges in the table
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+    /// Returns true iff the table contains no exchange
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+/// The kind of ticks a condition applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TickType {
+    #[serde(rename="trade")]
+    Trade,
+    #[serde(rename="quote")]
+    Quote,
+}
+impl TickType {
+    /// The name of the tick type in the url of the endpoint
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Trade => "trade",
+            Self::Quote => "quote",
+        }
+    }
+}
+
+/// The tapes of the consolidated feeds (each tape uses its own codes)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Tape {
+    /// NYSE listed securities (CTA)
+    A,
+    /// NYSE Arca, NYSE American and regional listed securities (CTA)
+    B,
+    /// Nasdaq listed securities (UTP)
+    C,
+}
+
+/// The descriptions of the conditions, indexed by code (e.g. "I" -> 
+/// "Odd Lot Trade")
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ConditionTable {
+    descriptions: HashMap<String, String>,
+}
+impl ConditionTable {
+    /// The description of the condition having the given code
+    pub fn description(&self, code: &str) -> Option<&str> {
+        self.descriptions.get(code).map(|d| d.as_str())
+    }
+    /// The descriptions of the conditions of the given trade (the unknown
+    /// codes are skipped)
+    pub fn describe<'a>(&'a self, trade: &'a TradeData) -> impl Iterator<Item=&'a str> {
+        trade.conditions.iter().filter_map(move |c| self.description(c))
+    }
+    /// Iterates over the (code, description) pairs of the table
+    pub fn iter(&self) -> impl Iterator<Item=(&str, &str)> {
+        self.descriptions.iter().map(|(c, d)| (c.as_str(), d.as_str()))
+    }
+    /// The number of conditions in the table
+    pub fn len(&self) -> usize {
+        self.descriptions.len()
+    }
+    /// Returns true iff the table contains no condition
+    pub fn is_empty(&self) -> bool {
+        self.descriptions.is_empty()
+    }
+}
+
+/// The codes of a regular sale (no special condition)
+const REGULAR_SALE: &[&str] = &["@", " ", ""];
+/// The conditions of the trades which do not update the open, high, low and
+/// close prices (odd lots, average price, derivatively priced, out of 
+/// sequence, official open/close reports...)
+const NOT_OHLC: &[&str] = &["B", "C", "G", "H", "I", "M", "N", "P", "Q", "R", "T", "U", "V", "W", "Z", "4", "7", "9"];
+/// The conditions of the reports which are not trades and hence do not 
+/// count in the volume (official open/close, corrected close)
+const NOT_VOLUME: &[&str] = &["M", "Q", "9"];
+
+/// Returns true iff the given conditions denote a regular sale
+pub fn is_regular_sale(conditions: &[String]) -> bool {
+    conditions.iter().all(|c| REGULAR_SALE.contains(&c.as_str()))
+}
+/// Returns true iff a trade with the given conditions updates the open, high,
+/// low and close prices of a bar
+pub fn updates_ohlc(conditions: &[String]) -> bool {
+    !conditions.iter().any(|c| NOT_OHLC.contains(&c.as_str()))
+}
+/// Returns true iff a trade with the given conditions counts in the volume
+pub fn updates_volume(conditions: &[String]) -> bool {
+    !conditions.iter().any(|c| NOT_VOLUME.contains(&c.as_str()))
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use crate::entities::Exchange;
+    use crate::meta::{Tape, TickType, is_regular_sale, updates_ohlc, updates_volume};
+    use crate::testing::fake_client;
+    use crate::transport::FakeTransport;
+
+    #[tokio::test]
+    async fn test_exchanges() {
+        let exchanges = r#"{"A":"NYSE American (AMEX)","V":"IEX","7":"Some New Venue"}"#;
+        let transport = FakeTransport::new()
+            .respond("GET", "/v2/stocks/meta/exchanges", 200, exchanges);
+        let client = fake_client(&transport);
+
+        let exchanges = client.exchanges().await.unwrap();
+        assert_eq!(3, exchanges.len());
+        assert_eq!(Some("Some New Venue"), exchanges.name("7"));
+        assert_eq!(Some("IEX"), exchanges.name_of(Exchange::Iex));
+        assert_eq!(None, exchanges.name_of(Exchange::Cboe));
+    }
+
+    #[tokio::test]
+    async fn test_conditions() {
+        let conditions = r#"{" ":"Regular Sale","I":"Odd Lot Trade","W":"Average Price Trade"}"#;
+        let transport = FakeTransport::new()
+            .respond("GET", "/v2/stocks/meta/conditions/trade", 200, conditions);
+        let client = fake_client(&transport);
+
+        let conditions = client.conditions(TickType::Trade, Tape::A).await.unwrap();
+        assert_eq!(Some("Odd Lot Trade"), conditions.description("I"));
+        assert!(transport.requests()[0].url.ends_with("/v2/stocks/meta/conditions/trade?tape=A"));
+
+        let odd_lot = vec!["@".to_string(), "I".to_string()];
+        assert!(is_regular_sale(&["@".to_string()]));
+        assert!(!is_regular_sale(&odd_lot));
+        assert!(!updates_ohlc(&odd_lot));
+        assert!(updates_volume(&odd_lot));
+        assert!(!updates_volume(&["M".to_string()]));
+    }
+}
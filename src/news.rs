@@ -0,0 +1,227 @@
+//! This module provides an access to the historical news API offered by
+//! Alpaca (`v1beta1/news`).
+//!
+//! The news articles (from Benzinga) are filtered with a `NewsRequest`: the
+//! symbols they mention, the time range of their publication, and whether
+//! their full (html) content is needed. The `news` stream goes through all
+//! the pages of the result, whereas `news_paged` fetches one single page.
+//!
+//! ```no_run
+//! # async fn example(client: apca_datav2::rest::Client) -> Result<(), apca_datav2::errors::Error> {
+//! use futures::StreamExt;
+//! use apca_datav2::news::NewsRequestBuilder;
+//!
+//! let request = NewsRequestBuilder::default()
+//!     .symbols("AAPL,TSLA".to_string())
+//!     .exclude_contentless(true)
+//!     .build().unwrap();
+//! let news = client.news(&request);
+//! futures::pin_mut!(news);
+//! while let Some(article) = news.next().await {
+//!     let article = article?;
+//!     println!("{} {}", article.created_at, article.headline);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::pin::Pin;
+
+use chrono::{DateTime, Utc};
+use futures::{Future, Stream};
+use serde::{Serialize, Deserialize};
+use derive_builder::Builder;
+use crate::{entities::Direction, errors::{Error, maybe_convert_to_hist_error, status_code_to_hist_error}, rest::{Client, FetchNextPage, Paged, PagedStream}, endpoints::Endpoint};
+
+/// The maximum number of articles in one page
+pub const MAX_NEWS_PAGE_SIZE: u32 = 50;
+
+impl Client {
+    /// This stream returns the news articles matching the given request
+    /// going through the several "pages" of the result asynchronously; upon
+    /// request.
+    pub fn news<'a>(&'a self, request: &NewsRequest) -> impl Stream<Item=Result<NewsArticle, Error>> + 'a {
+        PagedStream::new(FetchNextNews { client: self, request: request.clone() })
+            .prefetch(self.prefetches())
+    }
+    /// This endpoint returns one page of the news articles matching the
+    /// given request. The next page is fetched by setting the `page_token`
+    /// of the request to the token of this page.
+    pub async fn news_paged(&self, request: &NewsRequest) -> Result<NewsPage, Error> {
+        let url = Endpoint::News.url(self.data_url());
+        let mut request = request.clone();
+        request.symbols = request.symbols.map(|s| self.symbols().normalize_list(&s));
+        let rsp = self.get_authenticated(&url)
+            .query(&request)
+            .send().await
+            .map_err(maybe_convert_to_hist_error)?;
+
+        status_code_to_hist_error(rsp).await
+    }
+}
+
+/// The filters of the news articles
+#[derive(Builder, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NewsRequest {
+    /// A comma-separated list of symbols to filter by (ex. “AAPL,TSLA”). By
+    /// default, the articles about any symbol are returned.
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symbols: Option<String>,
+    /// The response will only include the articles published after this
+    /// timestamp (inclusive)
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<DateTime<Utc>>,
+    /// The response will only include the articles published before this
+    /// timestamp (inclusive)
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<DateTime<Utc>>,
+    /// The maximum number of articles in one page. Defaults to 10 and max
+    /// is 50.
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+    /// The chronological order of the articles. Defaults to desc.
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<Direction>,
+    /// If true, the articles include their full (html) content
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub include_content: Option<bool>,
+    /// If true, the articles without content (only a headline) are skipped
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exclude_contentless: Option<bool>,
+    /// The token of the page to fetch
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_token: Option<String>,
+}
+
+/// A news article
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct NewsArticle {
+    /// The id of the article
+    pub id: u64,
+    /// The headline (title) of the article
+    pub headline: String,
+    /// The original author of the article
+    #[serde(default)]
+    #[builder(default)]
+    pub author: String,
+    /// The date at which the article was published
+    pub created_at: DateTime<Utc>,
+    /// The date at which the article was last updated
+    pub updated_at: DateTime<Utc>,
+    /// A summary of the article (may be empty)
+    #[serde(default)]
+    #[builder(default)]
+    pub summary: String,
+    /// The content of the article (html; only when it was requested)
+    #[serde(default)]
+    #[builder(default)]
+    pub content: String,
+    /// The url of the original article (if any)
+    #[serde(default)]
+    #[builder(default)]
+    pub url: Option<String>,
+    /// The images of the article
+    #[serde(default, deserialize_with="crate::utils::null_as_emptyvec")]
+    #[builder(default)]
+    pub images: Vec<NewsImage>,
+    /// The symbols mentioned in the article
+    #[serde(default, deserialize_with="crate::utils::null_as_emptyvec")]
+    #[builder(default)]
+    pub symbols: Vec<String>,
+    /// The source of the article (e.g. benzinga)
+    pub source: String,
+}
+
+/// An image of a news article
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct NewsImage {
+    /// The size of the image (thumb, small or large)
+    pub size: String,
+    /// The url of the image
+    pub url: String,
+}
+
+/// A page of news articles
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct NewsPage {
+    /// The actual payload
+    #[serde(deserialize_with="crate::utils::null_as_emptyvec")]
+    #[builder(default)]
+    pub news : Vec<NewsArticle>,
+    #[serde(rename="next_page_token")]
+    #[builder(default)]
+    pub token: Option<String>,
+}
+impl Paged for NewsPage {
+    type Item = NewsArticle;
+    fn split(self) -> (Vec<Self::Item>, Option<String>) {
+        (self.news, self.token)
+    }
+}
+
+/// This structure encapsulates a call to `news_paged` and yields a future
+/// that can be used to asychronously fetch the next page of articles
+struct FetchNextNews<'a> {
+    client : &'a Client,
+    request: NewsRequest,
+}
+impl <'a> FetchNextPage<'a, NewsPage> for FetchNextNews<'a> {
+    fn fetch(self: Pin<&Self>, token: Option<String>) -> Pin<Box<dyn Future<Output=Result<NewsPage, Error>> + 'a >> {
+        let client      = self.client;
+        let mut request = self.request.clone();
+        if token.is_some() {
+            request.page_token = token;
+        }
+        Box::pin(async move { client.news_paged(&request).await })
+    }
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use futures::TryStreamExt;
+
+    use crate::news::NewsRequestBuilder;
+    use crate::testing::fake_client;
+    use crate::transport::FakeTransport;
+
+    #[tokio::test]
+    async fn test_news() {
+        let news = r#"{"news":[{"id":24843171,"headline":"Apple Is Working On A Tap To Pay Feature",
+            "author":"Ananya Gairola","created_at":"2022-01-28T10:08:12Z","updated_at":"2022-01-28T10:08:12Z",
+            "summary":"","content":"","url":"https://www.benzinga.com/news/22/01/24843171/apple",
+            "images":[{"size":"thumb","url":"https://cdn.benzinga.com/files/thumb.jpeg"}],
+            "symbols":["AAPL"],"source":"benzinga"}],"next_page_token":null}"#;
+        let transport = FakeTransport::new()
+            .respond("GET", "/v1beta1/news", 200, news);
+        let client = fake_client(&transport);
+
+        let request = NewsRequestBuilder::default()
+            .symbols("AAPL".to_string())
+            .include_content(true)
+            .exclude_contentless(true)
+            .build().unwrap();
+        let articles = client.news(&request).try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(1, articles.len());
+        assert_eq!(vec!["AAPL".to_string()], articles[0].symbols);
+        assert_eq!("thumb", articles[0].images[0].size);
+
+        let url = &transport.requests()[0].url;
+        assert!(url.starts_with("https://data.alpaca.markets/v1beta1/news?"));
+        assert!(url.contains("symbols=AAPL&include_content=true&exclude_contentless=true"));
+    }
+}
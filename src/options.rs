@@ -0,0 +1,527 @@
+//! This module provides an easy access to the historical options market data
+//! offered by Alpaca (`v1beta1/options` endpoints).
+//!
+//! Each contract is identified by its OCC symbol: the root symbol of the
+//! underlying, the expiration date (YYMMDD), the type of the contract (C or
+//! P) and the strike price times 1000 on 8 digits. For instance,
+//! `AAPL240419C00100000` is the call on AAPL expiring on 2024-04-19 with a
+//! strike of $100. The `OptionSymbol` parses and formats these symbols.
+//!
+//! Like the crypto endpoints, the options endpoints accept several symbols at
+//! once and return the data indexed by symbol. The chain of an underlying
+//! (the snapshots of all its contracts) can be filtered with an
+//! `OptionChainRequest`.
+//!
+//! ```no_run
+//! # async fn example(client: apca_datav2::rest::Client) -> Result<(), apca_datav2::errors::Error> {
+//! use chrono::NaiveDate;
+//! use futures::StreamExt;
+//! use apca_datav2::options::{OptionChainRequestBuilder, OptionType};
+//!
+//! let request = OptionChainRequestBuilder::default()
+//!     .kind(OptionType::Call)
+//!     .expiration_date(NaiveDate::from_ymd_opt(2024, 4, 19).unwrap())
+//!     .build().unwrap();
+//! let chain = client.option_chain("AAPL", &request);
+//! futures::pin_mut!(chain);
+//! while let Some((contract, snapshot)) = chain.next().await.transpose()? {
+//!     println!("{} strike {}: iv {:?}", contract, contract.strike, snapshot.implied_volatility);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{collections::HashMap, convert::TryFrom, fmt::Display, pin::Pin, str::FromStr};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::{Future, Stream};
+use itertools::Itertools;
+use serde::{Serialize, Deserialize};
+use derive_builder::Builder;
+use crate::{crypto::{FetchNextBySymbol, by_symbol}, errors::{Error, maybe_convert_to_hist_error, status_code_to_hist_error}, historical::TimeFrame, rest::{Client, FetchNextPage, Paged, PagedStream}, endpoints::Endpoint};
+
+impl Client {
+    /// This stream returns the desired option trades history of the given
+    /// contracts going through the several "pages" of the history
+    /// asynchronously; upon request.
+    pub fn option_trades<'a>(&'a self, symbols: &'a [&'a str], start: DateTime<Utc>, end: DateTime<Utc>, limit: Option<usize>) -> impl Stream<Item=Result<(String, OptionTradeData), Error>> + 'a {
+        PagedStream::new(FetchNextBySymbol {
+            client: self,
+            symbols,
+            start, end,
+            timeframe: None,
+            limit,
+            fetch: |client, params, token| Box::pin(client.option_trades_paged(params.symbols, params.start, params.end, params.limit, token)),
+        }).prefetch(self.prefetches())
+    }
+    /// This stream returns the desired option bars history of the given
+    /// contracts going through the several "pages" of the history
+    /// asynchronously; upon request.
+    pub fn option_bars<'a>(&'a self, symbols: &'a [&'a str], start: DateTime<Utc>, end: DateTime<Utc>, timeframe: TimeFrame, limit: Option<usize>) -> impl Stream<Item=Result<(String, OptionBarData), Error>> + 'a {
+        PagedStream::new(FetchNextBySymbol {
+            client: self,
+            symbols,
+            start, end,
+            timeframe: Some(timeframe),
+            limit,
+            fetch: |client, params, token| Box::pin(client.option_bars_paged(params.symbols, params.start, params.end, params.timeframe.unwrap_or(TimeFrame::Day), params.limit, token)),
+        }).prefetch(self.prefetches())
+    }
+    /// This stream returns the snapshots of all the contracts of the given
+    /// underlying which match the request; going through the several "pages"
+    /// of the chain asynchronously.
+    pub fn option_chain<'a>(&'a self, underlying: &'a str, request: &OptionChainRequest) -> impl Stream<Item=Result<(OptionSymbol, OptionSnapshotData), Error>> + 'a {
+        PagedStream::new(FetchNextChain { client: self, underlying, request: request.clone() })
+            .prefetch(self.prefetches())
+    }
+
+    /// This endpoint returns the option trades historical data for the
+    /// requested contracts
+    pub async fn option_trades_paged(&self, symbols: &[&str], start: DateTime<Utc>, end: DateTime<Utc>, limit: Option<usize>, page_token: Option<String>) -> Result<MultiOptionTrades, Error> {
+        self.history_by_symbol(Endpoint::OptionTrades, symbols, start, end, None, limit, page_token).await
+    }
+    /// This endpoint returns the aggregate option historical data for the
+    /// requested contracts
+    pub async fn option_bars_paged(&self, symbols: &[&str], start: DateTime<Utc>, end: DateTime<Utc>, timeframe: TimeFrame, limit: Option<usize>, page_token: Option<String>) -> Result<MultiOptionBars, Error> {
+        self.history_by_symbol(Endpoint::OptionBars, symbols, start, end, Some(timeframe), limit, page_token).await
+    }
+    /// This endpoint returns the latest trade of each requested contract
+    pub async fn option_latest_trades(&self, symbols: &[&str]) -> Result<HashMap<String, OptionTradeData>, Error> {
+        let latest: LatestOptionTrades = self.latest_by_symbol(Endpoint::OptionLatestTrades, symbols).await?;
+        Ok(latest.trades)
+    }
+    /// This endpoint returns the latest quote of each requested contract
+    pub async fn option_latest_quotes(&self, symbols: &[&str]) -> Result<HashMap<String, OptionQuoteData>, Error> {
+        let latest: LatestOptionQuotes = self.latest_by_symbol(Endpoint::OptionLatestQuotes, symbols).await?;
+        Ok(latest.quotes)
+    }
+    /// The snapshot of each requested contract: its latest trade, latest
+    /// quote, implied volatility and greeks.
+    pub async fn option_snapshots(&self, symbols: &[&str]) -> Result<HashMap<String, OptionSnapshotData>, Error> {
+        let snapshots: OptionChainPage = self.latest_by_symbol(Endpoint::OptionSnapshots, symbols).await?;
+        Ok(snapshots.snapshots)
+    }
+    /// This endpoint returns one page of the chain of the given underlying
+    /// (the snapshots of its contracts matching the request)
+    pub async fn option_chain_paged(&self, underlying: &str, request: &OptionChainRequest, page_token: Option<String>) -> Result<OptionChainPage, Error> {
+        let url = Endpoint::OptionChain(&self.normalize(underlying)).url(self.data_url());
+        let rsp = self.get_authenticated(&url)
+            .query(request)
+            .query(&[("page_token", page_token)])
+            .send().await
+            .map_err(maybe_convert_to_hist_error)?;
+
+        status_code_to_hist_error(rsp).await
+    }
+}
+
+/******************************************************************************
+ * CONTRACTS ******************************************************************
+ ******************************************************************************/
+
+/// The type of an option contract
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum OptionType {
+    /// The right to buy the underlying at the strike price
+    #[serde(rename="call")]
+    Call,
+    /// The right to sell the underlying at the strike price
+    #[serde(rename="put")]
+    Put,
+}
+
+/// The OCC symbol of an option contract (e.g. `AAPL240419C00100000`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from="String", into="String")]
+pub struct OptionSymbol {
+    /// The root symbol of the underlying (e.g. AAPL)
+    pub root: String,
+    /// The expiration date of the contract
+    pub expiration: NaiveDate,
+    /// Whether the contract is a call or a put
+    pub kind: OptionType,
+    /// The strike price of the contract
+    pub strike: f64,
+}
+impl Display for OptionSymbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.kind {
+            OptionType::Call => 'C',
+            OptionType::Put  => 'P',
+        };
+        write!(f, "{}{}{}{:08}", self.root, self.expiration.format("%y%m%d"), kind, (self.strike * 1000.0).round() as u64)
+    }
+}
+impl FromStr for OptionSymbol {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidOptionSymbol(s.to_string());
+        if !s.is_ascii() || s.len() < 16 || s.len() > 21 {
+            return Err(invalid());
+        }
+        let (root, rest)   = s.split_at(s.len() - 15);
+        let (date, rest)   = rest.split_at(6);
+        let (kind, strike) = rest.split_at(1);
+        let expiration = NaiveDate::parse_from_str(date, "%y%m%d").map_err(|_| invalid())?;
+        let kind = match kind {
+            "C" => OptionType::Call,
+            "P" => OptionType::Put,
+            _   => return Err(invalid()),
+        };
+        if !strike.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        let strike = strike.parse::<u64>().map_err(|_| invalid())? as f64 / 1000.0;
+        Ok(Self { root: root.to_string(), expiration, kind, strike })
+    }
+}
+impl TryFrom<String> for OptionSymbol {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+impl From<OptionSymbol> for String {
+    fn from(symbol: OptionSymbol) -> Self {
+        symbol.to_string()
+    }
+}
+
+/// The source of the options data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum OptionFeed {
+    /// The consolidated feed of the exchanges (subscription required)
+    #[serde(rename="opra")]
+    OPRA,
+    /// The free (delayed and modified) feed
+    #[serde(rename="indicative")]
+    Indicative,
+}
+
+/// The filters of an option chain
+#[derive(Builder, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OptionChainRequest {
+    /// Only the calls or the puts. Both by default.
+    #[builder(setter(strip_option), default="None")]
+    #[serde(rename="type", skip_serializing_if = "Option::is_none")]
+    pub kind: Option<OptionType>,
+    /// Only the contracts whose strike is greater than or equal to this one
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strike_price_gte: Option<f64>,
+    /// Only the contracts whose strike is less than or equal to this one
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strike_price_lte: Option<f64>,
+    /// Only the contracts which expire on this date
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expiration_date: Option<NaiveDate>,
+    /// Only the contracts which expire on or after this date
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expiration_date_gte: Option<NaiveDate>,
+    /// Only the contracts which expire on or before this date
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expiration_date_lte: Option<NaiveDate>,
+    /// Only the contracts having this root symbol (e.g. SPXW for SPX)
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub root_symbol: Option<String>,
+    /// The source of the data. Defaults to opra when the subscription
+    /// gives access to it; indicative otherwise.
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub feed: Option<OptionFeed>,
+    /// The maximum number of snapshots in one page
+    #[builder(setter(strip_option), default="None")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+}
+
+/******************************************************************************
+ * DATA POINTS ****************************************************************
+ ******************************************************************************/
+
+/// Datapoint encapsulating informations about a given option trade
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct OptionTradeData {
+    /// exchange code where the trade occurred
+    #[serde(rename="x")]
+    pub exchange_code: String,
+    /// trade price
+    #[serde(rename="p")]
+    pub trade_price: f64,
+    /// trade size (number of contracts)
+    #[serde(rename="s")]
+    pub trade_size: u64,
+    /// Condition
+    #[serde(rename="c", default)]
+    #[builder(default)]
+    pub condition: String,
+    /// RFC-3339 formatted timestamp with nanosecond precision.
+    #[serde(rename="t")]
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Datapoint encapsulating an option quote
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct OptionQuoteData {
+    /// ask exchange code
+    #[serde(rename="ax")]
+    pub ask_exchange: String,
+    /// ask price
+    #[serde(rename="ap")]
+    pub ask_price: f64,
+    /// ask size
+    #[serde(rename="as")]
+    pub ask_size: u64,
+    /// bid exchange code
+    #[serde(rename="bx")]
+    pub bid_exchange: String,
+    /// bid price
+    #[serde(rename="bp")]
+    pub bid_price: f64,
+    /// bid size
+    #[serde(rename="bs")]
+    pub bid_size: u64,
+    /// Condition
+    #[serde(rename="c", default)]
+    #[builder(default)]
+    pub condition: String,
+    /// RFC-3339 formatted timestamp with nanosecond precision.
+    #[serde(rename="t")]
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Datapoint encapsulating an option 'bar' (a.k.a. OHLC)
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct OptionBarData {
+    /// open price
+    #[serde(rename="o")]
+    pub open_price: f64,
+    /// high price
+    #[serde(rename="h")]
+    pub high_price: f64,
+    /// low price
+    #[serde(rename="l")]
+    pub low_price: f64,
+    /// close price
+    #[serde(rename="c")]
+    pub close_price: f64,
+    /// volume (number of contracts)
+    #[serde(rename="v")]
+    pub volume: u64,
+    /// number of trades
+    #[serde(rename="n", default)]
+    #[builder(default)]
+    pub trade_count: u64,
+    /// volume weighted average price
+    #[serde(rename="vw", default)]
+    #[builder(default)]
+    pub vwap: f64,
+    /// RFC-3339 formatted timestamp with nanosecond precision.
+    #[serde(rename="t")]
+    pub timestamp: DateTime<Utc>,
+}
+
+/// The sensitivities of the price of a contract
+#[derive(Builder, Debug, Clone, Copy, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Greeks {
+    /// Sensitivity to the price of the underlying
+    pub delta: f64,
+    /// Sensitivity of the delta to the price of the underlying
+    pub gamma: f64,
+    /// Sensitivity to the interest rate
+    pub rho: f64,
+    /// Sensitivity to the passing of time
+    pub theta: f64,
+    /// Sensitivity to the volatility of the underlying
+    pub vega: f64,
+}
+
+/// The latest trade, latest quote, bars, implied volatility and greeks of a
+/// contract. Any of these may be missing (e.g. for a contract that never
+/// traded).
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct OptionSnapshotData {
+    /// Latest trade object.
+    #[serde(rename = "latestTrade")]
+    #[builder(default)]
+    pub latest_trade: Option<OptionTradeData>,
+    /// Latest quote object
+    #[serde(rename = "latestQuote")]
+    #[builder(default)]
+    pub latest_quote: Option<OptionQuoteData>,
+    /// Minute bar object.
+    #[serde(rename = "minuteBar")]
+    #[builder(default)]
+    pub minute_bar: Option<OptionBarData>,
+    /// Daily bar object.
+    #[serde(rename = "dailyBar")]
+    #[builder(default)]
+    pub daily_bar: Option<OptionBarData>,
+    /// Previous daily close bar object
+    #[serde(rename = "prevDailyBar")]
+    #[builder(default)]
+    pub prev_daily_bar: Option<OptionBarData>,
+    /// The implied volatility
+    #[serde(rename = "impliedVolatility")]
+    #[builder(default)]
+    pub implied_volatility: Option<f64>,
+    /// The greeks
+    #[builder(default)]
+    pub greeks: Option<Greeks>,
+}
+
+/// A page of option trades, indexed by contract
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct MultiOptionTrades {
+    /// The actual payload
+    #[serde(default)]
+    #[builder(default)]
+    pub trades: HashMap<String, Vec<OptionTradeData>>,
+    #[serde(rename="next_page_token")]
+    #[builder(default)]
+    pub token : Option<String>,
+}
+/// A page of option bars, indexed by contract
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct MultiOptionBars {
+    /// The actual payload
+    #[serde(default)]
+    #[builder(default)]
+    pub bars : HashMap<String, Vec<OptionBarData>>,
+    #[serde(rename="next_page_token")]
+    #[builder(default)]
+    pub token: Option<String>,
+}
+/// A page of option snapshots, indexed by contract
+#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct OptionChainPage {
+    /// The actual payload
+    #[serde(default)]
+    #[builder(default)]
+    pub snapshots: HashMap<String, OptionSnapshotData>,
+    #[serde(rename="next_page_token", default)]
+    #[builder(default)]
+    pub token    : Option<String>,
+}
+
+/// Private: the payload of the latest trades endpoint
+#[derive(Debug, Deserialize)]
+struct LatestOptionTrades {
+    trades: HashMap<String, OptionTradeData>,
+}
+/// Private: the payload of the latest quotes endpoint
+#[derive(Debug, Deserialize)]
+struct LatestOptionQuotes {
+    quotes: HashMap<String, OptionQuoteData>,
+}
+
+/*----------------------------------------------------------------------------*/
+/* THE MULTI-* DATA POINTS ARE STRUCTURES THAT EMBODY THE PAGING MECHANISM    */
+/*----------------------------------------------------------------------------*/
+impl Paged for MultiOptionTrades {
+    type Item = (String, OptionTradeData);
+    fn split(self) -> (Vec<Self::Item>, Option<String>) {
+        (by_symbol(self.trades), self.token)
+    }
+}
+impl Paged for MultiOptionBars {
+    type Item = (String, OptionBarData);
+    fn split(self) -> (Vec<Self::Item>, Option<String>) {
+        (by_symbol(self.bars), self.token)
+    }
+}
+impl Paged for OptionChainPage {
+    type Item = (OptionSymbol, OptionSnapshotData);
+    fn split(self) -> (Vec<Self::Item>, Option<String>) {
+        let snapshots = self.snapshots.into_iter()
+            .sorted_by(|a, b| a.0.cmp(&b.0))
+            .filter_map(|(symbol, snapshot)| symbol.parse().ok().map(|s| (s, snapshot)))
+            .collect();
+        (snapshots, self.token)
+    }
+}
+
+/// This structure encapsulates a call to `option_chain_paged` and yields a
+/// future that can be used to asychronously fetch the next page of the chain
+struct FetchNextChain<'a> {
+    client    : &'a Client,
+    underlying: &'a str,
+    request   : OptionChainRequest,
+}
+impl <'a> FetchNextPage<'a, OptionChainPage> for FetchNextChain<'a> {
+    fn fetch(self: Pin<&Self>, token: Option<String>) -> Pin<Box<dyn Future<Output=Result<OptionChainPage, Error>> + 'a >> {
+        let client     = self.client;
+        let underlying = self.underlying;
+        let request    = self.request.clone();
+        Box::pin(async move { client.option_chain_paged(underlying, &request, token).await })
+    }
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use futures::TryStreamExt;
+
+    use crate::options::{OptionChainRequestBuilder, OptionSymbol, OptionType};
+    use crate::testing::fake_client;
+    use crate::transport::FakeTransport;
+
+    #[test]
+    fn test_occ_symbols() {
+        let symbol = "AAPL240419C00100000".parse::<OptionSymbol>().unwrap();
+        assert_eq!("AAPL", symbol.root);
+        assert_eq!(NaiveDate::from_ymd_opt(2024, 4, 19).unwrap(), symbol.expiration);
+        assert_eq!(OptionType::Call, symbol.kind);
+        assert!((symbol.strike - 100.0).abs() < 1e-9);
+        assert_eq!("AAPL240419C00100000", symbol.to_string());
+
+        let symbol = "SPXW240419P04512500".parse::<OptionSymbol>().unwrap();
+        assert!((symbol.strike - 4512.5).abs() < 1e-9);
+        assert_eq!("SPXW240419P04512500", symbol.to_string());
+
+        assert!("AAPL".parse::<OptionSymbol>().is_err());
+        assert!("AAPL241319C00100000".parse::<OptionSymbol>().is_err());
+        assert!("AAPL240419X00100000".parse::<OptionSymbol>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_option_chain() {
+        let chain = r#"{"snapshots":{
+            "AAPL240419P00100000":{"latestQuote":{"ap":0.05,"as":10,"ax":"C","bp":0.01,"bs":5,"bx":"X","c":"A","t":"2024-04-10T19:59:59Z"}},
+            "AAPL240419C00100000":{"greeks":{"delta":0.99,"gamma":0.0,"rho":0.02,"theta":-0.01,"vega":0.0},"impliedVolatility":0.61,
+                "latestTrade":{"c":"I","p":68.5,"s":1,"t":"2024-04-10T19:30:00Z","x":"N"}}
+            },"next_page_token":null}"#;
+        let transport = FakeTransport::new()
+            .respond("GET", "/v1beta1/options/snapshots/AAPL", 200, chain);
+        let client = fake_client(&transport);
+
+        let request = OptionChainRequestBuilder::default()
+            .expiration_date(NaiveDate::from_ymd_opt(2024, 4, 19).unwrap())
+            .build().unwrap();
+        let chain = client.option_chain("AAPL", &request).try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(2, chain.len());
+        assert_eq!(OptionType::Call, chain[0].0.kind);
+        assert_eq!(Some(0.61), chain[0].1.implied_volatility);
+        assert_eq!(10, chain[1].1.latest_quote.as_ref().unwrap().ask_size);
+        assert!(transport.requests()[0].url.contains("expiration_date=2024-04-19"));
+    }
+}
@@ -0,0 +1,229 @@
+//! This module maintains a local copy of the state of your orders.
+//!
+//! The `OrderBook` consumes the `OrderUpdate`s of the trade_updates stream
+//! and keeps the latest known version of each order (status, filled quantity,
+//! average fill price...), indexed by order id. This way, the strategy can
+//! query the state of its orders without hitting the REST api. Each update
+//! which modifies the book is reported as an `OrderChange`; which is both
+//! returned by `apply` and passed to the listeners registered with
+//! `on_change`.
+//!
+//! The book implements `OrderUpdateHandler`, hence it can be driven directly
+//! by the streaming client.
+//!
+//! ```no_run
+//! # async fn example(client: apca_datav2::rest::Client) -> Result<(), apca_datav2::errors::Error> {
+//! use apca_datav2::orderbook::OrderBook;
+//! use apca_datav2::streaming::{Client, MessageStream};
+//!
+//! let mut book = OrderBook::new()
+//!     .on_change(|change, order| println!("{} {:?} -> {:?}", order.symbol, change.previous, change.status));
+//! // start with the orders which are already open
+//! book.sync(&client).await?;
+//!
+//! let mut updates = Client::paper().await?;
+//! updates.authenticate("key".to_string(), "secret".to_string()).await?;
+//! updates.listen(vec![MessageStream::TradeUpdates]).await?;
+//! updates.run(&mut book).await;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+use futures::TryStreamExt;
+
+use crate::entities::{OrderData, OrderStatus};
+use crate::errors::Error;
+use crate::handlers::OrderUpdateHandler;
+use crate::orders::ListOrderRequest;
+use crate::rest::Client;
+use crate::streaming::OrderUpdate;
+
+/// Describes how an order was modified by an update
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderChange {
+    /// The id of the order
+    pub id: String,
+    /// The status of the order before the update (None when the order was
+    /// not known yet)
+    pub previous: Option<OrderStatus>,
+    /// The status of the order after the update
+    pub status: OrderStatus,
+    /// The quantity which was filled by this update
+    pub filled: f64,
+}
+
+/// A listener notified of each change of the book
+type Listener = Box<dyn FnMut(&OrderChange, &OrderData) + Send>;
+
+/// The latest known state of the orders, indexed by order id
+#[derive(Default)]
+pub struct OrderBook {
+    orders   : HashMap<String, OrderData>,
+    listeners: Vec<Listener>,
+}
+impl Debug for OrderBook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OrderBook").field("orders", &self.orders).finish()
+    }
+}
+impl OrderBook {
+    /// Creates an empty book
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Registers a listener which is notified of each change of the book
+    pub fn on_change<F>(mut self, listener: F) -> Self
+        where F: FnMut(&OrderChange, &OrderData) + Send + 'static
+    {
+        self.listeners.push(Box::new(listener));
+        self
+    }
+    /// Loads the open orders of the account into the book (all the pages
+    /// of them)
+    pub async fn sync(&mut self, client: &Client) -> Result<(), Error> {
+        let orders = client.orders(&ListOrderRequest::default());
+        futures::pin_mut!(orders);
+        while let Some(order) = orders.try_next().await? {
+            self.insert(order);
+        }
+        Ok(())
+    }
+    /// Inserts (or refreshes) an order in the book. The insertion is ignored
+    /// when the book already knows a more recent version of that order.
+    pub fn insert(&mut self, order: OrderData) -> Option<OrderChange> {
+        let previous = self.orders.get(&order.id);
+        if let Some(known) = previous {
+            if known.updated_at > order.updated_at {
+                return None;
+            }
+        }
+        let change = OrderChange {
+            id      : order.id.clone(),
+            previous: previous.map(|o| o.status),
+            status  : order.status,
+            filled  : order.filled_qty - previous.map(|o| o.filled_qty).unwrap_or(0.0),
+        };
+        for listener in self.listeners.iter_mut() {
+            listener(&change, &order);
+        }
+        self.orders.insert(order.id.clone(), order);
+        Some(change)
+    }
+    /// Applies an update received on the trade_updates stream
+    pub fn apply(&mut self, update: &OrderUpdate) -> Option<OrderChange> {
+        self.insert(update.order().clone())
+    }
+    /// Returns the order having the given id
+    pub fn get(&self, id: &str) -> Option<&OrderData> {
+        self.orders.get(id)
+    }
+    /// Returns the order having the given client order id
+    pub fn by_client_id(&self, client_order_id: &str) -> Option<&OrderData> {
+        self.orders.values().find(|o| o.client_order_id == client_order_id)
+    }
+    /// Iterates over all the orders of the book
+    pub fn iter(&self) -> impl Iterator<Item=&OrderData> {
+        self.orders.values()
+    }
+    /// Iterates over the orders which may still be (partially) executed
+    pub fn open(&self) -> impl Iterator<Item=&OrderData> {
+        self.iter().filter(|o| o.status.is_open())
+    }
+    /// Iterates over the orders of the given symbol
+    pub fn by_symbol<'a>(&'a self, symbol: &'a str) -> impl Iterator<Item=&'a OrderData> {
+        self.iter().filter(move |o| o.symbol == symbol)
+    }
+    /// Removes the orders which can no longer be executed
+    pub fn purge_closed(&mut self) {
+        self.orders.retain(|_, o| o.status.is_open());
+    }
+    /// The number of orders in the book
+    pub fn len(&self) -> usize {
+        self.orders.len()
+    }
+    /// Returns true iff the book contains no order
+    pub fn is_empty(&self) -> bool {
+        self.orders.is_empty()
+    }
+}
+impl OrderUpdateHandler for OrderBook {
+    fn on_update(&mut self, update: &OrderUpdate) {
+        self.apply(update);
+    }
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::entities::OrderStatus;
+    use crate::orderbook::OrderBook;
+    use crate::orders::ORDERS_PAGE_SIZE;
+    use crate::streaming::{OrderUpdate, Response};
+    use crate::testing::{ORDER, fake_client};
+    use crate::transport::FakeTransport;
+
+    fn update(event: &str, status: &str, filled: &str, updated_at: &str) -> OrderUpdate {
+        let order = ORDER
+            .replace(r#""filled_qty":"0""#, &format!(r#""filled_qty":"{}""#, filled))
+            .replace(r#""status":"new""#, &format!(r#""status":"{}""#, status))
+            .replace(r#""updated_at":null"#, &format!(r#""updated_at":"{}""#, updated_at));
+        let txt = format!(r#"{{"stream":"trade_updates","data":{{"event":"{}","price":"333.16","qty":"1","position_qty":"1",
+            "timestamp":"2021-11-11T17:11:17.557793708Z","order":{}}}}}"#, event, order);
+        match serde_json::from_str::<Response>(&txt).unwrap() {
+            Response::TradeUpdates { data } => data,
+            other => panic!("unexpected response {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_book_follows_updates() {
+        let changes  = Arc::new(Mutex::new(vec![]));
+        let recorded = changes.clone();
+        let mut book = OrderBook::new()
+            .on_change(move |change, _| recorded.lock().unwrap().push(change.clone()));
+
+        let new     = update("new",          "new",              "0", "2021-11-11T17:11:17Z");
+        let partial = update("partial_fill", "partially_filled", "1", "2021-11-11T17:11:18Z");
+        let fill    = update("fill",         "filled",           "2", "2021-11-11T17:11:19Z");
+
+        book.apply(&new);
+        book.apply(&partial);
+        assert_eq!(1, book.open().count());
+        let change = book.apply(&fill).unwrap();
+        assert_eq!(Some(OrderStatus::PartiallyFilled), change.previous);
+        assert!((change.filled - 1.0).abs() < 1e-9);
+        // an out of order update is ignored
+        assert!(book.apply(&partial).is_none());
+
+        assert_eq!(OrderStatus::Filled, book.get("810f77c9").unwrap().status);
+        assert_eq!("810f77c9", book.by_client_id("ad1a656c").unwrap().id);
+        assert_eq!(1, book.by_symbol("MSFT").count());
+        assert_eq!(0, book.open().count());
+        assert_eq!(3, changes.lock().unwrap().len());
+
+        book.purge_closed();
+        assert!(book.is_empty());
+    }
+    #[tokio::test]
+    async fn test_sync_loads_all_the_pages() {
+        let page      = (0..ORDERS_PAGE_SIZE).map(|i| ORDER.replace("810f77c9", &format!("o{}", i))).collect::<Vec<_>>();
+        let transport = FakeTransport::new()
+            .respond_once("GET", "/v2/orders", 200, &format!("[{}]", page.join(",")))
+            .respond("GET", "/v2/orders", 200, &format!("[{}]", ORDER));
+        let client    = fake_client(&transport);
+        let mut book  = OrderBook::new();
+        book.sync(&client).await.unwrap();
+
+        assert_eq!(ORDERS_PAGE_SIZE as usize + 1, book.len());
+        assert!(book.get("810f77c9").is_some());
+        assert_eq!(2, transport.requests().len());
+    }
+}
@@ -17,22 +17,115 @@
 //! 
 //! Please note that body parameters should be passed using a JSON encoded body.
 
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
 use chrono::{DateTime, Utc};
+use futures::{Future, Stream, StreamExt};
 use serde::{Deserialize, Serialize};
 use derive_builder::Builder;
 
-use crate::{entities::{CancelationStatus, CancellationData, Direction, OrderClass, OrderData, OrderSide, OrderType, TimeInForce}, errors::{Error, OrderError, maybe_convert_to_order_error, status_code_to_order_error}, rest::Client};
+use crate::{entities::{AssetClass, AssetData, CancelationStatus, CancellationData, Direction, OrderClass, OrderData, OrderDataBuilder, OrderSide, OrderStatus, OrderType, TimeInForce}, errors::{Error, OrderError, PositionError, api_error, maybe_convert_to_order_error, request_info, status_code_to_order_error}, rest::{Client, FetchNextPage, Paged, PagedStream}, streaming::Response, endpoints::Endpoint};
+
+/// The number of orders fetched per page when the request sets no limit
+pub const ORDERS_PAGE_SIZE: u32 = 500;
+/// The maximum length of a client order id accepted by Alpaca
+pub const MAX_CLIENT_ORDER_ID_LEN: usize = 48;
+/// The number of times `place_order_idempotent` attempts to place an order
+pub const SUBMIT_ATTEMPTS: usize = 3;
+/// The interval at which `place_and_await_fill` polls the order
+pub const FILL_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// The maximum number of decimals of a fractional qty
+pub const MAX_QTY_DECIMALS: usize = 9;
+/// The prefix of the client order ids assigned by `place_order`
+pub const CLIENT_ORDER_ID_PREFIX: &str = "apca";
+
+/// Generates a collision-resistant client order id: the given prefix, 
+/// followed by the current time (in microseconds), a sequence number and 
+/// random bits. The prefix is truncated (on a character boundary) when the
+/// id would otherwise exceed `MAX_CLIENT_ORDER_ID_LEN` bytes.
+///
+/// ```
+/// let id = apca_datav2::orders::client_order_id("momentum");
+/// assert!(id.starts_with("momentum-"));
+/// ```
+pub fn client_order_id(prefix: &str) -> String {
+  static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+  let mut random = RandomState::new().build_hasher();
+  random.write_u64(SEQUENCE.fetch_add(1, Ordering::Relaxed));
+  let suffix = format!("{:x}-{:016x}", Utc::now().timestamp_micros(), random.finish());
+  let mut len = prefix.len().min(MAX_CLIENT_ORDER_ID_LEN - suffix.len() - 1);
+  while !prefix.is_char_boundary(len) {
+    len -= 1;
+  }
+  format!("{}-{}", &prefix[..len], suffix)
+}
+
+/// Private: rounds a qty to the `MAX_QTY_DECIMALS` decimals Alpaca accepts
+/// (e.g. 0.1 + 0.2 is 0.30000000000000004)
+fn round_qty(qty: f64) -> f64 {
+  let scale = 10f64.powi(MAX_QTY_DECIMALS as i32);
+  (qty * scale).round() / scale
+}
 
-/// Path to the orders endpoint (used to list and place orders)
-pub const ORDERS: &str = "v2/orders";
+/// Private: returns an order placed in dry-run mode as if the server had 
+/// accepted it
+#[allow(clippy::result_large_err)]
+fn dry_run_order(request: PlaceOrderRequest) -> Result<OrderData, Error> {
+  let asset_class = if request.symbol.contains('/') { AssetClass::Crypto } else { AssetClass::UsEquity };
+  let now = Utc::now();
+  let order = OrderDataBuilder::default()
+    .id(client_order_id("dry-run"))
+    .client_order_id(request.client_order_id.unwrap_or_default())
+    .created_at(now)
+    .submitted_at(Some(now))
+    .asset_id(String::new())
+    .symbol(request.symbol)
+    .asset_class(asset_class)
+    .qty(request.qty)
+    .notional(request.notional)
+    .filled_qty(0.0)
+    .order_class(request.order_class)
+    .order_type(request.order_type)
+    .side(request.side)
+    .time_in_force(request.time_in_force)
+    .limit_price(request.limit_price)
+    .stop_price(request.stop_price)
+    .trail_price(request.trail_price)
+    .trail_percent(request.trail_percent)
+    .status(OrderStatus::Accepted)
+    .extended_hours(request.extended_hours)
+    .build()?;
+  Ok(order)
+}
 
 impl Client {
+  /// This stream returns all the orders matching the request, going through
+  /// the several "pages" of the list asynchronously; upon request. Hence, it
+  /// iterates over the full history of the account, well beyond the 500
+  /// orders a single call to `list_orders` is capped to. The limit of the
+  /// request is used as the page size (`ORDERS_PAGE_SIZE` when none is set).
+  /// Each page overlaps the previous one by the submission time of its last
+  /// order (so that the orders submitted at the very same time are not
+  /// skipped) and the orders which were already yielded are dropped. Should a
+  /// page fail to be fetched, the stream yields the error, then ends.
+  pub fn orders<'a>(&'a self, request: &ListOrderRequest) -> impl Stream<Item=Result<OrderData, Error>> + 'a {
+    let mut request = request.clone();
+    request.limit   = Some(request.limit.unwrap_or(ORDERS_PAGE_SIZE));
+    PagedStream::new(FetchNextOrders { client: self, request }).prefetch(self.prefetches())
+  }
+
   /// Retrieves a list of orders for the account, filtered by the supplied 
   /// query parameters.
   pub async fn list_orders(&self, request: &ListOrderRequest) -> Result<Vec<OrderData>, Error> {
-    let url = format!("{}/{}", self.env_url(), ORDERS);
+    let url = Endpoint::Orders.url(self.env_url());
+    let mut request = request.clone();
+    request.symbols = request.symbols.map(|s| self.symbols().normalize_list(&s));
     let rsp = self.get_authenticated(&url)
-      .query(request)
+      .query(&request)
       .send().await
       .map_err(maybe_convert_to_order_error)?;
     status_code_to_order_error(rsp).await
@@ -41,23 +134,191 @@ impl Client {
   /// Places a new order for the given account. An order request may be 
   /// rejected if the account is not authorized for trading, or if the tradable
   /// balance is insufficient to fill the order.
+  ///
+  /// The request is validated first: an inconsistent request fails with
+  /// `Error::InvalidOrder` without reaching the server. The order is given a
+  /// client order id (see `client_order_id`) unless it already has one; that
+  /// id is part of the returned order.
   pub async fn place_order(&self, request: &PlaceOrderRequest) -> Result<OrderData, Error> {
-    let url = format!("{}/{}", self.env_url(), ORDERS);
+    request.validate()?;
+    if self.preflights() {
+      self.preflight(request).await?;
+    }
+    let url = Endpoint::Orders.url(self.env_url());
+    let mut request = request.clone();
+    request.symbol  = self.symbols().normalize_target(&request.symbol.as_str().into());
+    request.qty     = request.qty.map(round_qty);
+    request.client_order_id.get_or_insert_with(|| client_order_id(CLIENT_ORDER_ID_PREFIX));
+    if self.dry_runs_orders() {
+      self.post_authenticated(&url).json(&request).dry_run()?;
+      return dry_run_order(request);
+    }
     let rsp = self.post_authenticated(&url)
-      .json(request)
+      .json(&request)
       .send().await
       .map_err(maybe_convert_to_order_error)?;
     status_code_to_order_error(rsp).await
   }
 
+  /// Checks that the asset of the given order is tradable and, when the 
+  /// order is fractional, fractionable (see `PlaceOrderRequest::validate_asset`). 
+  /// This costs a round trip to the server, but spares a cryptic rejection.
+  pub async fn check_fractionable(&self, request: &PlaceOrderRequest) -> Result<(), Error> {
+    let asset = self.get_asset(&request.symbol).await?;
+    request.validate_asset(&asset)
+  }
+
+  /// Checks the given order against the asset record before it is 
+  /// submitted: the asset must be tradable (and fractionable for a 
+  /// fractional order). A sell exceeding the long position held in the asset
+  /// is a short sale: the asset must then be shortable and easy to borrow.
+  /// This is done by `place_order` when the pre-trade checks are enabled 
+  /// (see `with_preflight`).
+  pub async fn preflight(&self, request: &PlaceOrderRequest) -> Result<(), Error> {
+    let asset = self.get_asset(&request.symbol).await?;
+    request.validate_asset(&asset)?;
+    if let (OrderSide::Sell, Some(qty)) = (request.side, request.qty) {
+      let held = match self.get_open_position(&request.symbol).await {
+        Ok(position)                                      => position.qty.max(0.0),
+        Err(Error::Position { kind: PositionError::NotFound, .. }) => 0.0,
+        Err(e)                                            => return Err(e),
+      };
+      if qty > held {
+        request.validate_short(&asset)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Places a new order without risking a duplicate. The order is given a 
+  /// client order id (unless it already has one). When the outcome of the 
+  /// submission is unknown (timeout, connection lost, gateway error...), the
+  /// order is looked up by its client order id rather than blindly sent
+  /// again: it is returned if the server did receive it; otherwise the 
+  /// submission is attempted again (at most `SUBMIT_ATTEMPTS` times). Should
+  /// a new attempt be rejected (e.g. because the client order id is already
+  /// taken), the order is looked up once more before failing.
+  pub async fn place_order_idempotent(&self, request: &PlaceOrderRequest) -> Result<OrderData, Error> {
+    let mut request = request.clone();
+    let id = request.client_order_id
+      .get_or_insert_with(|| client_order_id(CLIENT_ORDER_ID_PREFIX))
+      .clone();
+    let mut attempt = 1;
+    loop {
+      match self.place_order(&request).await {
+        Err(e) if attempt < SUBMIT_ATTEMPTS && is_outcome_unknown(&e) => {
+          match self.get_by_client_id(&id).await {
+            Ok(order)                                   => return Ok(order),
+            Err(Error::Order { kind: OrderError::NotFound, .. }) => attempt += 1,
+            Err(e)                                      => return Err(e),
+          }
+        },
+        // a previous attempt may have reached the server after all
+        Err(e @ Error::Order { kind: OrderError::Unprocessable, .. }) if attempt > 1 =>
+          return self.get_by_client_id(&id).await.map_err(|_| e),
+        result => return result,
+      }
+    }
+  }
+
+  /// Buys the given qty of a symbol at market price (day order)
+  pub async fn market_buy(&self, symbol: &str, qty: f64) -> Result<OrderData, Error> {
+    self.place_shortcut(symbol, qty, OrderSide::Buy, None).await
+  }
+  /// Sells the given qty of a symbol at market price (day order)
+  pub async fn market_sell(&self, symbol: &str, qty: f64) -> Result<OrderData, Error> {
+    self.place_shortcut(symbol, qty, OrderSide::Sell, None).await
+  }
+  /// Buys the given qty of a symbol at the given price or lower (day order)
+  pub async fn limit_buy(&self, symbol: &str, qty: f64, limit_price: f64) -> Result<OrderData, Error> {
+    self.place_shortcut(symbol, qty, OrderSide::Buy, Some(limit_price)).await
+  }
+  /// Sells the given qty of a symbol at the given price or higher (day order)
+  pub async fn limit_sell(&self, symbol: &str, qty: f64, limit_price: f64) -> Result<OrderData, Error> {
+    self.place_shortcut(symbol, qty, OrderSide::Sell, Some(limit_price)).await
+  }
+  /// Private: places a simple market (or limit, when a limit price is given)
+  /// day order
+  async fn place_shortcut(&self, symbol: &str, qty: f64, side: OrderSide, limit_price: Option<f64>) -> Result<OrderData, Error> {
+    let mut request = PlaceOrderRequestBuilder::default();
+    request.symbol(symbol.to_string()).qty(qty).side(side);
+    if let Some(price) = limit_price {
+      request.order_type(OrderType::Limit).limit_price(price);
+    }
+    let request = request.build()
+      .map_err(|e| Error::InvalidOrder(e.to_string()))?;
+    self.place_order(&request).await
+  }
+
+  /// Places a new order, then waits until it is done (filled, canceled, 
+  /// rejected, expired...) and returns its final state. The order is polled
+  /// every `FILL_POLL_INTERVAL`; when it is still open after the given 
+  /// timeout, this fails with `Error::OrderTimeout` (and the order is left
+  /// untouched). A dry-run order is returned as soon as it is placed.
+  pub async fn place_and_await_fill(&self, request: &PlaceOrderRequest, timeout: Duration) -> Result<OrderData, Error> {
+    let order = self.place_order(request).await?;
+    if self.dry_runs_orders() {
+      return Ok(order);
+    }
+    tokio::time::timeout(timeout, self.poll_until_done(&order.id)).await
+      .unwrap_or(Err(Error::OrderTimeout(order.id.clone(), timeout)))
+  }
+
+  /// Same as `place_and_await_fill`, except that the final state of the order
+  /// is read from the given trade_updates stream rather than polled. The 
+  /// stream must be subscribed to before the order is placed, lest the 
+  /// updates be missed. Since the updates sent while the stream reconnects
+  /// are lost, the order is fetched upon reconnection; and should the stream
+  /// end before the order is done, the order is polled instead.
+  pub async fn place_and_await_fill_on<S>(&self, request: &PlaceOrderRequest, updates: S, timeout: Duration) -> Result<OrderData, Error>
+    where S: Stream<Item=Result<Response, Error>>
+  {
+    let order = self.place_order(request).await?;
+    if self.dry_runs_orders() {
+      return Ok(order);
+    }
+    let wait = async {
+      futures::pin_mut!(updates);
+      while let Some(item) = updates.next().await {
+        match item {
+          Ok(Response::TradeUpdates { data }) if data.order().id == order.id && !data.order().status.is_open() => 
+            return Ok(data.order().clone()),
+          Ok(Response::Reconnected) => {
+            let current = self.get_by_id(&order.id, false).await?;
+            if !current.status.is_open() {
+              return Ok(current);
+            }
+          },
+          _ => (),
+        }
+      }
+      self.poll_until_done(&order.id).await
+    };
+    tokio::time::timeout(timeout, wait).await
+      .unwrap_or(Err(Error::OrderTimeout(order.id.clone(), timeout)))
+  }
+
+  /// Private: polls the given order every `FILL_POLL_INTERVAL` until it is
+  /// done, then returns its final state
+  async fn poll_until_done(&self, id: &str) -> Result<OrderData, Error> {
+    loop {
+      let current = self.get_by_id(id, false).await?;
+      if !current.status.is_open() {
+        return Ok(current);
+      }
+      tokio::time::sleep(FILL_POLL_INTERVAL).await;
+    }
+  }
+
   /// Retrieves a single order for the given order_id. 
   /// 
   /// ## Parameters
   /// - id: the order uuid
   /// - nested: If true, the result will roll up multi-leg orders under the 
-  ///     legs field of primary order.
+  ///   legs field of primary order (see `OrderData::leg_orders`). Otherwise,
+  ///   the legs have to be fetched separately.
   pub async fn get_by_id(&self, id: &str, nested: bool) -> Result<OrderData, Error> {
-    let url = format!("{}/{}/{}", self.env_url(), ORDERS, id);
+    let url = Endpoint::Order(id).url(self.env_url());
     let rsp = self.get_authenticated(&url)
       .query(&[("nested", nested)])
       .send().await
@@ -70,7 +331,7 @@ impl Client {
   /// ## Parameters
   /// - id: the client order-id
   pub async fn get_by_client_id(&self, id: &str) -> Result<OrderData, Error> {
-    let url = format!("{}/{}:by_client_order_id", self.env_url(), ORDERS);
+    let url = Endpoint::OrderByClientId.url(self.env_url());
     let rsp = self.get_authenticated(&url)
       .query(&[("client_order_id", id)])
       .send().await
@@ -95,8 +356,21 @@ impl Client {
   /// power is calculated based on the newly placed order. If you are replacing 
   /// it with a lower limit price, the buying power is calculated based on the 
   /// old order.
-  pub async fn replace(&self, id: &str, replacement: &ReplacementRequest) -> Result<OrderData, Error> {
-    let url = format!("{}/{}/{}", self.env_url(), ORDERS, id);
+  ///
+  /// ```no_run
+  /// # async fn example(client: apca_datav2::rest::Client) -> Result<(), apca_datav2::errors::Error> {
+  /// use apca_datav2::orders::ReplaceOrderRequestBuilder;
+  ///
+  /// let replacement = ReplaceOrderRequestBuilder::default()
+  ///   .limit_price(101.5)
+  ///   .build().unwrap();
+  /// let order = client.replace_order("810f77c9", &replacement).await?;
+  /// println!("{} replaces {:?}", order.id, order.replaces);
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub async fn replace_order(&self, id: &str, replacement: &ReplaceOrderRequest) -> Result<OrderData, Error> {
+    let url = Endpoint::Order(id).url(self.env_url());
     let rsp = self.patch_authenticated(&url)
       .json(replacement)
       .send().await
@@ -104,15 +378,24 @@ impl Client {
     status_code_to_order_error(rsp).await
   } 
 
+  /// Replaces a single order with updated parameters (see `replace_order`).
+  #[deprecated(note = "use replace_order")]
+  #[allow(deprecated)]
+  pub async fn replace(&self, id: &str, replacement: &ReplacementRequest) -> Result<OrderData, Error> {
+    self.replace_order(id, &replacement.into()).await
+  }
+
   /// Attempts to cancel all open orders. A response will be provided for 
   /// each order that is attempted to be cancelled. If an order is no longer 
   /// cancelable, the server will respond with status 500 and reject the request.
   /// 
   /// Response
   /// HTTP 207 Multi-Status with body; an array of objects that include the 
-  /// order id and http status code for each status request.
+  /// order id and http status code for each status request. Each of them is
+  /// decoded along with the order (or the error) the server sent back; so 
+  /// that you know exactly which cancelations failed and why.
   pub async fn cancel_all_orders(&self) -> Result<Vec<CancellationData>, Error> {
-    let url = format!("{}/{}", self.env_url(), ORDERS);
+    let url = Endpoint::Orders.url(self.env_url());
     let rsp = self.delete_authenticated(&url)
       .send().await
       .map_err(maybe_convert_to_order_error)?;
@@ -124,20 +407,102 @@ impl Client {
   /// reject the request. Upon acceptance of the cancel request, it returns 
   /// status 204.
   pub async fn cancel_by_id(&self, id: &str) -> Result<CancelationStatus, Error> {
-    let url = format!("{}/{}/{}", self.env_url(), ORDERS, id);
+    let url = Endpoint::Order(id).url(self.env_url());
     let rsp = self.delete_authenticated(&url)
       .send().await
       .map_err(maybe_convert_to_order_error)?;
 
+    let request = request_info(&rsp);
     match rsp.status().as_u16() {
       200 => Ok(CancelationStatus::Success),
       204 => Ok(CancelationStatus::NoContent),
-      403 => Err(Error::Order(OrderError::Forbidden)),
-      404 => Err(Error::Order(OrderError::NotFound)),
-      422 => Err(Error::Order(OrderError::Unprocessable)),
-      500 => Err(Error::Order(OrderError::InternalError)),
-      s   => Err(Error::Unexpected(s))
+      403 => Err(Error::Order { kind: OrderError::Forbidden, api: api_error(rsp).await, request }),
+      404 => Err(Error::Order { kind: OrderError::NotFound, api: api_error(rsp).await, request }),
+      422 => Err(Error::Order { kind: OrderError::Unprocessable, api: api_error(rsp).await, request }),
+      500 => Err(Error::Order { kind: OrderError::InternalError, api: api_error(rsp).await, request }),
+      s   => Err(Error::Unexpected { status: s, api: api_error(rsp).await, request })
+    }
+  }
+}
+
+/// Private: returns true iff the given error leaves it unknown whether an 
+/// order was placed or not
+fn is_outcome_unknown(error: &Error) -> bool {
+  match error {
+    Error::HttpError(e)                                  => e.is_timeout() || e.is_connect() || e.is_request(),
+    Error::Order { kind: OrderError::InternalError, .. } => true,
+    Error::Unexpected { status, .. }                     => *status >= 500,
+    _                                                    => false,
+  }
+}
+
+/// One page of the list of orders
+struct OrdersPage {
+  orders: Vec<OrderData>,
+  /// Whether the page was full (a shorter page is the last one)
+  full  : bool,
+  /// The boundary of the previous page (the time of its last order along
+  /// with the ids of the orders submitted at that time)
+  seen  : Option<(DateTime<Utc>, Vec<String>)>,
+}
+impl Paged for OrdersPage {
+  type Item = OrderData;
+  fn split(self) -> (Vec<Self::Item>, Option<String>) {
+    let token = match self.orders.last() {
+      Some(last) if self.full => {
+        let time    = order_time(last);
+        let mut ids = match self.seen {
+          Some((t, ids)) if t == time => ids,
+          _                           => vec![],
+        };
+        ids.extend(self.orders.iter().filter(|o| order_time(o) == time).map(|o| o.id.clone()));
+        Some(format!("{}|{}", time.to_rfc3339(), ids.join(",")))
+      },
+      _ => None,
+    };
+    (self.orders, token)
+  }
+}
+/// The time used to page through the orders
+fn order_time(order: &OrderData) -> DateTime<Utc> {
+  order.submitted_at.unwrap_or(order.created_at)
+}
+/// Decodes a page token: the time of the last order of the previous page and
+/// the ids of the orders submitted at that time
+fn parse_orders_token(token: &str) -> Option<(DateTime<Utc>, Vec<String>)> {
+  let (time, ids) = token.split_once('|')?;
+  let time = DateTime::parse_from_rfc3339(time).ok()?.with_timezone(&Utc);
+  let ids  = ids.split(',').filter(|id| !id.is_empty()).map(|id| id.to_string()).collect();
+  Some((time, ids))
+}
+/// This structure encapsulates a call to `list_orders` and yields a future
+/// that can be used to asychronously fetch the next orders page
+struct FetchNextOrders<'a> {
+  client : &'a Client,
+  request: ListOrderRequest,
+}
+impl <'a> FetchNextPage<'a, OrdersPage> for FetchNextOrders<'a> {
+  fn fetch(self: Pin<&Self>, token: Option<String>) -> Pin<Box<dyn Future<Output=Result<OrdersPage, Error>> + 'a >> {
+    let client      = self.client;
+    let mut request = self.request.clone();
+    let seen        = token.and_then(|t| parse_orders_token(&t));
+    // the bounds are exclusive: they are moved one microsecond past the last
+    // order so that the orders submitted at the same time are listed again
+    if let Some((time, _)) = &seen {
+      let one = chrono::Duration::microseconds(1);
+      match request.direction {
+        Some(Direction::Ascending) => request.after = Some(*time - one),
+        _                          => request.until = Some(*time + one),
+      }
     }
+    Box::pin(async move {
+      let mut orders = client.list_orders(&request).await?;
+      let full       = orders.len() >= request.limit.unwrap_or(ORDERS_PAGE_SIZE) as usize;
+      if let Some((_, ids)) = &seen {
+        orders.retain(|o| !ids.contains(&o.id));
+      }
+      Ok(OrdersPage { orders, full, seen })
+    })
   }
 }
 
@@ -153,7 +518,7 @@ pub enum SearchOrderStatus {
 }
 
 /// List Order Requests
-#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[derive(Builder, Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ListOrderRequest {
   /// Order status to be queried. open, closed or all. Defaults to open.
   #[builder(setter(strip_option), default="None")]
@@ -177,7 +542,8 @@ pub struct ListOrderRequest {
   #[serde(skip_serializing_if = "Option::is_none")]
   pub direction: Option<Direction>,
   /// If true, the result will roll up multi-leg orders under the legs field 
-  /// of primary order.
+  /// of primary order. Otherwise, the legs are listed as distinct orders and
+  /// their relationship with the primary order is lost.
   #[builder(setter(strip_option), default="None")]
   #[serde(skip_serializing_if = "Option::is_none")]
   pub nested: Option<bool>,
@@ -186,10 +552,19 @@ pub struct ListOrderRequest {
   #[builder(setter(strip_option), default="None")]
   #[serde(skip_serializing_if = "Option::is_none")]
   pub symbols: Option<String>,
+  /// Only the orders on this side (buy or sell) are returned
+  #[builder(setter(strip_option), default="None")]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub side: Option<OrderSide>,
 }
 
 /// Place Order Requests
+///
+/// The consistency of the request is checked upon build (see `validate`):
+/// e.g. a bracket or an OCO order needs both a take-profit and a stop-loss 
+/// leg, an OTO order needs exactly one of them and a simple order needs none.
 #[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+#[builder(build_fn(private, name = "build_unchecked"))]
 pub struct PlaceOrderRequest {
   /// symbol, asset ID, or currency pair to identify the asset to trade
   pub symbol: String,
@@ -222,13 +597,16 @@ pub struct PlaceOrderRequest {
   /// this or trail_percent is required if type is trailing_stop
   #[builder(setter(strip_option))]
   #[builder(default="None")]
+  #[serde(skip_serializing_if = "Option::is_none")]
   pub trail_price: Option<f64>,
-  /// this or trail_percent is required if type is trailing_stop
+  /// this or trail_price is required if type is trailing_stop
   #[builder(setter(strip_option))]
   #[builder(default="None")]
+  #[serde(skip_serializing_if = "Option::is_none")]
   pub trail_percent: Option<f64>,
   /// (default) false. If true, order will be eligible to execute in 
-  /// premarket/afterhours. Only works with type limit and time_in_force day.
+  /// premarket/afterhours. Only works with type limit and time_in_force day
+  /// (which is checked upon build).
   #[builder(default="false")]
   pub extended_hours: bool,
   /// A unique identifier for the order. Automatically generated if not sent.
@@ -239,6 +617,258 @@ pub struct PlaceOrderRequest {
   /// please see Bracket Order Overview
   #[builder(default="crate::entities::OrderClass::Simple")]
   pub order_class: OrderClass,
+  /// the take-profit leg of a bracket order
+  #[builder(setter(strip_option), default="None")]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub take_profit: Option<TakeProfitRequest>,
+  /// the stop-loss leg of a bracket order
+  #[builder(setter(strip_option), default="None")]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub stop_loss: Option<StopLossRequest>,
+}
+impl PlaceOrderRequestBuilder {
+  /// Turns the order into a bracket order: once the entry order is filled,
+  /// a take-profit limit order (at `take_profit`) and a stop-loss order (at
+  /// `stop_loss`) are placed; whichever gets filled first cancels the other.
+  ///
+  /// ```
+  /// use apca_datav2::entities::{OrderClass, OrderSide};
+  /// use apca_datav2::orders::{PlaceOrderRequestBuilder, StopLossRequest};
+  ///
+  /// let order = PlaceOrderRequestBuilder::default()
+  ///   .symbol("AAPL".to_string()).qty(10.0).side(OrderSide::Buy)
+  ///   .bracket(160.0, StopLossRequest::stop_limit(140.0, 139.5))
+  ///   .build().unwrap();
+  /// assert!(matches!(order.order_class, OrderClass::Bracket));
+  /// ```
+  pub fn bracket(&mut self, take_profit: f64, stop_loss: StopLossRequest) -> &mut Self {
+    self.order_class(OrderClass::Bracket)
+      .take_profit(TakeProfitRequest { limit_price: take_profit })
+      .stop_loss(stop_loss)
+  }
+  /// Turns the order into a one-cancels-other order: an exit made of a 
+  /// take-profit limit order (at `take_profit`) and a stop-loss order; 
+  /// whichever gets filled first cancels the other. An OCO order is always
+  /// a limit order.
+  pub fn one_cancels_other(&mut self, take_profit: f64, stop_loss: StopLossRequest) -> &mut Self {
+    self.order_class(OrderClass::OneCancelsOther)
+      .order_type(OrderType::Limit)
+      .take_profit(TakeProfitRequest { limit_price: take_profit })
+      .stop_loss(stop_loss)
+  }
+  /// Turns the order into a one-triggers-other order: once the order is 
+  /// filled, the given exit leg is placed.
+  ///
+  /// ```
+  /// use apca_datav2::entities::{OrderSide, OrderType};
+  /// use apca_datav2::orders::{ExitLeg, PlaceOrderRequestBuilder, StopLossRequest};
+  ///
+  /// let order = PlaceOrderRequestBuilder::default()
+  ///   .symbol("AAPL".to_string()).qty(10.0).side(OrderSide::Buy)
+  ///   .order_type(OrderType::Limit).limit_price(150.0)
+  ///   .one_triggers_other(ExitLeg::StopLoss(StopLossRequest::stop(140.0)))
+  ///   .build().unwrap();
+  /// assert!(order.take_profit.is_none());
+  /// ```
+  pub fn one_triggers_other(&mut self, exit: ExitLeg) -> &mut Self {
+    self.order_class(OrderClass::OneTriggersOther);
+    match exit {
+      ExitLeg::TakeProfit(take_profit) => {
+        self.take_profit = Some(Some(take_profit));
+        self.stop_loss   = Some(None);
+      },
+      ExitLeg::StopLoss(stop_loss) => {
+        self.take_profit = Some(None);
+        self.stop_loss   = Some(Some(stop_loss));
+      },
+    }
+    self
+  }
+  /// Turns the order into a limit order at the given price
+  pub fn limit(&mut self, limit_price: f64) -> &mut Self {
+    self.order_type(OrderType::Limit).limit_price(limit_price)
+  }
+  /// Turns the order into a stop order: it becomes a market order once the
+  /// stop price is reached
+  pub fn stop(&mut self, stop_price: f64) -> &mut Self {
+    self.order_type(OrderType::Stop).stop_price(stop_price)
+  }
+  /// Turns the order into a stop-limit order: it becomes a limit order (at
+  /// `limit_price`) once the stop price is reached
+  ///
+  /// ```
+  /// use apca_datav2::entities::{OrderSide, OrderType};
+  /// use apca_datav2::orders::PlaceOrderRequestBuilder;
+  ///
+  /// let order = PlaceOrderRequestBuilder::default()
+  ///   .symbol("AAPL".to_string()).qty(10.0).side(OrderSide::Sell)
+  ///   .stop_limit(140.0, 139.5)
+  ///   .build().unwrap();
+  /// assert_eq!(OrderType::StopLimit, order.order_type);
+  /// ```
+  pub fn stop_limit(&mut self, stop_price: f64, limit_price: f64) -> &mut Self {
+    self.order_type(OrderType::StopLimit)
+      .stop_price(stop_price)
+      .limit_price(limit_price)
+  }
+  /// Turns the order into a trailing stop order whose stop price trails
+  /// the high water mark by the given dollar amount
+  pub fn trailing_stop_price(&mut self, trail_price: f64) -> &mut Self {
+    self.trail_percent = Some(None);
+    self.order_type(OrderType::TrailingStop).trail_price(trail_price)
+  }
+  /// Turns the order into a trailing stop order whose stop price trails
+  /// the high water mark by the given percentage
+  pub fn trailing_stop_percent(&mut self, trail_percent: f64) -> &mut Self {
+    self.trail_price = Some(None);
+    self.order_type(OrderType::TrailingStop).trail_percent(trail_percent)
+  }
+  /// Builds the request after checking its consistency (see 
+  /// `PlaceOrderRequest::validate`)
+  pub fn build(&self) -> Result<PlaceOrderRequest, PlaceOrderRequestBuilderError> {
+    let mut request = self.build_unchecked()?;
+    request.qty     = request.qty.map(round_qty);
+    request.validate()
+      .map_err(|e| PlaceOrderRequestBuilderError::ValidationError(e.to_string()))?;
+    Ok(request)
+  }
+}
+/// The exit leg of a one-triggers-other order
+#[derive(Debug, Clone, Copy)]
+pub enum ExitLeg {
+  /// A limit order taking the profit
+  TakeProfit(TakeProfitRequest),
+  /// A stop (or stop-limit) order limiting the loss
+  StopLoss(StopLossRequest),
+}
+impl PlaceOrderRequest {
+  /// Checks that the request abides by the rules of Alpaca, so that an 
+  /// inconsistent order is reported before any round trip to the server:
+  /// - an order has either a qty or a notional amount (not both), which is
+  ///   positive
+  /// - a limit (or stop limit) order has a limit price 
+  /// - a stop (or stop limit) order has a stop price
+  /// - a trailing stop order trails by either a price or a percentage
+  /// - only a day limit order can be eligible to the extended hours
+  /// - the legs of the order match its class
+  ///
+  /// The qty is rounded to `MAX_QTY_DECIMALS` decimals when the request is 
+  /// built (and when it is placed). Whether the asset is fractionable, and 
+  /// whether a fractional order must be a simple market day order (it must
+  /// for a stock, not for a crypto) depends on the asset, which can only be
+  /// told by the server (see `validate_asset` and `Client::check_fractionable`).
+  #[allow(clippy::result_large_err)]
+  pub fn validate(&self) -> Result<(), Error> {
+    self.validate_quantity()
+      .and_then(|_| self.validate_prices())
+      .and_then(|_| self.validate_trail())
+      .and_then(|_| self.validate_extended_hours())
+      .and_then(|_| self.validate_legs())
+      .map_err(|e| Error::InvalidOrder(e.to_string()))
+  }
+  /// Private: checks that the order has either a (positive) qty or a 
+  /// notional amount
+  fn validate_quantity(&self) -> Result<(), &'static str> {
+    match (self.qty, self.notional) {
+      (Some(_), Some(_)) => Err("an order cannot have both a qty and a notional amount"),
+      (None, None)       => Err("an order needs either a qty or a notional amount"),
+      (Some(q), _) | (_, Some(q)) if q.is_nan() || q <= 0.0 => Err("the qty (or notional amount) of an order must be positive"),
+      _                  => Ok(()),
+    }
+  }
+  /// Returns true iff the order trades a fraction of a share (that is, its
+  /// qty is not a whole number or it is expressed as a notional amount)
+  pub fn is_fractional(&self) -> bool {
+    self.notional.is_some() || self.qty.is_some_and(|q| q.fract() != 0.0)
+  }
+  /// Checks that the given asset can be traded by this order: it must be
+  /// tradable, and fractionable when the order is fractional. A fractional
+  /// (or notional) stock order must moreover be a simple market day order
+  /// (crypto orders are always fractional).
+  #[allow(clippy::result_large_err)]
+  pub fn validate_asset(&self, asset: &AssetData) -> Result<(), Error> {
+    let simple = matches!(self.order_class, OrderClass::Simple);
+    let market = self.order_type == OrderType::Market;
+    let day    = self.time_in_force == TimeInForce::Day;
+    if !asset.tradable {
+      Err(Error::InvalidOrder(format!("{} is not tradable", asset.symbol)))
+    } else if self.is_fractional() && !asset.fractionable {
+      Err(Error::InvalidOrder(format!("{} is not fractionable", asset.symbol)))
+    } else if self.is_fractional() && asset.class == AssetClass::UsEquity && !(simple && market && day) {
+      Err(Error::InvalidOrder("a fractional (or notional) order must be a simple market day order".to_string()))
+    } else {
+      Ok(())
+    }
+  }
+  /// Checks that the given asset can be sold short: it must be shortable 
+  /// and easy to borrow
+  #[allow(clippy::result_large_err)]
+  pub fn validate_short(&self, asset: &AssetData) -> Result<(), Error> {
+    if !asset.shortable {
+      Err(Error::InvalidOrder(format!("{} is not shortable", asset.symbol)))
+    } else if !asset.easy_to_borrow {
+      Err(Error::InvalidOrder(format!("{} is not easy to borrow", asset.symbol)))
+    } else {
+      Ok(())
+    }
+  }
+  /// Private: checks that the order has the prices its type requires
+  fn validate_prices(&self) -> Result<(), &'static str> {
+    let limit = matches!(self.order_type, OrderType::Limit | OrderType::StopLimit);
+    let stop  = matches!(self.order_type, OrderType::Stop  | OrderType::StopLimit);
+    // the limit price of an oco order is that of its take-profit leg
+    let oco   = matches!(self.order_class, OrderClass::OneCancelsOther);
+    if limit && !oco && self.limit_price.is_none() {
+      Err("a limit order needs a limit_price")
+    } else if stop && self.stop_price.is_none() {
+      Err("a stop order needs a stop_price")
+    } else {
+      Ok(())
+    }
+  }
+  /// Private: checks that a trailing stop order trails by either a price or
+  /// a percentage (and that the other orders do not trail at all)
+  fn validate_trail(&self) -> Result<(), &'static str> {
+    let trail_price   = self.trail_price.is_some();
+    let trail_percent = self.trail_percent.is_some();
+    match self.order_type {
+      OrderType::TrailingStop if trail_price == trail_percent =>
+        Err("a trailing stop order needs exactly one of trail_price or trail_percent"),
+      OrderType::TrailingStop => Ok(()),
+      _ if trail_price || trail_percent =>
+        Err("only a trailing stop order can have a trail_price or trail_percent"),
+      _ => Ok(()),
+    }
+  }
+  /// Private: checks that an order eligible to the extended hours is a day
+  /// limit order
+  fn validate_extended_hours(&self) -> Result<(), &'static str> {
+    let limit = self.order_type == OrderType::Limit;
+    let day   = self.time_in_force == TimeInForce::Day;
+    if self.extended_hours && !(limit && day) {
+      Err("only a day limit order can be eligible to the extended hours")
+    } else {
+      Ok(())
+    }
+  }
+  /// Private: checks that the legs match the order class
+  fn validate_legs(&self) -> Result<(), &'static str> {
+    let take_profit = self.take_profit.is_some();
+    let stop_loss   = self.stop_loss.is_some();
+    match self.order_class {
+      OrderClass::Bracket if !(take_profit && stop_loss) =>
+        Err("a bracket order needs both a take-profit and a stop-loss leg"),
+      OrderClass::OneCancelsOther if !(take_profit && stop_loss) =>
+        Err("an oco order needs both a take-profit and a stop-loss leg"),
+      OrderClass::OneCancelsOther if self.order_type != OrderType::Limit =>
+        Err("an oco order must be a limit order"),
+      OrderClass::OneTriggersOther if take_profit == stop_loss =>
+        Err("an oto order needs exactly one take-profit or stop-loss leg"),
+      OrderClass::Simple | OrderClass::Closure if take_profit || stop_loss =>
+        Err("a simple order cannot have any take-profit or stop-loss leg"),
+      _ => Ok(()),
+    }
+  }
 }
 /// Additional parameters for take-profit leg of advanced orders
 #[derive(Builder, Debug, Clone, Copy, Serialize, Deserialize)]
@@ -248,11 +878,24 @@ pub struct TakeProfitRequest {
 }
 /// Additional parameters for stop-loss leg of advanced orders
 #[derive(Builder, Debug, Clone, Copy, Serialize, Deserialize)]
-pub struct StopLoss {
+pub struct StopLossRequest {
   /// required for bracket orders
   pub stop_price: f64,
   /// the stop-loss order becomes a stop-limit order if specified
-  pub limit_price: f64,
+  #[builder(setter(strip_option), default="None")]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub limit_price: Option<f64>,
+}
+impl StopLossRequest {
+  /// A stop-loss leg which becomes a market order at the given stop price
+  pub fn stop(stop_price: f64) -> Self {
+    Self { stop_price, limit_price: None }
+  }
+  /// A stop-loss leg which becomes a limit order (at `limit_price`) at the
+  /// given stop price
+  pub fn stop_limit(stop_price: f64, limit_price: f64) -> Self {
+    Self { stop_price, limit_price: Some(limit_price) }
+  }
 }
 /// Replace Order Requests
 /// 
@@ -278,23 +921,479 @@ pub struct StopLoss {
 /// be reused multiple times without needing a mutable access to the replacement
 /// request.
 /// 
-#[derive(Builder, Debug, Clone, Serialize, Deserialize)]
-pub struct ReplacementRequest {
+#[derive(Builder, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReplaceOrderRequest {
   /// number of shares to trade
-  /// 
-  /// FIXME: (in the docs)
-  /// Does it make sense that this parameter be an integer rather than a plain
-  /// floating point number ?
-  pub qty: Option<u32>,
+  #[builder(setter(strip_option), default="None")]
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub qty: Option<f64>,
   /// day, gtc, opg, cls, ioc, fok. 
+  #[builder(setter(strip_option), default="None")]
+  #[serde(skip_serializing_if = "Option::is_none")]
   pub time_in_force: Option<TimeInForce>,
   /// required if type is limit or stop_limit
+  #[builder(setter(strip_option), default="None")]
+  #[serde(skip_serializing_if = "Option::is_none")]
   pub limit_price: Option<f64>,
   /// required if type is stop or stop_limit
+  #[builder(setter(strip_option), default="None")]
+  #[serde(skip_serializing_if = "Option::is_none")]
   pub stop_price: Option<f64>,
   /// the new value of the trail_price or trail_percent value 
   /// (works only for type="trailing_stop”)
+  #[builder(setter(strip_option), default="None")]
+  #[serde(skip_serializing_if = "Option::is_none")]
   pub trail: Option<f64>,
   /// A unique identifier for the order. Automatically generated if not sent.
+  #[builder(setter(strip_option), default="None")]
+  #[serde(skip_serializing_if = "Option::is_none")]
   pub client_order_id: Option<String>
-}
\ No newline at end of file
+}
+// The replacement requests of the former api, kept (deprecated) for the
+// existing callers. They live in their own module so that the code generated
+// by the builder derive does not warn about the deprecation.
+#[allow(deprecated)]
+mod replacement {
+  use derive_builder::Builder;
+  use serde::{Deserialize, Serialize};
+
+  use crate::entities::TimeInForce;
+  use crate::orders::ReplaceOrderRequest;
+
+  /// Replacement Requests (see `ReplaceOrderRequest`)
+  #[deprecated(note = "use replace_order")]
+  #[derive(Builder, Debug, Clone, Serialize, Deserialize)]
+  pub struct ReplacementRequest {
+    /// number of shares to trade
+    pub qty: Option<u32>,
+    /// day, gtc, opg, cls, ioc, fok. 
+    pub time_in_force: Option<TimeInForce>,
+    /// required if type is limit or stop_limit
+    pub limit_price: Option<f64>,
+    /// required if type is stop or stop_limit
+    pub stop_price: Option<f64>,
+    /// the new value of the trail_price or trail_percent value 
+    /// (works only for type="trailing_stop”)
+    pub trail: Option<f64>,
+    /// A unique identifier for the order. Automatically generated if not sent.
+    pub client_order_id: Option<String>
+  }
+  impl From<&ReplacementRequest> for ReplaceOrderRequest {
+    fn from(r: &ReplacementRequest) -> Self {
+      Self {
+        qty            : r.qty.map(f64::from),
+        time_in_force  : r.time_in_force,
+        limit_price    : r.limit_price,
+        stop_price     : r.stop_price,
+        trail          : r.trail,
+        client_order_id: r.client_order_id.clone(),
+      }
+    }
+  }
+}
+#[allow(deprecated)]
+pub use replacement::{ReplacementRequest, ReplacementRequestBuilder};
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+  use std::sync::{Arc, Mutex};
+  use std::time::Duration;
+
+  use futures::{StreamExt, TryStreamExt};
+
+  use crate::entities::{CancelationStatus, Direction, OrderClass, OrderSide, OrderStatus, OrderType, TimeInForce};
+  use crate::errors::{Error, OrderError};
+  use crate::orders::{ListOrderRequestBuilder, PlaceOrderRequestBuilder, ReplaceOrderRequestBuilder, ReplacementRequestBuilder, ExitLeg, StopLossRequest, client_order_id, MAX_CLIENT_ORDER_ID_LEN};
+  use crate::rest::{Client, Interceptor};
+  use crate::streaming::Response;
+  use crate::testing::{ORDER, POSITION, fake_client};
+  use crate::transport::FakeTransport;
+
+  #[tokio::test]
+  async fn test_orders_are_paged() {
+    // two orders submitted at the very same time fill the first page
+    let page      = format!("[{},{}]", ORDER, ORDER.replace("810f77c9", "810f77ca"));
+    let transport = FakeTransport::new().respond("GET", "/v2/orders", 200, &page);
+    let client    = fake_client(&transport);
+    let request   = ListOrderRequestBuilder::default().limit(2).build().unwrap();
+
+    // the next page overlaps the last submission time, and the orders which
+    // were already yielded are dropped (hence the stream ends)
+    let orders = client.orders(&request).try_collect::<Vec<_>>().await.unwrap();
+    assert_eq!(vec!["810f77c9", "810f77ca"], orders.iter().map(|o| o.id.as_str()).collect::<Vec<_>>());
+    let requests = transport.requests();
+    assert_eq!(2, requests.len());
+    assert!(!requests[0].url.contains("until="));
+    assert!(requests[1].url.contains("until=2021-11-11T17%3A11%3A17.000001Z"));
+  }
+  #[tokio::test]
+  async fn test_ascending_orders_move_after_forward() {
+    let transport = FakeTransport::new().respond("GET", "/v2/orders", 200, &format!("[{}]", ORDER));
+    let client    = fake_client(&transport);
+    let request   = ListOrderRequestBuilder::default()
+      .limit(1).direction(Direction::Ascending).side(OrderSide::Buy)
+      .build().unwrap();
+
+    let orders = client.orders(&request).try_collect::<Vec<_>>().await.unwrap();
+    assert_eq!(1, orders.len());
+    let requests = transport.requests();
+    assert!(requests[0].url.ends_with("/v2/orders?limit=1&direction=asc&side=buy"));
+    assert!(requests[1].url.contains("after=2021-11-11T17%3A11%3A16.999999Z"));
+  }
+  #[tokio::test]
+  async fn test_orders_stream_surfaces_errors() {
+    let transport = FakeTransport::new().respond("GET", "/v2/orders", 500, r#"{"code":50010000,"message":"internal server error"}"#);
+    let client    = fake_client(&transport);
+    let request   = ListOrderRequestBuilder::default().build().unwrap();
+
+    let mut orders = Box::pin(client.orders(&request));
+    assert!(orders.next().await.unwrap().is_err());
+    assert!(orders.next().await.is_none());
+  }
+
+  #[tokio::test]
+  async fn test_cancel_all_orders_reports_each_order() {
+    let statuses  = format!(r#"[{{"id":"810f77c9","status":200,"body":{}}},
+      {{"id":"filled","status":500,"body":{{"code":50010000,"message":"order is not cancelable"}}}},
+      {{"id":"throttled","status":429,"body":null}}]"#, ORDER);
+    let transport = FakeTransport::new().respond("DELETE", "/v2/orders", 207, &statuses);
+    let client    = fake_client(&transport);
+
+    let canceled = client.cancel_all_orders().await.unwrap();
+    assert!(canceled[0].is_success());
+    assert_eq!("MSFT", canceled[0].order.as_ref().unwrap().symbol);
+    assert!(!canceled[1].is_success());
+    assert!(canceled[1].order.is_none());
+    assert_eq!(50010000, canceled[1].error.as_ref().unwrap().code);
+    assert_eq!(CancelationStatus::InternalError, canceled[1].status);
+    // statuses without a dedicated variant are kept as is
+    assert_eq!(CancelationStatus::Other(429), canceled[2].status);
+  }
+
+  #[test]
+  fn test_client_order_ids_are_unique() {
+    let a = client_order_id("a-very-long-prefix-which-leaves-no-room-for-anything-else");
+    let b = client_order_id("a-very-long-prefix-which-leaves-no-room-for-anything-else");
+    assert_ne!(a, b);
+    assert_eq!(MAX_CLIENT_ORDER_ID_LEN, a.len());
+    // the limit is in bytes, and multi-byte characters are never split
+    let c = client_order_id(&"é".repeat(MAX_CLIENT_ORDER_ID_LEN));
+    assert!(c.len() <= MAX_CLIENT_ORDER_ID_LEN);
+    assert!(c.starts_with("éé"));
+  }
+  #[tokio::test]
+  async fn test_idempotent_placement_checks_before_retrying() {
+    // the gateway timed out, yet the order did reach the server
+    let transport = FakeTransport::new()
+      .respond("POST", "/v2/orders", 504, "")
+      .respond("GET", "/v2/orders:by_client_order_id", 200, ORDER);
+    let client    = fake_client(&transport);
+    let request   = PlaceOrderRequestBuilder::default()
+      .symbol("MSFT".to_string()).qty(2.0).side(OrderSide::Buy)
+      .build().unwrap();
+
+    assert_eq!("810f77c9", client.place_order_idempotent(&request).await.unwrap().id);
+    let requests = transport.requests();
+    assert_eq!(2, requests.len());
+    assert!(requests[0].body.as_ref().unwrap().contains(r#""client_order_id":"apca-"#));
+  }
+  #[tokio::test]
+  async fn test_idempotent_placement_checks_after_a_duplicate() {
+    // the first attempt reached the server, but too late to be looked up
+    let transport = FakeTransport::new()
+      .respond_once("POST", "/v2/orders", 504, "")
+      .respond("POST", "/v2/orders", 422, r#"{"code":40010001,"message":"client_order_id must be unique"}"#)
+      .respond_once("GET", "/v2/orders:by_client_order_id", 404, r#"{"code":40410000,"message":"order not found"}"#)
+      .respond("GET", "/v2/orders:by_client_order_id", 200, ORDER);
+    let client    = fake_client(&transport);
+    let request   = PlaceOrderRequestBuilder::default()
+      .symbol("MSFT".to_string()).qty(2.0).side(OrderSide::Buy)
+      .build().unwrap();
+
+    assert_eq!("810f77c9", client.place_order_idempotent(&request).await.unwrap().id);
+    assert_eq!(4, transport.requests().len());
+  }
+  #[tokio::test]
+  async fn test_place_order_assigns_a_client_order_id() {
+    let transport = FakeTransport::new()
+      .respond("POST", "/v2/orders", 200, ORDER);
+    let client    = fake_client(&transport);
+    let request   = PlaceOrderRequestBuilder::default()
+      .symbol("MSFT".to_string()).qty(2.0).side(OrderSide::Buy)
+      .build().unwrap();
+
+    client.place_order(&request).await.unwrap();
+    assert!(transport.requests()[0].body.as_ref().unwrap().contains(r#""client_order_id":"apca-"#));
+
+    let dry_run = Client::paper("key".to_string(), "secret".to_string()).with_dry_run_orders(true);
+    assert!(dry_run.place_order(&request).await.unwrap().client_order_id.starts_with("apca-"));
+  }
+  #[tokio::test]
+  async fn test_replace_order() {
+    let transport = FakeTransport::new()
+      .respond("PATCH", "/v2/orders/810f77c9", 200, ORDER)
+      .respond("PATCH", "/v2/orders/filled", 422, r#"{"code":42210000,"message":"order is not open"}"#);
+    let client    = fake_client(&transport);
+    let request   = ReplaceOrderRequestBuilder::default()
+      .qty(3.0).limit_price(101.5)
+      .build().unwrap();
+
+    assert_eq!("810f77c9", client.replace_order("810f77c9", &request).await.unwrap().id);
+    assert_eq!(Some(r#"{"qty":3.0,"limit_price":101.5}"#.to_string()), transport.requests()[0].body);
+    let result = client.replace_order("filled", &request).await;
+    assert!(matches!(result, Err(Error::Order { kind: OrderError::Unprocessable, api: Some(_), .. })));
+  }
+  #[tokio::test]
+  #[allow(deprecated)]
+  async fn test_deprecated_replace_forwards_to_replace_order() {
+    let transport = FakeTransport::new()
+      .respond("PATCH", "/v2/orders/810f77c9", 200, ORDER);
+    let client    = fake_client(&transport);
+    let request   = ReplacementRequestBuilder::default()
+      .qty(Some(3)).time_in_force(None).limit_price(Some(101.5))
+      .stop_price(None).trail(None).client_order_id(None)
+      .build().unwrap();
+
+    assert_eq!("810f77c9", client.replace("810f77c9", &request).await.unwrap().id);
+    assert_eq!(Some(r#"{"qty":3.0,"limit_price":101.5}"#.to_string()), transport.requests()[0].body);
+  }
+  #[tokio::test]
+  async fn test_nested_legs() {
+    let leg    = ORDER.replace(r#""id":"810f77c9""#, r#""id":"leg""#).replace(r#""type":"market""#, r#""type":"stop""#);
+    let nested = format!("[{}]", ORDER.replace(r#""legs":null"#, &format!(r#""legs":[{}]"#, leg)));
+    let transport = FakeTransport::new()
+      .respond("GET", "/v2/orders", 200, &nested)
+      .respond("GET", "/v2/orders/810f77c9", 200, &nested[1..nested.len()-1]);
+    let client    = fake_client(&transport);
+    let request   = ListOrderRequestBuilder::default().nested(true).build().unwrap();
+
+    let orders = client.list_orders(&request).await.unwrap();
+    assert_eq!(1, orders[0].leg_orders().len());
+    assert_eq!("leg", orders[0].leg_of_type(OrderType::Stop).unwrap().id);
+    let order  = client.get_by_id("810f77c9", true).await.unwrap();
+    assert_eq!(1, order.leg_orders().len());
+
+    let requests = transport.requests();
+    assert!(requests[0].url.ends_with("/v2/orders?nested=true"));
+    assert!(requests[1].url.ends_with("/v2/orders/810f77c9?nested=true"));
+  }
+  #[test]
+  fn test_bracket_legs_are_serialized() {
+    let request = PlaceOrderRequestBuilder::default()
+      .symbol("MSFT".to_string()).qty(2.0).side(OrderSide::Buy)
+      .bracket(310.0, StopLossRequest::stop(290.0))
+      .build().unwrap();
+    let json = serde_json::to_value(&request).unwrap();
+    assert_eq!("bracket", json["order_class"]);
+    assert_eq!(310.0, json["take_profit"]["limit_price"]);
+    assert_eq!(290.0, json["stop_loss"]["stop_price"]);
+    assert!(json["stop_loss"].get("limit_price").is_none());
+  }
+  #[test]
+  fn test_order_class_legs_are_validated() {
+    let mut request = PlaceOrderRequestBuilder::default();
+    request.symbol("MSFT".to_string()).qty(2.0).side(OrderSide::Sell);
+
+    assert!(request.clone().one_cancels_other(310.0, StopLossRequest::stop(290.0)).build().is_ok());
+    assert!(request.clone().one_triggers_other(ExitLeg::StopLoss(StopLossRequest::stop(290.0))).build().is_ok());
+    assert!(request.clone().order_class(OrderClass::OneTriggersOther).build().is_err());
+    assert!(request.clone().order_class(OrderClass::Bracket).stop_loss(StopLossRequest::stop(290.0)).build().is_err());
+    assert!(request.clone().stop_loss(StopLossRequest::stop(290.0)).build().is_err());
+    assert!(request.build().is_ok());
+  }
+  #[test]
+  fn test_trailing_stop_needs_one_trail() {
+    let mut request = PlaceOrderRequestBuilder::default();
+    request.symbol("MSFT".to_string()).qty(2.0).side(OrderSide::Sell);
+
+    let order = request.clone().trailing_stop_percent(1.5).build().unwrap();
+    let json  = serde_json::to_value(&order).unwrap();
+    assert_eq!("trailing_stop", json["type"]);
+    assert_eq!(1.5, json["trail_percent"]);
+    assert!(json.get("trail_price").is_none());
+    assert!(request.clone().trailing_stop_price(2.0).trail_percent(1.5).build().is_err());
+    assert!(request.clone().order_type(OrderType::TrailingStop).build().is_err());
+    assert!(request.trail_price(2.0).build().is_err());
+  }
+  #[test]
+  fn test_stop_limit_sets_both_prices() {
+    let order = PlaceOrderRequestBuilder::default()
+      .symbol("MSFT".to_string()).qty(2.0).side(OrderSide::Sell)
+      .stop_limit(290.0, 289.5)
+      .build().unwrap();
+    let json  = serde_json::to_value(&order).unwrap();
+    assert_eq!("stop_limit", json["type"]);
+    assert_eq!(290.0, json["stop_price"]);
+    assert_eq!(289.5, json["limit_price"]);
+  }
+  #[tokio::test]
+  async fn test_invalid_orders_are_not_sent() {
+    let mut request = PlaceOrderRequestBuilder::default();
+    request.symbol("MSFT".to_string()).side(OrderSide::Buy);
+
+    assert!(request.clone().build().is_err());
+    assert!(request.clone().qty(2.0).notional(500.0).build().is_err());
+    assert!(request.clone().qty(2.0).order_type(OrderType::Limit).build().is_err());
+    assert!(request.clone().qty(2.0).order_type(OrderType::StopLimit).limit_price(300.0).build().is_err());
+    assert!(request.clone().notional(500.0).build().is_ok());
+
+    // a request which did not go through the builder is checked nonetheless
+    let mut order = request.qty(2.0).build().unwrap();
+    order.qty = None;
+    let transport = FakeTransport::new();
+    let client    = fake_client(&transport);
+    let result    = client.place_order(&order).await;
+    assert!(matches!(result, Err(Error::InvalidOrder(e)) if e.contains("qty")));
+    assert!(transport.requests().is_empty());
+  }
+  #[tokio::test]
+  async fn test_dry_run_orders_are_not_sent() {
+    struct Payloads(Arc<Mutex<Vec<String>>>);
+    impl Interceptor for Payloads {
+      fn on_dry_run(&self, request: &reqwest::Request) {
+        let body = request.body().and_then(|b| b.as_bytes()).unwrap_or_default();
+        self.0.lock().unwrap().push(String::from_utf8_lossy(body).to_string());
+      }
+    }
+    let payloads  = Arc::new(Mutex::new(vec![]));
+    let transport = FakeTransport::new();
+    let client    = fake_client(&transport)
+      .with_interceptor(Payloads(payloads.clone()))
+      .with_dry_run_orders(true);
+    let request   = PlaceOrderRequestBuilder::default()
+      .symbol("MSFT".to_string())
+      .qty(2.0)
+      .side(OrderSide::Buy)
+      .order_type(OrderType::Limit)
+      .limit_price(300.0)
+      .client_order_id("my-order".to_string())
+      .build().unwrap();
+
+    let order = client.place_order(&request).await.unwrap();
+    assert!(transport.requests().is_empty());
+    assert_eq!(OrderStatus::Accepted, order.status);
+    assert_eq!("my-order", order.client_order_id);
+    assert_eq!(Some(300.0), order.limit_price);
+    assert_eq!(0.0, order.filled_qty);
+    assert!(payloads.lock().unwrap()[0].contains(r#""client_order_id":"my-order""#));
+
+    // the dry-run orders are validated as well
+    let mut invalid = request.clone();
+    invalid.limit_price = None;
+    assert!(matches!(client.place_order(&invalid).await, Err(Error::InvalidOrder(_))));
+  }
+  #[tokio::test]
+  async fn test_preflight_rejects_hard_to_borrow_shorts() {
+    let asset = r#"{"id":"b6d1aa75","class":"us_equity","exchange":"NASDAQ","symbol":"MSFT","status":"active",
+      "tradable":true,"marginable":true,"shortable":true,"easy_to_borrow":false,"fractionable":true}"#;
+    let transport = FakeTransport::new()
+      .respond("GET", "/v2/assets/MSFT", 200, asset)
+      .respond("GET", "/v2/positions/MSFT", 200, POSITION)
+      .respond("POST", "/v2/orders", 200, ORDER);
+    let client    = fake_client(&transport)
+      .with_preflight(true);
+
+    // selling what is held is fine, selling more is a short sale
+    assert!(client.market_sell("MSFT", 2.0).await.is_ok());
+    let result = client.market_sell("MSFT", 3.0).await;
+    assert!(matches!(result, Err(Error::InvalidOrder(e)) if e.contains("not easy to borrow")));
+    assert_eq!(5, transport.requests().len());
+  }
+  #[tokio::test]
+  async fn test_order_shortcuts() {
+    let transport = FakeTransport::new().respond("POST", "/v2/orders", 200, ORDER);
+    let client    = fake_client(&transport);
+
+    client.market_buy("MSFT", 2.0).await.unwrap();
+    client.limit_sell("MSFT", 2.0, 330.5).await.unwrap();
+    let requests = transport.requests();
+    assert!(requests[0].body.as_ref().unwrap().contains(r#""side":"buy","type":"market""#));
+    assert!(requests[1].body.as_ref().unwrap().contains(r#""side":"sell","type":"limit","time_in_force":"day","limit_price":330.5"#));
+
+    // the requests are validated nonetheless (this qty rounds to zero)
+    let result = client.limit_buy("MSFT", 0.0000000001, 330.5).await;
+    assert!(matches!(result, Err(Error::InvalidOrder(_))));
+    assert_eq!(2, transport.requests().len());
+  }
+  #[tokio::test]
+  async fn test_place_and_await_fill() {
+    let filled    = ORDER.replace(r#""status":"new""#, r#""status":"filled""#);
+    let transport = FakeTransport::new()
+      .respond("POST", "/v2/orders", 200, ORDER)
+      .respond("GET", "/v2/orders/810f77c9", 200, &filled);
+    let client    = fake_client(&transport);
+    let request   = PlaceOrderRequestBuilder::default()
+      .symbol("MSFT".to_string()).qty(2.0).side(OrderSide::Buy)
+      .build().unwrap();
+    let timeout   = Duration::from_millis(50);
+
+    let order = client.place_and_await_fill(&request, timeout).await.unwrap();
+    assert_eq!(OrderStatus::Filled, order.status);
+
+    // the update of the stream is used as such
+    let update = format!(r#"{{"stream":"trade_updates","data":{{"event":"fill","order":{},
+      "timestamp":"2021-11-11T17:11:18Z","price":"330.5","position_qty":"2"}}}}"#, filled);
+    let update = serde_json::from_str::<Response>(&update).unwrap();
+    let order  = client.place_and_await_fill_on(&request, futures::stream::iter(vec![Ok(update)]), timeout).await.unwrap();
+    assert_eq!(OrderStatus::Filled, order.status);
+
+    // an order which remains open times out
+    let transport = FakeTransport::new()
+      .respond("POST", "/v2/orders", 200, ORDER)
+      .respond("GET", "/v2/orders/810f77c9", 200, ORDER);
+    let client    = fake_client(&transport);
+    let result    = client.place_and_await_fill(&request, timeout).await;
+    assert!(matches!(result, Err(Error::OrderTimeout(id, _)) if id == "810f77c9"));
+
+    // so does it when the stream reconnects, then ends, before it is done
+    let updates = futures::stream::iter(vec![Ok(Response::Reconnected)]);
+    let result  = client.place_and_await_fill_on(&request, updates, timeout).await;
+    assert!(matches!(result, Err(Error::OrderTimeout(id, _)) if id == "810f77c9"));
+  }
+  #[tokio::test]
+  async fn test_fractional_orders() {
+    let mut request = PlaceOrderRequestBuilder::default();
+    request.symbol("MSFT".to_string()).side(OrderSide::Buy);
+
+    // the qty is rounded to 9 decimals
+    assert_eq!(Some(0.123456789), request.clone().qty(0.123456789).build().unwrap().qty);
+    assert_eq!(Some(0.3), request.clone().qty(0.1 + 0.2).build().unwrap().qty);
+
+    let asset = r#"{"id":"b6d1aa75","class":"us_equity","exchange":"NYSE","symbol":"BRK.A","status":"active",
+      "tradable":true,"marginable":true,"shortable":true,"easy_to_borrow":true,"fractionable":false}"#;
+    let pair  = r#"{"id":"276e2673","class":"crypto","exchange":"CRYPTO","symbol":"BTCUSD","status":"active",
+      "tradable":true,"marginable":false,"shortable":false,"easy_to_borrow":false,"fractionable":true}"#;
+    let transport = FakeTransport::new()
+      .respond("GET", "/v2/assets/BRK.A", 200, asset)
+      .respond("GET", "/v2/assets/MSFT", 200, &asset.replace("BRK.A", "MSFT").replace(r#""fractionable":false"#, r#""fractionable":true"#))
+      .respond("GET", "/v2/assets/BTCUSD", 200, pair);
+    let client    = fake_client(&transport);
+    let fraction  = request.clone().symbol("BRK.A".to_string()).qty(0.5).build().unwrap();
+    let whole     = request.clone().symbol("BRK.A".to_string()).qty(1.0).build().unwrap();
+    let result    = client.check_fractionable(&fraction).await;
+    assert!(matches!(result, Err(Error::InvalidOrder(e)) if e.contains("not fractionable")));
+    assert!(client.check_fractionable(&whole).await.is_ok());
+
+    // a fractional stock order must be a simple market day order
+    let limit = request.clone().qty(0.5).order_type(OrderType::Limit).limit_price(300.0).build().unwrap();
+    let gtc   = request.clone().qty(0.5).time_in_force(TimeInForce::GoodUntilCanceled).build().unwrap();
+    assert!(client.check_fractionable(&limit).await.is_err());
+    assert!(client.check_fractionable(&gtc).await.is_err());
+    // which does not apply to the crypto pairs (with or without a slash)
+    let crypto = request.symbol("BTCUSD".to_string()).qty(0.5).time_in_force(TimeInForce::GoodUntilCanceled).build().unwrap();
+    assert!(client.check_fractionable(&crypto).await.is_ok());
+  }
+  #[test]
+  fn test_extended_hours_needs_a_day_limit_order() {
+    let mut request = PlaceOrderRequestBuilder::default();
+    request.symbol("MSFT".to_string()).qty(2.0).side(OrderSide::Buy).extended_hours(true);
+
+    assert!(request.clone().build().is_err());
+    assert!(request.clone().order_type(OrderType::Limit).limit_price(300.0).build().is_ok());
+    assert!(request.order_type(OrderType::Limit).limit_price(300.0)
+      .time_in_force(TimeInForce::GoodUntilCanceled).build().is_err());
+  }
+}
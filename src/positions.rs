@@ -4,23 +4,27 @@
 //! information is updated. Once a position is closed, it will no longer be 
 //! queryable through this API.
 
-use crate::{entities::{ClosureData, OrderData, PositionData}, errors::{Error, maybe_convert_to_position_error, status_code_to_position_error}, rest::Client};
+use std::collections::BTreeMap;
 
-/// Path to the positions endpoints
-static ENDPOINT: &str = "/v2/positions";
+use crate::{entities::{AssetClass, ClosureData, OrderData, PositionData, PositionSide}, errors::{Error, PositionError, api_error, maybe_convert_to_position_error, request_info, status_code_to_position_error}, rest::Client, endpoints::Endpoint, symbols::SymbolOrAssetId};
 
 impl Client {
   /// Retrieves a list of the account’s open positions. 
   pub async fn list_open_positions(&self) -> Result<Vec<PositionData>, Error> {
-    let url = format!("{}/{}", self.env_url(), ENDPOINT);
+    let url = Endpoint::Positions.url(self.env_url());
     let rsp = self.get_authenticated(&url)
       .send().await
       .map_err(maybe_convert_to_position_error)?;
     status_code_to_position_error(rsp).await
   }
-  /// Retrieves the account’s open position for the given symbol.
-  pub async fn get_open_position(&self, symbol: &str) -> Result<PositionData, Error> {
-    let url = format!("{}/{}/{}", self.env_url(), ENDPOINT, symbol);
+  /// Aggregates the account’s open positions (see `PortfolioSummary`)
+  pub async fn portfolio_summary(&self) -> Result<PortfolioSummary, Error> {
+    Ok(PortfolioSummary::from_positions(&self.list_open_positions().await?))
+  }
+  /// Retrieves the account’s open position for the given symbol (or asset
+  /// id, see `SymbolOrAssetId`).
+  pub async fn get_open_position<S: Into<SymbolOrAssetId>>(&self, symbol: S) -> Result<PositionData, Error> {
+    let url = Endpoint::Position(&self.symbols().normalize_target(&symbol.into())).url(self.env_url());
     let rsp = self.get_authenticated(&url)
       .send().await
       .map_err(maybe_convert_to_position_error)?;
@@ -31,11 +35,14 @@ impl Client {
   /// cancelled. If an order is no longer cancelable, the server will respond 
   /// with status 500 and reject the request.
   /// 
+  /// Each entry carries the liquidation order that was placed (see 
+  /// `ClosureData::order`), which lets the caller track it to completion.
+  /// 
   /// # Param
   /// - cancel_orders: If true is specified, cancel all open orders before 
-  ///     liquidating all positions.
+  ///   liquidating all positions.
   pub async fn close_all_positions(&self, cancel_orders: bool) -> Result<Vec<ClosureData>, Error> {
-    let url = format!("{}/{}", self.env_url(), ENDPOINT);
+    let url = Endpoint::Positions.url(self.env_url());
     let rsp = self.delete_authenticated(&url)
       .query(&[("cancel_orders", cancel_orders)])
       .send().await
@@ -44,14 +51,14 @@ impl Client {
   }
 
   /// # Params
-  /// - symbol: symbol or asset_id
+  /// - symbol: symbol or asset_id (see `SymbolOrAssetId`)
   /// - qty   : the number of shares to liquidate. Can accept up to 9 decimal 
-  ///     points. Cannot work with percentage
+  ///   points. Cannot work with percentage
   /// - percentage: percentage of position to liquidate. Must be between 
-  ///     0 and 100. Would only sell fractional if position is originally 
-  ///     fractional. Can accept up to 9 decimal points. Cannot work with qty 
-  pub async fn close_position(&self, symbol: &str, qty: Option<f64>, percentage: Option<f64>) -> Result<OrderData, Error> {
-    let url = format!("{}/{}/{}", self.env_url(), ENDPOINT, symbol);
+  ///   0 and 100. Would only sell fractional if position is originally 
+  ///   fractional. Can accept up to 9 decimal points. Cannot work with qty 
+  pub async fn close_position<S: Into<SymbolOrAssetId>>(&self, symbol: S, qty: Option<f64>, percentage: Option<f64>) -> Result<OrderData, Error> {
+    let url = Endpoint::Position(&self.symbols().normalize_target(&symbol.into())).url(self.env_url());
     let mut params = vec![];
     if let Some(qty) = qty {
       params.push(("qty", qty));
@@ -65,4 +72,225 @@ impl Client {
       .map_err(maybe_convert_to_position_error)?;
     status_code_to_position_error(rsp).await
   }
-}
\ No newline at end of file
+
+  /// Trims the given dollar amount off the position held on a symbol. The 
+  /// position is valued at the latest quote (the bid for a long position, 
+  /// the ask for a short one) and the matching percentage of it is closed;
+  /// the whole position is closed when it is worth less than the amount.
+  pub async fn close_position_by_amount(&self, symbol: &str, amount: f64) -> Result<OrderData, Error> {
+    if amount <= 0.0 || !amount.is_finite() {
+      return Err(Error::InvalidOrder(format!("cannot close a position by {} dollars", amount)));
+    }
+    let position = self.get_open_position(symbol).await?;
+    let price    = if position.asset_class == AssetClass::Crypto {
+      position.current_price
+    } else {
+      let quote = self.latest_quote(&position.symbol).await?.quote;
+      match position.side {
+        PositionSide::Long  => quote.bid_price,
+        PositionSide::Short => quote.ask_price,
+      }
+    };
+    let price    = if price > 0.0 { price } else { position.current_price };
+    let value    = position.qty.abs() * price;
+    if value <= amount {
+      self.close_position(symbol, None, None).await
+    } else {
+      let percentage = (amount / value * 100.0 * 1e9).floor() / 1e9;
+      self.close_position(symbol, None, Some(percentage)).await
+    }
+  }
+
+  /// Exercises the options position held on the given contract. All the 
+  /// contracts held are exercised; the exercise is processed at the end of 
+  /// the day (and a do-not-exercise instruction can no longer be issued).
+  /// 
+  /// # Params
+  /// - symbol: the symbol (e.g. AAPL250620C00100000) or id of the contract
+  pub async fn exercise_position<S: Into<SymbolOrAssetId>>(&self, symbol: S) -> Result<(), Error> {
+    let url = Endpoint::ExercisePosition(&self.symbols().normalize_target(&symbol.into())).url(self.env_url());
+    let rsp = self.post_authenticated(&url)
+      .send().await
+      .map_err(maybe_convert_to_position_error)?;
+
+    let request = request_info(&rsp);
+    match rsp.status().as_u16() {
+      200 | 204 => Ok(()),
+      403 => Err(Error::Position { kind: PositionError::Forbidden, api: api_error(rsp).await, request }),
+      404 => Err(Error::Position { kind: PositionError::NotFound, api: api_error(rsp).await, request }),
+      422 => Err(Error::Api { status: 422, api: api_error(rsp).await, request }),
+      500 => Err(Error::Position { kind: PositionError::InternalError, api: api_error(rsp).await, request }),
+      s   => Err(Error::Unexpected { status: s, api: api_error(rsp).await, request }),
+    }
+  }
+}
+
+/// The aggregates of a set of positions. The market value of a short 
+/// position is negative: the net exposure is the total market value, whereas
+/// the gross exposure is the sum of the long and short exposures.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PortfolioSummary {
+  /// The number of positions
+  pub positions: usize,
+  /// The total market value of the positions (in dollars)
+  pub market_value: f64,
+  /// The total cost basis of the positions (in dollars)
+  pub cost_basis: f64,
+  /// The unrealized profit/loss of the positions (in dollars)
+  pub unrealized_pl: f64,
+  /// The unrealized profit/loss of the positions for the day (in dollars)
+  pub unrealized_intraday_pl: f64,
+  /// The market value of the long positions
+  pub long_exposure: f64,
+  /// The absolute market value of the short positions
+  pub short_exposure: f64,
+  /// The same aggregates, per asset class (e.g. us_equity, crypto)
+  pub by_asset_class: BTreeMap<AssetClass, AssetClassSummary>,
+}
+impl PortfolioSummary {
+  /// Aggregates the given positions
+  pub fn from_positions(positions: &[PositionData]) -> Self {
+    let mut summary = Self::default();
+    for position in positions {
+      summary.positions              += 1;
+      summary.market_value           += position.market_value;
+      summary.cost_basis             += position.cost_basis;
+      summary.unrealized_pl          += position.unrealized_pl;
+      summary.unrealized_intraday_pl += position.unrealized_intraday_pl;
+      match position.side {
+        PositionSide::Long  => summary.long_exposure  += position.market_value.abs(),
+        PositionSide::Short => summary.short_exposure += position.market_value.abs(),
+      }
+      let class = summary.by_asset_class.entry(position.asset_class.clone()).or_default();
+      class.positions     += 1;
+      class.market_value  += position.market_value;
+      class.cost_basis    += position.cost_basis;
+      class.unrealized_pl += position.unrealized_pl;
+    }
+    summary
+  }
+  /// The unrealized profit/loss percent (by a factor of 1) wrt the cost basis
+  pub fn unrealized_plpc(&self) -> f64 {
+    if self.cost_basis == 0.0 { 0.0 } else { self.unrealized_pl / self.cost_basis.abs() }
+  }
+  /// The sum of the long and short exposures
+  pub fn gross_exposure(&self) -> f64 {
+    self.long_exposure + self.short_exposure
+  }
+  /// The long exposure minus the short exposure
+  pub fn net_exposure(&self) -> f64 {
+    self.long_exposure - self.short_exposure
+  }
+}
+
+/// The aggregates of the positions of one asset class
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AssetClassSummary {
+  /// The number of positions
+  pub positions: usize,
+  /// The total market value of the positions (in dollars)
+  pub market_value: f64,
+  /// The total cost basis of the positions (in dollars)
+  pub cost_basis: f64,
+  /// The unrealized profit/loss of the positions (in dollars)
+  pub unrealized_pl: f64,
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+  use crate::entities::{AssetClass, ClosureStatus};
+  use crate::errors::{Error, PositionError};
+  use crate::symbols::SymbolNormalizer;
+  use crate::testing::{ORDER, POSITION, fake_client};
+  use crate::transport::FakeTransport;
+
+  #[tokio::test]
+  async fn test_exercise_position() {
+    let transport = FakeTransport::new()
+      .respond("POST", "/v2/positions/AAPL250620C00100000/exercise", 200, "")
+      .respond("POST", "/v2/positions/AAPL250620P00100000/exercise", 404, r#"{"code":40410000,"message":"position not found"}"#);
+    let client    = fake_client(&transport);
+
+    client.exercise_position("AAPL250620C00100000").await.unwrap();
+    let result = client.exercise_position("AAPL250620P00100000").await;
+    assert!(matches!(result, Err(Error::Position { kind: PositionError::NotFound, api: Some(_), .. })));
+    assert_eq!(2, transport.requests().len());
+  }
+  #[tokio::test]
+  async fn test_portfolio_summary() {
+    let positions = format!(r#"[
+      {},
+      {{"asset_id":"a1b2c3d4","symbol":"TSLA","exchange":"NASDAQ","asset_class":"us_equity","avg_entry_price":"200",
+       "qty":"-1","side":"short","market_value":"-220","cost_basis":"-200","unrealized_pl":"-20","unrealized_plpc":"-0.1",
+       "unrealized_intraday_pl":"-5","unrealized_intraday_plpc":"-0.02","current_price":"220","lastday_price":"215","change_today":"0.02"}},
+      {{"asset_id":"c3d4e5f6","symbol":"BTCUSD","exchange":"CRYPTO","asset_class":"crypto","avg_entry_price":"40000",
+       "qty":"0.01","side":"long","market_value":"500","cost_basis":"400","unrealized_pl":"100","unrealized_plpc":"0.25",
+       "unrealized_intraday_pl":"5","unrealized_intraday_plpc":"0.01","current_price":"50000","lastday_price":"49500","change_today":"0.01"}}
+    ]"#, POSITION);
+    let transport = FakeTransport::new().respond("GET", "/v2/positions", 200, &positions);
+    let client    = fake_client(&transport);
+
+    let summary = client.portfolio_summary().await.unwrap();
+    assert_eq!(3, summary.positions);
+    assert_eq!(940.0, summary.market_value);
+    assert_eq!(140.0, summary.unrealized_pl);
+    assert_eq!(1160.0, summary.long_exposure);
+    assert_eq!(220.0, summary.short_exposure);
+    assert_eq!(1380.0, summary.gross_exposure());
+    assert_eq!(0.175, summary.unrealized_plpc());
+    assert_eq!(2, summary.by_asset_class[&AssetClass::UsEquity].positions);
+    assert_eq!(100.0, summary.by_asset_class[&AssetClass::Crypto].unrealized_pl);
+  }
+  #[tokio::test]
+  async fn test_close_all_positions_returns_the_orders() {
+    let closed = format!(r#"[
+      {{"symbol":"MSFT","status":200,"body":{}}},
+      {{"symbol":"TSLA","status":500,"body":{{"code":40310000,"message":"insufficient qty available for order"}}}},
+      {{"symbol":"AMZN","status":429,"body":null}}
+    ]"#, ORDER);
+    let transport = FakeTransport::new().respond("DELETE", "/v2/positions", 207, &closed);
+    let client    = fake_client(&transport);
+
+    let closures = client.close_all_positions(true).await.unwrap();
+    assert!(closures[0].is_success());
+    assert_eq!("810f77c9", closures[0].order.as_ref().unwrap().id);
+    assert!(!closures[1].is_success());
+    assert_eq!(40310000, closures[1].error.as_ref().unwrap().code);
+    // statuses without a dedicated variant are kept as is
+    assert_eq!(ClosureStatus::Other(429), closures[2].status);
+  }
+  #[tokio::test]
+  async fn test_close_position_by_amount() {
+    let position = POSITION.replace(r#""qty":"2""#, r#""qty":"10""#);
+    let quote    = r#"{"symbol":"MSFT","quote":{"t":"2021-11-11T17:11:17Z","ax":"V","ap":401,"as":1,"bx":"V","bp":400,"bs":2,"c":["R"],"z":"C"}}"#;
+    let transport = FakeTransport::new()
+      .respond("GET", "/v2/positions/MSFT", 200, &position)
+      .respond("GET", "/v2/stocks/MSFT/quotes/latest", 200, quote)
+      .respond("DELETE", "/v2/positions/MSFT", 200, ORDER);
+    let client    = fake_client(&transport);
+
+    // the position is worth $4000 at the bid: trimming $1000 closes 25% of it
+    client.close_position_by_amount("MSFT", 1000.0).await.unwrap();
+    assert!(transport.requests()[2].url.ends_with("/v2/positions/MSFT?percentage=25.0"));
+    // trimming more than the position is worth closes it entirely
+    client.close_position_by_amount("MSFT", 5000.0).await.unwrap();
+    assert!(transport.requests()[5].url.ends_with("/v2/positions/MSFT"));
+    assert!(client.close_position_by_amount("MSFT", -1.0).await.is_err());
+  }
+  #[tokio::test]
+  async fn test_close_position_by_asset_id() {
+    let transport = FakeTransport::new();
+    let client    = fake_client(&transport)
+      .with_normalizer(SymbolNormalizer::share_classes());
+
+    let _ = client.close_position("b0b6dd9d-8b9b-48a9-ba46-b9d54906e415", None, None).await;
+    let _ = client.close_position("BRK-B", Some(1.0), None).await;
+    let requests = transport.requests();
+    assert!(requests[0].url.ends_with("/v2/positions/b0b6dd9d-8b9b-48a9-ba46-b9d54906e415"));
+    assert!(requests[1].url.contains("/v2/positions/BRK.B?qty="));
+  }
+}
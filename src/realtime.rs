@@ -61,17 +61,25 @@
 //! participant timestamps may have broader resolution such as milliseconds or 
 //! seconds.
 
-use crate::{entities::{BarData, QuoteData, TradeData}, errors::{Error, RealtimeErrorCode}};
+use crate::{entities::{BarData, CancelErrorData, CorrectionData, LuldData, Ohlcv, OrderbookData, QuoteData, StatusData, TradeData}, errors::{Error, RealtimeError}};
+use crate::news::NewsArticle;
+use crate::historical::{SingleBar, SingleQuote, SingleTrade};
+use crate::rest::Credentials;
+use crate::symbols::SymbolNormalizer;
+use std::convert::TryFrom;
+use crate::websocket::{self, ConnectionConfig, WsStream};
+use std::collections::HashMap;
+use chrono::{DateTime, Duration, Utc};
 use futures::{SinkExt, StreamExt, stream::{SplitSink, SplitStream}};
-use tokio::net::TcpStream;
-use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async, tungstenite as tungstenite};
+use tokio_tungstenite::tungstenite as tungstenite;
 use tungstenite::{Message};
 use serde::{Serialize, Deserialize};
 use derive_builder::Builder;
 
 /// The websocket endpoint used to communicate with Alpaca's real time data v2 API
 const WSS_ENDPOINT : &str = "wss://stream.data.alpaca.markets/v2/";
-type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+/// Environment variable selecting the data source (`iex` when it is not set)
+pub const ENV_DATA_FEED: &str = "APCA_API_DATA_FEED";
 
 /// This is the object you'll want to create in order to interact with Alpaca's
 /// market data api. The object allows both server to client and client to 
@@ -86,15 +94,40 @@ pub struct Client {
 impl Client {
     /// Creates a client that fetches data from the given source.
     pub async fn new(source: Source) -> Result<Self, Error> {
+        Self::with_config(source, &ConnectionConfig::default()).await
+    }
+    /// Creates a client that fetches data from the given source using the
+    /// given connection config (proxy, tls...)
+    pub async fn with_config(source: Source, config: &ConnectionConfig) -> Result<Self, Error> {
         // --- Connect to websocket
         let url = format!("{}{}", WSS_ENDPOINT, source);
-        let (socket, _rsp) = connect_async(url).await?;
+        let socket         = websocket::connect(&url, config).await?;
         let (write, read)  = socket.split();
         let write          = ClientSender::new(write);
         let read           = ClientReceiver::new(read);
         //
         Ok(Self {write, read})
     }
+    /// Creates a client connected to the source selected by the 
+    /// `APCA_API_DATA_FEED` environment variable (`iex` or `sip`) and 
+    /// authenticated with the `APCA_API_KEY_ID` and `APCA_API_SECRET_KEY` 
+    /// environment variables.
+    pub async fn from_env() -> Result<Self, Error> {
+        let auth   = AuthData::from(Credentials::from_env()?);
+        let source = match std::env::var(ENV_DATA_FEED) {
+            Ok(feed) => feed.parse()?,
+            Err(_)   => Source::default(),
+        };
+        let mut client = Self::new(source).await?;
+        client.authenticate(auth).await?;
+        Ok(client)
+    }
+    /// Sets the normalizer which is applied to the symbols of the 
+    /// subscriptions
+    pub fn with_normalizer(mut self, symbols: SymbolNormalizer) -> Self {
+        self.write = self.write.with_normalizer(symbols);
+        self
+    }
     /// Splits the send/receive responsibilities for independant processing
     pub fn split(self) -> (ClientSender, ClientReceiver) {
         (self.write, self.read)
@@ -112,18 +145,73 @@ impl Client {
         self.write.unsubscribe(sub).await
     }
     /// Returns the stream which is used to receive the responses from the server
+    /// (the frames which cannot be decoded are skipped, see `try_stream`)
     pub fn stream(self) -> impl StreamExt<Item=Response> {
         self.read.stream()
     }
+    /// Returns the stream which is used to receive the responses from the 
+    /// server, along with an error for each frame which cannot be decoded
+    pub fn try_stream(self) -> impl StreamExt<Item=Result<Response, Error>> {
+        self.read.try_stream()
+    }
+    /// Returns the stream which is used to receive the responses from the 
+    /// server, along with their receipt time and frame sequence number
+    pub fn received_stream(self) -> impl StreamExt<Item=Received<Response>> {
+        self.read.received_stream()
+    }
+    /// Same as `received_stream`, along with an error for each frame which
+    /// cannot be decoded
+    pub fn try_received_stream(self) -> impl StreamExt<Item=Result<Received<Response>, Error>> {
+        self.read.try_received_stream()
+    }
+    /// Returns the stream of the raw (undecoded) text frames received from 
+    /// the server, stamped with their receipt time and sequence number.
+    pub fn frames(self) -> impl StreamExt<Item=Received<String>> {
+        self.read.frames()
+    }
+    /// Gracefully closes the connection: a Close frame is sent to the server
+    /// and the messages that were still in flight are drained until the 
+    /// server acknowledges the closure. These messages are returned so that
+    /// no data gets lost.
+    pub async fn close(mut self) -> Result<Vec<Response>, Error> {
+        self.write.close().await?;
+        self.read.drain().await
+    }
+}
+/// Decodes the responses carried by a stream of raw text frames. Each frame 
+/// may carry several responses which all share the stamp of that frame. A
+/// frame which cannot be decoded yields one single error.
+pub(crate) fn decode<S>(frames: S) -> impl StreamExt<Item=Result<Received<Response>, Error>>
+    where S: StreamExt<Item=Received<String>>
+{
+    frames.flat_map(|frame| futures::stream::iter(decode_frame(frame)))
+}
+/// Decodes the responses carried by one single raw text frame
+#[allow(clippy::result_large_err)]
+pub(crate) fn decode_frame(frame: Received<String>) -> Vec<Result<Received<Response>, Error>> {
+    let Received { received_at, sequence, message: t } = frame;
+    match serde_json::from_str::<Vec<Response>>(&t) {
+        Ok(data) => data.into_iter()
+            .map(|message| Ok(Received { received_at, sequence, message }))
+            .collect(),
+        Err(e)   => vec![Err(e.into())],
+    }
 }
 /// The portion of the client devoted to the client to server communication
 pub struct ClientSender {
-    write : SplitSink<WsStream, Message>,
+    write  : SplitSink<WsStream, Message>,
+    symbols: SymbolNormalizer,
 }
 impl ClientSender {
     /// Creates a new instance from a given write sink
     pub fn new(write: SplitSink<WsStream, Message>) -> Self {
-        Self {write}
+        Self {write, symbols: SymbolNormalizer::default()}
+    }
+    /// Sets the normalizer which is applied to the symbols of the 
+    /// subscriptions
+    pub fn with_normalizer(mut self, symbols: SymbolNormalizer) -> Self {
+        self.symbols = symbols;
+        self
     }
     /// Authenticates the client
     pub async fn authenticate(&mut self, auth: AuthData) -> Result<(), Error> {
@@ -131,11 +219,11 @@ impl ClientSender {
     }
     /// Subscribe for realtime data about certain trades, quotes or bars
     pub async fn subscribe(&mut self, sub: SubscriptionData) -> Result<(), Error> {
-        self.action(Action::Subscribe(sub)).await
+        self.action(Action::Subscribe(sub.normalized(&self.symbols))).await
     }
     /// Unsubscribe from realtime data about certain trades, quotes or bars
     pub async fn unsubscribe(&mut self, sub: SubscriptionData) -> Result<(), Error> {
-        self.action(Action::Unsubscribe(sub)).await
+        self.action(Action::Unsubscribe(sub.normalized(&self.symbols))).await
     }
     /// Performs the specified action on the server
     pub async fn action(&mut self, action: Action) -> Result<(), Error> {
@@ -143,6 +231,13 @@ impl ClientSender {
         self.write.send(Message::Text(json)).await?;
         Ok(())
     }
+    /// Sends a Close frame to the server, hence initiating the closing 
+    /// handshake. The server acknowledgement is received by the 
+    /// `ClientReceiver` (see `ClientReceiver::drain`).
+    pub async fn close(&mut self) -> Result<(), Error> {
+        self.write.send(Message::Close(None)).await?;
+        Ok(())
+    }
 }
 /// The portion of the client devoted to the server to client communication.
 /// This object is essentially used as a means to obtain an opaquely-types 
@@ -156,18 +251,55 @@ impl ClientReceiver {
         Self {read}
     }
     /// Returns the stream which is used to receive the responses from the server
+    /// (the frames which cannot be decoded are skipped, see `try_stream`)
     pub fn stream(self) -> impl StreamExt<Item=Response> {
+        self.received_stream().map(|r| r.message)
+    }
+    /// Returns the stream which is used to receive the responses from the 
+    /// server, along with an error for each frame which cannot be decoded
+    #[allow(clippy::result_large_err)]
+    pub fn try_stream(self) -> impl StreamExt<Item=Result<Response, Error>> {
+        self.try_received_stream().map(|r| r.map(|r| r.message))
+    }
+    /// Returns the stream which is used to receive the responses from the 
+    /// server. Each response is stamped with the local time at which it was
+    /// received and with the sequence number of the frame that carried it.
+    /// The frames which cannot be decoded are skipped.
+    pub fn received_stream(self) -> impl StreamExt<Item=Received<Response>> {
+        self.try_received_stream().filter_map(|r| futures::future::ready(r.ok()))
+    }
+    /// Same as `received_stream`, along with an error for each frame which
+    /// cannot be decoded
+    pub fn try_received_stream(self) -> impl StreamExt<Item=Result<Received<Response>, Error>> {
+        decode(self.frames())
+    }
+    /// Returns the stream of the raw (undecoded) data frames received from 
+    /// the server, stamped with their receipt time and sequence number.
+    /// Both Text and Binary frames are accepted.
+    pub fn frames(self) -> impl StreamExt<Item=Received<String>> {
         self.read
-        .filter_map(|m| async move {
-            if let Ok(Message::Text(t)) = m {
-                let data: Vec<Response> = serde_json::from_str(&t).unwrap_or_else(|_| panic!("unexpected message '{}'", t));
-                let data = futures::stream::iter(data);
-                Some(data)
-            } else {
-                None
-            }
+        .enumerate()
+        .filter_map(|(sequence, m)| async move {
+            let received_at = Utc::now();
+            let message     = websocket::payload(m.ok()?)?;
+            Some(Received { received_at, sequence: sequence as u64, message })
         })
-        .flatten()
+    }
+    /// Consumes all the messages that are still in flight until the server
+    /// acknowledges the closure of the connection, and returns them.
+    pub async fn drain(mut self) -> Result<Vec<Response>, Error> {
+        let mut drained = vec![];
+        while let Some(message) = self.read.next().await {
+            match message {
+                Ok(Message::Close(_)) => break,
+                Ok(m) => if let Some(t) = websocket::payload(m) {
+                    drained.extend(serde_json::from_str::<Vec<Response>>(&t)?)
+                },
+                Err(tungstenite::Error::ConnectionClosed) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(drained)
     }
 }
 /******************************************************************************
@@ -175,16 +307,25 @@ impl ClientReceiver {
  ******************************************************************************/
  
 /// The data source for the real time data
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
 pub enum Source {
     /// Investor's Exchange (IEX) is the default datasource, and the one 
     /// included in the free subscription plan
+    #[default]
     IEX,
     /// If you intend to use SIP as data source (unlimited plan only)
     SIP
 }
-impl Default for Source {
-    fn default() -> Self { Self::IEX }
+impl std::str::FromStr for Source {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "iex" => Ok(Self::IEX),
+            "sip" => Ok(Self::SIP),
+            _     => Err(Error::InvalidEnv(ENV_DATA_FEED, s.to_string())),
+        }
+    }
 }
 impl std::fmt::Display for Source {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -213,12 +354,77 @@ pub enum Action {
     Unsubscribe(SubscriptionData),
 }
 
+/// Key sent in lieu of an api key-id when authenticating with an OAuth token
+const OAUTH_KEY: &str = "oauth";
+
 /// After connecting you will have to authenticate as follows:
 /// ```{"action":"auth","key":"PK************","secret":"************"}```
-#[derive(Debug, Clone, Serialize, Builder)]
-pub struct AuthData {
-    pub key:    String,
-    pub secret: String,
+///
+/// Apps acting on behalf of end users can authenticate with the OAuth access
+/// token they were granted instead of a key/secret pair:
+/// ```{"action":"auth","key":"oauth","secret":"************"}```
+#[derive(Debug, Clone)]
+pub enum AuthData {
+    /// Authentication with an api key-id and secret key
+    Credentials {
+        key:    String,
+        secret: String,
+    },
+    /// Authentication with an OAuth access token
+    Token(String),
+}
+impl From<Credentials> for AuthData {
+    fn from(credentials: Credentials) -> Self {
+        match credentials {
+            Credentials::Keys { key, secret } => Self::Credentials { key, secret },
+            Credentials::Bearer(token)        => Self::Token(token),
+        }
+    }
+}
+impl Serialize for AuthData {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (key, secret) = match self {
+            Self::Credentials { key, secret } => (key.as_str(), secret.as_str()),
+            Self::Token(token)                => (OAUTH_KEY, token.as_str()),
+        };
+        AuthFrame { key, secret }.serialize(serializer)
+    }
+}
+/// Private: the actual payload of an authentication message
+#[derive(Serialize)]
+struct AuthFrame<'a> {
+    key:    &'a str,
+    secret: &'a str,
+}
+
+/// Private: the fields which are collected by an `AuthDataBuilder`
+#[derive(Builder)]
+#[builder(name = "AuthDataBuilder", public, build_fn(private, name = "fields"))]
+struct AuthFields {
+    #[builder(setter(strip_option), default)]
+    key:    Option<String>,
+    #[builder(setter(strip_option), default)]
+    secret: Option<String>,
+    /// OAuth access token (cannot be used along with a key and secret)
+    #[builder(setter(strip_option), default)]
+    token:  Option<String>,
+}
+impl AuthDataBuilder {
+    /// Builds the authentication data from either a key/secret pair or a token
+    pub fn build(&self) -> Result<AuthData, AuthDataBuilderError> {
+        match self.fields()? {
+            AuthFields { key: Some(key), secret: Some(secret), token: None } =>
+                Ok(AuthData::Credentials { key, secret }),
+            AuthFields { key: None, secret: None, token: Some(token) } =>
+                Ok(AuthData::Token(token)),
+            AuthFields { token: Some(_), .. } =>
+                Err("a token cannot be combined with a key/secret pair".to_string().into()),
+            AuthFields { key: None, .. } =>
+                Err(AuthDataBuilderError::UninitializedField("key")),
+            _ =>
+                Err(AuthDataBuilderError::UninitializedField("secret")),
+        }
+    }
 }
 
 /// You can subscribe to trades, quotes and bars of a particular symbol 
@@ -226,21 +432,85 @@ pub struct AuthData {
 /// contain what subscription you want to add to your current subscriptions in 
 /// your session so you don’t have to send what you’re already subscribed to.
 ///
-/// You can also omit either one of them (trades,quotes or bars) if you don’t 
-/// want to subscribe to any symbols in that category but be sure to include at 
-/// least one of the three.
+/// You can also omit any of the channels (trades, quotes, bars, ...) if you 
+/// don’t want to subscribe to any symbols in that category but be sure to 
+/// include at least one of them.
 ///
 /// Subscription data is also used when you mean to send an `unsubscribe` 
 /// message that subtracts the list of subscriptions specified from your current
 /// set of subscriptions.
-#[derive(Debug, Clone, Serialize, Deserialize, Builder)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, Builder)]
 pub struct SubscriptionData {
-    #[builder(setter(strip_option), default)]
-    pub trades: Option<Vec<String>>,
-    #[builder(setter(strip_option), default)]
-    pub quotes: Option<Vec<String>>,
-    #[builder(setter(strip_option), default)]
-    pub bars  : Option<Vec<String>>,
+    /// The symbols subscribed to on each channel
+    #[serde(flatten)]
+    #[builder(setter(custom), default)]
+    pub channels: HashMap<Channel, Vec<Symbol>>,
+}
+impl SubscriptionData {
+    /// The symbols subscribed to on the given channel (if any)
+    pub fn symbols(&self, channel: Channel) -> Option<&[Symbol]> {
+        self.channels.get(&channel).map(|v| v.as_slice())
+    }
+    /// Rewrites all the symbols of this subscription with the given normalizer
+    pub fn normalized(mut self, symbols: &SymbolNormalizer) -> Self {
+        self.channels.values_mut()
+            .flat_map(|v| v.iter_mut())
+            .for_each(|s| *s = symbols.normalize(s));
+        self
+    }
+}
+impl SubscriptionDataBuilder {
+    /// Sets the symbols to subscribe to on the given channel
+    pub fn channel(&mut self, channel: Channel, symbols: Vec<Symbol>) -> &mut Self {
+        self.channels.get_or_insert_with(HashMap::new).insert(channel, symbols);
+        self
+    }
+    /// Sets the symbols whose trades are subscribed to
+    pub fn trades(&mut self, symbols: Vec<Symbol>) -> &mut Self {
+        self.channel(Channel::Trades, symbols)
+    }
+    /// Sets the symbols whose quotes are subscribed to
+    pub fn quotes(&mut self, symbols: Vec<Symbol>) -> &mut Self {
+        self.channel(Channel::Quotes, symbols)
+    }
+    /// Sets the symbols whose minute bars are subscribed to
+    pub fn bars(&mut self, symbols: Vec<Symbol>) -> &mut Self {
+        self.channel(Channel::Bars, symbols)
+    }
+}
+
+/// A symbol (e.g. AAPL) or * to denote all symbols
+pub type Symbol = String;
+
+/// The channels one can subscribe to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum Channel {
+    #[serde(rename="trades")]
+    Trades,
+    #[serde(rename="quotes")]
+    Quotes,
+    #[serde(rename="bars")]
+    Bars,
+    #[serde(rename="dailyBars")]
+    DailyBars,
+    #[serde(rename="updatedBars")]
+    UpdatedBars,
+    #[serde(rename="statuses")]
+    Statuses,
+    #[serde(rename="lulds")]
+    Lulds,
+    #[serde(rename="news")]
+    News,
+    #[serde(rename="orderbooks")]
+    Orderbooks,
+    #[serde(rename="corrections")]
+    Corrections,
+    #[serde(rename="cancelErrors")]
+    CancelErrors,
+    /// Any channel which is not known to this crate (never sent)
+    #[serde(other)]
+    Unknown,
 }
 
 
@@ -273,6 +543,7 @@ pub struct SubscriptionData {
 /// ```
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "T")]
+#[non_exhaustive]
 pub enum Response {
     /// Obviously, this variant is used to denote control message informing 
     /// you that some error has happened. You may receive an error during your 
@@ -319,7 +590,7 @@ pub enum Response {
     /// * An unexpected error occurred on our end and we are investigating the issue.
     ///   ```[{"T":"error","code":500,"msg":"internal error"}```
     #[serde(rename="error")]
-    Error(RealtimeErrorCode),
+    Error(RealtimeError),
     /// This variant denotes a **control message** meant to inform you of the
     /// successful completion of the action you requested. For instance, 
     /// upon successfully connecting, you will receive the  welcome message: 
@@ -359,10 +630,98 @@ pub enum Response {
     Quote(DataPoint<QuoteData>),
     #[serde(rename="b")]
     Bar(DataPoint<BarData>),
+    /// A trading status change (e.g. a halt) received on the statuses channel
+    #[serde(rename="s")]
+    Status(DataPoint<StatusData>),
+    /// A daily bar, updated with each minute bar of the session
+    #[serde(rename="d")]
+    DailyBar(DataPoint<BarData>),
+    /// A minute bar which was updated by a late trade
+    #[serde(rename="u")]
+    UpdatedBar(DataPoint<BarData>),
+    /// A limit up - limit down price band
+    #[serde(rename="l")]
+    Luld(DataPoint<LuldData>),
+    /// A news article
+    #[serde(rename="n")]
+    News(NewsArticle),
+    /// An update of the order book of a crypto pair
+    #[serde(rename="o")]
+    Orderbook(DataPoint<OrderbookData>),
+    /// The correction of a trade
+    #[serde(rename="c")]
+    Correction(DataPoint<CorrectionData>),
+    /// The cancelation (or error) of a trade
+    #[serde(rename="x")]
+    CancelError(DataPoint<CancelErrorData>),
+    /// A message of a kind which is not supported by this crate (yet). Its
+    /// payload is left undecoded.
+    #[serde(untagged)]
+    Unknown {
+        /// The type of the message (its "T" field)
+        #[serde(rename="T", deserialize_with="unknown_kind")]
+        kind: String,
+        /// The other fields of the message
+        #[serde(flatten)]
+        data: serde_json::Map<String, serde_json::Value>,
+    },
+}
+/// Private: only accepts the types of messages that have no dedicated variant
+/// in `Response` (a malformed message of a known type must fail to decode 
+/// rather than pass for an unknown one)
+fn unknown_kind<'de, D: serde::Deserializer<'de>>(d: D) -> Result<String, D::Error> {
+    let kind = String::deserialize(d)?;
+    match kind.as_str() {
+        "error" | "success" | "subscription" | "t" | "q" | "b" | "s" | "d" | "u" | "l" | "n" | "o" | "c" | "x" =>
+            Err(serde::de::Error::custom(format!("malformed {:?} message", kind))),
+        _ => Ok(kind),
+    }
+}
+
+impl Response {
+    /// The timestamp of the data point carried by this response (if any)
+    pub fn timestamp(&self) -> Option<DateTime<Utc>> {
+        match self {
+            Response::Trade(t)       => Some(t.data.timestamp),
+            Response::Quote(q)       => Some(q.data.timestamp),
+            Response::Bar(b)         => Some(b.data.timestamp),
+            Response::Status(s)      => Some(s.data.timestamp),
+            Response::DailyBar(b)    => Some(b.data.timestamp),
+            Response::UpdatedBar(b)  => Some(b.data.timestamp),
+            Response::Luld(l)        => Some(l.data.timestamp),
+            Response::News(n)        => Some(n.created_at),
+            Response::Orderbook(o)   => Some(o.data.timestamp),
+            Response::Correction(c)  => Some(c.data.timestamp),
+            Response::CancelError(c) => Some(c.data.timestamp),
+            _                        => None,
+        }
+    }
+}
+
+/// A message decoded from the server, stamped with the information that lets
+/// you compute the processing latency and detect reordering.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Received<T> {
+    /// Local time at which the frame carrying the message was received
+    pub received_at: DateTime<Utc>,
+    /// Sequence number of the websocket frame carrying the message. Frames
+    /// are numbered from zero in the order they were received; all the 
+    /// messages of a given frame share the same sequence number.
+    pub sequence: u64,
+    /// The decoded message
+    pub message: T,
+}
+impl Received<Response> {
+    /// The time elapsed between the timestamp of the data point and its 
+    /// receipt. This is `None` for control messages.
+    pub fn latency(&self) -> Option<Duration> {
+        self.message.timestamp().map(|t| self.received_at - t)
+    }
 }
 
 /// A generic datapoint that holds information related to a given symbol
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct DataPoint<T> {
     /// The symbol
     #[serde(rename="S")]
@@ -371,6 +730,83 @@ pub struct DataPoint<T> {
     #[serde(flatten)]
     pub data  : T,
 }
+impl<T> DataPoint<T> {
+    /// Creates a data point carrying the given payload for the given symbol
+    pub fn new(symbol: String, data: T) -> Self {
+        Self { symbol, data }
+    }
+}
+impl Ohlcv for DataPoint<BarData> {
+    fn open(&self) -> f64 { self.data.open() }
+    fn high(&self) -> f64 { self.data.high() }
+    fn low(&self) -> f64 { self.data.low() }
+    fn close(&self) -> f64 { self.data.close() }
+    fn volume(&self) -> u64 { self.data.volume() }
+    fn timestamp(&self) -> DateTime<Utc> { self.data.timestamp() }
+}
+
+// --- CONVERSIONS FROM/TO THE HISTORICAL DATA POINTS -------------------------
+impl From<SingleTrade> for DataPoint<TradeData> {
+    fn from(x: SingleTrade) -> Self {
+        Self { symbol: x.symbol, data: x.trade }
+    }
+}
+impl From<SingleQuote> for DataPoint<QuoteData> {
+    fn from(x: SingleQuote) -> Self {
+        Self { symbol: x.symbol, data: x.quote }
+    }
+}
+impl From<SingleBar> for DataPoint<BarData> {
+    fn from(x: SingleBar) -> Self {
+        Self { symbol: x.symbol, data: x.bar }
+    }
+}
+impl From<DataPoint<TradeData>> for SingleTrade {
+    fn from(x: DataPoint<TradeData>) -> Self {
+        Self { symbol: x.symbol, trade: x.data }
+    }
+}
+impl From<DataPoint<QuoteData>> for SingleQuote {
+    fn from(x: DataPoint<QuoteData>) -> Self {
+        Self { symbol: x.symbol, quote: x.data }
+    }
+}
+impl From<DataPoint<BarData>> for SingleBar {
+    fn from(x: DataPoint<BarData>) -> Self {
+        Self { symbol: x.symbol, bar: x.data }
+    }
+}
+
+// --- EXTRACTION OF THE DATA POINTS FROM A RESPONSE --------------------------
+// These conversions fail when the response does not carry the requested kind
+// of data point. In that case, the original response is given back.
+impl TryFrom<Response> for DataPoint<TradeData> {
+    type Error = Response;
+    fn try_from(r: Response) -> Result<Self, Response> {
+        match r {
+            Response::Trade(x) => Ok(x),
+            other => Err(other),
+        }
+    }
+}
+impl TryFrom<Response> for DataPoint<QuoteData> {
+    type Error = Response;
+    fn try_from(r: Response) -> Result<Self, Response> {
+        match r {
+            Response::Quote(x) => Ok(x),
+            other => Err(other),
+        }
+    }
+}
+impl TryFrom<Response> for DataPoint<BarData> {
+    type Error = Response;
+    fn try_from(r: Response) -> Result<Self, Response> {
+        match r {
+            Response::Bar(x) => Ok(x),
+            other => Err(other),
+        }
+    }
+}
 
 
 /******************************************************************************
@@ -379,7 +815,17 @@ pub struct DataPoint<T> {
 
  #[cfg(test)]
  mod tests {
-    use crate::realtime::Response;
+    use futures::{SinkExt, StreamExt};
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::{accept_async, connect_async, tungstenite::Message};
+
+    use chrono::{Duration, TimeZone, Utc};
+
+    use std::convert::TryFrom;
+    use crate::entities::{BarData, Ohlcv};
+    use crate::errors::RealtimeErrorCode;
+    use crate::historical::SingleBar;
+    use crate::realtime::{Action, AuthDataBuilder, Channel, SubscriptionDataBuilder, Client, ClientReceiver, ClientSender, Received, Response, DataPoint, Source};
  
      #[test]
     fn test_deserialize_trade() {
@@ -435,4 +881,180 @@ pub struct DataPoint<T> {
           let deserialized = serde_json::from_str::<Response>(txt);
           assert!(deserialized.is_ok());
     }
- }
\ No newline at end of file
+    #[test]
+    fn test_serialize_credentials_auth() {
+        let auth = AuthDataBuilder::default()
+            .key("PKXXX".to_string())
+            .secret("SECRET".to_string())
+            .build().unwrap();
+        let txt = serde_json::to_string(&Action::Authenticate(auth)).unwrap();
+        assert_eq!(txt, r#"{"action":"auth","key":"PKXXX","secret":"SECRET"}"#);
+    }
+    #[test]
+    fn test_serialize_token_auth() {
+        let auth = AuthDataBuilder::default()
+            .token("TOKEN".to_string())
+            .build().unwrap();
+        let txt = serde_json::to_string(&Action::Authenticate(auth)).unwrap();
+        assert_eq!(txt, r#"{"action":"auth","key":"oauth","secret":"TOKEN"}"#);
+    }
+    #[test]
+    fn test_token_and_credentials_are_exclusive() {
+        let auth = AuthDataBuilder::default()
+            .key("PKXXX".to_string())
+            .secret("SECRET".to_string())
+            .token("TOKEN".to_string())
+            .build();
+        assert!(auth.is_err());
+    }
+    #[test]
+    fn test_source_from_str() {
+        assert!(matches!("SIP".parse::<Source>(), Ok(Source::SIP)));
+        assert!(matches!("iex".parse::<Source>(), Ok(Source::IEX)));
+        assert!("otc".parse::<Source>().is_err());
+    }
+    #[tokio::test]
+    async fn test_close_drains_in_flight_messages() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url      = format!("ws://{}", listener.local_addr().unwrap());
+        let server   = tokio::spawn(async move {
+            let (sock, _) = listener.accept().await.unwrap();
+            let mut ws    = accept_async(sock).await.unwrap();
+            let msg = r#"[{"T":"success","msg":"authenticated"}]"#;
+            ws.send(Message::Text(msg.to_string())).await.unwrap();
+            let msg = r#"[{"T":"success","msg":"sent as binary"}]"#;
+            ws.send(Message::Binary(msg.as_bytes().to_vec())).await.unwrap();
+            // replying to the close frame is taken care of by tungstenite
+            while let Some(Ok(_)) = ws.next().await {}
+        });
+
+        let (socket, _) = connect_async(url).await.unwrap();
+        let (write, read) = socket.split();
+        let client = Client { write: ClientSender::new(write), read: ClientReceiver::new(read) };
+        let drained = client.close().await.unwrap();
+        assert!(matches!(drained[..], [Response::Success{..}, Response::Success{..}]));
+        server.await.unwrap();
+    }
+    #[test]
+    fn test_received_latency() {
+        let txt = r#"{"T":"t","i":96921,"S":"AAPL","x":"D","p":126.55,"s":1,"t":"2021-02-22T15:51:44.208Z","c":["@","I"],"z":"C"}"#;
+        let message     = serde_json::from_str::<Response>(txt).unwrap();
+        let received_at = Utc.with_ymd_and_hms(2021, 2, 22, 15, 51, 44).unwrap() + Duration::milliseconds(258);
+        let received    = Received { received_at, sequence: 0, message };
+        assert_eq!(Some(Duration::milliseconds(50)), received.latency());
+
+        let control = Received { received_at, sequence: 1, message: Response::Success{message: "connected".to_string()} };
+        assert_eq!(None, control.latency());
+    }
+    #[test]
+    fn test_deserialize_error() {
+        let txt = r#"[{"T":"error","code":405,"msg":"symbol limit exceeded"}]"#;
+        let deserialized = serde_json::from_str::<Vec<Response>>(txt).unwrap();
+        match &deserialized[0] {
+            Response::Error(e) => {
+                assert_eq!(RealtimeErrorCode::SymbolLimitExceeded, e.code);
+                assert_eq!("symbol limit exceeded", e.message);
+            },
+            other => panic!("unexpected response {:?}", other),
+        }
+    }
+    #[test]
+    fn test_serialize_subscription() {
+        let sub = SubscriptionDataBuilder::default()
+            .channel(Channel::Lulds, vec!["AAPL".to_string()])
+            .build().unwrap();
+        let txt = serde_json::to_string(&Action::Subscribe(sub)).unwrap();
+        assert_eq!(txt, r#"{"action":"subscribe","lulds":["AAPL"]}"#);
+    }
+    #[test]
+    fn test_deserialize_subscription() {
+        let txt = r#"{"T":"subscription","trades":["AAPL"],"quotes":["AMD","CLDR"],"bars":["*"],"statuses":[]}"#;
+        match serde_json::from_str::<Response>(txt).unwrap() {
+            Response::Subscription(sub) => {
+                assert_eq!(Some(&["AMD".to_string(), "CLDR".to_string()][..]), sub.symbols(Channel::Quotes));
+                assert_eq!(Some(&[][..]), sub.symbols(Channel::Statuses));
+                assert_eq!(None, sub.symbols(Channel::News));
+            },
+            other => panic!("unexpected response {:?}", other),
+        }
+    }
+    #[test]
+    fn test_bars_are_generic_over_their_source() {
+        fn range<T: Ohlcv>(bar: &T) -> f64 { bar.high() - bar.low() }
+
+        let txt = r#"{"T":"b","S":"SPY","o":388.985,"h":389.13,"l":388.975,"c":389.12,"v":49378,"t":"2021-02-22T19:15:00Z"}"#;
+        let response = serde_json::from_str::<Response>(txt).unwrap();
+        let realtime = DataPoint::<BarData>::try_from(response).unwrap();
+        let history  = SingleBar::from(realtime.clone());
+        assert_eq!("SPY", history.symbol);
+        assert_eq!(range(&realtime), range(&history));
+
+        let control = Response::Success{message: "connected".to_string()};
+        assert!(DataPoint::<BarData>::try_from(control).is_err());
+    }
+    #[tokio::test]
+    async fn test_lulds_are_received() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url      = format!("ws://{}", listener.local_addr().unwrap());
+        let server   = tokio::spawn(async move {
+            let (sock, _) = listener.accept().await.unwrap();
+            let mut ws    = accept_async(sock).await.unwrap();
+            let request   = ws.next().await.unwrap().unwrap();
+            assert_eq!(r#"{"action":"subscribe","lulds":["AAPL"]}"#, request.to_text().unwrap());
+            for msg in [
+                r#"[{"T":"subscription","lulds":["AAPL"]}]"#,
+                r#"[{"T":"l","S":"AAPL","u":152.2,"d":137.7,"i":"B","t":"2021-11-26T14:30:01Z","z":"C"}]"#,
+                r#"[{"T":"brand_new","S":"AAPL"}]"#,
+                r#"[{"T":"l","S":"AAPL"}]"#,
+            ] {
+                ws.send(Message::Text(msg.to_string())).await.unwrap();
+            }
+            ws.close(None).await.unwrap();
+        });
+
+        let (socket, _) = connect_async(url).await.unwrap();
+        let (write, read) = socket.split();
+        let mut client = Client { write: ClientSender::new(write), read: ClientReceiver::new(read) };
+        let sub = SubscriptionDataBuilder::default()
+            .channel(Channel::Lulds, vec!["AAPL".to_string()])
+            .build().unwrap();
+        client.subscribe(sub).await.unwrap();
+
+        let responses = client.try_stream().collect::<Vec<_>>().await;
+        assert_eq!(4, responses.len());
+        assert!(matches!(&responses[0], Ok(Response::Subscription(_))));
+        match &responses[1] {
+            Ok(Response::Luld(luld)) => {
+                assert_eq!("AAPL", luld.symbol);
+                assert_eq!(152.2, luld.data.limit_up_price);
+                assert_eq!(137.7, luld.data.limit_down_price);
+            },
+            other => panic!("unexpected response {:?}", other),
+        }
+        assert!(matches!(&responses[2], Ok(Response::Unknown { kind, .. }) if kind == "brand_new"));
+        // a malformed message of a known type does not pass for an unknown one
+        assert!(responses[3].is_err());
+        server.await.unwrap();
+    }
+    #[test]
+    fn test_deserialize_new_channels() {
+        let txt = r#"[
+            {"T":"d","S":"SPY","o":388.985,"h":389.13,"l":388.975,"c":389.12,"v":49378,"t":"2021-02-22T05:00:00Z"},
+            {"T":"u","S":"SPY","o":388.985,"h":389.13,"l":388.975,"c":389.12,"v":49378,"t":"2021-02-22T19:15:00Z"},
+            {"T":"c","S":"EEM","x":"N","oi":52983525033527,"op":39.1582,"os":440000,"oc":[" ","7","V"],
+             "ci":52983525034326,"cp":39.1809,"cs":440000,"cc":[" ","7","V"],"z":"A","t":"2022-01-18T16:04:36.759Z"},
+            {"T":"x","S":"AAPL","i":52983525034326,"x":"D","p":172.3,"s":100,"a":"C","t":"2022-01-18T16:04:36.759Z","z":"C"},
+            {"T":"o","S":"BTC/USD","t":"2024-03-12T10:38:50.79613221Z","b":[{"p":71859.53,"s":0.27994}],"a":[],"r":true},
+            {"T":"n","id":24918784,"headline":"Corsair Reports Purchase Of Majority Ownership In iDisplay",
+             "summary":"","author":"Benzinga Newsdesk","created_at":"2022-01-05T22:00:37Z","updated_at":"2022-01-05T22:00:38Z",
+             "url":"https://www.benzinga.com/","content":"","symbols":["CRSR"],"source":"benzinga"}
+        ]"#;
+        let responses = serde_json::from_str::<Vec<Response>>(txt).unwrap();
+        assert!(matches!(&responses[0], Response::DailyBar(_)));
+        assert!(matches!(&responses[1], Response::UpdatedBar(_)));
+        assert!(matches!(&responses[2], Response::Correction(c) if c.data.corrected_price == 39.1809));
+        assert!(matches!(&responses[3], Response::CancelError(x) if x.data.action == "C"));
+        assert!(matches!(&responses[4], Response::Orderbook(o) if o.data.reset && o.data.bids.len() == 1));
+        assert!(matches!(&responses[5], Response::News(n) if n.symbols == vec!["CRSR"]));
+    }
+ }
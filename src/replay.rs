@@ -0,0 +1,361 @@
+//! This module lets you record realtime sessions and replay them offline.
+//!
+//! The [`Recorder`] writes every frame received from the realtime server to a
+//! file (one JSON object per line) along with the time it was received. The
+//! [`Replayer`] feeds such a file back through the very same `Response`
+//! stream as the live client; either at the original pace or at an
+//! accelerated one. This is invaluable when it comes to debugging or
+//! regression-testing a strategy offline.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), apca_datav2::errors::Error> {
+//! use futures::StreamExt;
+//! use apca_datav2::realtime::{Client, Source};
+//! use apca_datav2::replay::{Recorder, Replayer};
+//!
+//! // record a live session
+//! let client   = Client::new(Source::IEX).await?;
+//! let recorder = Recorder::create("session.jsonl")?;
+//! let mut live = Box::pin(recorder.record(client.frames()));
+//! while let Some(response) = live.next().await {
+//!     println!("{:?}", response?.message);
+//! }
+//!
+//! // replay it ten times faster than it was recorded
+//! let mut replay = Box::pin(Replayer::open("session.jsonl")?.speed(10.0)?.stream());
+//! while let Some(response) = replay.next().await {
+//!     println!("{:?}", response.message);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! # Historical replay
+//! The [`HistoricalReplay`] fetches the bars and/or trades of some past days
+//! through the REST API and emits them as the very same `Response` stream.
+//! Hence, a strategy written against the live stream can be dry-run on any
+//! past day without changing a single line of code. The data points are
+//! emitted in chronological order and stamped as if they had been received
+//! live: a trade at its own timestamp, and a bar at the end of the interval
+//! it aggregates (so that it never precedes its own trades).
+//!
+//! ```no_run
+//! # async fn example(client: apca_datav2::rest::Client) -> Result<(), apca_datav2::errors::Error> {
+//! use chrono::{TimeZone, Utc};
+//! use futures::StreamExt;
+//! use apca_datav2::historical::TimeFrame;
+//! use apca_datav2::replay::HistoricalReplay;
+//!
+//! let start  = Utc.with_ymd_and_hms(2021, 11, 26, 14, 30, 0).unwrap();
+//! let end    = Utc.with_ymd_and_hms(2021, 11, 26, 18, 0, 0).unwrap();
+//! let bars   = HistoricalReplay::bars(&client, &["AAPL", "MSFT"], start, end, TimeFrame::Minutes(1)).await?;
+//! let trades = HistoricalReplay::trades(&client, &["AAPL"], start, end).await?;
+//!
+//! // replay the session sixty times faster than it happened
+//! let mut replay = Box::pin(bars.merge(trades).speed(60.0)?.stream());
+//! while let Some(response) = replay.next().await {
+//!     println!("{:?}", response.message);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{fs::File, io::{BufRead, BufReader, BufWriter, Write}, path::Path};
+
+use chrono::{DateTime, Utc};
+use futures::{Stream, StreamExt, TryStreamExt};
+
+use crate::errors::Error;
+use crate::historical::{MAX_LIMIT, TimeFrame};
+use crate::realtime::{self, DataPoint, Received, Response};
+use crate::rest;
+
+/// Writes the frames of a realtime session to a JSONL file
+pub struct Recorder<W: Write> {
+    out: W,
+}
+impl Recorder<BufWriter<File>> {
+    /// Creates a recorder writing to the file at the given path
+    #[allow(clippy::result_large_err)]
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+}
+impl<W: Write> Recorder<W> {
+    /// Creates a recorder writing to the given output
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+    /// Writes one frame to the output
+    #[allow(clippy::result_large_err)]
+    pub fn write(&mut self, frame: &Received<String>) -> Result<(), Error> {
+        serde_json::to_writer(&mut self.out, frame)?;
+        writeln!(self.out)?;
+        self.out.flush()?;
+        Ok(())
+    }
+    /// Records each of the given frames while decoding them into responses.
+    /// A frame which cannot be decoded yields an error; so does a frame which
+    /// cannot be written to the output (in which case the recording stops,
+    /// but the responses keep flowing).
+    pub fn record<S>(mut self, frames: S) -> impl Stream<Item=Result<Received<Response>, Error>>
+        where S: Stream<Item=Received<String>>
+    {
+        let mut recording = true;
+        frames.flat_map(move |frame| {
+            let mut items = vec![];
+            if recording {
+                if let Err(e) = self.write(&frame) {
+                    recording = false;
+                    items.push(Err(e));
+                }
+            }
+            items.extend(realtime::decode_frame(frame));
+            futures::stream::iter(items)
+        })
+    }
+}
+
+/// Feeds a recorded session back through a stream of responses
+pub struct Replayer {
+    frames: Vec<Received<String>>,
+    speed : f64,
+}
+impl Replayer {
+    /// Loads the session recorded in the file at the given path
+    #[allow(clippy::result_large_err)]
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+    /// Loads the session recorded in the given input
+    #[allow(clippy::result_large_err)]
+    pub fn from_reader<R: BufRead>(input: R) -> Result<Self, Error> {
+        let mut frames = vec![];
+        for line in input.lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                frames.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(Self { frames, speed: 1.0 })
+    }
+    /// Sets the replay speed. A factor of 1.0 (the default) replays the frames
+    /// at their original pace, 2.0 replays them twice as fast and
+    /// `f64::INFINITY` replays them without any pause. This fails with
+    /// `Error::InvalidSpeed` unless the factor is positive.
+    #[allow(clippy::result_large_err)]
+    pub fn speed(mut self, factor: f64) -> Result<Self, Error> {
+        self.speed = check_speed(factor)?;
+        Ok(self)
+    }
+    /// Returns the stream of the recorded frames, paced according to the
+    /// replay speed
+    pub fn frames(self) -> impl Stream<Item=Received<String>> {
+        paced(self.frames, self.speed)
+    }
+    /// Returns the stream of the recorded responses, paced according to the
+    /// replay speed (the frames which cannot be decoded are skipped, see
+    /// `try_stream`)
+    pub fn stream(self) -> impl Stream<Item=Received<Response>> {
+        self.try_stream().filter_map(|r| futures::future::ready(r.ok()))
+    }
+    /// Same as `stream`, along with an error for each recorded frame which
+    /// cannot be decoded
+    pub fn try_stream(self) -> impl Stream<Item=Result<Received<Response>, Error>> {
+        realtime::decode(self.frames())
+    }
+}
+
+/// Feeds the historical data of some past days through a stream of responses
+pub struct HistoricalReplay {
+    responses: Vec<Received<Response>>,
+    speed    : f64,
+}
+impl HistoricalReplay {
+    /// Fetches the bars of the given symbols between start and end
+    pub async fn bars(client: &rest::Client, symbols: &[&str], start: DateTime<Utc>, end: DateTime<Utc>, timeframe: TimeFrame) -> Result<Self, Error> {
+        let mut responses = vec![];
+        for symbol in symbols {
+            let name = client.normalize(symbol);
+            let bars = client.bars(symbol, start, end, timeframe, Some(MAX_LIMIT)).try_collect::<Vec<_>>().await?;
+            responses.extend(bars.into_iter()
+                .map(|b| (b.timestamp + timeframe.duration(), Response::Bar(DataPoint::new(name.clone(), b)))));
+        }
+        Ok(Self::new(responses))
+    }
+    /// Fetches the trades of the given symbols between start and end
+    pub async fn trades(client: &rest::Client, symbols: &[&str], start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Self, Error> {
+        let mut responses = vec![];
+        for symbol in symbols {
+            let name   = client.normalize(symbol);
+            let trades = client.trades(symbol, start, end, Some(MAX_LIMIT)).try_collect::<Vec<_>>().await?;
+            responses.extend(trades.into_iter()
+                .map(|t| (t.timestamp, Response::Trade(DataPoint::new(name.clone(), t)))));
+        }
+        Ok(Self::new(responses))
+    }
+    /// Private: sorts the given responses by the time they would have been
+    /// received at
+    fn new(responses: Vec<(DateTime<Utc>, Response)>) -> Self {
+        let mut replay = Self { responses: vec![], speed: 1.0 };
+        replay.extend(responses);
+        replay
+    }
+    /// Private: adds the given responses and restores the chronological order
+    fn extend<I: IntoIterator<Item=(DateTime<Utc>, Response)>>(&mut self, responses: I) {
+        let mut all = self.responses.drain(..).map(|r| (r.received_at, r.message)).collect::<Vec<_>>();
+        all.extend(responses);
+        all.sort_by_key(|(received_at, _)| *received_at);
+        self.responses = all.into_iter().enumerate()
+            .map(|(i, (received_at, message))| Received { received_at, sequence: i as u64, message })
+            .collect();
+    }
+    /// Interleaves the data points of both replays (e.g. bars and trades)
+    pub fn merge(mut self, other: Self) -> Self {
+        self.extend(other.responses.into_iter().map(|r| (r.received_at, r.message)));
+        self
+    }
+    /// Sets the replay speed (see `Replayer::speed`)
+    #[allow(clippy::result_large_err)]
+    pub fn speed(mut self, factor: f64) -> Result<Self, Error> {
+        self.speed = check_speed(factor)?;
+        Ok(self)
+    }
+    /// The number of data points of the replay
+    pub fn len(&self) -> usize {
+        self.responses.len()
+    }
+    /// Returns true iff the replay has no data point
+    pub fn is_empty(&self) -> bool {
+        self.responses.is_empty()
+    }
+    /// Returns the stream of the historical responses, paced according to
+    /// the replay speed
+    pub fn stream(self) -> impl Stream<Item=Received<Response>> {
+        paced(self.responses, self.speed)
+    }
+}
+
+/// Private: rejects the speeds which are not positive (NaN included)
+#[allow(clippy::result_large_err)]
+fn check_speed(factor: f64) -> Result<f64, Error> {
+    if factor > 0.0 {
+        Ok(factor)
+    } else {
+        Err(Error::InvalidSpeed(factor))
+    }
+}
+
+/// Private: emits the given items with the pauses between their reception
+/// times, scaled down by the given speed
+fn paced<T>(items: Vec<Received<T>>, speed: f64) -> impl Stream<Item=Received<T>> {
+    let mut previous: Option<DateTime<Utc>> = None;
+    futures::stream::iter(items)
+        .then(move |item| {
+            let pause = previous
+                .map(|p| (item.received_at - p).to_std().unwrap_or_default())
+                .map(|d| d.div_f64(speed))
+                .unwrap_or_default();
+            previous = Some(item.received_at);
+            async move {
+                if !pause.is_zero() {
+                    tokio::time::sleep(pause).await;
+                }
+                item
+            }
+        })
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+    use futures::StreamExt;
+
+    use crate::errors::Error;
+    use crate::historical::TimeFrame;
+    use crate::realtime::{Received, Response};
+    use crate::replay::{HistoricalReplay, Recorder, Replayer};
+    use crate::testing::fake_client;
+    use crate::transport::FakeTransport;
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let now    = Utc::now();
+        let frames = [
+            Received { received_at: now, sequence: 0, message: r#"[{"T":"success","msg":"connected"}]"#.to_string() },
+            Received { received_at: now + Duration::seconds(3600), sequence: 1,
+                message: r#"[{"T":"b","S":"SPY","o":388.985,"h":389.13,"l":388.975,"c":389.12,"v":49378,"t":"2021-02-22T19:15:00Z"},
+                             {"T":"b","S":"AAPL","o":126.0,"h":127.0,"l":125.0,"c":126.5,"v":1000,"t":"2021-02-22T19:15:00Z"}]"#.to_string() },
+        ];
+        let mut out = vec![];
+        let mut recorder = Recorder::new(&mut out);
+        frames.iter().for_each(|f| recorder.write(f).unwrap());
+
+        let replayed = Replayer::from_reader(out.as_slice()).unwrap()
+            .speed(f64::INFINITY).unwrap()
+            .stream()
+            .collect::<Vec<_>>().await;
+
+        assert_eq!(3, replayed.len());
+        assert!(matches!(replayed[0].message, Response::Success{..}));
+        assert!(matches!(replayed[2].message, Response::Bar(_)));
+        assert_eq!(1, replayed[2].sequence);
+        assert_eq!(now + Duration::seconds(3600), replayed[2].received_at);
+    }
+
+    #[tokio::test]
+    async fn test_write_failures_are_surfaced() {
+        /// An output which is always full
+        struct Full;
+        impl std::io::Write for Full {
+            fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("disk full"))
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let now    = Utc::now();
+        let frame  = |sequence| Received { received_at: now, sequence, message: r#"[{"T":"success","msg":"connected"}]"#.to_string() };
+        let frames = futures::stream::iter(vec![frame(0), frame(1)]);
+
+        let recorded = Recorder::new(Full).record(frames).collect::<Vec<_>>().await;
+        assert_eq!(3, recorded.len());
+        assert!(matches!(recorded[0], Err(Error::Json(_)) | Err(Error::Io(_))));
+        assert!(matches!(&recorded[1], Ok(r) if r.sequence == 0));
+        assert!(matches!(&recorded[2], Ok(r) if r.sequence == 1));
+    }
+    #[tokio::test]
+    async fn test_historical_replay() {
+        let bars = r#"{"bars":[
+            {"t":"2021-08-02T13:30:00Z","o":1,"h":2,"l":0.5,"c":1.5,"v":100},
+            {"t":"2021-08-02T13:32:00Z","o":1,"h":2,"l":0.5,"c":1.5,"v":100}
+        ],"symbol":"AAPL","next_page_token":null}"#;
+        let trades = r#"{"trades":[
+            {"t":"2021-08-02T13:30:30Z","x":"V","p":1.5,"s":10,"c":["@"],"i":1,"z":"C"}
+        ],"symbol":"AAPL","next_page_token":null}"#;
+        let transport = FakeTransport::new()
+            .respond("GET", "/v2/stocks/AAPL/bars", 200, bars)
+            .respond("GET", "/v2/stocks/AAPL/trades", 200, trades);
+        let client = fake_client(&transport);
+
+        let start  = Utc.with_ymd_and_hms(2021, 8, 2, 13, 30, 0).unwrap();
+        let end    = Utc.with_ymd_and_hms(2021, 8, 2, 14, 0, 0).unwrap();
+        let bars   = HistoricalReplay::bars(&client, &["AAPL"], start, end, TimeFrame::Minutes(1)).await.unwrap();
+        let trades = HistoricalReplay::trades(&client, &["AAPL"], start, end).await.unwrap();
+        let replayed = bars.merge(trades).speed(f64::INFINITY).unwrap().stream().collect::<Vec<_>>().await;
+
+        // the bar of 13:30 is only complete at 13:31, after its trades
+        assert_eq!(3, replayed.len());
+        assert!(matches!(replayed[0].message, Response::Trade(_)));
+        assert!(matches!(&replayed[1].message, Response::Bar(b) if b.symbol == "AAPL"));
+        assert!(matches!(replayed[2].message, Response::Bar(_)));
+        assert_eq!(start + Duration::minutes(1), replayed[1].received_at);
+        assert_eq!(2, replayed[2].sequence);
+        assert!(Replayer::from_reader(&b""[..]).unwrap().speed(0.0).is_err());
+    }
+}
@@ -2,30 +2,133 @@
 //! As such, it does not provide any business information. It does however help
 //! in implementing a seamless interface to Alpaca's services.
 
-use std::{pin::Pin, task::Poll};
+use std::{pin::Pin, sync::{Arc, Mutex, RwLock}, task::Poll, time::{Duration, Instant}};
 
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use derive_builder::Builder;
 use futures::{Future, FutureExt, Stream};
-use reqwest::RequestBuilder;
+use reqwest::{Method, RequestBuilder, header::HeaderMap};
+use serde::{Serialize, de::DeserializeOwned};
 
-use crate::errors::Error;
+use crate::{cache::ResponseCache, endpoints::DATA_URL, entities::Direction, historical::Feed, errors::{Error, RequestInfo, status_code_to_api_error}, symbols::SymbolNormalizer, transport::HttpTransport};
 
 /// Header used to send the key-id authentication
 pub const APCA_API_KEY_ID: &str = "APCA-API-KEY-ID";
 /// Header used to send the secret-key for authentication
 pub const APCA_API_SECRET_KEY: &str = "APCA-API-SECRET-KEY";
+/// Header carrying the id the server assigned to a request
+pub const X_REQUEST_ID: &str = "X-Request-ID";
 
 /// Base URL to interact with live trading api
 pub const LIVE_TRADING_URL: &str = "https://api.alpaca.markets";
 /// Base URL to interact with paper trading api
 pub const PAPER_TRADING_URL: &str = "https://paper-api.alpaca.markets";
+/// The number of requests per minute Alpaca allows for one account
+pub const ALPACA_RATE_LIMIT: u32 = 200;
 
+/// Environment variable holding the api key-id
+pub const ENV_KEY_ID: &str = "APCA_API_KEY_ID";
+/// Environment variable holding the secret key
+pub const ENV_SECRET_KEY: &str = "APCA_API_SECRET_KEY";
+/// Environment variable selecting the trading api (paper when it is not set)
+pub const ENV_BASE_URL: &str = "APCA_API_BASE_URL";
+/// Environment variable overriding the base URL of the market data api
+pub const ENV_DATA_URL: &str = "APCA_API_DATA_URL";
+
+
+/// The credentials used to authenticate the requests
+#[derive(Clone)]
+pub enum Credentials {
+    /// An API key id and its secret (sent as `APCA-API-KEY-ID` and 
+    /// `APCA-API-SECRET-KEY` headers)
+    Keys { key: String, secret: String },
+    /// An OAuth access token (sent as an `Authorization: Bearer` header)
+    Bearer(String),
+}
+impl Credentials {
+    /// Reads the api key-id and secret key from the `APCA_API_KEY_ID` and
+    /// `APCA_API_SECRET_KEY` environment variables
+    #[allow(clippy::result_large_err)]
+    pub fn from_env() -> Result<Self, Error> {
+        Self::from_lookup(&env)
+    }
+    /// Private: same as `from_env`, reading the variables with `lookup`
+    #[allow(clippy::result_large_err)]
+    fn from_lookup(lookup: &dyn Fn(&str) -> Option<String>) -> Result<Self, Error> {
+        Ok(Credentials::Keys { key: required(lookup, ENV_KEY_ID)?, secret: required(lookup, ENV_SECRET_KEY)? })
+    }
+}
+/// Private: the value of an environment variable (if it is set)
+fn env(name: &str) -> Option<String> {
+    std::env::var(name).ok()
+}
+/// Private: the value of a mandatory variable
+#[allow(clippy::result_large_err)]
+fn required(lookup: &dyn Fn(&str) -> Option<String>, name: &'static str) -> Result<String, Error> {
+    lookup(name).ok_or(Error::MissingEnv(name))
+}
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Credentials::Keys { key, .. } => write!(f, "Keys {{ key: {:?}, secret: *** }}", key),
+            Credentials::Bearer(_)        => write!(f, "Bearer(***)"),
+        }
+    }
+}
+
+/// A hook invoked around each request sent by a `Client` (e.g. to inject 
+/// custom headers, sign the requests or keep an audit log). All methods do
+/// nothing by default.
+///
+/// ```
+/// use apca_datav2::rest::{Client, Interceptor};
+///
+/// struct Audit;
+/// impl Interceptor for Audit {
+///     fn before_send(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+///         request.header("X-Audit-Id", "42")
+///     }
+///     fn after_receive(&self, response: &reqwest::Response) {
+///         println!("{} {}", response.url(), response.status());
+///     }
+/// }
+/// let client = Client::paper("key".to_string(), "secret".to_string())
+///     .with_interceptor(Audit);
+/// ```
+#[allow(unused_variables)]
+pub trait Interceptor: Send + Sync {
+    /// Invoked once per request, before it is sent (for the first time)
+    fn before_send(&self, request: RequestBuilder) -> RequestBuilder {
+        request
+    }
+    /// Invoked for each response received from the server (including the 
+    /// ones of the attempts which are retried)
+    fn after_receive(&self, response: &reqwest::Response) {}
+    /// Invoked instead of sending the request when the client does not send
+    /// it for real (e.g. an order placed in dry-run mode)
+    fn on_dry_run(&self, request: &reqwest::Request) {}
+}
 
 /// An authenticated REST client
 pub struct Client {
-    key: String,
-    secret: String,
+    credentials: RwLock<Credentials>,
     client: reqwest::Client,
-    env_url: &'static str,
+    transport: Arc<dyn HttpTransport>,
+    env_url: String,
+    data_url: String,
+    symbols: SymbolNormalizer,
+    limiter: Option<RateLimiter>,
+    retry: Option<RetryPolicy>,
+    rate_limit: Mutex<Option<RateLimitStatus>>,
+    interceptors: Vec<Arc<dyn Interceptor>>,
+    prefetch: bool,
+    cache: Option<ResponseCache>,
+    feed: Option<Feed>,
+    asof: Option<NaiveDate>,
+    sort: Option<Direction>,
+    currency: Option<String>,
+    dry_run: bool,
+    preflight: bool,
 }
 
 impl Client {
@@ -37,37 +140,707 @@ impl Client {
   }
   pub fn new(key: String, secret: String, live: bool) -> Self {
     let env_url = if live { LIVE_TRADING_URL } else { PAPER_TRADING_URL };
-    Self {key, secret, client: reqwest::Client::new(), env_url}
+    Self::with_url(key, secret, env_url)
+  }
+  /// Creates a client using an arbitrary base URL for the trading api (e.g.
+  /// the broker api, a corporate proxy or a mock server).
+  pub fn with_url(key: String, secret: String, url: &str) -> Self {
+    Self::with_credentials(Credentials::Keys { key, secret }, url)
+  }
+  /// Creates a client configured with the same environment variables as the
+  /// other Alpaca SDKs: `APCA_API_KEY_ID` and `APCA_API_SECRET_KEY` for the 
+  /// credentials, `APCA_API_BASE_URL` to select the trading api (paper by 
+  /// default) and `APCA_API_DATA_URL` to override the market data api.
+  ///
+  /// ```no_run
+  /// # fn example() -> Result<(), apca_datav2::errors::Error> {
+  /// // APCA_API_BASE_URL=https://api.alpaca.markets to trade live
+  /// let client = apca_datav2::rest::Client::from_env()?;
+  /// # Ok(())
+  /// # }
+  /// ```
+  #[allow(clippy::result_large_err)]
+  pub fn from_env() -> Result<Self, Error> {
+    Self::from_lookup(&env)
+  }
+  /// Private: same as `from_env`, reading the variables with `lookup`
+  #[allow(clippy::result_large_err)]
+  fn from_lookup(lookup: &dyn Fn(&str) -> Option<String>) -> Result<Self, Error> {
+    let env_url = lookup(ENV_BASE_URL).unwrap_or_else(|| PAPER_TRADING_URL.to_string());
+    let client  = Self::with_credentials(Credentials::from_lookup(lookup)?, &env_url);
+    match lookup(ENV_DATA_URL) {
+      Some(url) => Ok(client.with_data_url(&url)),
+      None      => Ok(client),
+    }
+  }
+  /// Creates a client authenticated with an OAuth access token, connected
+  /// to the live (or paper) trading api
+  pub fn oauth(token: String, live: bool) -> Self {
+    let env_url = if live { LIVE_TRADING_URL } else { PAPER_TRADING_URL };
+    Self::with_credentials(Credentials::Bearer(token), env_url)
+  }
+  /// Creates a client using the given credentials and base URL for the 
+  /// trading api
+  pub fn with_credentials(credentials: Credentials, url: &str) -> Self {
+    Self {
+      credentials: RwLock::new(credentials), 
+      client    : reqwest::Client::new(), 
+      transport : Arc::new(reqwest::Client::new()),
+      env_url   : url.to_string(), 
+      data_url  : DATA_URL.to_string(),
+      symbols   : SymbolNormalizer::default(), 
+      limiter   : None, 
+      retry     : None, 
+      rate_limit: Mutex::new(None),
+      interceptors: vec![],
+      prefetch  : false,
+      cache     : None,
+      feed      : None,
+      asof      : None,
+      sort      : None,
+      currency  : None,
+      dry_run   : false,
+      preflight : false,
+    }
+  }
+  /// Returns a builder to configure the client (timeouts, proxy...)
+  pub fn builder(key: String, secret: String) -> ClientBuilder {
+    ClientBuilder::new(Credentials::Keys { key, secret })
+  }
+  /// Overrides the base URL of the market data api
+  pub fn with_data_url(mut self, url: &str) -> Self {
+    self.data_url = url.to_string();
+    self
+  }
+  /// Limits the rate at which the requests are sent to at most `requests` 
+  /// per `period` (e.g. `ALPACA_RATE_LIMIT` per minute). The requests which 
+  /// exceed that rate are transparently delayed. By default, no limit is
+  /// enforced.
+  pub fn with_rate_limit(mut self, requests: u32, period: Duration) -> Self {
+    self.limiter = Some(RateLimiter::new(requests, period));
+    self
+  }
+  /// Retries the GET requests which fail for a transient reason, as defined
+  /// by the given policy. The other requests are never retried: a DELETE 
+  /// (e.g. closing part of a position) or a PUT which timed out may have 
+  /// been processed nonetheless. By default, no request is retried.
+  pub fn with_retry(mut self, policy: RetryPolicy) -> Self {
+    self.retry = Some(policy);
+    self
+  }
+  /// Sends the requests through the given transport rather than over the 
+  /// network (see the `transport` module)
+  pub fn with_transport<T: HttpTransport + 'static>(mut self, transport: T) -> Self {
+    self.transport = Arc::new(transport);
+    self
+  }
+  /// Enables (or disables) the prefetching of the next page in the paged 
+  /// streams (historical data, orders...). This roughly halves the time it 
+  /// takes to go through a large history.
+  pub fn with_prefetch(mut self, enabled: bool) -> Self {
+    self.prefetch = enabled;
+    self
+  }
+  /// Whether the paged streams prefetch their next page
+  pub fn prefetches(&self) -> bool {
+    self.prefetch
+  }
+  /// Pins the historical requests to the given data feed. By default, the 
+  /// server picks the best feed the subscription gives access to. This is
+  /// the default of all requests; `HistoricalOptions` overrides it per call.
+  pub fn with_feed(mut self, feed: Feed) -> Self {
+    self.feed = Some(feed);
+    self
+  }
+  /// The feed the historical requests are pinned to (if any)
+  pub fn feed(&self) -> Option<Feed> {
+    self.feed
+  }
+  /// Resolves the symbols of the historical requests as they were on the 
+  /// given day (by default, as they are on the current day). This lets you 
+  /// query the history of a ticker which has since been renamed.
+  pub fn with_asof(mut self, date: NaiveDate) -> Self {
+    self.asof = Some(date);
+    self
+  }
+  /// The day as of which the symbols of the historical requests are resolved
+  pub fn asof(&self) -> Option<NaiveDate> {
+    self.asof
+  }
+  /// Sorts the historical trades, quotes and bars in the given chronological
+  /// order (by default, ascending). With `Direction::Descending`, the most
+  /// recent data comes first; which is handy along with a limit. This is
+  /// the default of all requests; `HistoricalOptions` overrides it per call.
+  pub fn with_sort(mut self, sort: Direction) -> Self {
+    self.sort = Some(sort);
+    self
+  }
+  /// The chronological order of the historical trades, quotes and bars (if
+  /// it was set)
+  pub fn sort(&self) -> Option<Direction> {
+    self.sort
+  }
+  /// Converts the prices of the historical data and snapshots into the 
+  /// given currency (ISO 4217 code, e.g. "EUR"). By default, the prices 
+  /// are in USD. This is only available to some accounts. This is the 
+  /// default of all requests; `HistoricalOptions` overrides it per call.
+  pub fn with_currency(mut self, currency: &str) -> Self {
+    self.currency = Some(currency.to_string());
+    self
+  }
+  /// The currency of the prices of the historical data (if it was set)
+  pub fn currency(&self) -> Option<&str> {
+    self.currency.as_deref()
+  }
+  /// Keeps the responses of the static endpoints (e.g. the list of assets) 
+  /// in memory for the given time to live (see the `cache` module). By 
+  /// default, nothing is cached.
+  pub fn with_cache(mut self, ttl: Duration) -> Self {
+    self.cache = Some(ResponseCache::new(ttl));
+    self
+  }
+  /// Drops all the cached responses (if the cache is enabled)
+  pub fn clear_cache(&self) {
+    if let Some(cache) = self.cache.as_ref() {
+      cache.clear();
+    }
+  }
+  /// Enables (or disables) the dry-run mode of the order placement. In that
+  /// mode, `place_order` validates the order and hands the request it would
+  /// send to the `Interceptor::on_dry_run` hooks, then returns a synthetic 
+  /// accepted order without hitting the api. Only the placement of orders is
+  /// affected: all the other requests (including the cancellations, the 
+  /// replacements and the closing of positions) are sent as usual.
+  pub fn with_dry_run_orders(mut self, enabled: bool) -> Self {
+    self.dry_run = enabled;
+    self
+  }
+  /// Whether the orders are placed in dry-run mode
+  pub fn dry_runs_orders(&self) -> bool {
+    self.dry_run
+  }
+  /// Enables (or disables) the pre-trade checks. When they are enabled,
+  /// `place_order` consults the asset record (and, for a sell, the position)
+  /// before submitting an order, and rejects it locally when the asset is 
+  /// not tradable, or cannot be shorted (see `Client::preflight`). Enabling
+  /// the cache as well spares most of the asset lookups.
+  pub fn with_preflight(mut self, enabled: bool) -> Self {
+    self.preflight = enabled;
+    self
+  }
+  /// Whether the orders are checked before they are submitted
+  pub fn preflights(&self) -> bool {
+    self.preflight
+  }
+  /// Adds a hook invoked around each request. The interceptors are invoked
+  /// in the order in which they were added.
+  pub fn with_interceptor<I: Interceptor + 'static>(mut self, interceptor: I) -> Self {
+    self.interceptors.push(Arc::new(interceptor));
+    self
+  }
+  /// Sets the normalizer which is applied to all the symbols passed as 
+  /// parameters of the requests
+  pub fn with_normalizer(mut self, symbols: SymbolNormalizer) -> Self {
+    self.symbols = symbols;
+    self
   }
-  pub fn get_authenticated(&self, url: &str) -> RequestBuilder {
-    self.client.get(url)
-        .header(APCA_API_KEY_ID,     &self.key)
-        .header(APCA_API_SECRET_KEY, &self.secret)        
+  /// The normalizer which is applied to the symbols
+  pub fn symbols(&self) -> &SymbolNormalizer {
+    &self.symbols
   }
-  pub fn put_authenticated(&self, url: &str) -> RequestBuilder {
-    self.client.put(url)
-        .header(APCA_API_KEY_ID,     &self.key)
-        .header(APCA_API_SECRET_KEY, &self.secret)        
+  /// Rewrites the given symbol into Alpaca's expected format
+  pub fn normalize(&self, symbol: &str) -> String {
+    self.symbols.normalize(symbol)
   }
-  pub fn post_authenticated(&self, url: &str) -> RequestBuilder {
-    self.client.post(url)
-        .header(APCA_API_KEY_ID,     &self.key)
-        .header(APCA_API_SECRET_KEY, &self.secret)        
+  pub fn get_authenticated(&self, url: &str) -> Request<'_> {
+    self.authenticated(self.client.get(url), true)
   }
-  pub fn patch_authenticated(&self, url: &str) -> RequestBuilder {
-    self.client.patch(url)
-        .header(APCA_API_KEY_ID,     &self.key)
-        .header(APCA_API_SECRET_KEY, &self.secret)        
+  pub fn put_authenticated(&self, url: &str) -> Request<'_> {
+    self.authenticated(self.client.put(url), false)
   }
-  pub fn delete_authenticated(&self, url: &str) -> RequestBuilder {
-    self.client.delete(url)
-        .header(APCA_API_KEY_ID,     &self.key)
-        .header(APCA_API_SECRET_KEY, &self.secret)        
+  pub fn post_authenticated(&self, url: &str) -> Request<'_> {
+    self.authenticated(self.client.post(url), false)
   }
-  pub fn env_url(&self) -> &'static str {
-    self.env_url
+  pub fn patch_authenticated(&self, url: &str) -> Request<'_> {
+    self.authenticated(self.client.patch(url), false)
+  }
+  pub fn delete_authenticated(&self, url: &str) -> Request<'_> {
+    self.authenticated(self.client.delete(url), false)
+  }
+  /// Replaces the api key-id and secret used to authenticate the requests.
+  /// This lets a long-running service rotate its keys without having to 
+  /// rebuild the client: the requests issued after this call use the new
+  /// keys (the ones which are already in flight are left untouched).
+  pub fn set_credentials(&self, key: String, secret: String) {
+    *self.credentials.write().unwrap() = Credentials::Keys { key, secret };
+  }
+  /// Private: adds the authentication headers to the request
+  fn authenticated(&self, builder: RequestBuilder, idempotent: bool) -> Request<'_> {
+    let builder = match &*self.credentials.read().unwrap() {
+        Credentials::Keys { key, secret } => builder
+            .header(APCA_API_KEY_ID,     key)
+            .header(APCA_API_SECRET_KEY, secret),
+        Credentials::Bearer(token) => builder.bearer_auth(token),
+    };
+    Request { client: self, builder, idempotent }
+  }
+  /// Sends a GET request to an endpoint this crate does not model (yet) and
+  /// decodes its JSON response. The path is relative to the trading api 
+  /// (unless it is a full URL, e.g. one of the market data api). The 
+  /// request is authenticated, rate limited and retried like any other; an
+  /// unsuccessful status yields an `Error::Api`.
+  ///
+  /// ```no_run
+  /// # async fn example(client: apca_datav2::rest::Client) -> Result<(), apca_datav2::errors::Error> {
+  /// let calendar: serde_json::Value = client
+  ///     .get_json("/v2/calendar", &[("start", "2021-11-01"), ("end", "2021-11-30")])
+  ///     .await?;
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub async fn get_json<T, Q>(&self, path: &str, query: &Q) -> Result<T, Error> 
+    where T: DeserializeOwned, Q: Serialize + ?Sized
+  {
+    let rsp = self.get_authenticated(&self.resolve(path))
+      .query(query)
+      .send().await?;
+    status_code_to_api_error(rsp).await
+  }
+  /// Sends a POST request with the given JSON body to an endpoint this crate
+  /// does not model (yet) and decodes its JSON response (see `get_json`).
+  pub async fn post_json<T, B>(&self, path: &str, body: &B) -> Result<T, Error> 
+    where T: DeserializeOwned, B: Serialize + ?Sized
+  {
+    let rsp = self.post_authenticated(&self.resolve(path))
+      .json(body)
+      .send().await?;
+    status_code_to_api_error(rsp).await
+  }
+  /// Private: the full URL of the given path
+  fn resolve(&self, path: &str) -> String {
+    if path.starts_with("http://") || path.starts_with("https://") {
+      path.to_string()
+    } else {
+      format!("{}/{}", self.env_url.trim_end_matches('/'), path.trim_start_matches('/'))
+    }
+  }
+  /// The rate limit status reported by the server in its last response 
+  /// (None until a response carrying the rate limit headers is received)
+  pub fn rate_limit_status(&self) -> Option<RateLimitStatus> {
+    *self.rate_limit.lock().unwrap()
+  }
+  /// Private: records the rate limit status reported in a response. When the
+  /// limiter is enabled and the server asks to slow down (the quota is 
+  /// exhausted or a Retry-After is given), the next requests are held until
+  /// the server accepts them again.
+  fn observe(&self, headers: &HeaderMap) {
+    if let Some(status) = RateLimitStatus::from_headers(headers) {
+      *self.rate_limit.lock().unwrap() = Some(status);
+      if let (Some(limiter), Some(wait)) = (self.limiter.as_ref(), status.wait()) {
+        limiter.pause_until(Instant::now() + wait);
+      }
+    }
+  }
+  /// The base URL of the trading api
+  pub fn env_url(&self) -> &str {
+    &self.env_url
+  }
+  /// The base URL of the market data api
+  pub fn data_url(&self) -> &str {
+    &self.data_url
   }
 }
+
+/// Configures the http client used by a `Client`. By default, requests 
+/// never time out, which may block a trading loop indefinitely: setting a
+/// timeout is strongly advised.
+///
+/// ```
+/// use std::time::Duration;
+/// use apca_datav2::rest::Client;
+///
+/// let client = Client::builder("key".to_string(), "secret".to_string())
+///     .live(false)
+///     .timeout(Duration::from_secs(10))
+///     .connect_timeout(Duration::from_secs(2))
+///     .user_agent("my-trading-bot/1.0")
+///     .pool_idle_timeout(Duration::from_secs(90))
+///     .tcp_keepalive(Duration::from_secs(30))
+///     .build()
+///     .unwrap();
+/// ```
+pub struct ClientBuilder {
+    credentials    : Credentials,
+    url            : String,
+    data_url       : String,
+    timeout        : Option<Duration>,
+    connect_timeout: Option<Duration>,
+    proxy          : Option<reqwest::Proxy>,
+    user_agent     : Option<String>,
+    pool_max_idle  : Option<usize>,
+    idle_timeout   : Option<Duration>,
+    tcp_keepalive  : Option<Duration>,
+    http_client    : Option<reqwest::Client>,
+}
+impl ClientBuilder {
+    /// Creates a builder for a paper trading client
+    pub fn new(credentials: Credentials) -> Self {
+        Self {
+            credentials,
+            url            : PAPER_TRADING_URL.to_string(),
+            data_url       : DATA_URL.to_string(),
+            timeout        : None,
+            connect_timeout: None,
+            proxy          : None,
+            user_agent     : None,
+            pool_max_idle  : None,
+            idle_timeout   : None,
+            tcp_keepalive  : None,
+            http_client    : None,
+        }
+    }
+    /// Selects the live (or paper) trading api
+    pub fn live(mut self, live: bool) -> Self {
+        self.url = if live { LIVE_TRADING_URL } else { PAPER_TRADING_URL }.to_string();
+        self
+    }
+    /// Uses an arbitrary base URL for the trading api
+    pub fn url(mut self, url: &str) -> Self {
+        self.url = url.to_string();
+        self
+    }
+    /// Uses an arbitrary base URL for the market data api
+    pub fn data_url(mut self, url: &str) -> Self {
+        self.data_url = url.to_string();
+        self
+    }
+    /// Sets the maximum duration of a request (from connection to the end of
+    /// the response body)
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+    /// Sets the maximum duration of the connection phase
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+    /// Sends all the requests through the given proxy
+    pub fn proxy(mut self, proxy: reqwest::Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+    /// Sets the User-Agent header of the requests
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = Some(user_agent.to_string());
+        self
+    }
+    /// Sets the maximum number of idle connections kept open per host (no
+    /// limit by default)
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle = Some(max);
+        self
+    }
+    /// Sets how long an idle connection is kept open for reuse (90 seconds 
+    /// by default). When polling the orders or positions at a slower pace,
+    /// a longer timeout avoids re-negotiating a TLS connection each time.
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+    /// Enables the TCP keepalive probes with the given interval, so that the
+    /// idle connections are not silently dropped by the network
+    pub fn tcp_keepalive(mut self, interval: Duration) -> Self {
+        self.tcp_keepalive = Some(interval);
+        self
+    }
+    /// Uses the given http client as is. When set, the timeouts, proxy, 
+    /// user-agent and pool settings of this builder are ignored.
+    pub fn http_client(mut self, client: reqwest::Client) -> Self {
+        self.http_client = Some(client);
+        self
+    }
+    /// Creates the client
+    #[allow(clippy::result_large_err)]
+    pub fn build(self) -> Result<Client, Error> {
+        let http = match self.http_client {
+            Some(client) => client,
+            None => {
+                let mut builder = reqwest::Client::builder();
+                if let Some(timeout) = self.timeout {
+                    builder = builder.timeout(timeout);
+                }
+                if let Some(timeout) = self.connect_timeout {
+                    builder = builder.connect_timeout(timeout);
+                }
+                if let Some(proxy) = self.proxy {
+                    builder = builder.proxy(proxy);
+                }
+                if let Some(user_agent) = self.user_agent {
+                    builder = builder.user_agent(user_agent);
+                }
+                if let Some(max) = self.pool_max_idle {
+                    builder = builder.pool_max_idle_per_host(max);
+                }
+                if let Some(timeout) = self.idle_timeout {
+                    builder = builder.pool_idle_timeout(timeout);
+                }
+                if let Some(interval) = self.tcp_keepalive {
+                    builder = builder.tcp_keepalive(interval);
+                }
+                builder.build()?
+            },
+        };
+        let mut client = Client::with_credentials(self.credentials, &self.url)
+            .with_data_url(&self.data_url);
+        client.client    = http.clone();
+        client.transport = Arc::new(http);
+        Ok(client)
+    }
+}
+
+/// An authenticated request which has not been sent yet. It is sent through
+/// the client, which enforces its rate limit and retry policy (if any).
+pub struct Request<'a> {
+    client    : &'a Client,
+    builder   : RequestBuilder,
+    /// True iff the request can safely be sent several times (GET only)
+    idempotent: bool,
+}
+impl Request<'_> {
+    /// Appends the given query parameters to the url
+    pub fn query<T: Serialize + ?Sized>(mut self, query: &T) -> Self {
+        self.builder = self.builder.query(query);
+        self
+    }
+    /// Sets the given object as the JSON body of the request
+    pub fn json<T: Serialize + ?Sized>(mut self, json: &T) -> Self {
+        self.builder = self.builder.json(json);
+        self
+    }
+    /// Hands the request to the interceptors (`before_send`, then 
+    /// `on_dry_run`) without sending it, and returns it
+    pub fn dry_run(self) -> Result<reqwest::Request, reqwest::Error> {
+        let interceptors = &self.client.interceptors;
+        let request      = interceptors.iter()
+            .fold(self.builder, |builder, i| i.before_send(builder))
+            .build()?;
+        interceptors.iter().for_each(|i| i.on_dry_run(&request));
+        Ok(request)
+    }
+    /// Sends the request (once the rate limiter allows it). When the request
+    /// is idempotent, it is retried according to the retry policy. The 
+    /// responses of the static endpoints are served from the cache (if it 
+    /// is enabled).
+    pub async fn send(self) -> Result<reqwest::Response, reqwest::Error> {
+        let client   = self.client;
+        let request  = client.interceptors.iter()
+            .fold(self.builder, |builder, i| i.before_send(builder))
+            .build()?;
+        let cache    = client.cache.as_ref().filter(|_| 
+            request.method() == Method::GET && ResponseCache::is_cacheable(request.url().path()));
+        let key      = format!("{} {}", identity(&request), request.url());
+        if let Some(cached) = cache.and_then(|c| c.get(&key)) {
+            return Ok(cached);
+        }
+        let response = Self::execute(client, request, self.idempotent).await?;
+        match cache {
+            Some(cache) => cache.store(&key, response).await,
+            None        => Ok(response),
+        }
+    }
+    /// Private: sends the request; retrying it according to the policy
+    async fn execute(client: &Client, mut request: reqwest::Request, idempotent: bool) -> Result<reqwest::Response, reqwest::Error> {
+        let policy      = client.retry.as_ref().filter(|_| idempotent);
+        let mut attempt = 1;
+        loop {
+            let retry = policy.filter(|p| attempt < p.max_attempts);
+            let next  = retry.and_then(|_| request.try_clone());
+            if let Some(limiter) = client.limiter.as_ref() {
+                limiter.acquire().await;
+            }
+            let method     = request.method().to_string();
+            let path       = request.url().path().to_string();
+            let mut result = client.transport.execute(request).await;
+            if let Ok(rsp) = result.as_mut() {
+                let request_id = rsp.headers().get(X_REQUEST_ID)
+                    .and_then(|id| id.to_str().ok())
+                    .map(str::to_string);
+                rsp.extensions_mut().insert(RequestInfo { method, path, request_id });
+            }
+            let status = result.as_ref().ok().and_then(|rsp| {
+                client.interceptors.iter().for_each(|i| i.after_receive(rsp));
+                client.observe(rsp.headers());
+                RateLimitStatus::from_headers(rsp.headers())
+            });
+            match (retry, next) {
+                (Some(policy), Some(next)) if policy.is_retryable(&result) => {
+                    let delay = policy.backoff(attempt);
+                    let delay = status.and_then(|s| s.retry_after).map_or(delay, |d| d.max(delay));
+                    tokio::time::sleep(delay).await;
+                    request  = next;
+                    attempt += 1;
+                },
+                _ => return result,
+            }
+        }
+    }
+}
+/// Private: the credentials a request is sent with (the api key id or the
+/// bearer token). They are part of the cache key so that the responses
+/// obtained with other credentials are never served.
+fn identity(request: &reqwest::Request) -> &str {
+    let headers = request.headers();
+    headers.get(APCA_API_KEY_ID)
+        .or_else(|| headers.get(reqwest::header::AUTHORIZATION))
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+}
+
+/// Tells which requests are retried and how long to wait between attempts.
+/// The delay doubles after each failed attempt.
+///
+/// ```
+/// use std::time::Duration;
+/// use apca_datav2::rest::{Client, RetryPolicyBuilder};
+///
+/// let policy = RetryPolicyBuilder::default()
+///     .max_attempts(5)
+///     .initial_backoff(Duration::from_millis(250))
+///     .build()
+///     .unwrap();
+/// let client = Client::paper("key".to_string(), "secret".to_string())
+///     .with_retry(policy);
+/// ```
+#[derive(Debug, Clone, Builder)]
+pub struct RetryPolicy {
+    /// The maximum number of times a request is sent (first attempt included)
+    #[builder(default = "3")]
+    pub max_attempts: u32,
+    /// The delay before the first retry
+    #[builder(default = "Duration::from_millis(500)")]
+    pub initial_backoff: Duration,
+    /// The maximum delay between two attempts
+    #[builder(default = "Duration::from_secs(30)")]
+    pub max_backoff: Duration,
+    /// The http statuses which denote a transient failure
+    #[builder(default = "vec![429, 500, 502, 503, 504]")]
+    pub statuses: Vec<u16>,
+    /// Whether the connection errors (refused, reset, timed out) are retried
+    #[builder(default = "true")]
+    pub connection_errors: bool,
+}
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicyBuilder::default().build().unwrap()
+    }
+}
+impl RetryPolicy {
+    /// Returns true iff the given outcome denotes a transient failure
+    pub fn is_retryable(&self, result: &Result<reqwest::Response, reqwest::Error>) -> bool {
+        match result {
+            Ok(rsp) => self.statuses.contains(&rsp.status().as_u16()),
+            Err(e)  => self.connection_errors && (e.is_connect() || e.is_timeout() || e.is_request()),
+        }
+    }
+    /// The delay to wait after the given (failed) attempt
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2_u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+/// The rate limit information sent by the server along with its responses
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateLimitStatus {
+    /// The number of requests allowed per minute (`X-RateLimit-Limit`)
+    pub limit: Option<u32>,
+    /// The number of requests left in the current window 
+    /// (`X-RateLimit-Remaining`)
+    pub remaining: Option<u32>,
+    /// The time at which the current window ends (`X-RateLimit-Reset`)
+    pub reset: Option<DateTime<Utc>>,
+    /// The delay requested by the server before retrying (`Retry-After`, 
+    /// only the delay-seconds form is understood)
+    pub retry_after: Option<Duration>,
+}
+impl RateLimitStatus {
+    /// Reads the status from the headers of a response (None when the 
+    /// response does not carry any rate limit header)
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let number = |name: &str| headers.get(name)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok());
+        let status = Self {
+            limit      : number("x-ratelimit-limit").map(|n| n as u32),
+            remaining  : number("x-ratelimit-remaining").map(|n| n as u32),
+            reset      : number("x-ratelimit-reset").and_then(|n| Utc.timestamp_opt(n as i64, 0).single()),
+            retry_after: number("retry-after").map(Duration::from_secs),
+        };
+        if status == Self::default() { None } else { Some(status) }
+    }
+    /// How long the client should wait before sending its next request (None
+    /// when the server does not ask to slow down)
+    pub fn wait(&self) -> Option<Duration> {
+        if self.retry_after.is_some() {
+            return self.retry_after;
+        }
+        match (self.remaining, self.reset) {
+            (Some(0), Some(reset)) => (reset - Utc::now()).to_std().ok(),
+            _ => None,
+        }
+    }
+}
+
+/// A token bucket: the bucket holds up to `capacity` tokens and is refilled
+/// at a steady pace. Sending a request consumes one token; when the bucket 
+/// is empty, the request waits for the next token.
+#[derive(Debug)]
+pub struct RateLimiter {
+    capacity: f64,
+    /// The number of tokens added to the bucket per second
+    refill  : f64,
+    /// The number of available tokens and the time at which it was computed
+    state   : Mutex<(f64, Instant)>,
+    /// No token is handed out before that time (when the server asked to 
+    /// slow down)
+    paused  : Mutex<Option<Instant>>,
+}
+impl RateLimiter {
+    /// Creates a limiter allowing `requests` per `period` (bursts included)
+    pub fn new(requests: u32, period: Duration) -> Self {
+        let capacity = f64::from(requests.max(1));
+        let refill   = capacity / period.as_secs_f64();
+        Self { capacity, refill, state: Mutex::new((capacity, Instant::now())), paused: Mutex::new(None) }
+    }
+    /// Holds all the requests until the given time
+    pub fn pause_until(&self, until: Instant) {
+        let mut paused = self.paused.lock().unwrap();
+        *paused = Some(paused.map_or(until, |p| p.max(until)));
+    }
+    /// Waits until a token is available and consumes it
+    pub async fn acquire(&self) {
+        let paused = *self.paused.lock().unwrap();
+        if let Some(until) = paused {
+            tokio::time::sleep_until(until.into()).await;
+        }
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                let now       = Instant::now();
+                let tokens    = (state.0 + now.duration_since(state.1).as_secs_f64() * self.refill).min(self.capacity);
+                if tokens >= 1.0 {
+                    *state = (tokens - 1.0, now);
+                    return;
+                }
+                *state = (tokens, now);
+                Duration::from_secs_f64((1.0 - tokens) / self.refill)
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
 /******************************************************************************
  ******************************************************************************
  ******************************************************************************/
@@ -92,17 +865,29 @@ pub trait FetchNextPage<'a, T: Paged> {
 
 /// A future bound to some given lifetime, returning an Ok(T) or an Error
 pub type FailibleFuture<'a, T> = dyn Future<Output=Result<T, Error>> + 'a;
+/// The items of a page along with the token of the next one
+type Page<T> = (Vec<<T as Paged>::Item>, Option<String>);
 
 /// A paged stream is a stream that buffers a chunk of data and transparently 
 /// fetches the next page whenever whenever needed.
+///
+/// Should a page fail to be fetched, the stream yields the error, then ends.
+///
+/// When prefetching is enabled, the next page is fetched while the current
+/// one is being consumed (rather than once it is exhausted), so that the 
+/// network latency overlaps with the processing of the data.
 pub struct PagedStream<'a, T, F> 
 where T: Paged, 
       T::Item: Unpin,
       F: FetchNextPage<'a, T> + Unpin
 {
-    source: Pin<Box<F>>,
-    data  : Vec<T::Item>,
-    fut   : Option<Pin<Box< FailibleFuture<'a, T> >>>
+    source  : Pin<Box<F>>,
+    data    : Vec<T::Item>,
+    fut     : Option<Pin<Box< FailibleFuture<'a, T> >>>,
+    /// Whether the next page is fetched ahead of time
+    prefetch: bool,
+    /// The next page (data and token) when it was fetched ahead of time
+    ready   : Option<Result<Page<T>, Error>>,
 }
 
 impl <'a, T, F> PagedStream<'a, T, F> 
@@ -118,10 +903,24 @@ where T: Paged,
 
         Self {
             source,
-            data: vec![],
-            fut : Some(fut),
+            data    : vec![],
+            fut     : Some(fut),
+            prefetch: false,
+            ready   : None,
         }
     }
+    /// Enables (or disables) the prefetching of the next page
+    pub fn prefetch(mut self, enabled: bool) -> Self {
+        self.prefetch = enabled;
+        self
+    }
+    /// Private: makes the given page the current one and starts fetching the
+    /// next one (if any)
+    fn load(&mut self, (data, token): Page<T>) {
+        self.fut  = token.map(|t| self.source.as_ref().fetch(Some(t)));
+        self.data = data;
+        self.data.reverse();
+    }
 }
 
 impl <'a, T, F> Stream for PagedStream<'a, T, F> 
@@ -129,31 +928,266 @@ where T: Paged,
       T::Item: Unpin,
       F: FetchNextPage<'a, T> + Unpin
 {
-    type Item = T::Item;
+    type Item = Result<T::Item, Error>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<Option<Self::Item>> {
-        let data = self.data.pop();
-        if data.is_some() {
-            Poll::Ready(data)
-        } else if let Some(fut) = self.fut.as_mut() {
-            match fut.poll_unpin(cx) {
-                std::task::Poll::Pending => std::task::Poll::Pending,
-                std::task::Poll::Ready(data) => {
-                    let (data, token) = data.unwrap().split();
-                    
-                    if token.is_some() {
-                        self.fut = Some(self.source.as_ref().fetch(token));
-                    } else {
-                        self.fut = None;
+        loop {
+            if let Some(data) = self.data.pop() {
+                // drive the fetch of the next page while this one is consumed
+                if self.prefetch && self.ready.is_none() {
+                    if let Some(Poll::Ready(page)) = self.fut.as_mut().map(|f| f.poll_unpin(cx)) {
+                        self.fut   = None;
+                        self.ready = Some(page.map(Paged::split));
                     }
-                    self.data = data;
-                    self.data.reverse();
-
-                    std::task::Poll::Ready(self.data.pop())
                 }
+                return Poll::Ready(Some(Ok(data)));
+            }
+            let page = if let Some(page) = self.ready.take() {
+                page
+            } else if let Some(fut) = self.fut.as_mut() {
+                match fut.poll_unpin(cx) {
+                    Poll::Pending     => return Poll::Pending,
+                    Poll::Ready(page) => page.map(Paged::split),
+                }
+            } else {
+                return Poll::Ready(None);
+            };
+            match page {
+                Ok(page) => self.load(page),
+                Err(e)   => {
+                    self.fut = None;
+                    return Poll::Ready(Some(Err(e)));
+                },
+            }
+        }
+    }
+}
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+    use std::time::{Duration, Instant};
+
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    use crate::errors::Error;
+    use crate::testing::{fake_client, response, serve, serve_once};
+    use crate::transport::FakeTransport;
+    use std::pin::Pin;
+
+    use futures::{Future, StreamExt, TryStreamExt};
+
+    use crate::rest::{Client, Credentials, ENV_BASE_URL, ENV_DATA_URL, ENV_KEY_ID, ENV_SECRET_KEY, FetchNextPage, Interceptor, Paged, PagedStream, RateLimitStatus, RateLimiter, RetryPolicyBuilder};
+
+    #[tokio::test]
+    async fn test_rate_limiter_delays_the_excess() {
+        let limiter = RateLimiter::new(2, Duration::from_millis(200));
+        let start   = Instant::now();
+        limiter.acquire().await;
+        limiter.acquire().await;
+        assert!(start.elapsed() < Duration::from_millis(50));
+        // the bucket is empty: the next token comes 100ms later
+        limiter.acquire().await;
+        assert!(start.elapsed() >= Duration::from_millis(90));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("X-RateLimit-Limit",     HeaderValue::from_static("200"));
+        headers.insert("X-RateLimit-Remaining", HeaderValue::from_static("0"));
+        headers.insert("X-RateLimit-Reset",     HeaderValue::from_static("1617021243"));
+        let status = RateLimitStatus::from_headers(&headers).unwrap();
+        assert_eq!(Some(200), status.limit);
+        assert_eq!(Some(0),   status.remaining);
+        assert_eq!(1617021243, status.reset.unwrap().timestamp());
+        // the window is over, there is no need to wait
+        assert_eq!(None, status.wait());
+
+        headers.insert("Retry-After", HeaderValue::from_static("2"));
+        let status = RateLimitStatus::from_headers(&headers).unwrap();
+        assert_eq!(Some(Duration::from_secs(2)), status.wait());
+        assert_eq!(None, RateLimitStatus::from_headers(&HeaderMap::new()));
+
+        let limiter = RateLimiter::new(10, Duration::from_secs(1));
+        let start   = Instant::now();
+        limiter.pause_until(start + Duration::from_millis(100));
+        limiter.acquire().await;
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn test_transient_failures_are_retried() {
+        let statuses = ["503 Service Unavailable", "429 Too Many Requests", "200 OK", "503 Service Unavailable"];
+        let server   = serve(statuses.iter().map(|status| Some(response(status, "[]"))).collect()).await;
+        let url      = format!("{}/v2/assets", server.url);
+        let policy = RetryPolicyBuilder::default()
+            .initial_backoff(Duration::from_millis(10))
+            .build().unwrap();
+        let client = Client::paper("key".to_string(), "secret".to_string()).with_retry(policy);
+
+        let rsp = client.get_authenticated(&url).send().await.unwrap();
+        assert_eq!(200, rsp.status().as_u16());
+        // a DELETE is never retried (it could close a position twice)
+        let rsp = client.delete_authenticated(&url).send().await.unwrap();
+        assert_eq!(503, rsp.status().as_u16());
+        assert_eq!(4, server.requests().len());
+    }
+
+    #[tokio::test]
+    async fn test_oauth_token_is_sent_as_bearer() {
+        let server  = serve_once().await;
+        let client  = Client::with_credentials(Credentials::Bearer("token".to_string()), &server.url);
+        client.list_open_positions().await.unwrap();
+        let request = server.requests()[0].to_lowercase();
+        assert!(request.contains("authorization: bearer token"));
+        assert!(!request.contains("apca-api-key-id"));
+    }
+
+    #[tokio::test]
+    async fn test_interceptors() {
+        struct Audit(Arc<AtomicUsize>);
+        impl Interceptor for Audit {
+            fn before_send(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+                request.header("X-Audit-Id", "42")
+            }
+            fn after_receive(&self, _: &reqwest::Response) {
+                self.0.fetch_add(1, Ordering::SeqCst);
             }
-        } else {
-            Poll::Ready(None)
         }
+        let server   = serve_once().await;
+        let received = Arc::new(AtomicUsize::new(0));
+        let client   = Client::with_url("key".to_string(), "secret".to_string(), &server.url)
+            .with_interceptor(Audit(received.clone()));
+        client.list_open_positions().await.unwrap();
+        assert!(server.requests()[0].to_lowercase().contains("x-audit-id: 42"));
+        assert_eq!(1, received.load(Ordering::SeqCst));
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_custom_base_url() {
+        // the trailing slash of the base url is not doubled
+        let server = serve_once().await;
+        let client = Client::with_url("key".to_string(), "secret".to_string(), &format!("{}/", server.url))
+            .with_data_url("http://localhost:1234");
+        assert_eq!("http://localhost:1234", client.data_url());
+
+        assert!(client.list_open_positions().await.unwrap().is_empty());
+        assert!(server.requests()[0].starts_with("GET /v2/positions HTTP/1.1"));
+    }
+
+    #[tokio::test]
+    async fn test_untyped_endpoints() {
+        let transport = FakeTransport::new()
+            .respond("GET", "/v2/clock", 200, r#"{"is_open":true}"#)
+            .respond("POST", "/v2/account/configurations", 422, r#"{"code":42210000,"message":"invalid"}"#);
+        let client = fake_client(&transport);
+
+        let clock: serde_json::Value = client.get_json("v2/clock", &[("tz", "UTC")]).await.unwrap();
+        assert_eq!(Some(true), clock["is_open"].as_bool());
+        let result = client.post_json::<serde_json::Value, _>("/v2/account/configurations", &clock).await;
+        assert!(matches!(result, Err(Error::Api { status: 422, api: Some(_), request: Some(_) })));
+        assert_eq!("https://paper-api.alpaca.markets/v2/clock?tz=UTC", transport.requests()[0].url);
+    }
+
+    #[tokio::test]
+    async fn test_builder_timeout_and_user_agent() {
+        // the server never answers
+        let server = serve(vec![None]).await;
+        let client = Client::builder("key".to_string(), "secret".to_string())
+            .url(&server.url)
+            .timeout(Duration::from_millis(100))
+            .user_agent("test-agent")
+            .build().unwrap();
+
+        match client.list_open_positions().await {
+            Err(Error::HttpError(e)) => assert!(e.is_timeout()),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(server.requests()[0].to_lowercase().contains("user-agent: test-agent"));
+    }
+
+    #[tokio::test]
+    async fn test_builder_pool_settings() {
+        // the connections are kept alive: one per request means no reuse
+        let keep_alive = "HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\n[]".to_string();
+        let server     = serve(vec![Some(keep_alive.clone()), Some(keep_alive)]).await;
+        let client = Client::builder("key".to_string(), "secret".to_string())
+            .url(&server.url)
+            .pool_max_idle_per_host(0)
+            .tcp_keepalive(Duration::from_secs(30))
+            .build().unwrap();
+
+        client.list_open_positions().await.unwrap();
+        client.list_open_positions().await.unwrap();
+        assert_eq!(2, server.requests().len());
+    }
+
+    struct Page(Vec<u32>, Option<String>);
+    impl Paged for Page {
+        type Item = u32;
+        fn split(self) -> (Vec<u32>, Option<String>) {
+            (self.0, self.1)
+        }
+    }
+    struct Pages(Arc<AtomicUsize>);
+    impl FetchNextPage<'static, Page> for Pages {
+        fn fetch(self: Pin<&Self>, token: Option<String>) -> Pin<Box<dyn Future<Output=Result<Page, Error>>>> {
+            let fetched = self.0.clone();
+            Box::pin(async move {
+                fetched.fetch_add(1, Ordering::SeqCst);
+                let page = token.map_or(0, |t| t.parse::<u32>().unwrap());
+                let next = if page < 2 { Some((page + 1).to_string()) } else { None };
+                Ok(Page(vec![2 * page, 2 * page + 1], next))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_paged_stream_prefetch() {
+        for prefetch in [false, true].iter() {
+            let fetched    = Arc::new(AtomicUsize::new(0));
+            let mut stream = PagedStream::new(Pages(fetched.clone())).prefetch(*prefetch);
+            assert_eq!(Some(0), stream.next().await.transpose().unwrap());
+            // the second page is fetched while the first one is consumed
+            assert_eq!(if *prefetch { 2 } else { 1 }, fetched.load(Ordering::SeqCst));
+            assert_eq!(vec![1, 2, 3, 4, 5], stream.try_collect::<Vec<_>>().await.unwrap());
+            assert_eq!(3, fetched.load(Ordering::SeqCst));
+        }
+    }
+
+    #[test]
+    fn test_from_env() {
+        let lookup = |vars: &'static [(&'static str, &'static str)]| move |name: &str| {
+            vars.iter().find(|(n, _)| *n == name).map(|(_, v)| v.to_string())
+        };
+        let vars   = &[(ENV_KEY_ID, "key")];
+        assert!(matches!(Client::from_lookup(&lookup(vars)), Err(Error::MissingEnv(ENV_SECRET_KEY))));
+
+        let vars   = &[(ENV_KEY_ID, "key"), (ENV_SECRET_KEY, "secret")];
+        let client = Client::from_lookup(&lookup(vars)).unwrap();
+        assert_eq!("https://paper-api.alpaca.markets", client.env_url());
+        assert_eq!("https://data.alpaca.markets", client.data_url());
+
+        let vars   = &[(ENV_KEY_ID, "key"), (ENV_SECRET_KEY, "secret"), (ENV_BASE_URL, "https://api.alpaca.markets"), (ENV_DATA_URL, "http://localhost:8080")];
+        let client = Client::from_lookup(&lookup(vars)).unwrap();
+        assert_eq!("https://api.alpaca.markets", client.env_url());
+        assert_eq!("http://localhost:8080", client.data_url());
+    }
+
+    #[tokio::test]
+    async fn test_credentials_rotation() {
+        let server = serve(vec![Some(response("200 OK", "[]")), Some(response("200 OK", "[]"))]).await;
+        let client = Client::with_url("old".to_string(), "secret".to_string(), &server.url);
+        client.list_open_positions().await.unwrap();
+        client.set_credentials("new".to_string(), "secret".to_string());
+        client.list_open_positions().await.unwrap();
+
+        let requests = server.requests();
+        assert!(requests[0].to_lowercase().contains("apca-api-key-id: old"));
+        assert!(requests[1].to_lowercase().contains("apca-api-key-id: new"));
+    }
+}
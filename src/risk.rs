@@ -0,0 +1,396 @@
+//! This module provides a client side risk guard which vets the orders
+//! before they are sent to Alpaca.
+//!
+//! The guard does not only look at the size of the order being placed; it
+//! evaluates the impact of that order on the exposure of the whole account.
+//! That exposure comprises the open positions *and* the open orders (which
+//! may still be executed). Open orders are accounted for in a conservative
+//! way: for each symbol, the guard considers the worst case where all pending
+//! buys (resp. all pending sells) would be filled.
+//!
+//! The orders placed through the guard are accounted for as soon as they are
+//! accepted by Alpaca; hence a burst of orders cannot exceed the limits. The
+//! exposure can be rebuilt at any time from the live view of the positions
+//! and open orders maintained by the [trackers](crate::tracker).
+//!
+//! ```no_run
+//! # async fn example(client: apca_datav2::rest::Client, equity: f64) -> Result<(), apca_datav2::errors::Error> {
+//! use apca_datav2::orders::{ListOrderRequestBuilder, PlaceOrderRequestBuilder};
+//! use apca_datav2::risk::{Exposure, RiskGuard, RiskLimitsBuilder};
+//! use apca_datav2::entities::OrderSide;
+//!
+//! let positions = client.list_open_positions().await?;
+//! let orders    = client.list_orders(&ListOrderRequestBuilder::default().build().unwrap()).await?;
+//! let limits    = RiskLimitsBuilder::default()
+//!     .max_gross_exposure(100_000.0)
+//!     .max_leverage(2.0)
+//!     .build().unwrap();
+//! let mut guard = RiskGuard::new(limits, Exposure::from_account(equity, &positions, &orders));
+//!
+//! let order = PlaceOrderRequestBuilder::default()
+//!     .symbol("AAPL".to_string())
+//!     .qty(10.0)
+//!     .side(OrderSide::Buy)
+//!     .build().unwrap();
+//! guard.place_order(&client, &order).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+
+use derive_builder::Builder;
+
+use crate::{entities::{OrderData, OrderSide, PositionData, PositionSide}, errors::{Error, RiskViolation}, orders::PlaceOrderRequest};
+use crate::rest::Client;
+use crate::tracker::{LivePosition, OrderTracker, PositionTracker};
+
+/// The limits enforced by the risk guard. A limit which is not set is not
+/// enforced.
+#[derive(Debug, Clone, Default, Builder)]
+#[builder(default)]
+pub struct RiskLimits {
+    /// Maximum notional value of one single order
+    #[builder(setter(strip_option))]
+    pub max_order_notional: Option<f64>,
+    /// Maximum gross exposure (long + short notional) of the account
+    #[builder(setter(strip_option))]
+    pub max_gross_exposure: Option<f64>,
+    /// Maximum short exposure of the account
+    #[builder(setter(strip_option))]
+    pub max_short_exposure: Option<f64>,
+    /// Maximum leverage multiplier (gross exposure / equity)
+    #[builder(setter(strip_option))]
+    pub max_leverage: Option<f64>,
+}
+
+/// The exposure of one given symbol
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SymbolExposure {
+    /// Signed notional value of the position (negative when short)
+    pub position: f64,
+    /// Notional value of the open buy orders
+    pub pending_buys: f64,
+    /// Notional value of the open sell orders
+    pub pending_sells: f64,
+}
+impl SymbolExposure {
+    /// Long exposure if all pending buys were filled
+    pub fn worst_long(&self) -> f64 {
+        (self.position + self.pending_buys).max(0.0)
+    }
+    /// Short exposure if all pending sells were filled
+    pub fn worst_short(&self) -> f64 {
+        (self.pending_sells - self.position).max(0.0)
+    }
+}
+
+/// A snapshot of the account exposure
+#[derive(Debug, Clone, Default)]
+pub struct Exposure {
+    /// Equity of the account (used to compute the leverage)
+    pub equity: f64,
+    /// The exposure of each symbol
+    pub symbols: HashMap<String, SymbolExposure>,
+    /// The reference price of each symbol (used to estimate the notional
+    /// value of the orders having no limit price)
+    pub prices: HashMap<String, f64>,
+}
+impl Exposure {
+    /// Creates an empty exposure for an account with the given equity
+    pub fn new(equity: f64) -> Self {
+        Self { equity, ..Default::default() }
+    }
+    /// Creates the exposure of an account given its equity, open positions
+    /// and orders. Orders which are no longer open are ignored.
+    pub fn from_account(equity: f64, positions: &[PositionData], orders: &[OrderData]) -> Self {
+        let mut exposure = Self::new(equity);
+        positions.iter().for_each(|p| exposure.add_position(p));
+        orders.iter().for_each(|o| exposure.add_order(o));
+        exposure
+    }
+    /// Creates the exposure of an account given its equity and the live view
+    /// of its positions and open orders maintained by the trackers
+    pub fn from_trackers(equity: f64, positions: &PositionTracker<'_>, orders: &OrderTracker<'_>) -> Self {
+        Self::new(equity).with_trackers(positions, orders)
+    }
+    /// Private: accounts for the positions and open orders of the trackers
+    fn with_trackers(mut self, positions: &PositionTracker<'_>, orders: &OrderTracker<'_>) -> Self {
+        positions.positions().iter().for_each(|p| self.add_live_position(p));
+        orders.open_orders().iter().for_each(|o| self.add_order(o));
+        self
+    }
+    /// Records the reference price of a symbol
+    pub fn mark(&mut self, symbol: &str, price: f64) {
+        self.prices.insert(symbol.to_string(), price);
+    }
+    /// Accounts for an open position
+    pub fn add_position(&mut self, position: &PositionData) {
+        let notional = position.qty.abs() * position.current_price;
+        let signed   = match position.side {
+            PositionSide::Long  =>  notional,
+            PositionSide::Short => -notional,
+        };
+        self.mark(&position.symbol, position.current_price);
+        self.symbols.entry(position.symbol.clone()).or_default().position += signed;
+    }
+    /// Accounts for a position maintained by the `PositionTracker`. It is 
+    /// valued at the reference price of its symbol when that price is known,
+    /// and at its average entry price otherwise.
+    pub fn add_live_position(&mut self, position: &LivePosition) {
+        let price = *self.prices.entry(position.symbol.clone()).or_insert(position.avg_entry_price);
+        self.symbols.entry(position.symbol.clone()).or_default().position += position.qty * price;
+    }
+    /// Accounts for the unfilled part of an open order
+    pub fn add_order(&mut self, order: &OrderData) {
+        if !order.status.is_open() {
+            return;
+        }
+        let price    = order.limit_price
+            .or(order.stop_price)
+            .or_else(|| self.prices.get(&order.symbol).copied());
+        let notional = match (order.notional, order.qty, price) {
+            (Some(notional), _, _) => notional - order.filled_qty * order.filled_avg_price.unwrap_or(0.0),
+            (None, Some(qty), Some(price)) => (qty - order.filled_qty) * price,
+            _ => 0.0,
+        };
+        self.add_pending(&order.symbol, order.side, notional.max(0.0));
+    }
+    /// Long exposure of the account (worst case)
+    pub fn long(&self) -> f64 {
+        self.symbols.values().map(SymbolExposure::worst_long).sum()
+    }
+    /// Short exposure of the account (worst case)
+    pub fn short(&self) -> f64 {
+        self.symbols.values().map(SymbolExposure::worst_short).sum()
+    }
+    /// Gross exposure of the account (worst case)
+    pub fn gross(&self) -> f64 {
+        self.long() + self.short()
+    }
+    /// Leverage multiplier of the account (worst case). It is infinite when
+    /// the equity of the account is not positive.
+    pub fn leverage(&self) -> f64 {
+        if self.equity <= 0.0 {
+            return f64::INFINITY;
+        }
+        self.gross() / self.equity
+    }
+
+    fn add_pending(&mut self, symbol: &str, side: OrderSide, notional: f64) {
+        let entry = self.symbols.entry(symbol.to_string()).or_default();
+        match side {
+            OrderSide::Buy  => entry.pending_buys  += notional,
+            OrderSide::Sell => entry.pending_sells += notional,
+        }
+    }
+}
+
+/// The risk guard checks that orders comply with the risk limits before
+/// they are sent
+#[derive(Debug, Clone)]
+pub struct RiskGuard {
+    limits: RiskLimits,
+    exposure: Exposure,
+}
+impl RiskGuard {
+    /// Creates a new guard enforcing the given limits
+    pub fn new(limits: RiskLimits, exposure: Exposure) -> Self {
+        Self { limits, exposure }
+    }
+    /// The limits enforced by this guard
+    pub fn limits(&self) -> &RiskLimits {
+        &self.limits
+    }
+    /// The current exposure of the account
+    pub fn exposure(&self) -> &Exposure {
+        &self.exposure
+    }
+    /// Gives access to the exposure so that it can be kept up to date
+    pub fn exposure_mut(&mut self) -> &mut Exposure {
+        &mut self.exposure
+    }
+    /// Estimates the notional value of the given order
+    #[allow(clippy::result_large_err)]
+    pub fn notional(&self, order: &PlaceOrderRequest) -> Result<f64, Error> {
+        if let Some(notional) = order.notional {
+            return Ok(notional);
+        }
+        let price = order.limit_price
+            .or(order.stop_price)
+            .or_else(|| self.exposure.prices.get(&order.symbol).copied())
+            .ok_or_else(|| RiskViolation::UnknownPrice(order.symbol.clone()))?;
+        Ok(order.qty.unwrap_or(0.0) * price)
+    }
+    /// Rebuilds the exposure from the live view of the positions and open
+    /// orders maintained by the trackers (the equity and the reference 
+    /// prices are kept)
+    pub fn refresh(&mut self, positions: &PositionTracker<'_>, orders: &OrderTracker<'_>) {
+        let mut exposure = Exposure::new(self.exposure.equity);
+        exposure.prices  = std::mem::take(&mut self.exposure.prices);
+        self.exposure    = exposure.with_trackers(positions, orders);
+    }
+    /// Accounts for an order which has been placed: its notional value is
+    /// pending until the exposure is refreshed
+    #[allow(clippy::result_large_err)]
+    pub fn record(&mut self, order: &PlaceOrderRequest) -> Result<(), Error> {
+        let notional = self.notional(order)?;
+        self.exposure.add_pending(&order.symbol, order.side, notional);
+        Ok(())
+    }
+    /// Checks the given order against the risk limits, places it and 
+    /// accounts for it in the exposure (so that the next orders are checked
+    /// against it)
+    pub async fn place_order(&mut self, client: &Client, order: &PlaceOrderRequest) -> Result<OrderData, Error> {
+        self.check(order)?;
+        let placed = client.place_order(order).await?;
+        self.record(order)?;
+        Ok(placed)
+    }
+    /// Checks whether the given order complies with the risk limits, taking
+    /// into account the current exposure of the account
+    #[allow(clippy::result_large_err)]
+    pub fn check(&self, order: &PlaceOrderRequest) -> Result<(), Error> {
+        let notional = self.notional(order)?;
+        if let Some(limit) = self.limits.max_order_notional {
+            if notional > limit {
+                return Err(RiskViolation::OrderNotional { notional, limit }.into());
+            }
+        }
+
+        let mut after = self.exposure.clone();
+        after.add_pending(&order.symbol, order.side, notional);
+
+        if let Some(limit) = self.limits.max_gross_exposure {
+            let exposure = after.gross();
+            if exposure > limit {
+                return Err(RiskViolation::GrossExposure { exposure, limit }.into());
+            }
+        }
+        if let Some(limit) = self.limits.max_short_exposure {
+            let exposure = after.short();
+            if exposure > limit {
+                return Err(RiskViolation::ShortExposure { exposure, limit }.into());
+            }
+        }
+        if let Some(limit) = self.limits.max_leverage {
+            let leverage = after.leverage();
+            if leverage > limit {
+                return Err(RiskViolation::Leverage { leverage, limit }.into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use crate::{entities::OrderSide, errors::{Error, RiskViolation}, orders::PlaceOrderRequestBuilder};
+    use crate::risk::{Exposure, RiskGuard, RiskLimitsBuilder};
+    use crate::testing::{ORDER, POSITION, fake_client};
+    use crate::tracker::{OrderTracker, PositionTracker};
+    use crate::transport::FakeTransport;
+
+    #[test]
+    fn test_exposure_accounts_for_positions_and_orders() {
+        let mut exposure = Exposure::new(10_000.0);
+        exposure.mark("AAPL", 100.0);
+        exposure.symbols.entry("AAPL".to_string()).or_default().position = 5_000.0;
+        exposure.symbols.entry("TSLA".to_string()).or_default().pending_sells = 2_000.0;
+
+        let limits = RiskLimitsBuilder::default()
+            .max_short_exposure(2_500.0)
+            .max_leverage(1.0)
+            .build().unwrap();
+        let guard  = RiskGuard::new(limits, exposure);
+
+        // 5000 long + 2000 short + 2000 more long = 9000 => leverage 0.9
+        let buy = PlaceOrderRequestBuilder::default()
+            .symbol("AAPL".to_string()).qty(20.0).side(OrderSide::Buy)
+            .build().unwrap();
+        assert!(guard.check(&buy).is_ok());
+
+        // 5000 long + 2000 short + 3000 more long => leverage 1.0 is exceeded
+        let buy = PlaceOrderRequestBuilder::default()
+            .symbol("AAPL".to_string()).qty(31.0).side(OrderSide::Buy)
+            .build().unwrap();
+        assert!(matches!(guard.check(&buy), Err(Error::Risk(RiskViolation::Leverage{..}))));
+
+        // Selling more than the long position opens a short position
+        let sell = PlaceOrderRequestBuilder::default()
+            .symbol("AAPL".to_string()).qty(56.0).side(OrderSide::Sell)
+            .build().unwrap();
+        assert!(matches!(guard.check(&sell), Err(Error::Risk(RiskViolation::ShortExposure{..}))));
+
+        let unknown = PlaceOrderRequestBuilder::default()
+            .symbol("MSFT".to_string()).qty(1.0).side(OrderSide::Buy)
+            .build().unwrap();
+        assert!(matches!(guard.check(&unknown), Err(Error::Risk(RiskViolation::UnknownPrice(_)))));
+    }
+
+    #[test]
+    fn test_leverage_without_equity_is_infinite() {
+        let mut exposure = Exposure::new(-500.0);
+        exposure.mark("AAPL", 100.0);
+        exposure.symbols.entry("AAPL".to_string()).or_default().position = -5_000.0;
+        assert_eq!(f64::INFINITY, exposure.leverage());
+
+        let limits = RiskLimitsBuilder::default().max_leverage(2.0).build().unwrap();
+        let guard  = RiskGuard::new(limits, exposure);
+        let buy    = PlaceOrderRequestBuilder::default()
+            .symbol("AAPL".to_string()).qty(1.0).side(OrderSide::Buy)
+            .build().unwrap();
+        assert!(matches!(guard.check(&buy), Err(Error::Risk(RiskViolation::Leverage{..}))));
+    }
+
+    #[tokio::test]
+    async fn test_placed_orders_are_accounted_for() {
+        let transport = FakeTransport::new().respond("POST", "/v2/orders", 200, ORDER);
+        let client    = fake_client(&transport);
+        let limits    = RiskLimitsBuilder::default().max_gross_exposure(1_500.0).build().unwrap();
+        let mut guard = RiskGuard::new(limits, Exposure::new(10_000.0));
+        let order     = PlaceOrderRequestBuilder::default()
+            .symbol("AAPL".to_string()).qty(10.0).limit_price(100.0).side(OrderSide::Buy)
+            .build().unwrap();
+
+        // each order fits alone, both together are over the limit
+        assert!(guard.check(&order).is_ok());
+        assert!(guard.place_order(&client, &order).await.is_ok());
+        assert_eq!(1_000.0, guard.exposure().gross());
+        assert!(matches!(guard.place_order(&client, &order).await, Err(Error::Risk(RiskViolation::GrossExposure{..}))));
+        assert_eq!(1, transport.requests().len());
+    }
+    #[tokio::test]
+    async fn test_exposure_is_fed_by_the_trackers() {
+        let transport = FakeTransport::new()
+            .respond("GET", "/v2/positions", 200, &format!("[{}]", POSITION))
+            .respond("GET", "/v2/orders", 200, &format!("[{}]", ORDER));
+        let client    = fake_client(&transport);
+        let positions = PositionTracker::new(&client);
+        let orders    = OrderTracker::new(&client);
+        positions.load().await.unwrap();
+        orders.load().await.unwrap();
+
+        // 2 MSFT held and 2 more being bought, all valued at 310
+        let mut exposure = Exposure::new(10_000.0);
+        exposure.mark("MSFT", 310.0);
+        let limits    = RiskLimitsBuilder::default().max_gross_exposure(1_500.0).build().unwrap();
+        let mut guard = RiskGuard::new(limits, exposure);
+        guard.refresh(&positions, &orders);
+        assert_eq!(1_240.0, guard.exposure().gross());
+
+        let buy = PlaceOrderRequestBuilder::default()
+            .symbol("MSFT".to_string()).qty(1.0).side(OrderSide::Buy)
+            .build().unwrap();
+        assert!(matches!(guard.check(&buy), Err(Error::Risk(RiskViolation::GrossExposure{..}))));
+
+        // without any reference price, the position is valued at its entry price
+        let exposure = Exposure::from_trackers(10_000.0, &positions, &orders);
+        assert_eq!(Some(&300.0), exposure.prices.get("MSFT"));
+        assert_eq!(1_200.0, exposure.gross());
+    }
+}
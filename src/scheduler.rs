@@ -0,0 +1,174 @@
+//! This module runs user-provided tasks at given points of the regular
+//! sessions of the market: some time after the open, some time before the
+//! close, or at the end of every bar interval.
+//!
+//! The `Scheduler` plans the instants at which its tasks must run from the
+//! trading days of the calendar (hence, it accounts for the holidays and the
+//! early closes). The tasks are run one after the other: a task which runs
+//! longer than the gap to the next instant delays that instant.
+//!
+//! ```no_run
+//! # async fn example(client: apca_datav2::rest::Client) -> Result<(), apca_datav2::errors::Error> {
+//! use chrono::{Duration, NaiveDate};
+//! use apca_datav2::historical::TimeFrame;
+//! use apca_datav2::scheduler::Scheduler;
+//!
+//! let first = NaiveDate::from_ymd_opt(2021, 11, 1).unwrap();
+//! let last  = NaiveDate::from_ymd_opt(2021, 11, 30).unwrap();
+//! let days  = client.calendar(first, last).await?;
+//!
+//! Scheduler::new(days)
+//!     .after_open(Duration::minutes(5), |t| async move { println!("{}: entering", t) })
+//!     .every(TimeFrame::Minutes(15), |t| async move { println!("{}: rebalancing", t) })?
+//!     .before_close(Duration::minutes(1), |t| async move { println!("{}: flattening", t) })
+//!     .run().await;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::pin::Pin;
+
+use chrono::{DateTime, Duration, Utc};
+use futures::Future;
+
+use crate::{calendar::TradingDay, errors::Error, gapfill::expected_slots, historical::TimeFrame};
+
+/// The points of a session at which a task runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    /// Some time after the open of the regular session
+    AfterOpen(Duration),
+    /// Some time before the close of the regular session
+    BeforeClose(Duration),
+    /// At the end of every interval of the given (intraday) timeframe during
+    /// the regular session
+    Every(TimeFrame),
+}
+impl Trigger {
+    /// The instants at which this trigger fires during the given day. This
+    /// fails when the timeframe of an `Every` trigger is not valid.
+    #[allow(clippy::result_large_err)]
+    pub fn instants(&self, day: &TradingDay) -> Result<Vec<DateTime<Utc>>, Error> {
+        Ok(match self {
+            Trigger::AfterOpen(offset)   => vec![day.open_at() + *offset],
+            Trigger::BeforeClose(offset) => vec![day.close_at() - *offset],
+            Trigger::Every(timeframe)    => expected_slots(std::slice::from_ref(day), *timeframe)?.into_iter()
+                .map(|slot| (slot + timeframe.duration()).min(day.close_at()))
+                .collect(),
+        })
+    }
+}
+
+/// A task run by the scheduler; it is given the instant it was planned for
+type Task<'a> = Box<dyn FnMut(DateTime<Utc>) -> Pin<Box<dyn Future<Output=()> + 'a>> + 'a>;
+
+/// Runs tasks at given points of the sessions of the given trading days
+pub struct Scheduler<'a> {
+    days : Vec<TradingDay>,
+    tasks: Vec<(Trigger, Task<'a>)>,
+}
+impl<'a> Scheduler<'a> {
+    /// Creates a scheduler (without any task) for the given trading days
+    pub fn new(days: Vec<TradingDay>) -> Self {
+        Self { days, tasks: vec![] }
+    }
+    /// Runs the given task some time after the open of each session
+    pub fn after_open<F, Fut>(self, offset: Duration, task: F) -> Self
+        where F: FnMut(DateTime<Utc>) -> Fut + 'a, Fut: Future<Output=()> + 'a
+    {
+        self.push(Trigger::AfterOpen(offset), task)
+    }
+    /// Runs the given task some time before the close of each session
+    pub fn before_close<F, Fut>(self, offset: Duration, task: F) -> Self
+        where F: FnMut(DateTime<Utc>) -> Fut + 'a, Fut: Future<Output=()> + 'a
+    {
+        self.push(Trigger::BeforeClose(offset), task)
+    }
+    /// Runs the given task at the end of every interval of the given
+    /// timeframe during each session. This fails when the timeframe is not
+    /// valid (e.g. `Minutes(0)`).
+    #[allow(clippy::result_large_err)]
+    pub fn every<F, Fut>(self, timeframe: TimeFrame, task: F) -> Result<Self, Error>
+        where F: FnMut(DateTime<Utc>) -> Fut + 'a, Fut: Future<Output=()> + 'a
+    {
+        self.on(Trigger::Every(timeframe), task)
+    }
+    /// Runs the given task whenever the trigger fires. This fails when the
+    /// timeframe of an `Every` trigger is not valid.
+    #[allow(clippy::result_large_err)]
+    pub fn on<F, Fut>(self, trigger: Trigger, task: F) -> Result<Self, Error>
+        where F: FnMut(DateTime<Utc>) -> Fut + 'a, Fut: Future<Output=()> + 'a
+    {
+        if let Trigger::Every(timeframe) = trigger {
+            timeframe.validate()?;
+        }
+        Ok(self.push(trigger, task))
+    }
+    /// Private: adds the task (whose trigger is known to be valid)
+    fn push<F, Fut>(mut self, trigger: Trigger, mut task: F) -> Self
+        where F: FnMut(DateTime<Utc>) -> Fut + 'a, Fut: Future<Output=()> + 'a
+    {
+        self.tasks.push((trigger, Box::new(move |t| Box::pin(task(t)))));
+        self
+    }
+    /// The instants at which the tasks are planned (in chronological order),
+    /// along with the index of the task to run (in the order they were added)
+    pub fn schedule(&self) -> Vec<(DateTime<Utc>, usize)> {
+        let mut schedule = self.days.iter()
+            .flat_map(|day| self.tasks.iter().enumerate()
+                .flat_map(move |(i, (trigger, _))| trigger.instants(day).unwrap_or_default().into_iter().map(move |t| (t, i))))
+            .collect::<Vec<_>>();
+        schedule.sort();
+        schedule
+    }
+    /// Runs the tasks at their planned instants until the last one; the
+    /// instants which are already past when this starts are skipped
+    pub async fn run(mut self) {
+        let start = Utc::now();
+        for (at, i) in self.schedule().into_iter().filter(|(at, _)| *at >= start) {
+            if let Ok(wait) = (at - Utc::now()).to_std() {
+                tokio::time::sleep(wait).await;
+            }
+            (self.tasks[i].1)(at).await;
+        }
+    }
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+
+    use crate::calendar::TradingDay;
+    use crate::historical::TimeFrame;
+    use crate::scheduler::Scheduler;
+
+    #[test]
+    fn test_tasks_are_planned_on_the_sessions() {
+        // an early close at 13:00 (18:00 UTC)
+        let day = TradingDay {
+            date : NaiveDate::from_ymd_opt(2021, 11, 26).unwrap(),
+            open : NaiveTime::from_hms_opt(9, 30, 0).unwrap(),
+            close: NaiveTime::from_hms_opt(13, 0, 0).unwrap(),
+        };
+        let scheduler = Scheduler::new(vec![day])
+            .before_close(Duration::minutes(1), |_| async {})
+            .after_open(Duration::minutes(5), |_| async {})
+            .every(TimeFrame::Hours(1), |_| async {}).unwrap();
+
+        let at = |h, m| Utc.with_ymd_and_hms(2021, 11, 26, h, m, 0).unwrap();
+        assert_eq!(vec![
+            (at(14, 35), 1),
+            (at(15,  0), 2), (at(16, 0), 2), (at(17, 0), 2),
+            (at(17, 59), 0),
+            (at(18,  0), 2),
+        ], scheduler.schedule());
+    }
+    #[test]
+    fn test_empty_intervals_are_rejected() {
+        assert!(Scheduler::new(vec![]).every(TimeFrame::Minutes(0), |_| async {}).is_err());
+    }
+}
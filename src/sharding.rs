@@ -0,0 +1,377 @@
+//! This module lets you spread the realtime subscriptions over several
+//! websocket connections.
+//!
+//! With the unlimited plan, one single connection cannot always keep up with
+//! the flow of data when subscribing to thousands of symbols (and the server
+//! disconnects the slow clients). The `ShardedClient` opens several
+//! connections, partitions the symbol universe across them and merges the
+//! resulting streams into one. Each connection (shard) authenticates and
+//! subscribes on its own; and transparently reconnects when it is lost.
+//!
+//! # Pruning
+//! A symbol which has been delisted must not be subscribed to again and again
+//! upon each reconnection. Therefore, the symbols which are rejected by the
+//! server (i.e. the ones absent from its subscription acknowledgement) and
+//! those which have been [retired](ShardedClient::retire) (e.g. because the
+//! assets API reports them as inactive) are automatically removed from the
+//! desired subscriptions. A `ShardEvent::Pruned` notifies you when it happens.
+//!
+//! ```no_run
+//! # async fn example() {
+//! use futures::StreamExt;
+//! use apca_datav2::realtime::{AuthDataBuilder, Channel, Source};
+//! use apca_datav2::sharding::{ShardEvent, ShardedClient};
+//!
+//! let auth    = AuthDataBuilder::default()
+//!     .key("key".to_string())
+//!     .secret("secret".to_string())
+//!     .build().unwrap();
+//! let symbols = vec!["AAPL".to_string(), "MSFT".to_string(), "TSLA".to_string()];
+//! let mut events = ShardedClient::new(Source::SIP, auth, 2)
+//!     .start(&[Channel::Trades, Channel::Quotes], symbols);
+//!
+//! while let Some(event) = events.next().await {
+//!     if let ShardEvent::Response { shard, response } = event {
+//!         println!("{} {:?}", shard, response);
+//!     }
+//! }
+//! # }
+//! ```
+
+use std::{collections::HashSet, sync::{Arc, Mutex}};
+
+use futures::{FutureExt, Stream, StreamExt, channel::mpsc::{self, UnboundedSender}};
+use tokio::sync::watch;
+
+use crate::entities::{AssetData, AssetStatus};
+use crate::errors::Error;
+use crate::realtime::{AuthData, Channel, Client, Response, Source, SubscriptionData, Symbol};
+use crate::symbols::SymbolNormalizer;
+use crate::websocket::{ConnectionConfig, MAX_RECONNECT_DELAY, RECONNECT_DELAY};
+
+/// An event emitted by the merged stream of a sharded client
+#[derive(Debug)]
+pub enum ShardEvent {
+    /// A response which was received on one of the shards
+    Response { shard: usize, response: Response },
+    /// The shard had lost its connection; it has successfully reconnected,
+    /// authenticated and subscribed again
+    Reconnected { shard: usize },
+    /// The shard could not (re)connect, or dropped its connection because it
+    /// received a frame it could not decode. It will try again after some 
+    /// delay
+    Disconnected { shard: usize, error: Error },
+    /// The given symbols were removed from the subscriptions of the shard
+    /// because they were rejected by the server or have been retired
+    Pruned { shard: usize, symbols: Vec<Symbol> },
+}
+
+/// A realtime client which spreads its subscriptions over several connections
+#[derive(Clone)]
+pub struct ShardedClient {
+    source: Source,
+    /// The credentials (shared with the shards, which are told when these
+    /// are replaced)
+    auth  : watch::Receiver<AuthData>,
+    rotate: Arc<watch::Sender<AuthData>>,
+    config: ConnectionConfig,
+    shards: usize,
+    /// The symbols that must no longer be subscribed to (shared with the shards)
+    retired: Arc<Mutex<HashSet<Symbol>>>,
+    /// The normalizer applied to the symbols before they are partitioned
+    symbols: SymbolNormalizer,
+}
+impl ShardedClient {
+    /// Creates a client that spreads its subscriptions over `shards`
+    /// connections to the given source
+    pub fn new(source: Source, auth: AuthData, shards: usize) -> Self {
+        Self::with_config(source, auth, shards, ConnectionConfig::default())
+    }
+    /// Creates a sharded client whose connections use the given config
+    pub fn with_config(source: Source, auth: AuthData, shards: usize, config: ConnectionConfig) -> Self {
+        let (rotate, auth) = watch::channel(auth);
+        Self { source, auth, rotate: Arc::new(rotate), config, shards: shards.max(1), retired: Default::default(), symbols: Default::default() }
+    }
+    /// Replaces the credentials used by the shards (e.g. to rotate the api
+    /// keys of a long-running service). The running shards reconnect with
+    /// the new credentials (which is notified by `ShardEvent::Reconnected`).
+    pub fn set_credentials(&self, auth: AuthData) {
+        // this client holds a receiver, hence the value is always delivered
+        let _ = self.rotate.send(auth);
+    }
+    /// Sets the normalizer which is applied to the symbols (hence, these
+    /// match the ones acknowledged by the server)
+    pub fn with_normalizer(mut self, symbols: SymbolNormalizer) -> Self {
+        self.symbols = symbols;
+        self
+    }
+    /// The number of connections used by this client
+    pub fn shards(&self) -> usize {
+        self.shards
+    }
+    /// Marks the given symbols as retired: they will be pruned from the 
+    /// subscriptions of the shards the next time these (re)subscribe.
+    pub fn retire<I: IntoIterator<Item=Symbol>>(&self, symbols: I) {
+        let symbols = symbols.into_iter().map(|s| self.symbols.normalize(&s));
+        self.retired.lock().unwrap().extend(symbols);
+    }
+    /// Retires all the assets which are reported as inactive
+    pub fn retire_inactive(&self, assets: &[AssetData]) {
+        self.retire(assets.iter()
+            .filter(|a| a.status == AssetStatus::Inactive)
+            .map(|a| a.symbol.clone()));
+    }
+    /// Partitions the given symbols across the shards. The partition is
+    /// balanced and does not depend on the order of the symbols.
+    pub fn partition(&self, symbols: Vec<Symbol>) -> Vec<Vec<Symbol>> {
+        let mut symbols: Vec<Symbol> = symbols.iter().map(|s| self.symbols.normalize(s)).collect();
+        symbols.sort();
+        symbols.dedup();
+        let mut partition = vec![vec![]; self.shards];
+        for (i, symbol) in symbols.into_iter().enumerate() {
+            partition[i % self.shards].push(symbol);
+        }
+        partition
+    }
+    /// Opens the connections, subscribes each shard to the given channels for
+    /// its part of the symbols and returns the merged stream of events.
+    ///
+    /// The shards run in the background on the tokio runtime (hence this
+    /// method must be called from within a runtime). They stop as soon as
+    /// the returned stream is dropped.
+    pub fn start(&self, channels: &[Channel], symbols: Vec<Symbol>) -> impl Stream<Item=ShardEvent> {
+        let (tx, rx) = mpsc::unbounded();
+        for (shard, symbols) in self.partition(symbols).into_iter().enumerate() {
+            if symbols.is_empty() {
+                continue;
+            }
+            let sub = SubscriptionData {
+                channels: channels.iter().map(|c| (*c, symbols.clone())).collect()
+            };
+            let shard = Shard {
+                id    : shard,
+                source: self.source,
+                auth  : self.auth.clone(),
+                config: self.config.clone(),
+                sub,
+                retired: self.retired.clone(),
+                tx    : tx.clone(),
+            };
+            tokio::spawn(shard.run());
+        }
+        rx
+    }
+}
+
+/// Private: the state of one single shard
+struct Shard {
+    id    : usize,
+    source: Source,
+    auth  : watch::Receiver<AuthData>,
+    config: ConnectionConfig,
+    sub   : SubscriptionData,
+    retired: Arc<Mutex<HashSet<Symbol>>>,
+    tx    : UnboundedSender<ShardEvent>,
+}
+impl Shard {
+    /// Forwards the responses of the shard; reconnecting whenever needed
+    async fn run(mut self) {
+        let mut delay     = RECONNECT_DELAY;
+        let mut connected = false;
+        while !self.tx.is_closed() {
+            let retired = self.retired.lock().unwrap().clone();
+            if !self.prune(|s| retired.contains(s)) {
+                return;
+            }
+            if self.sub.channels.values().all(Vec::is_empty) {
+                return;
+            }
+            // the connection uses the latest credentials: they are not new
+            let _ = self.auth.changed().now_or_never();
+            match self.connect().await {
+                Ok(client) => {
+                    if connected && !self.emit(ShardEvent::Reconnected { shard: self.id }) {
+                        return;
+                    }
+                    connected = true;
+
+                    let stream = client.try_stream();
+                    futures::pin_mut!(stream);
+                    if !self.forward(&mut stream, &mut delay).await {
+                        return;
+                    }
+                },
+                Err(error) => {
+                    if !self.emit(ShardEvent::Disconnected { shard: self.id, error }) {
+                        return;
+                    }
+                }
+            }
+            // back off so that a connection which is immediately dropped (e.g.
+            // because the authentication failed) does not cause a storm
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+        }
+    }
+    /// Forwards the responses of one connection until it is lost, a frame
+    /// cannot be decoded (which is reported as `Disconnected`) or the 
+    /// credentials are replaced. The reconnection delay is reset as soon as
+    /// data flows. Returns false when nobody listens anymore
+    async fn forward<S>(&mut self, stream: &mut S, delay: &mut std::time::Duration) -> bool
+        where S: Stream<Item=Result<Response, Error>> + Unpin
+    {
+        while let Some(response) = self.next(stream).await {
+            let response = match response {
+                Ok(response) => response,
+                Err(error)   => return self.emit(ShardEvent::Disconnected { shard: self.id, error }),
+            };
+            // the connection is deemed healthy once data flows
+            if response.timestamp().is_some() {
+                *delay = RECONNECT_DELAY;
+            }
+            if let Response::Subscription(ack) = &response {
+                if !self.prune_rejected(ack) {
+                    return false;
+                }
+            }
+            if !self.emit(ShardEvent::Response { shard: self.id, response }) {
+                return false;
+            }
+        }
+        true
+    }
+    /// Returns the next response of the stream; or None when the stream 
+    /// ends or the credentials are replaced (the connection must then be
+    /// reopened)
+    async fn next<S>(&mut self, stream: &mut S) -> Option<Result<Response, Error>>
+        where S: Stream<Item=Result<Response, Error>> + Unpin
+    {
+        tokio::select! {
+            response = stream.next()       => response,
+            rotated  = self.auth.changed() => match rotated {
+                Ok(_)  => None,
+                // the client is gone: the credentials won't change anymore
+                Err(_) => stream.next().await,
+            },
+        }
+    }
+    /// Opens an authenticated and subscribed connection
+    async fn connect(&self) -> Result<Client, Error> {
+        let auth       = self.auth.borrow().clone();
+        let mut client = Client::with_config(self.source, &self.config).await?;
+        client.authenticate(auth).await?;
+        client.subscribe(self.sub.clone()).await?;
+        Ok(client)
+    }
+    /// Removes the symbols matching the given predicate from the desired 
+    /// subscriptions. Returns false when nobody listens anymore
+    fn prune<F: Fn(&Symbol) -> bool>(&mut self, pruned: F) -> bool {
+        self.prune_by_channel(|_, s| pruned(s))
+    }
+    /// Removes the symbols which are absent from the subscription 
+    /// acknowledged by the server (only for the channels it lists). Returns
+    /// false when nobody listens anymore
+    fn prune_rejected(&mut self, ack: &SubscriptionData) -> bool {
+        self.prune_by_channel(|channel, symbol| match ack.symbols(channel) {
+            Some(symbols) => !symbols.iter().any(|s| s == symbol || s == "*"),
+            // the ack says nothing about this channel: nothing was rejected
+            None          => false,
+        })
+    }
+    /// Removes the symbols matching the given predicate (evaluated for each
+    /// channel) from the desired subscriptions. Returns false when nobody
+    /// listens anymore
+    fn prune_by_channel<F: Fn(Channel, &Symbol) -> bool>(&mut self, pruned: F) -> bool {
+        let mut symbols = vec![];
+        for (channel, subscribed) in self.sub.channels.iter_mut() {
+            let (out, keep): (Vec<Symbol>, Vec<Symbol>) = subscribed.drain(..)
+                .partition(|s| pruned(*channel, s));
+            *subscribed = keep;
+            symbols.extend(out);
+        }
+        symbols.sort();
+        symbols.dedup();
+        symbols.is_empty() || self.emit(ShardEvent::Pruned { shard: self.id, symbols })
+    }
+    /// Emits an event; returns false when nobody listens anymore
+    fn emit(&self, event: ShardEvent) -> bool {
+        self.tx.unbounded_send(event).is_ok()
+    }
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use futures::channel::mpsc;
+    use tokio::sync::watch;
+
+    use crate::realtime::{AuthData, Channel, Response, Source, SubscriptionDataBuilder};
+    use crate::sharding::{Shard, ShardEvent, ShardedClient};
+    use crate::websocket::RECONNECT_DELAY;
+
+    #[test]
+    fn test_rejected_symbols_are_pruned() {
+        let (tx, mut rx) = mpsc::unbounded();
+        let sub = SubscriptionDataBuilder::default()
+            .trades(vec!["AAPL".to_string(), "DEAD".to_string()])
+            .quotes(vec!["AAPL".to_string()])
+            .bars(vec!["MSFT".to_string()])
+            .build().unwrap();
+        let mut shard = Shard {
+            id: 0, source: Source::IEX, auth: watch::channel(AuthData::Token("token".to_string())).1,
+            config: Default::default(), sub, retired: Default::default(), tx
+        };
+        let txt = r#"{"T":"subscription","trades":["AAPL"],"quotes":["*"]}"#;
+        let ack = match serde_json::from_str::<Response>(txt).unwrap() {
+            Response::Subscription(ack) => ack,
+            _ => unreachable!(),
+        };
+        shard.prune_rejected(&ack);
+
+        assert_eq!(Some(&["AAPL".to_string()][..]), shard.sub.symbols(Channel::Trades));
+        assert_eq!(Some(&["AAPL".to_string()][..]), shard.sub.symbols(Channel::Quotes));
+        // the ack does not list the bars channel, which is kept untouched
+        assert_eq!(Some(&["MSFT".to_string()][..]), shard.sub.symbols(Channel::Bars));
+        match rx.try_recv().unwrap() {
+            ShardEvent::Pruned { symbols, .. } => assert_eq!(vec!["DEAD"], symbols),
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+    #[tokio::test]
+    async fn test_undecodable_frames_disconnect_the_shard() {
+        let (tx, mut rx) = mpsc::unbounded();
+        let sub = SubscriptionDataBuilder::default()
+            .trades(vec!["AAPL".to_string()])
+            .build().unwrap();
+        let (_rotate, auth) = watch::channel(AuthData::Token("token".to_string()));
+        let mut shard = Shard {
+            id: 1, source: Source::IEX, auth, config: Default::default(), sub, retired: Default::default(), tx
+        };
+        let txt    = r#"{"T":"t","i":96921,"S":"AAPL","x":"D","p":126.55,"s":1,"t":"2021-02-22T15:51:44.208Z","c":["@","I"],"z":"C"}"#;
+        let trade  = serde_json::from_str::<Response>(txt).unwrap();
+        let error  = serde_json::from_str::<Response>("{").unwrap_err();
+        let mut stream = futures::stream::iter(vec![Ok(trade), Err(error.into())]);
+        let mut delay  = RECONNECT_DELAY * 4;
+
+        assert!(shard.forward(&mut stream, &mut delay).await);
+        assert_eq!(RECONNECT_DELAY, delay);
+        assert!(matches!(rx.try_recv().unwrap(), ShardEvent::Response { shard: 1, response: Response::Trade(_) }));
+        assert!(matches!(rx.try_recv().unwrap(), ShardEvent::Disconnected { shard: 1, .. }));
+    }
+    #[test]
+    fn test_partition_is_balanced() {
+        let auth    = AuthData::Token("token".to_string());
+        let client  = ShardedClient::new(Source::SIP, auth, 3);
+        let symbols = ["TSLA", "AAPL", "MSFT", "AMD", "IBM", "AAPL", "VOO"]
+            .iter().map(|s| s.to_string()).collect();
+
+        let partition = client.partition(symbols);
+        assert_eq!(3, partition.len());
+        assert_eq!(vec!["AAPL", "MSFT"], partition[0]);
+        assert_eq!(vec!["AMD",  "TSLA"], partition[1]);
+        assert_eq!(vec!["IBM",  "VOO"],  partition[2]);
+    }
+}
@@ -21,6 +21,10 @@
 //! So far, the client can chose to listen to:
 //!   * trade_updates (the only one at the time being)
 //! 
+//! The streams which are not known to this crate can still be listened to 
+//! with `MessageStream::Other`; their messages are yielded as 
+//! `Response::Unknown` (with an undecoded payload).
+//! 
 //! ## Conversation Protocol
 //! Even though the format of the messages differ, the communication protocol
 //! of this service is somewhat similar to that of the realtime data api.
@@ -166,25 +170,25 @@
 //! These are the events that are the expected results of actions you may 
 //! have taken by sending API requests.
 //! 
-//!     * new: Sent when an order has been routed to exchanges for execution.
-//!     * fill: Sent when your order has been completely filled.
-//!         * timestamp: The time at which the order was filled.
-//!         * price: The average price per share at which the order was filled.
-//!         * position_qty: The size of your total position, after this fill 
-//!             event, in shares. Positive for long positions, negative for 
-//!             short positions.
-//!     * partial_fill: Sent when a number of shares less than the total remaining
-//!         quantity on your order has been filled.
-//!         * timestamp: The time at which the shares were filled.
-//!         * price: The average price per share at which the shares were filled.
-//!         * position_qty: The size of your total position, after this fill event, in shares. Positive for long positions, negative for short positions.
-//!     * canceled: Sent when your requested cancelation of an order is processed.
-//!         * timestamp: The time at which the order was canceled.
-//!     * expired: Sent when an order has reached the end of its lifespan, as determined by the order’s time in force value.
-//!         * timestamp: The time at which the order expired.
-//!     * done_for_day: Sent when the order is done executing for the day, and will not receive further updates until the next trading day.
-//!     * replaced: Sent when your requested replacement of an order is processed.
-//!         * timestamp: The time at which the order was replaced.
+//! * new: Sent when an order has been routed to exchanges for execution.
+//! * fill: Sent when your order has been completely filled.
+//!   * timestamp: The time at which the order was filled.
+//!   * price: The average price per share at which the order was filled.
+//!   * position_qty: The size of your total position, after this fill 
+//!     event, in shares. Positive for long positions, negative for 
+//!     short positions.
+//! * partial_fill: Sent when a number of shares less than the total remaining
+//!   quantity on your order has been filled.
+//!   * timestamp: The time at which the shares were filled.
+//!   * price: The average price per share at which the shares were filled.
+//!   * position_qty: The size of your total position, after this fill event, in shares. Positive for long positions, negative for short positions.
+//! * canceled: Sent when your requested cancelation of an order is processed.
+//!   * timestamp: The time at which the order was canceled.
+//! * expired: Sent when an order has reached the end of its lifespan, as determined by the order’s time in force value.
+//!   * timestamp: The time at which the order expired.
+//! * done_for_day: Sent when the order is done executing for the day, and will not receive further updates until the next trading day.
+//! * replaced: Sent when your requested replacement of an order is processed.
+//!   * timestamp: The time at which the order was replaced.
 //! 
 //! #### Rarer events:
 //! 
@@ -193,24 +197,24 @@
 //! them, but you may still wish to account for the possibility that they 
 //! will occur.
 //! 
-//!     * rejected: Sent when your order has been rejected.
-//!         * timestamp: The time at which the rejection occurred.
-//!     * pending_new: Sent when the order has been received by Alpaca and 
-//!           routed to the exchanges, but has not yet been accepted for 
-//!           execution.
-//!     * stopped: Sent when your order has been stopped, and a trade is 
-//!           guaranteed for the order, usually at a stated price or better, 
-//!           but has not yet occurred.
-//!     * pending_cancel: Sent when the order is awaiting cancelation. Most 
-//!           cancelations will occur without the order entering this state.
-//!     * pending_replace: Sent when the order is awaiting replacement.
-//!     * calculated: Sent when the order has been completed for the day 
-//!           - it is either “filled” or “done_for_day” - but remaining 
-//!           settlement calculations are still pending.
-//!     * suspended: Sent when the order has been suspended and is not 
-//!           eligible for trading.
-//!     * order_replace_rejected: Sent when the order replace has been rejected.
-//!     * order_cancel_rejected: Sent when the order cancel has been rejected.
+//! * rejected: Sent when your order has been rejected.
+//!   * timestamp: The time at which the rejection occurred.
+//! * pending_new: Sent when the order has been received by Alpaca and 
+//!   routed to the exchanges, but has not yet been accepted for 
+//!   execution.
+//! * stopped: Sent when your order has been stopped, and a trade is 
+//!   guaranteed for the order, usually at a stated price or better, 
+//!   but has not yet occurred.
+//! * pending_cancel: Sent when the order is awaiting cancelation. Most 
+//!   cancelations will occur without the order entering this state.
+//! * pending_replace: Sent when the order is awaiting replacement.
+//! * calculated: Sent when the order has been completed for the day 
+//!   (it is either “filled” or “done_for_day”) but remaining 
+//!   settlement calculations are still pending.
+//! * suspended: Sent when the order has been suspended and is not 
+//!   eligible for trading.
+//! * order_replace_rejected: Sent when the order replace has been rejected.
+//! * order_cancel_rejected: Sent when the order cancel has been rejected.
 //! 
 //! Example
 //! 
@@ -238,13 +242,16 @@
 //! }
 //! ```
 
+use std::{collections::VecDeque, sync::{Arc, Mutex}, time::Instant};
+
 use chrono::{DateTime, Utc};
-use futures::{SinkExt, StreamExt, stream::{SplitSink, SplitStream}};
+use futures::{SinkExt, StreamExt, lock::Mutex as AsyncMutex, stream::{SplitSink, SplitStream}};
+use derive_builder::Builder;
 use serde::{Deserialize, Serialize};
-use tokio::net::TcpStream;
-use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async, tungstenite::Message};
+use tokio_tungstenite::tungstenite::{self, Message};
 
-use crate::{entities::OrderData, errors::Error};
+use crate::{entities::OrderData, errors::Error, handlers::{self, OrderUpdateHandler}};
+use crate::websocket::{self, ConnectionConfig, MAX_RECONNECT_DELAY, RECONNECT_DELAY, WsStream};
 
 /// Header used to send the key-id authentication
 pub const APCA_API_KEY_ID: &str = "APCA-API-KEY-ID";
@@ -255,19 +262,45 @@ pub const APCA_API_SECRET_KEY: &str = "APCA-API-SECRET-KEY";
 pub const LIVE_TRADING_URL: &str = "wss://api.alpaca.markets/stream";
 /// Base URL to interact with paper trading api
 pub const PAPER_TRADING_URL: &str = "wss://paper-api.alpaca.markets/stream";
+/// How long the client waits for the server to acknowledge a request
+pub const ACK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
 
 
-type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
-
 /// This is the object you'll want to create in order to interact with Alpaca's
 /// market data api. The object allows both server to client and client to 
 /// server communication (these responsibilities can be split for independant/
 /// asynchronous processing).
+///
+/// ```no_run
+/// # async fn example() -> Result<(), apca_datav2::errors::Error> {
+/// use futures::StreamExt;
+/// use apca_datav2::streaming::{Client, MessageStream};
+///
+/// let mut client = Client::paper().await?;
+/// client.authenticate("key".to_string(), "secret".to_string()).await?;
+/// client.listen(vec![MessageStream::TradeUpdates]).await?;
+///
+/// // the sender can still be used while the responses are being processed
+/// let (mut sender, stream) = client.split();
+/// let mut stream = Box::pin(stream);
+/// while let Some(response) = stream.next().await {
+///     println!("{:?}", response?);
+///     sender.listen(vec![]).await?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
 pub struct Client {
     /// The portion of the client devoted to the client to server communication
     write : ClientSender,
     /// The portion of the client devoted to the server to client communication
     read  : ClientReceiver,
+    /// What it takes to restore the session when the connection is lost
+    session: Session,
+    /// The heartbeat watchdog (if any)
+    heartbeat: Option<Heartbeat>,
+    /// The responses received while waiting for an acknowledgement
+    pending: VecDeque<Response>,
 }
 impl Client {
   pub async fn paper() -> Result<Self, Error> {
@@ -276,55 +309,321 @@ impl Client {
   pub async fn live() -> Result<Self, Error> {
     Self::new(true).await
   }
-  /// Creates a client that fetches data from the given source.
+  /// Creates a client connected to the live (or paper) trading stream.
   pub async fn new(live: bool) -> Result<Self, Error> {
-      // --- Connect to websocket
+    Self::with_config(live, &ConnectionConfig::default()).await
+  }
+  /// Creates a client using the given connection config (proxy, tls...)
+  pub async fn with_config(live: bool, config: &ConnectionConfig) -> Result<Self, Error> {
       let url = if live { LIVE_TRADING_URL } else { PAPER_TRADING_URL };
-      let (socket, _rsp) = connect_async(url).await?;
-      let (write, read)  = socket.split();
-      let write          = ClientSender::new(write);
-      let read           = ClientReceiver::new(read);
+      Self::with_url_and_config(url, config).await
+  }
+  /// Creates a client connected to an arbitrary endpoint (e.g. the broker 
+  /// api, a regional endpoint or a mock server).
+  pub async fn with_url(url: &str) -> Result<Self, Error> {
+    Self::with_url_and_config(url, &ConnectionConfig::default()).await
+  }
+  /// Creates a client connected to an arbitrary endpoint using the given 
+  /// connection config (proxy, tls...)
+  pub async fn with_url_and_config(url: &str, config: &ConnectionConfig) -> Result<Self, Error> {
+      let session        = Session { url: url.to_string(), config: config.clone(), state: Default::default() };
+      let (write, read)  = session.connect().await?;
+      let write          = ClientSender { write: Arc::new(AsyncMutex::new(write)), state: session.state.clone() };
       //
-      Ok(Self {write, read})
+      Ok(Self {write, read: ClientReceiver::new(read), session, heartbeat: None, pending: VecDeque::new()})
   }
 
-  /// Authenticates the client
-  pub async fn authenticate<'a>(&mut self, key: String, secret: String) -> Result<(), Error> {
-      self.write.authenticate(key, secret).await
+  /// Enables the heartbeat watchdog. A quiet order-update stream cannot be
+  /// told apart from a stale one; hence the client regularly pings the 
+  /// server and expects some frame (a pong at least) to arrive within the 
+  /// given timeout. Otherwise, the stream yields an `Error::Timeout` item 
+  /// and, depending on the policy, reconnects.
+  pub fn with_heartbeat(mut self, timeout: std::time::Duration, on_timeout: OnTimeout) -> Self {
+      self.heartbeat = Some(Heartbeat { timeout, on_timeout });
+      self
   }
-  /// Subscribe for realtime data about certain trades, quotes or bars
-  pub async fn listen(&mut self, sub: Vec<MessageStream>) -> Result<(), Error> {
-      self.write.listen(sub).await
+  /// Authenticates the client and waits for the server to authorize the 
+  /// connection. When the credentials are rejected, this returns an 
+  /// `Error::Unauthorized`.
+  pub async fn authenticate(&mut self, key: String, secret: String) -> Result<(), Error> {
+      self.write.authenticate(key, secret).await?;
+      let ack = self.await_ack(|r| matches!(r, 
+          Response::Authorization{data: AuthorizationData{action: Action::Authenticate, ..}})).await?;
+      match ack {
+          Response::Authorization{data: AuthorizationData{status: AuthorizationStatus::Authorized, ..}} => Ok(()),
+          _ => Err(Error::Unauthorized),
+      }
+  }
+  /// Listens to the given streams and waits for the server to acknowledge
+  /// the request. The streams which are actually listened to are returned:
+  /// the streams which are not available are silently dropped by the server.
+  /// When the connection is not authorized, this returns an 
+  /// `Error::Unauthorized`.
+  pub async fn listen(&mut self, sub: Vec<MessageStream>) -> Result<Vec<MessageStream>, Error> {
+      self.write.listen(sub).await?;
+      let ack = self.await_ack(|r| matches!(r, Response::Listening{..} | 
+          Response::Authorization{data: AuthorizationData{action: Action::Listen, ..}})).await?;
+      match ack {
+          Response::Listening{data} => Ok(data.streams),
+          _ => Err(Error::Unauthorized),
+      }
+  }
+  /// Splits the client in a sender and the stream of the responses from the
+  /// server. Both halves can be used concurrently: e.g. the sender can issue
+  /// a new `listen` request while the stream is being processed.
+  ///
+  /// Whenever the connection is lost, the stream transparently reconnects 
+  /// (retrying with an exponential backoff), authenticates again with the 
+  /// last credentials and re-issues the last `listen` request (even when
+  /// these were sent through the sender). A `Response::Reconnected` notice
+  /// is then inserted in the stream so that you can reconcile the state of
+  /// your orders (updates sent while the connection was down are not 
+  /// replayed by the server). The sender keeps working after a reconnection.
+  ///
+  /// A frame which cannot be decoded yields an error item, but the stream
+  /// carries on with the next frames.
+  pub fn split(self) -> (ClientSender, impl StreamExt<Item=Result<Response, Error>>) {
+      let Self { write, read, session, heartbeat, pending } = self;
+      let reader = Reader {
+          read     : read.read,
+          pending,
+          sink     : write.write.clone(),
+          session,
+          heartbeat,
+          last_seen: Instant::now(),
+          stale    : false,
+      };
+      let stream = futures::stream::unfold(reader, |mut reader| async move {
+          let item = reader.next().await;
+          Some((item, reader))
+      });
+      (write, stream)
+  }
+  /// Returns the stream which is used to receive the responses from the server.
+  /// It reconnects whenever the connection is lost (see `split`).
+  pub fn stream(self) -> impl StreamExt<Item=Result<Response, Error>> {
+      self.split().1
+  }
+  /// Runs the client: each order update is dispatched to the matching 
+  /// method of the given handler (see `handlers::dispatch`).
+  pub async fn run<H: OrderUpdateHandler>(self, handler: &mut H) {
+      handlers::dispatch(self.stream(), handler).await
+  }
+  /// Gracefully closes the connection: a Close frame is sent to the server
+  /// and the messages that were still in flight are drained until the 
+  /// server acknowledges the closure. These messages are returned so that
+  /// no order update gets lost.
+  pub async fn close(mut self) -> Result<Vec<Response>, Error> {
+      self.write.close().await?;
+      let mut drained = self.pending.drain(..).collect::<Vec<_>>();
+      drained.extend(self.read.drain().await?);
+      Ok(drained)
+  }
+  /// Private: waits (at most `ACK_TIMEOUT`) for the first response 
+  /// satisfying the given predicate. The other responses received in the 
+  /// meantime are kept and will be yielded by the stream.
+  async fn await_ack<F: Fn(&Response) -> bool>(&mut self, is_ack: F) -> Result<Response, Error> {
+      let read    = &mut self.read.read;
+      let pending = &mut self.pending;
+      let wait    = async move {
+          while let Some(frame) = read.next().await {
+              match decode(frame) {
+                  Some(Ok(r)) if is_ack(&r) => return Ok(r),
+                  Some(Ok(r))               => pending.push_back(r),
+                  Some(Err(e))              => return Err(e),
+                  None                      => {},
+              }
+          }
+          Err(tungstenite::Error::ConnectionClosed.into())
+      };
+      tokio::time::timeout(ACK_TIMEOUT, wait).await
+          .unwrap_or(Err(Error::Timeout(ACK_TIMEOUT)))
+  }
+}
+/// Private: what it takes to (re)open an authenticated session
+struct Session {
+  url    : String,
+  config : ConnectionConfig,
+  state  : Arc<Mutex<SessionState>>,
+}
+/// Private: the last requests that were sent to the server (shared with the
+/// `ClientSender`, hence they can be replayed upon reconnection)
+#[derive(Debug, Clone, Default)]
+struct SessionState {
+  auth   : Option<AuthData>,
+  listen : Option<StreamList>,
+}
+impl Session {
+  /// Opens a connection and restores the session state on it
+  async fn connect(&self) -> Result<(SplitSink<WsStream, Message>, SplitStream<WsStream>), Error> {
+      let socket         = websocket::connect(&self.url, &self.config).await?;
+      let (mut write, read) = socket.split();
+      let state          = self.state.lock().unwrap().clone();
+      if let Some(data) = state.auth {
+          send(&mut write, &Request::Authenticate{data}).await?;
+      }
+      if let Some(data) = state.listen {
+          send(&mut write, &Request::Listen{data}).await?;
+      }
+      Ok((write, read))
   }
-  /// Returns the stream which is used to receive the responses from the server
-  pub fn stream(self) -> impl StreamExt<Item=Response> {
-      self.read.stream()
+  /// Reconnects; retrying with an exponential backoff until it succeeds. 
+  /// The new write half replaces the lost one in the given sink (which is
+  /// locked all along so that no request can be sent in the meantime).
+  async fn reconnect(&self, sink: &AsyncMutex<SplitSink<WsStream, Message>>) -> SplitStream<WsStream> {
+      let mut delay = RECONNECT_DELAY;
+      let mut sink  = sink.lock().await;
+      loop {
+          tokio::time::sleep(delay).await;
+          if let Ok((write, read)) = self.connect().await {
+              *sink = write;
+              return read;
+          }
+          delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+      }
   }
 }
-// The portion of the client devoted to the client to server communication
+/// What the stream does when the heartbeat watchdog times out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnTimeout {
+  /// Yield an `Error::Timeout` item and keep waiting on the same connection
+  Error,
+  /// Yield an `Error::Timeout` item, then reconnect (which is notified by a
+  /// `Response::Reconnected` item)
+  Reconnect,
+}
+/// Private: the configuration of the heartbeat watchdog
+#[derive(Debug, Clone, Copy)]
+struct Heartbeat {
+  timeout   : std::time::Duration,
+  on_timeout: OnTimeout,
+}
+/// Private: the state of the stream of responses
+struct Reader {
+  read     : SplitStream<WsStream>,
+  /// The responses which were received before the stream was created
+  pending  : VecDeque<Response>,
+  sink     : Arc<AsyncMutex<SplitSink<WsStream, Message>>>,
+  session  : Session,
+  heartbeat: Option<Heartbeat>,
+  /// The time at which the last frame was received
+  last_seen: Instant,
+  /// True when the connection must be replaced (heartbeat timeout)
+  stale    : bool,
+}
+impl Reader {
+  /// Returns the next item of the stream of responses
+  async fn next(&mut self) -> Result<Response, Error> {
+      if let Some(response) = self.pending.pop_front() {
+          return Ok(response);
+      }
+      loop {
+          if self.stale {
+              self.stale = false;
+              self.reconnect().await;
+              return Ok(Response::Reconnected);
+          }
+          let frame = match self.heartbeat {
+              None     => self.read.next().await,
+              Some(hb) => match tokio::time::timeout(hb.timeout / 3, self.read.next()).await {
+                  Ok(frame) => frame,
+                  Err(_)    => {
+                      if self.last_seen.elapsed() >= hb.timeout {
+                          self.last_seen = Instant::now();
+                          self.stale     = hb.on_timeout == OnTimeout::Reconnect;
+                          return Err(Error::Timeout(hb.timeout));
+                      }
+                      // the pong will prove that the connection is alive
+                      let _ = self.sink.lock().await.send(Message::Ping(vec![])).await;
+                      continue;
+                  },
+              },
+          };
+          match frame {
+              None        => {
+                  self.reconnect().await;
+                  return Ok(Response::Reconnected);
+              },
+              Some(frame) => {
+                  self.last_seen = Instant::now();
+                  if let Some(item) = decode(frame) {
+                      return item;
+                  }
+              },
+          }
+      }
+  }
+  /// Replaces the current connection with a new one
+  async fn reconnect(&mut self) {
+      self.read      = self.session.reconnect(&self.sink).await;
+      self.last_seen = Instant::now();
+  }
+}
+/// Private: decodes one frame received from the server. Control frames are
+/// ignored (hence `None`).
+#[allow(clippy::result_large_err)]
+fn decode(frame: Result<Message, tungstenite::Error>) -> Option<Result<Response, Error>> {
+  match frame {
+      Ok(m) => websocket::payload(m)
+          .map(|text| serde_json::from_str::<Response>(&text).map_err(Error::from)),
+      Err(tungstenite::Error::ConnectionClosed) => None,
+      Err(e) => Some(Err(e.into())),
+  }
+}
+/// Private: serializes a request and sends it over the given sink
+async fn send(write: &mut SplitSink<WsStream, Message>, request: &Request) -> Result<(), Error> {
+  let json = serde_json::to_string(request)?;
+  //write.send(Message::Text(json)).await?;
+  write.send(Message::Binary(json.as_bytes().to_vec())).await?;
+  Ok(())
+}
+
+/// The portion of the client devoted to the client to server communication.
+/// It can be cloned so as to send requests from several tasks.
+#[derive(Clone)]
 pub struct ClientSender {
-  write : SplitSink<WsStream, Message>,
+  write : Arc<AsyncMutex<SplitSink<WsStream, Message>>>,
+  state : Arc<Mutex<SessionState>>,
 }
 impl ClientSender {
   /// Creates a new instance from a given write sink
   pub fn new(write: SplitSink<WsStream, Message>) -> Self {
-      Self {write}
+      Self { write: Arc::new(AsyncMutex::new(write)), state: Default::default() }
   }
-  /// Authenticates the client
-  pub async fn authenticate<'a>(&mut self, key: String, secret: String) -> Result<(), Error> {
+  /// Authenticates the client. Unlike `Client::authenticate`, this does not
+  /// wait for the server to authorize the connection: the authorization 
+  /// response is yielded by the stream of responses.
+  pub async fn authenticate(&mut self, key: String, secret: String) -> Result<(), Error> {
       let data = AuthData { key, secret };
       self.action(Request::Authenticate{data}).await
   }
+  /// Replaces the credentials of the session (e.g. to rotate the api keys
+  /// of a long-running service). The current connection is closed; hence
+  /// the stream reconnects, authenticates with the new credentials and 
+  /// listens again (which is notified by a `Response::Reconnected`).
+  pub async fn set_credentials(&mut self, key: String, secret: String) -> Result<(), Error> {
+      self.state.lock().unwrap().auth = Some(AuthData { key, secret });
+      self.close().await
+  }
   /// Subscribe for realtime data about certain trades, quotes or bars
   pub async fn listen(&mut self, sub: Vec<MessageStream>) -> Result<(), Error> {
       let data = StreamList {streams: sub};
       self.action(Request::Listen{data}).await
   }
-  /// Performs the specified action on the server
+  /// Performs the specified action on the server. The action is remembered
+  /// so that it can be performed again upon reconnection.
   pub async fn action(&mut self, action: Request) -> Result<(), Error> {
-      let json = serde_json::to_string(&action)?;
-      //self.write.send(Message::Text(json)).await?;
-      self.write.send(Message::Binary(json.as_bytes().to_vec())).await?;
+      match &action {
+          Request::Authenticate{data} => self.state.lock().unwrap().auth   = Some(data.clone()),
+          Request::Listen{data}       => self.state.lock().unwrap().listen = Some(data.clone()),
+      }
+      send(&mut *self.write.lock().await, &action).await
+  }
+  /// Sends a Close frame to the server, hence initiating the closing 
+  /// handshake. The server acknowledgement is received by the 
+  /// `ClientReceiver` (see `ClientReceiver::drain`).
+  pub async fn close(&mut self) -> Result<(), Error> {
+      self.write.lock().await.send(Message::Close(None)).await?;
       Ok(())
   }
 }
@@ -339,19 +638,29 @@ impl ClientReceiver {
   pub fn new(read: SplitStream<WsStream>) -> Self {
       Self {read}
   }
-  /// Returns the stream which is used to receive the responses from the server
-  pub fn stream(self) -> impl StreamExt<Item=Response> {
-      self.read
-      .filter_map(|m| async move {
-          if let Ok(Message::Binary(bytes)) = m {
-              let text = String::from_utf8_lossy(&bytes);
-              let data = serde_json::from_str::<Response>(&text)
-                   .unwrap_or_else(|_| panic!("unexpected message '{}'", text));
-              Some(data)
-          } else {
-              None
+  /// Returns the stream which is used to receive the responses from the server.
+  ///
+  /// A frame which cannot be decoded (or a transmission error) yields an
+  /// error item; it does not end the stream. The stream ends when the 
+  /// connection is closed.
+  pub fn stream(self) -> impl StreamExt<Item=Result<Response, Error>> {
+      self.read.filter_map(|m| async move { decode(m) })
+  }
+  /// Consumes all the messages that are still in flight until the server
+  /// acknowledges the closure of the connection, and returns them.
+  pub async fn drain(mut self) -> Result<Vec<Response>, Error> {
+      let mut drained = vec![];
+      while let Some(message) = self.read.next().await {
+          match message {
+              Ok(Message::Close(_)) => break,
+              Ok(m) => if let Some(text) = websocket::payload(m) {
+                  drained.push(serde_json::from_str::<Response>(&text)?)
+              },
+              Err(tungstenite::Error::ConnectionClosed) => break,
+              Err(e) => return Err(e.into()),
           }
-      })
+      }
+      Ok(drained)
   }
 }
 
@@ -384,10 +693,36 @@ pub struct AuthData {
   secret: String,
 }
 /// The messages streams a client may decide to listen to
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from="String", into="String")]
 pub enum MessageStream {
-  #[serde(rename = "trade_updates")]
-  TradeUpdates
+  /// The updates of the orders
+  TradeUpdates,
+  /// Any other stream (e.g. one that was recently added by Alpaca and is 
+  /// not supported by this crate yet)
+  Other(String),
+}
+impl MessageStream {
+  /// The name of the stream as used by the server
+  pub fn name(&self) -> &str {
+    match self {
+      MessageStream::TradeUpdates => "trade_updates",
+      MessageStream::Other(name)  => name,
+    }
+  }
+}
+impl From<String> for MessageStream {
+  fn from(name: String) -> Self {
+    match name.as_str() {
+      "trade_updates" => MessageStream::TradeUpdates,
+      _               => MessageStream::Other(name),
+    }
+  }
+}
+impl From<MessageStream> for String {
+  fn from(stream: MessageStream) -> Self {
+    stream.name().to_string()
+  }
 }
 
 
@@ -398,6 +733,7 @@ pub enum MessageStream {
 /// fields that will be in their messages are listed below.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "event")]
+#[non_exhaustive]
 pub enum OrderUpdate {
   /// Sent when an order has been routed to exchanges for execution.
   #[serde(rename="new")]
@@ -505,7 +841,7 @@ pub enum OrderUpdate {
     order: OrderData, 
   },
   /// Sent when the order has been completed for the day 
-  /// - it is either “filled” or “done_for_day” - but remaining settlement 
+  /// (it is either “filled” or “done_for_day”) but remaining settlement 
   /// calculations are still pending
   #[serde(rename="calculated")]
   Calculated {
@@ -536,6 +872,7 @@ pub enum OrderUpdate {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag="stream")]
 #[allow(clippy::large_enum_variant)]
+#[non_exhaustive]
 pub enum Response {
     #[serde(rename = "authorization")] 
     Authorization {
@@ -548,7 +885,58 @@ pub enum Response {
     #[serde(rename = "trade_updates")]
     TradeUpdates {
       data: OrderUpdate
+    },
+    /// Not sent by the server: this notice is inserted in the stream when the
+    /// client has reconnected after losing its connection. The updates sent 
+    /// while it was disconnected are lost.
+    #[serde(skip)]
+    Reconnected,
+    /// A message of a stream which is not supported by this crate (yet). Its
+    /// payload is left undecoded.
+    #[serde(untagged)]
+    Unknown {
+      /// The name of the stream
+      #[serde(deserialize_with="unknown_stream")]
+      stream: String,
+      /// The payload of the message
+      #[serde(default)]
+      data: serde_json::Value,
+    },
+}
+/// Private: only accepts the name of the streams that have no dedicated 
+/// variant in `Response` (a malformed message of a known stream must fail to
+/// decode rather than pass for an unknown one)
+fn unknown_stream<'de, D: serde::Deserializer<'de>>(d: D) -> Result<String, D::Error> {
+  let stream = String::deserialize(d)?;
+  match stream.as_str() {
+    "authorization" | "listening" | "trade_updates" => 
+      Err(serde::de::Error::custom(format!("malformed {} message", stream))),
+    _ => Ok(stream),
+  }
+}
+
+impl OrderUpdate {
+  /// The order which is impacted by this event
+  pub fn order(&self) -> &OrderData {
+    match self {
+      OrderUpdate::New { order }                  => order,
+      OrderUpdate::Fill { order, .. }             => order,
+      OrderUpdate::PartialFill { order, .. }      => order,
+      OrderUpdate::Canceled { order, .. }         => order,
+      OrderUpdate::Expired { order, .. }          => order,
+      OrderUpdate::DoneForDay { order }           => order,
+      OrderUpdate::Replaced { order, .. }         => order,
+      OrderUpdate::Rejected { order, .. }         => order,
+      OrderUpdate::PendingNew { order }           => order,
+      OrderUpdate::Stopped { order }              => order,
+      OrderUpdate::PendingCancel { order }        => order,
+      OrderUpdate::PendingReplace { order }       => order,
+      OrderUpdate::Calculated { order }           => order,
+      OrderUpdate::Suspended { order }            => order,
+      OrderUpdate::OrderReplaceRejected { order } => order,
+      OrderUpdate::OrderCancelRejected { order }  => order,
     }
+  }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -557,6 +945,7 @@ pub struct StreamList {
 }
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub enum AuthorizationStatus {
   #[serde(rename="authorized")]
   Authorized, 
@@ -566,6 +955,7 @@ pub enum AuthorizationStatus {
 
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub enum Action {
     #[serde(rename = "authenticate")] 
     Authenticate,
@@ -573,7 +963,8 @@ pub enum Action {
     Listen,
 }
 
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Builder, Debug, Copy, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct AuthorizationData {
   pub status: AuthorizationStatus,
   pub action: Action
@@ -581,8 +972,162 @@ pub struct AuthorizationData {
 
 #[cfg(test)]
 mod tests {
-    use crate::streaming::Response;
+    use std::time::Duration;
+
+    use futures::{SinkExt, StreamExt};
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::{accept_async, tungstenite::Message};
+
+    use crate::errors::Error;
+    use crate::streaming::{Client, MessageStream, OnTimeout, Response};
 
+  #[tokio::test]
+  async fn test_sender_is_usable_while_streaming() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url      = format!("ws://{}", listener.local_addr().unwrap());
+    let server   = tokio::spawn(async move {
+      let (sock, _) = listener.accept().await.unwrap();
+      let mut ws    = accept_async(sock).await.unwrap();
+      let msg = r#"{"stream":"listening","data":{"streams":[]}}"#;
+      ws.send(Message::Binary(msg.as_bytes().to_vec())).await.unwrap();
+      match ws.next().await {
+        Some(Ok(Message::Binary(bytes))) => String::from_utf8(bytes).unwrap(),
+        other => panic!("unexpected message {:?}", other),
+      }
+    });
+
+    let client = Client::with_url(&url).await.unwrap();
+    let (mut sender, stream) = client.split();
+    let mut stream = Box::pin(stream);
+    assert!(matches!(stream.next().await, Some(Ok(Response::Listening{..}))));
+    // the stream is still alive; yet a new listen request can be sent
+    sender.listen(vec![MessageStream::TradeUpdates]).await.unwrap();
+    let request = server.await.unwrap();
+    assert_eq!(r#"{"action":"listen","data":{"streams":["trade_updates"]}}"#, request);
+  }
+
+  #[tokio::test]
+  async fn test_undecodable_frame_does_not_end_stream() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url      = format!("ws://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+      let (sock, _) = listener.accept().await.unwrap();
+      let mut ws    = accept_async(sock).await.unwrap();
+      let odd = r#"{"stream":"trade_updates","data":{"event":"brand_new_event"}}"#;
+      let msg = r#"{"stream":"listening","data":{"streams":["trade_updates"]}}"#;
+      ws.send(Message::Binary(odd.as_bytes().to_vec())).await.unwrap();
+      // text frames are accepted as well
+      ws.send(Message::Text(msg.to_string())).await.unwrap();
+      while let Some(Ok(_)) = ws.next().await {}
+    });
+
+    let client = Client::with_url(&url).await.unwrap();
+    let mut stream = Box::pin(client.stream());
+    assert!(matches!(stream.next().await, Some(Err(Error::Json(_)))));
+    assert!(matches!(stream.next().await, Some(Ok(Response::Listening{..}))));
+  }
+  #[tokio::test]
+  async fn test_heartbeat_timeout() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url      = format!("ws://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+      let (sock, _) = listener.accept().await.unwrap();
+      // the server never reads, hence it never answers the pings
+      let _ws = accept_async(sock).await.unwrap();
+      tokio::time::sleep(Duration::from_secs(5)).await;
+    });
+
+    let client = Client::with_url(&url).await.unwrap()
+      .with_heartbeat(Duration::from_millis(300), OnTimeout::Error);
+    let mut stream = Box::pin(client.stream());
+    assert!(matches!(stream.next().await, Some(Err(Error::Timeout(_)))));
+  }
+  #[tokio::test]
+  async fn test_authenticate_awaits_ack() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url      = format!("ws://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+      for status in ["authorized", "unauthorized"].iter() {
+        let (sock, _) = listener.accept().await.unwrap();
+        let mut ws    = accept_async(sock).await.unwrap();
+        ws.next().await;
+        let early = r#"{"stream":"listening","data":{"streams":[]}}"#;
+        let ack   = format!(r#"{{"stream":"authorization","data":{{"action":"authenticate","status":"{}"}}}}"#, status);
+        ws.send(Message::Binary(early.as_bytes().to_vec())).await.unwrap();
+        ws.send(Message::Binary(ack.into_bytes())).await.unwrap();
+        tokio::spawn(async move { while let Some(Ok(_)) = ws.next().await {} });
+      }
+    });
+
+    let mut client = Client::with_url(&url).await.unwrap();
+    client.authenticate("key".to_string(), "secret".to_string()).await.unwrap();
+    // the responses received before the ack are not lost
+    let mut stream = Box::pin(client.stream());
+    assert!(matches!(stream.next().await, Some(Ok(Response::Listening{..}))));
+
+    let mut client = Client::with_url(&url).await.unwrap();
+    let result = client.authenticate("key".to_string(), "wrong".to_string()).await;
+    assert!(matches!(result, Err(Error::Unauthorized)));
+  }
+  #[tokio::test]
+  async fn test_credentials_rotation() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url      = format!("ws://{}", listener.local_addr().unwrap());
+    let server   = tokio::spawn(async move {
+      let mut auths = vec![];
+      for _ in 0..2 {
+        let (sock, _) = listener.accept().await.unwrap();
+        let mut ws    = accept_async(sock).await.unwrap();
+        if let Some(Ok(Message::Binary(bytes))) = ws.next().await {
+          auths.push(String::from_utf8(bytes).unwrap());
+        }
+        tokio::spawn(async move { while let Some(Ok(_)) = ws.next().await {} });
+      }
+      auths
+    });
+
+    let client = Client::with_url(&url).await.unwrap();
+    let (mut sender, stream) = client.split();
+    let mut stream = Box::pin(stream);
+    sender.authenticate("old".to_string(), "secret".to_string()).await.unwrap();
+    sender.set_credentials("new".to_string(), "secret".to_string()).await.unwrap();
+    assert!(matches!(stream.next().await, Some(Ok(Response::Reconnected))));
+
+    let auths = server.await.unwrap();
+    assert!(auths[0].contains(r#""key_id":"old""#));
+    assert!(auths[1].contains(r#""key_id":"new""#));
+  }
+  #[tokio::test]
+  async fn test_listen_returns_acknowledged_streams() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url      = format!("ws://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+      let (sock, _) = listener.accept().await.unwrap();
+      let mut ws    = accept_async(sock).await.unwrap();
+      ws.next().await;
+      // the requested stream is silently dropped
+      let ack = r#"{"stream":"listening","data":{"streams":[]}}"#;
+      ws.send(Message::Binary(ack.as_bytes().to_vec())).await.unwrap();
+      while let Some(Ok(_)) = ws.next().await {}
+    });
+
+    let mut client = Client::with_url(&url).await.unwrap();
+    let streams    = client.listen(vec![MessageStream::TradeUpdates]).await.unwrap();
+    assert!(streams.is_empty());
+  }
+  #[test]
+  fn deserialize_unknown_stream() {
+    let text = r#"{"stream":"listening","data":{"streams":["trade_updates","account_updates"]}}"#;
+    match serde_json::from_str::<Response>(text).unwrap() {
+      Response::Listening{data} => assert_eq!(
+        vec![MessageStream::TradeUpdates, MessageStream::Other("account_updates".to_string())], data.streams),
+      other => panic!("unexpected response {:?}", other),
+    }
+    let text = r#"{"stream":"account_updates","data":{"cash":"1000"}}"#;
+    assert!(matches!(serde_json::from_str::<Response>(text).unwrap(), Response::Unknown{stream, ..} if stream == "account_updates"));
+    let text = r#"{"stream":"trade_updates","data":{"event":"brand_new_event"}}"#;
+    assert!(serde_json::from_str::<Response>(text).is_err());
+  }
   #[test]
   fn deserialize_auth_response() {
     let text = r#"{"stream":"authorization","data":{"action":"authenticate","status":"authorized"}}"#;
@@ -0,0 +1,183 @@
+//! This module maps the various notations of the symbols used by the data
+//! vendors onto the format expected by Alpaca.
+//!
+//! Class shares and preferreds are notoriously written in different ways
+//! depending on the vendor: Berkshire Hathaway class B shares can be seen as
+//! `BRK.B`, `BRK-B`, `BRK_B` or even `BRKB`; whereas Alpaca expects `BRK.B`.
+//! A `SymbolNormalizer` rewrites the symbols given as input to the REST and
+//! websocket clients. It does so in two ways:
+//! * it first looks the symbol up in a (pluggable) mapping table. This is
+//!   required for the notations which cannot be inferred, such as `BRKB`.
+//! * otherwise, it replaces the configured separators by a dot.
+//!
+//! By default, a normalizer leaves the symbols untouched. The crypto pairs 
+//! (e.g. `BTC/USD`) and the asset ids are never rewritten by the separators.
+//!
+//! ```
+//! use apca_datav2::symbols::SymbolNormalizer;
+//!
+//! let normalizer = SymbolNormalizer::share_classes()
+//!     .mapping("BRKB", "BRK.B");
+//! assert_eq!("BRK.B", normalizer.normalize("BRK-B"));
+//! assert_eq!("BRK.B", normalizer.normalize("BRK_B"));
+//! assert_eq!("BRK.B", normalizer.normalize("BRKB"));
+//! assert_eq!("AAPL",  normalizer.normalize("AAPL"));
+//! assert_eq!("BTC/USD", normalizer.normalize("BTC/USD"));
+//! ```
+
+use std::collections::HashMap;
+
+/// The separator used by Alpaca between the root of a symbol and its class
+pub const CLASS_SEPARATOR: char = '.';
+
+/// Rewrites the symbols into Alpaca's expected format
+#[derive(Debug, Clone, Default)]
+pub struct SymbolNormalizer {
+    /// The characters which are replaced by the class separator
+    separators: Vec<char>,
+    /// The explicit mappings (these take precedence over the separators)
+    mappings: HashMap<String, String>,
+}
+impl SymbolNormalizer {
+    /// Creates a normalizer which leaves all symbols untouched
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Creates a normalizer which rewrites the common class separators
+    /// (dash, space and underscore) as a dot
+    pub fn share_classes() -> Self {
+        Self::new()
+            .separator('-')
+            .separator(' ')
+            .separator('_')
+    }
+    /// Adds a character which must be replaced by the class separator
+    pub fn separator(mut self, separator: char) -> Self {
+        self.separators.push(separator);
+        self
+    }
+    /// Adds an explicit mapping from a vendor notation to Alpaca's one
+    pub fn mapping(mut self, from: &str, to: &str) -> Self {
+        self.mappings.insert(from.to_string(), to.to_string());
+        self
+    }
+    /// Adds all the given explicit mappings
+    pub fn mappings<I: IntoIterator<Item=(String, String)>>(mut self, mappings: I) -> Self {
+        self.mappings.extend(mappings);
+        self
+    }
+    /// Rewrites the given symbol into Alpaca's expected format. Only the
+    /// explicit mappings apply to the crypto pairs (which contain a slash)
+    /// and to the asset ids.
+    pub fn normalize(&self, symbol: &str) -> String {
+        if let Some(mapped) = self.mappings.get(symbol) {
+            return mapped.clone();
+        }
+        if symbol.contains('/') || is_asset_id(symbol) {
+            return symbol.to_string();
+        }
+        symbol.chars()
+            .map(|c| if self.separators.contains(&c) { CLASS_SEPARATOR } else { c })
+            .collect()
+    }
+    /// Rewrites each symbol of a comma separated list
+    pub fn normalize_list(&self, symbols: &str) -> String {
+        symbols.split(',')
+            .map(|s| self.normalize(s.trim()))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+    /// Rewrites the given symbol into Alpaca's expected format, leaving an
+    /// asset id untouched
+    pub fn normalize_target(&self, target: &SymbolOrAssetId) -> String {
+        match target {
+            SymbolOrAssetId::Symbol(symbol) => self.normalize(symbol),
+            SymbolOrAssetId::AssetId(id)    => id.clone(),
+        }
+    }
+}
+
+/// Identifies an asset either by its symbol or by its id (uuid). Most of the 
+/// trading endpoints accept both. The conversion from a string tells them
+/// apart, so that an asset id is never mangled by the `SymbolNormalizer`.
+///
+/// ```
+/// use apca_datav2::symbols::SymbolOrAssetId;
+///
+/// assert_eq!(SymbolOrAssetId::Symbol("AAPL".to_string()), "AAPL".into());
+/// assert!(matches!(SymbolOrAssetId::from("b0b6dd9d-8b9b-48a9-ba46-b9d54906e415"), SymbolOrAssetId::AssetId(_)));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SymbolOrAssetId {
+    /// The symbol of the asset (e.g. AAPL)
+    Symbol(String),
+    /// The id of the asset
+    AssetId(String),
+}
+impl From<&str> for SymbolOrAssetId {
+    fn from(text: &str) -> Self {
+        if is_asset_id(text) {
+            SymbolOrAssetId::AssetId(text.to_string())
+        } else {
+            SymbolOrAssetId::Symbol(text.to_string())
+        }
+    }
+}
+impl From<&String> for SymbolOrAssetId {
+    fn from(text: &String) -> Self {
+        Self::from(text.as_str())
+    }
+}
+impl From<String> for SymbolOrAssetId {
+    fn from(text: String) -> Self {
+        Self::from(text.as_str())
+    }
+}
+impl std::fmt::Display for SymbolOrAssetId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SymbolOrAssetId::Symbol(symbol) => write!(f, "{}", symbol),
+            SymbolOrAssetId::AssetId(id)    => write!(f, "{}", id),
+        }
+    }
+}
+
+/// Returns true iff the given text is an asset id: a uuid such as 
+/// `b0b6dd9d-8b9b-48a9-ba46-b9d54906e415`
+pub fn is_asset_id(text: &str) -> bool {
+    text.len() == 36 && text.char_indices().all(|(i, c)| match i {
+        8 | 13 | 18 | 23 => c == '-',
+        _                => c.is_ascii_hexdigit(),
+    })
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use crate::symbols::{SymbolNormalizer, SymbolOrAssetId};
+
+    #[test]
+    fn test_normalize() {
+        let identity = SymbolNormalizer::new();
+        assert_eq!("BRK-B", identity.normalize("BRK-B"));
+
+        let normalizer = SymbolNormalizer::share_classes()
+            .mapping("BRKB", "BRK.B")
+            .mapping("BAC-PL", "BAC.PRL");
+        assert_eq!("BAC.PRL", normalizer.normalize("BAC-PL"));
+        assert_eq!("BRK.A", normalizer.normalize("BRK_A"));
+        assert_eq!("AAPL,BRK.B,BF.B", normalizer.normalize_list("AAPL, BRKB,BF-B"));
+    }
+    #[test]
+    fn test_asset_ids_are_not_normalized() {
+        let normalizer = SymbolNormalizer::share_classes();
+        let id         = "b0b6dd9d-8b9b-48a9-ba46-b9d54906e415";
+        assert_eq!(id, normalizer.normalize_target(&id.into()));
+        assert_eq!("BRK.B", normalizer.normalize_target(&SymbolOrAssetId::from("BRK-B")));
+        assert_eq!(id, normalizer.normalize(id));
+        assert_eq!("BTC/USD", normalizer.normalize("BTC/USD"));
+    }
+}
@@ -0,0 +1,80 @@
+//! This module gathers the helpers shared by the unit tests of the crate: a
+//! client wired to a `FakeTransport`, a local http server for the tests which
+//! must go through the network stack, and the json of the usual entities.
+
+use std::sync::{Arc, Mutex};
+
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpListener};
+
+use crate::{rest::Client, transport::FakeTransport};
+
+/// A new market order to buy 2 MSFT
+pub const ORDER: &str = r#"{"asset_class":"us_equity","asset_id":"b6d1aa75","canceled_at":null,"client_order_id":"ad1a656c",
+    "created_at":"2021-11-11T17:11:17.353294Z","expired_at":null,"extended_hours":false,"failed_at":null,
+    "filled_at":null,"filled_avg_price":null,"filled_qty":"0","hwm":null,"id":"810f77c9",
+    "legs":null,"limit_price":null,"notional":null,"order_class":"simple","order_type":"market","qty":"2","replaced_at":null,
+    "replaced_by":null,"replaces":null,"side":"buy","status":"new","stop_price":null,"submitted_at":"2021-11-11T17:11:17Z",
+    "symbol":"MSFT","time_in_force":"day","trail_percent":null,"trail_price":null,"type":"market","updated_at":null}"#;
+
+/// A long position of 2 MSFT bought at 300 and now worth 330 each
+pub const POSITION: &str = r#"{"asset_id":"b6d1aa75","symbol":"MSFT","exchange":"NASDAQ","asset_class":"us_equity",
+    "avg_entry_price":"300","qty":"2","side":"long","market_value":"660","cost_basis":"600","unrealized_pl":"60",
+    "unrealized_plpc":"0.1","unrealized_intraday_pl":"10","unrealized_intraday_plpc":"0.015","current_price":"330",
+    "lastday_price":"325","change_today":"0.015"}"#;
+
+/// A paper client which sends its requests to the given transport
+pub fn fake_client(transport: &FakeTransport) -> Client {
+    Client::paper("key".to_string(), "secret".to_string())
+        .with_transport(transport.clone())
+}
+
+/// A raw http response with the given status line (e.g. "200 OK") and body,
+/// which closes the connection
+pub fn response(status: &str, body: &str) -> String {
+    format!("HTTP/1.1 {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}", status, body.len(), body)
+}
+
+/// A local http server (see `serve`)
+pub struct TestServer {
+    /// The base url of the server
+    pub url: String,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+impl TestServer {
+    /// The raw requests received so far (one per connection)
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// Starts a local http server which answers the first request of each
+/// connection with the next of the given raw responses (or never answers it
+/// when that response is `None`). The connections are kept open until the
+/// end of the test.
+pub async fn serve(responses: Vec<Option<String>>) -> TestServer {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url      = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Arc::new(Mutex::new(vec![]));
+    let received = requests.clone();
+    tokio::spawn(async move {
+        let mut socks = vec![];
+        for response in responses {
+            let (mut sock, _) = listener.accept().await.unwrap();
+            let mut buffer = [0; 4096];
+            let n = sock.read(&mut buffer).await.unwrap();
+            received.lock().unwrap().push(String::from_utf8_lossy(&buffer[..n]).to_string());
+            if let Some(response) = response {
+                sock.write_all(response.as_bytes()).await.unwrap();
+            }
+            socks.push(sock);
+        }
+        std::future::pending::<()>().await;
+    });
+    TestServer { url, requests }
+}
+
+/// Starts a local http server which answers one single request with an
+/// empty json array
+pub async fn serve_once() -> TestServer {
+    serve(vec![Some(response("200 OK", "[]"))]).await
+}
@@ -0,0 +1,408 @@
+//! This module keeps an always-current view of the orders of the account.
+//!
+//! The `OrderTracker` loads the open orders through the REST API, then
+//! applies each update received on the trade_updates stream. Hence, it knows
+//! the current status, filled quantity and average fill price of every order
+//! without polling. Should the stream reconnect (and thus lose some updates),
+//! the tracker reloads the open orders and fetches each tracked order which
+//! is no longer among them (e.g. it was filled while the stream was down).
+//!
+//! Each tracked order can be awaited through an `OrderHandle`: e.g. to wait
+//! until an order is filled, canceled or rejected.
+//!
+//! Likewise, the `PositionTracker` seeds the open positions through the REST
+//! API, then applies the fills of the trade_updates stream to maintain the
+//! live quantity and average entry price of each position. Its `reconcile`
+//! method compares that view with the positions reported by the REST API and
+//! flags the divergences (e.g. a missed fill, or a corporate action).
+//!
+//! ```no_run
+//! # async fn example(client: apca_datav2::rest::Client, order: apca_datav2::orders::PlaceOrderRequest) -> Result<(), apca_datav2::errors::Error> {
+//! use apca_datav2::streaming::{self, MessageStream};
+//! use apca_datav2::tracker::OrderTracker;
+//!
+//! let mut updates = streaming::Client::paper().await?;
+//! updates.authenticate("key".to_string(), "secret".to_string()).await?;
+//! updates.listen(vec![MessageStream::TradeUpdates]).await?;
+//!
+//! let tracker = OrderTracker::new(&client);
+//! tracker.load().await?;
+//! let placed  = client.place_order(&order).await?;
+//! let handle  = tracker.track_order(&placed);
+//!
+//! let (_, done) = futures::join!(tracker.track(updates.stream()), handle.done());
+//! println!("{} is {:?} ({} filled)", done.id, done.status, done.filled_qty);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Mutex;
+
+use futures::{Stream, StreamExt, TryStreamExt};
+use tokio::sync::watch;
+
+use crate::entities::{OrderData, PositionData};
+use crate::errors::Error;
+use crate::orders::{ListOrderRequest, SearchOrderStatus, ORDERS_PAGE_SIZE};
+use crate::rest::Client;
+use crate::streaming::{OrderUpdate, Response};
+
+/// Keeps track of the current state of the orders of the account
+pub struct OrderTracker<'a> {
+    client: &'a Client,
+    orders: Mutex<HashMap<String, watch::Sender<OrderData>>>,
+}
+impl<'a> OrderTracker<'a> {
+    /// Creates a tracker which (re)loads the open orders with the given
+    /// client
+    pub fn new(client: &'a Client) -> Self {
+        Self { client, orders: Mutex::new(HashMap::new()) }
+    }
+    /// Loads the open orders of the account (their legs included)
+    pub async fn load(&self) -> Result<(), Error> {
+        self.load_open().await.map(|_| ())
+    }
+    /// Reloads the open orders of the account, then fetches each tracked 
+    /// order which is still open but was not listed (i.e. the ones which 
+    /// were filled, canceled... since they were last updated)
+    pub async fn refresh(&self) -> Result<(), Error> {
+        let open  = self.load_open().await?;
+        let stale = self.open_orders().into_iter()
+            .filter(|order| !open.contains(&order.id));
+        for order in stale {
+            self.update(self.client.get_by_id(&order.id, false).await?);
+        }
+        Ok(())
+    }
+    /// Applies the updates of the given trade_updates stream until it ends.
+    /// The orders are refreshed whenever the stream reconnects.
+    pub async fn track<S>(&self, stream: S)
+        where S: Stream<Item=Result<Response, Error>>
+    {
+        futures::pin_mut!(stream);
+        while let Some(item) = stream.next().await {
+            match item {
+                Ok(Response::TradeUpdates { data }) => self.apply(&data),
+                // the updates sent while the stream was down are lost
+                Ok(Response::Reconnected)           => { let _ = self.refresh().await; },
+                _                                   => (),
+            }
+        }
+    }
+    /// Applies one update of the trade_updates stream
+    pub fn apply(&self, update: &OrderUpdate) {
+        self.update(update.order().clone());
+    }
+    /// Starts tracking the given order (e.g. an order that was just placed)
+    /// and returns a handle on it
+    pub fn track_order(&self, order: &OrderData) -> OrderHandle {
+        let mut orders = self.orders.lock().unwrap();
+        let sender = orders.entry(order.id.clone())
+            .or_insert_with(|| watch::channel(order.clone()).0);
+        OrderHandle { receiver: sender.subscribe() }
+    }
+    /// A handle on the order having the given id (if it is tracked)
+    pub fn handle(&self, id: &str) -> Option<OrderHandle> {
+        self.orders.lock().unwrap()
+            .get(id)
+            .map(|sender| OrderHandle { receiver: sender.subscribe() })
+    }
+    /// The current state of the order having the given id (if it is tracked)
+    pub fn order(&self, id: &str) -> Option<OrderData> {
+        self.orders.lock().unwrap()
+            .get(id)
+            .map(|sender| sender.borrow().clone())
+    }
+    /// The current state of the tracked orders which are still open
+    pub fn open_orders(&self) -> Vec<OrderData> {
+        self.orders.lock().unwrap()
+            .values()
+            .map(|sender| sender.borrow().clone())
+            .filter(|order| order.status.is_open())
+            .collect()
+    }
+    /// Private: loads the open orders (their legs included, all the pages
+    /// of them) and returns their ids
+    async fn load_open(&self) -> Result<HashSet<String>, Error> {
+        let request = ListOrderRequest {
+            status: Some(SearchOrderStatus::Open),
+            limit : Some(ORDERS_PAGE_SIZE),
+            nested: Some(true),
+            ..Default::default()
+        };
+        let orders  = self.client.orders(&request);
+        futures::pin_mut!(orders);
+        let mut ids = HashSet::new();
+        while let Some(order) = orders.try_next().await? {
+            for leg in order.leg_orders() {
+                ids.insert(leg.id.clone());
+                self.update(leg.clone());
+            }
+            ids.insert(order.id.clone());
+            self.update(order);
+        }
+        Ok(ids)
+    }
+    /// Private: records the latest state of an order
+    fn update(&self, order: OrderData) {
+        let mut orders = self.orders.lock().unwrap();
+        match orders.get(&order.id) {
+            Some(sender) => { sender.send_replace(order); },
+            None         => { orders.insert(order.id.clone(), watch::channel(order).0); },
+        }
+    }
+}
+
+/// A handle which follows the state of one tracked order
+#[derive(Debug, Clone)]
+pub struct OrderHandle {
+    receiver: watch::Receiver<OrderData>,
+}
+impl OrderHandle {
+    /// The current state of the order
+    pub fn current(&self) -> OrderData {
+        self.receiver.borrow().clone()
+    }
+    /// Waits for the next update of the order and returns its new state.
+    /// This returns None when the tracker is gone.
+    pub async fn changed(&mut self) -> Option<OrderData> {
+        self.receiver.changed().await.ok()?;
+        Some(self.current())
+    }
+    /// Waits until the order is no longer open (filled, canceled, expired,
+    /// rejected...) and returns its final state. Should the tracker be gone
+    /// before that, the last known state is returned.
+    pub async fn done(mut self) -> OrderData {
+        while self.receiver.borrow().status.is_open() {
+            if self.receiver.changed().await.is_err() {
+                break;
+            }
+        }
+        self.current()
+    }
+}
+
+/// The quantities below this threshold are considered equal
+pub const QTY_TOLERANCE: f64 = 1e-9;
+
+/// The live state of a position
+#[derive(Debug, Clone, PartialEq)]
+pub struct LivePosition {
+    /// The symbol of the position
+    pub symbol: String,
+    /// The number of shares (negative for a short position)
+    pub qty: f64,
+    /// The average entry price of the position
+    pub avg_entry_price: f64,
+}
+
+/// A position whose tracked quantity disagrees with the one reported by the
+/// REST API
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    /// The symbol of the position
+    pub symbol: String,
+    /// The quantity maintained from the fills
+    pub tracked_qty: f64,
+    /// The quantity reported by the REST API
+    pub actual_qty: f64,
+}
+
+/// Keeps track of the live quantity and average entry price of the positions
+/// of the account
+pub struct PositionTracker<'a> {
+    client: &'a Client,
+    positions: Mutex<BTreeMap<String, LivePosition>>,
+}
+impl<'a> PositionTracker<'a> {
+    /// Creates a tracker which seeds (and reconciles) the positions with the
+    /// given client
+    pub fn new(client: &'a Client) -> Self {
+        Self { client, positions: Mutex::new(BTreeMap::new()) }
+    }
+    /// Seeds the tracker with the open positions of the account
+    pub async fn load(&self) -> Result<(), Error> {
+        let positions = self.client.list_open_positions().await?;
+        self.reset(&positions);
+        Ok(())
+    }
+    /// Fetches the open positions of the account, returns the ones which
+    /// disagree with the tracked quantities, then resets the tracker to the
+    /// positions which were fetched
+    pub async fn reconcile(&self) -> Result<Vec<Divergence>, Error> {
+        let positions  = self.client.list_open_positions().await?;
+        let divergence = self.divergences(&positions);
+        self.reset(&positions);
+        Ok(divergence)
+    }
+    /// Applies the fills of the given trade_updates stream until it ends.
+    /// The positions are reloaded whenever the stream reconnects.
+    pub async fn track<S>(&self, stream: S)
+        where S: Stream<Item=Result<Response, Error>>
+    {
+        futures::pin_mut!(stream);
+        while let Some(item) = stream.next().await {
+            match item {
+                Ok(Response::TradeUpdates { data }) => self.apply(&data),
+                // the fills sent while the stream was down are lost
+                Ok(Response::Reconnected)           => { let _ = self.load().await; },
+                _                                   => (),
+            }
+        }
+    }
+    /// Applies one update of the trade_updates stream (only the fills and
+    /// partial fills affect the positions). An update which was already 
+    /// applied leaves the positions unchanged.
+    pub fn apply(&self, update: &OrderUpdate) {
+        let (symbol, price, position_qty) = match update {
+            OrderUpdate::Fill { order, price, position_qty, .. }        |
+            OrderUpdate::PartialFill { order, price, position_qty, .. } => (&order.symbol, *price, *position_qty),
+            _ => return,
+        };
+        let mut positions = self.positions.lock().unwrap();
+        if position_qty.abs() < QTY_TOLERANCE {
+            positions.remove(symbol);
+            return;
+        }
+        let position = positions.entry(symbol.clone())
+            .or_insert_with(|| LivePosition { symbol: symbol.clone(), qty: 0.0, avg_entry_price: price });
+        let previous = position.qty;
+        if previous * position_qty < 0.0 {
+            // the position was flipped: the remainder was entered at this price
+            position.avg_entry_price = price;
+        } else if position_qty.abs() > previous.abs() {
+            let added = position_qty.abs() - previous.abs();
+            position.avg_entry_price = (position.avg_entry_price * previous.abs() + price * added) / position_qty.abs();
+        }
+        position.qty = position_qty;
+    }
+    /// The live state of the position on the given symbol (if any)
+    pub fn position(&self, symbol: &str) -> Option<LivePosition> {
+        self.positions.lock().unwrap().get(symbol).cloned()
+    }
+    /// The live state of all the positions (by symbol)
+    pub fn positions(&self) -> Vec<LivePosition> {
+        self.positions.lock().unwrap().values().cloned().collect()
+    }
+    /// The positions whose tracked quantity disagrees with the given ones
+    pub fn divergences(&self, actual: &[PositionData]) -> Vec<Divergence> {
+        let tracked = self.positions.lock().unwrap();
+        let mut divergences = actual.iter()
+            .map(|p| (p.symbol.clone(), tracked.get(&p.symbol).map(|t| t.qty).unwrap_or_default(), p.qty))
+            .chain(tracked.values()
+                .filter(|t| actual.iter().all(|p| p.symbol != t.symbol))
+                .map(|t| (t.symbol.clone(), t.qty, 0.0)))
+            .filter(|(_, tracked, actual)| (tracked - actual).abs() >= QTY_TOLERANCE)
+            .map(|(symbol, tracked_qty, actual_qty)| Divergence { symbol, tracked_qty, actual_qty })
+            .collect::<Vec<_>>();
+        divergences.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        divergences
+    }
+    /// Private: replaces the tracked positions by the given ones
+    fn reset(&self, positions: &[PositionData]) {
+        *self.positions.lock().unwrap() = positions.iter()
+            .map(|p| (p.symbol.clone(), LivePosition {
+                symbol: p.symbol.clone(), qty: p.qty, avg_entry_price: p.avg_entry_price
+            }))
+            .collect();
+    }
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use crate::entities::OrderStatus;
+    use crate::orders::ORDERS_PAGE_SIZE;
+    use crate::streaming::Response;
+    use crate::tracker::{OrderTracker, PositionTracker};
+    use crate::testing::{ORDER, POSITION, fake_client};
+    use crate::transport::FakeTransport;
+
+    #[tokio::test]
+    async fn test_orders_are_kept_current() {
+        let transport = FakeTransport::new().respond("GET", "/v2/orders", 200, &format!("[{}]", ORDER));
+        let client    = fake_client(&transport);
+        let tracker   = OrderTracker::new(&client);
+        tracker.load().await.unwrap();
+        assert!(transport.requests()[0].url.contains("status=open"));
+
+        let handle = tracker.handle("810f77c9").unwrap();
+        let filled = ORDER
+            .replace(r#""status":"new""#, r#""status":"filled""#)
+            .replace(r#""filled_qty":"0""#, r#""filled_qty":"2""#)
+            .replace(r#""filled_avg_price":null"#, r#""filled_avg_price":"330.5""#);
+        let update = format!(r#"{{"stream":"trade_updates","data":{{"event":"fill","order":{},
+            "timestamp":"2021-11-11T17:11:18Z","price":"330.5","position_qty":"2"}}}}"#, filled);
+        let update = serde_json::from_str::<Response>(&update).unwrap();
+
+        let (_, done) = futures::join!(
+            tracker.track(futures::stream::iter(vec![Ok(update)])),
+            handle.done());
+        assert_eq!(OrderStatus::Filled, done.status);
+        assert_eq!(Some(330.5), done.filled_avg_price);
+        assert!(tracker.open_orders().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_all_the_pages_of_open_orders_are_loaded() {
+        let page      = (0..ORDERS_PAGE_SIZE).map(|i| ORDER.replace("810f77c9", &format!("o{}", i))).collect::<Vec<_>>();
+        let transport = FakeTransport::new()
+            .respond_once("GET", "/v2/orders", 200, &format!("[{}]", page.join(",")))
+            .respond("GET", "/v2/orders", 200, &format!("[{}]", ORDER));
+        let client    = fake_client(&transport);
+        let tracker   = OrderTracker::new(&client);
+        tracker.load().await.unwrap();
+
+        assert_eq!(ORDERS_PAGE_SIZE as usize + 1, tracker.open_orders().len());
+        assert!(tracker.order("810f77c9").is_some());
+        assert!(transport.requests().iter().all(|r| r.url.contains("status=open") && r.url.contains("nested=true")));
+    }
+    #[tokio::test]
+    async fn test_orders_filled_while_disconnected_are_refreshed() {
+        let filled    = ORDER.replace(r#""status":"new""#, r#""status":"filled""#);
+        let transport = FakeTransport::new()
+            .respond("GET", "/v2/orders", 200, "[]")
+            .respond("GET", "/v2/orders/810f77c9", 200, &filled);
+        let client    = fake_client(&transport);
+        let tracker   = OrderTracker::new(&client);
+        let order     = serde_json::from_str(ORDER).unwrap();
+        let handle    = tracker.track_order(&order);
+
+        let (_, done) = futures::join!(
+            tracker.track(futures::stream::iter(vec![Ok(Response::Reconnected)])),
+            handle.done());
+        assert_eq!(OrderStatus::Filled, done.status);
+    }
+
+    #[tokio::test]
+    #[allow(clippy::result_large_err)]
+    async fn test_positions_follow_the_fills() {
+        let transport = FakeTransport::new().respond("GET", "/v2/positions", 200, &format!("[{}]", POSITION));
+        let client    = fake_client(&transport);
+        let tracker   = PositionTracker::new(&client);
+        tracker.load().await.unwrap();
+
+        // two more shares are bought at 330, then one is sold
+        let fill = |event: &str, price: &str, position_qty: &str| {
+            let update = format!(r#"{{"stream":"trade_updates","data":{{"event":"{}","order":{},
+                "timestamp":"2021-11-11T17:11:18Z","price":"{}","position_qty":"{}"}}}}"#, event, ORDER, price, position_qty);
+            Ok(serde_json::from_str::<Response>(&update).unwrap())
+        };
+        let updates = vec![fill("partial_fill", "330", "3"), fill("fill", "330", "4"), fill("fill", "330", "4"), fill("fill", "340", "3")];
+        tracker.track(futures::stream::iter(updates)).await;
+
+        let msft = tracker.position("MSFT").unwrap();
+        assert_eq!(3.0, msft.qty);
+        assert_eq!(315.0, msft.avg_entry_price);
+
+        // the REST api still reports 2 shares
+        let divergences = tracker.reconcile().await.unwrap();
+        assert_eq!(1, divergences.len());
+        assert_eq!((3.0, 2.0), (divergences[0].tracked_qty, divergences[0].actual_qty));
+        assert_eq!(Some(2.0), tracker.position("MSFT").map(|p| p.qty));
+    }
+}
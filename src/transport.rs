@@ -0,0 +1,159 @@
+//! This module abstracts the way the REST client exchanges http messages
+//! with the server.
+//!
+//! By default, the `rest::Client` sends its requests over the network with
+//! `reqwest`. The `HttpTransport` trait lets you plug another transport in;
+//! which is mostly useful to unit-test the code built on top of this crate
+//! without hitting Alpaca's servers. To that end, the `FakeTransport` serves
+//! canned responses from memory and records the requests it receives.
+//!
+//! ```
+//! # async fn example() -> Result<(), apca_datav2::errors::Error> {
+//! use apca_datav2::rest::Client;
+//! use apca_datav2::transport::FakeTransport;
+//!
+//! let transport = FakeTransport::new()
+//!     .respond("GET", "/v2/positions", 200, "[]");
+//! let client = Client::paper("key".to_string(), "secret".to_string())
+//!     .with_transport(transport.clone());
+//!
+//! assert!(client.list_open_positions().await?.is_empty());
+//! assert_eq!(1, transport.requests().len());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::sync::{Arc, Mutex};
+
+use futures::future::BoxFuture;
+use reqwest::{Request, Response};
+
+use crate::rest::X_REQUEST_ID;
+
+/// Sends an http request and returns the response of the server
+pub trait HttpTransport: Send + Sync {
+    /// Executes the given request
+    fn execute(&self, request: Request) -> BoxFuture<'static, Result<Response, reqwest::Error>>;
+}
+impl HttpTransport for reqwest::Client {
+    fn execute(&self, request: Request) -> BoxFuture<'static, Result<Response, reqwest::Error>> {
+        Box::pin(reqwest::Client::execute(self, request))
+    }
+}
+
+/// A request received by the `FakeTransport`
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    /// The http method (GET, POST...)
+    pub method: String,
+    /// The full url of the request (query included)
+    pub url: String,
+    /// The body of the request (if any)
+    pub body: Option<String>,
+}
+
+/// Private: a canned response
+#[derive(Debug, Clone)]
+struct Route {
+    method: String,
+    path  : String,
+    status: u16,
+    body  : String,
+    /// Whether the response is discarded once it has been served
+    once  : bool,
+}
+
+/// An in-memory transport serving canned responses. A request is answered
+/// with the first response registered for its method and path (the query
+/// string is ignored); or with a 404 when there is none. The responses
+/// registered with `respond_once` are only served once. Like Alpaca, each 
+/// response carries a request id (`fake-0`, `fake-1`...). Clones share the
+/// same responses and recorded requests.
+#[derive(Debug, Clone, Default)]
+pub struct FakeTransport {
+    routes  : Arc<Mutex<Vec<Route>>>,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
+impl FakeTransport {
+    /// Creates a transport which answers all requests with a 404
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Registers the response (status and JSON body) to the requests with
+    /// the given method and path
+    pub fn respond(self, method: &str, path: &str, status: u16, body: &str) -> Self {
+        self.route(method, path, status, body, false)
+    }
+    /// Registers a response which is only served to the first matching
+    /// request (the next ones get the responses registered after it)
+    pub fn respond_once(self, method: &str, path: &str, status: u16, body: &str) -> Self {
+        self.route(method, path, status, body, true)
+    }
+    /// Private: registers a canned response
+    fn route(self, method: &str, path: &str, status: u16, body: &str, once: bool) -> Self {
+        self.routes.lock().unwrap().push(Route {
+            method: method.to_uppercase(),
+            path  : path.to_string(),
+            status,
+            body  : body.to_string(),
+            once,
+        });
+        self
+    }
+    /// The requests received so far
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+impl HttpTransport for FakeTransport {
+    fn execute(&self, request: Request) -> BoxFuture<'static, Result<Response, reqwest::Error>> {
+        let method = request.method().as_str().to_string();
+        let path   = request.url().path().to_string();
+        let body   = request.body()
+            .and_then(|b| b.as_bytes())
+            .map(|b| String::from_utf8_lossy(b).to_string());
+        let mut requests = self.requests.lock().unwrap();
+        let request_id   = format!("fake-{}", requests.len());
+        requests.push(RecordedRequest { method: method.clone(), url: request.url().to_string(), body });
+        drop(requests);
+
+        let mut routes = self.routes.lock().unwrap();
+        let (status, body) = match routes.iter().position(|r| r.method == method && r.path == path) {
+            Some(i) if routes[i].once => { let r = routes.remove(i); (r.status, r.body) },
+            Some(i) => (routes[i].status, routes[i].body.clone()),
+            None    => (404, r#"{"code":40410000,"message":"not found"}"#.to_string()),
+        };
+        drop(routes);
+        let response = http::Response::builder()
+            .status(status)
+            .header("content-type", "application/json")
+            .header(X_REQUEST_ID, request_id)
+            .body(body)
+            .unwrap();
+        Box::pin(futures::future::ready(Ok(Response::from(response))))
+    }
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::fake_client;
+    use crate::transport::FakeTransport;
+
+    #[tokio::test]
+    async fn test_fake_transport() {
+        let transport = FakeTransport::new()
+            .respond("GET", "/v2/positions", 200, "[]");
+        let client = fake_client(&transport);
+
+        assert!(client.list_open_positions().await.unwrap().is_empty());
+        assert!(client.get_open_position("AAPL").await.is_err());
+
+        let requests = transport.requests();
+        assert_eq!(2, requests.len());
+        assert_eq!("https://paper-api.alpaca.markets/v2/positions/AAPL", requests[1].url);
+    }
+}
@@ -50,3 +50,20 @@ where D: serde::Deserializer<'de>,
     }
 }
 
+
+pub(crate) fn comma_separated<S, T>(items: &[T], s: S) -> Result<S::Ok, S::Error>
+where S: serde::Serializer,
+      T: std::fmt::Display
+{
+    let txt = items.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(",");
+    s.serialize_str(&txt)
+}
+
+pub(crate) fn hours_minutes<'de, D>(d: D) -> Result<chrono::NaiveTime, D::Error>
+where D: serde::Deserializer<'de>,
+{
+    let txt = String::deserialize(d)?;
+    chrono::NaiveTime::parse_from_str(&txt, "%H:%M")
+        .or_else(|_| chrono::NaiveTime::parse_from_str(&txt, "%H:%M:%S"))
+        .map_err(serde::de::Error::custom)
+}
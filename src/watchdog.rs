@@ -0,0 +1,222 @@
+//! This module watches over the completeness of a realtime bar stream.
+//!
+//! Minute bars are published shortly after the end of each minute. When one
+//! of them gets lost, the downstream aggregations silently skip a minute.
+//! The `BarWatchdog` wraps the stream of responses and, for each symbol that
+//! is actively trading, detects the bars that did not arrive within a grace
+//! period. It then emits a `MissingBar` event; optionally backfilled with the
+//! bar fetched from the latest-bar REST endpoint.
+//!
+//! A symbol is deemed to be actively trading from the moment it receives a
+//! bar. It is no longer watched when it is halted (according to the statuses
+//! channel) or when several consecutive bars are missing (in which case the
+//! symbol most likely stopped trading: Alpaca does not publish any bar for a
+//! minute without trades). The watch resumes as soon as a new bar arrives.
+//!
+//! ```no_run
+//! # async fn example(client: apca_datav2::rest::Client) -> Result<(), apca_datav2::errors::Error> {
+//! use chrono::Duration;
+//! use futures::StreamExt;
+//! use apca_datav2::realtime::{Client, Source};
+//! use apca_datav2::watchdog::{BarWatchdog, WatchdogEvent};
+//!
+//! let realtime = Client::new(Source::SIP).await?;
+//! let events   = BarWatchdog::new(Duration::seconds(10))
+//!     .backfill(&client)
+//!     .watch(realtime.stream());
+//! futures::pin_mut!(events);
+//! while let Some(event) = events.next().await {
+//!     if let WatchdogEvent::MissingBar { symbol, timestamp, backfill } = event {
+//!         println!("{} missed the bar of {} (backfill: {:?})", symbol, timestamp, backfill);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use futures::{Stream, StreamExt};
+
+use crate::entities::BarData;
+use crate::realtime::{Response, Symbol};
+use crate::rest::Client;
+
+/// Period at which the watchdog checks for missing bars
+const CHECK_PERIOD: std::time::Duration = std::time::Duration::from_secs(1);
+/// Default number of consecutive missing bars after which a symbol is deemed
+/// to have stopped trading
+pub const STALE_AFTER: usize = 3;
+
+/// An event emitted by the watchdog
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+pub enum WatchdogEvent {
+    /// A response received from the server (forwarded untouched)
+    Response(Response),
+    /// The bar of the given minute did not arrive in time
+    MissingBar {
+        /// The symbol whose bar is missing
+        symbol: Symbol,
+        /// The (start) time of the missing bar
+        timestamp: DateTime<Utc>,
+        /// The missing bar as fetched from the REST api (when backfilling is
+        /// enabled and the bar could be fetched)
+        backfill: Option<BarData>,
+    },
+}
+
+/// Private: what the watchdog knows about one symbol
+#[derive(Debug, Clone, Copy)]
+struct Tracker {
+    /// The (start) time of the last bar that was either received or reported
+    /// as missing
+    last: DateTime<Utc>,
+    /// The number of consecutive missing bars
+    missing: usize,
+    /// False when the symbol is halted
+    trading: bool,
+}
+
+/// Detects the minute bars which did not arrive in time
+pub struct BarWatchdog<'a> {
+    grace      : Duration,
+    stale_after: usize,
+    client     : Option<&'a Client>,
+    symbols    : HashMap<Symbol, Tracker>,
+}
+impl<'a> BarWatchdog<'a> {
+    /// Creates a watchdog which tolerates that a bar arrives up to `grace`
+    /// after the end of the minute that follows its start time
+    pub fn new(grace: Duration) -> Self {
+        Self { grace, stale_after: STALE_AFTER, client: None, symbols: HashMap::new() }
+    }
+    /// Sets the number of consecutive missing bars after which a symbol is
+    /// no longer watched (until it receives a new bar)
+    pub fn stale_after(mut self, bars: usize) -> Self {
+        self.stale_after = bars.max(1);
+        self
+    }
+    /// Enables the backfilling of the missing bars using the given client
+    pub fn backfill(mut self, client: &'a Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+    /// Watches the given stream of responses. The returned stream forwards
+    /// all the responses and inserts the `MissingBar` events. It ends when
+    /// the stream of responses ends.
+    pub fn watch<S>(self, responses: S) -> impl Stream<Item=WatchdogEvent> + 'a
+        where S: Stream<Item=Response> + 'a
+    {
+        let ticks  = futures::stream::unfold(tokio::time::interval(CHECK_PERIOD), |mut interval| async move {
+            interval.tick().await;
+            Some((None, interval))
+        });
+        let inputs = responses.map(|r| Some(Some(r)))
+            .chain(futures::stream::once(async { None }));
+        let inputs = futures::stream::select(inputs, ticks.map(Some))
+            .take_while(|input| futures::future::ready(input.is_some()))
+            .filter_map(futures::future::ready);
+
+        futures::stream::unfold((self, Box::pin(inputs)), |(mut dog, mut inputs)| async move {
+            let events = match inputs.next().await? {
+                Some(response) => {
+                    dog.observe(&response);
+                    vec![WatchdogEvent::Response(response)]
+                },
+                None => {
+                    let missing = dog.missing(Utc::now());
+                    dog.backfilled(missing).await
+                },
+            };
+            Some((events, (dog, inputs)))
+        })
+        .flat_map(futures::stream::iter)
+    }
+    /// Updates the state of the symbols given a response from the server
+    fn observe(&mut self, response: &Response) {
+        match response {
+            Response::Bar(bar) => {
+                let tracker = self.symbols.entry(bar.symbol.clone()).or_insert(Tracker {
+                    last: bar.data.timestamp, missing: 0, trading: true
+                });
+                tracker.last    = tracker.last.max(bar.data.timestamp);
+                tracker.missing = 0;
+                tracker.trading = true;
+            },
+            Response::Status(status) if status.data.is_halted() => {
+                if let Some(tracker) = self.symbols.get_mut(&status.symbol) {
+                    tracker.trading = false;
+                }
+            },
+            _ => {},
+        }
+    }
+    /// Returns the bars which should have arrived by the given time
+    fn missing(&mut self, now: DateTime<Utc>) -> Vec<(Symbol, DateTime<Utc>)> {
+        let minute  = Duration::minutes(1);
+        let mut out = vec![];
+        for (symbol, tracker) in self.symbols.iter_mut() {
+            while tracker.trading && tracker.missing < self.stale_after {
+                let expected = tracker.last + minute;
+                if now < expected + minute + self.grace {
+                    break;
+                }
+                out.push((symbol.clone(), expected));
+                tracker.last     = expected;
+                tracker.missing += 1;
+            }
+        }
+        out.sort();
+        out
+    }
+    /// Turns the missing bars into events; fetching them when backfilling
+    async fn backfilled(&self, missing: Vec<(Symbol, DateTime<Utc>)>) -> Vec<WatchdogEvent> {
+        let mut events = vec![];
+        for (symbol, timestamp) in missing {
+            let backfill = match self.client {
+                Some(client) => client.latest_bar(&symbol).await.ok()
+                    .map(|b| b.bar)
+                    .filter(|b| b.timestamp == timestamp),
+                None => None,
+            };
+            events.push(WatchdogEvent::MissingBar { symbol, timestamp, backfill });
+        }
+        events
+    }
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use crate::realtime::Response;
+    use crate::watchdog::BarWatchdog;
+
+    #[test]
+    fn test_missing_bars_are_detected() {
+        let bar = r#"{"T":"b","S":"SPY","o":388.985,"h":389.13,"l":388.975,"c":389.12,"v":49378,"t":"2021-02-22T19:15:00Z"}"#;
+        let halt = r#"{"T":"s","S":"SPY","sc":"H","sm":"Trading Halt","rc":"T12","rm":"","t":"2021-02-22T19:20:00Z","z":"C"}"#;
+        let mut dog = BarWatchdog::new(Duration::seconds(10)).stale_after(2);
+        dog.observe(&serde_json::from_str::<Response>(bar).unwrap());
+
+        // the 19:16 bar is expected by 19:17:10
+        assert!(dog.missing(Utc.with_ymd_and_hms(2021, 2, 22, 19, 17, 5).unwrap()).is_empty());
+        let missing = dog.missing(Utc.with_ymd_and_hms(2021, 2, 22, 19, 17, 15).unwrap());
+        assert_eq!(vec![("SPY".to_string(), Utc.with_ymd_and_hms(2021, 2, 22, 19, 16, 0).unwrap())], missing);
+        // after two consecutive missing bars, the symbol is deemed stale
+        let missing = dog.missing(Utc.with_ymd_and_hms(2021, 2, 22, 19, 30, 0).unwrap());
+        assert_eq!(vec![("SPY".to_string(), Utc.with_ymd_and_hms(2021, 2, 22, 19, 17, 0).unwrap())], missing);
+        assert!(dog.missing(Utc.with_ymd_and_hms(2021, 2, 22, 19, 40, 0).unwrap()).is_empty());
+
+        // a halted symbol is not watched
+        dog.observe(&serde_json::from_str::<Response>(bar).unwrap());
+        dog.observe(&serde_json::from_str::<Response>(halt).unwrap());
+        assert!(dog.missing(Utc.with_ymd_and_hms(2021, 2, 22, 19, 40, 0).unwrap()).is_empty());
+    }
+}
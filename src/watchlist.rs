@@ -7,15 +7,12 @@
 //!
 use serde::{Serialize, Deserialize};
 
-use crate::{entities::WatchlistData, errors::{Error, maybe_convert_to_watchlist_error, status_code_to_watchlist_error, status_code_to_watchlist_error_noparse}, rest::Client};
-
-/// General endpoint of the watchlist API
-static ENDPOINT: &str = "/v2/watchlists";
+use crate::{entities::WatchlistData, errors::{Error, maybe_convert_to_watchlist_error, status_code_to_watchlist_error, status_code_to_watchlist_error_noparse}, rest::Client, endpoints::Endpoint};
 
 impl Client {
     /// Returns the list of watchlists registered under the account
     pub async fn list_watchlists(&self) -> Result<Vec<WatchlistData>, Error> {
-        let url = format!("{}{}", self.env_url(), ENDPOINT);
+        let url = Endpoint::Watchlists.url(self.env_url());
         let rsp = self.get_authenticated(&url)
             .send().await
             .map_err(maybe_convert_to_watchlist_error)?;
@@ -30,10 +27,10 @@ impl Client {
     /// - name arbitrary name string, up to 64 characters
     /// - symbols set of symbol string
     pub async fn create_watchlist(&self, name: &str, symbols: &[&str]) -> Result<WatchlistData, Error> {
-        let url = format!("{}{}", self.env_url(), ENDPOINT);
+        let url = Endpoint::Watchlists.url(self.env_url());
         let req = CreateUpdate {
             name: name.to_string(),
-            symbols: symbols.iter().map(|x| x.to_string()).collect()
+            symbols: symbols.iter().map(|x| self.normalize(x)).collect()
         };
         let rsp = self.post_authenticated(&url)
             .json(&req)
@@ -45,7 +42,7 @@ impl Client {
 
     /// Returns a watchlist identified by the ID
     pub async fn get_watchlist(&self, id: &str) -> Result<WatchlistData, Error> {
-        let url = format!("{}{}/{}", self.env_url(), ENDPOINT, id);
+        let url = Endpoint::Watchlist(id).url(self.env_url());
         let rsp = self.get_authenticated(&url)
             .send().await
             .map_err(maybe_convert_to_watchlist_error)?;
@@ -65,10 +62,10 @@ impl Client {
             name: &str,
             symbols: &[&str]
         ) -> Result<WatchlistData, Error> {
-        let url = format!("{}{}/{}", self.env_url(), ENDPOINT, id);
+        let url = Endpoint::Watchlist(id).url(self.env_url());
         let req = CreateUpdate{
             name: name.to_string(),
-            symbols: symbols.iter().map(|x| x.to_string()).collect()
+            symbols: symbols.iter().map(|x| self.normalize(x)).collect()
         };
         let rsp = self.put_authenticated(&url)
             .json(&req)
@@ -85,8 +82,8 @@ impl Client {
     /// - id the uuid of the watchlist
     /// - symbol the asset to add to the watchlist
     pub async fn add_asset_to_watchlist(&self, id: &str, symbol: &str) -> Result<WatchlistData, Error> {
-        let url = format!("{}{}/{}", self.env_url(), ENDPOINT, id);
-        let req = Add { symbol: symbol.to_string() };
+        let url = Endpoint::Watchlist(id).url(self.env_url());
+        let req = Add { symbol: self.normalize(symbol) };
         let rsp = self.post_authenticated(&url)
             .json(&req)
             .send().await
@@ -101,7 +98,7 @@ impl Client {
     /// 
     /// - id the uuid of the watchlist to delete
     pub async fn delete_watchlist(&self, id: &str) -> Result<(), Error> {
-        let url = format!("{}{}/{}", self.env_url(), ENDPOINT, id);
+        let url = Endpoint::Watchlist(id).url(self.env_url());
         
         let rsp = self.delete_authenticated(&url)
             .send().await
@@ -117,7 +114,7 @@ impl Client {
     /// - id the uuid of the watchlist
     /// - symbol the symbol to remove from watchlist
     pub async fn remove_asset_from_watchlist(&self, id: &str, symbol: &str) -> Result<(), Error> {
-        let url = format!("{}{}/{}/{}", self.env_url(), ENDPOINT, id, symbol);
+        let url = Endpoint::WatchlistSymbol(id, &self.normalize(symbol)).url(self.env_url());
         
         let rsp = self.delete_authenticated(&url)
             .send().await
@@ -0,0 +1,194 @@
+//! This module encapsulates the technicalities of opening the websocket
+//! connections used by the [realtime](crate::realtime) and
+//! [streaming](crate::streaming) clients.
+//!
+//! By default, these clients connect directly to Alpaca's servers and trust
+//! the system root certificates. Corporate environments which cannot connect
+//! directly may tune this behavior by passing a [`ConnectionConfig`] to the
+//! `with_config` constructor of the clients. Such a config lets you:
+//! * tunnel the connection through an HTTP (CONNECT) or SOCKS5 proxy,
+//! * trust additional root certificates (i.e. the ones of an inspecting proxy),
+//! * or use a fully custom `native_tls::TlsConnector`.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), apca_datav2::errors::Error> {
+//! use apca_datav2::realtime::{Client, Source};
+//! use apca_datav2::websocket::{ConnectionConfigBuilder, Proxy};
+//!
+//! let config = ConnectionConfigBuilder::default()
+//!     .proxy(Proxy::Http { addr: "proxy.corp:3128".to_string(), credentials: None })
+//!     .build()
+//!     .unwrap();
+//! let client = Client::with_config(Source::IEX, &config).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::time::Duration;
+
+use derive_builder::Builder;
+use native_tls::{Certificate, TlsConnector};
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpStream};
+use tokio_socks::tcp::Socks5Stream;
+use tokio_tungstenite::{Connector, MaybeTlsStream, WebSocketStream, client_async_tls_with_config, tungstenite};
+use tungstenite::{client::IntoClientRequest, error::{TlsError, UrlError}};
+
+use crate::errors::Error;
+
+pub(crate) type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Delay before the first attempt to reconnect a lost connection
+pub(crate) const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+/// Maximum delay between two consecutive attempts to reconnect
+pub(crate) const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+/// A proxy through which the websocket connection is tunneled
+#[derive(Debug, Clone)]
+pub enum Proxy {
+    /// An HTTP proxy supporting the CONNECT method. The address has the form
+    /// `host:port`; the credentials (user, password) are sent using basic auth.
+    Http { addr: String, credentials: Option<(String, String)> },
+    /// A SOCKS5 proxy. The address has the form `host:port`; the credentials
+    /// (user, password) are used for username/password authentication.
+    Socks5 { addr: String, credentials: Option<(String, String)> },
+}
+
+/// The configuration used to open a websocket connection
+#[derive(Clone, Default, Builder)]
+#[builder(default)]
+pub struct ConnectionConfig {
+    /// The proxy to go through (a direct connection is used when none is set)
+    #[builder(setter(strip_option))]
+    pub proxy: Option<Proxy>,
+    /// Additional root certificates to trust on top of the system ones
+    #[builder(setter(each = "root_certificate"))]
+    pub root_certificates: Vec<Certificate>,
+    /// A custom TLS connector. When set, the root certificates are ignored
+    #[builder(setter(strip_option))]
+    pub tls_connector: Option<TlsConnector>,
+}
+impl ConnectionConfig {
+    /// Returns the connector which is used to negotiate TLS (none means the
+    /// default connector is used)
+    #[allow(clippy::result_large_err)]
+    fn connector(&self) -> Result<Option<Connector>, Error> {
+        if let Some(connector) = &self.tls_connector {
+            return Ok(Some(Connector::NativeTls(connector.clone())));
+        }
+        if self.root_certificates.is_empty() {
+            return Ok(None);
+        }
+        let mut builder = TlsConnector::builder();
+        for cert in self.root_certificates.iter() {
+            builder.add_root_certificate(cert.clone());
+        }
+        let connector = builder.build().map_err(TlsError::from).map_err(tungstenite::Error::from)?;
+        Ok(Some(Connector::NativeTls(connector)))
+    }
+}
+
+/// Opens a websocket connection to the given url using the given config
+pub(crate) async fn connect(url: &str, config: &ConnectionConfig) -> Result<WsStream, Error> {
+    let request = url.into_client_request()?;
+    let host    = request.uri().host()
+        .ok_or(tungstenite::Error::Url(UrlError::NoHostName))?
+        .to_string();
+    let port    = request.uri().port_u16()
+        .unwrap_or(if request.uri().scheme_str() == Some("wss") { 443 } else { 80 });
+
+    let stream  = match &config.proxy {
+        None => TcpStream::connect((host.as_str(), port)).await?,
+        Some(Proxy::Http { addr, credentials }) =>
+            http_tunnel(addr, &host, port, credentials.as_ref()).await?,
+        Some(Proxy::Socks5 { addr, credentials }) => {
+            let target = (host.as_str(), port);
+            let stream = match credentials {
+                None => Socks5Stream::connect(addr.as_str(), target).await,
+                Some((user, pass)) =>
+                    Socks5Stream::connect_with_password(addr.as_str(), target, user, pass).await,
+            };
+            stream.map_err(proxy_error)?.into_inner()
+        }
+    };
+
+    let (socket, _rsp) = client_async_tls_with_config(request, stream, None, config.connector()?).await?;
+    Ok(socket)
+}
+
+/// Extracts the payload of a data frame. Alpaca's endpoints do not all use
+/// the same kind of frames (the trading stream of the paper environment 
+/// sends Binary frames where the other ones send Text frames), hence both
+/// are accepted. Control frames carry no payload.
+pub(crate) fn payload(message: tungstenite::Message) -> Option<String> {
+    match message {
+        tungstenite::Message::Text(text)    => Some(text),
+        tungstenite::Message::Binary(bytes) => Some(String::from_utf8_lossy(&bytes).into_owned()),
+        _ => None,
+    }
+}
+
+/// Private: opens a tunnel to host:port through the given HTTP proxy
+async fn http_tunnel(proxy: &str, host: &str, port: u16, credentials: Option<&(String, String)>)
+    -> Result<TcpStream, Error>
+{
+    let mut stream  = TcpStream::connect(proxy).await?;
+    let mut request = format!("CONNECT {0}:{1} HTTP/1.1\r\nHost: {0}:{1}\r\n", host, port);
+    if let Some((user, pass)) = credentials {
+        let token = base64::encode(format!("{}:{}", user, pass));
+        request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", token));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // The response is read byte per byte so as to not consume any of the
+    // bytes which belong to the tunneled connection.
+    let mut response = vec![];
+    while !response.ends_with(b"\r\n\r\n") {
+        let byte = stream.read_u8().await?;
+        response.push(byte);
+    }
+    let response = String::from_utf8_lossy(&response);
+    let status   = response.split_whitespace().nth(1);
+    if status != Some("200") {
+        let line = response.lines().next().unwrap_or_default();
+        return Err(proxy_error(format!("proxy refused to connect: {}", line)));
+    }
+    Ok(stream)
+}
+
+/// Private: reports a proxy failure as an i/o error
+fn proxy_error<E>(error: E) -> Error
+    where E: Into<Box<dyn std::error::Error + Send + Sync>>
+{
+    Error::Io(std::io::Error::other(error))
+}
+
+/******************************************************************************
+ * TESTS **********************************************************************
+ ******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpListener};
+
+    use crate::websocket::http_tunnel;
+
+    #[tokio::test]
+    async fn test_http_tunnel() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr     = listener.local_addr().unwrap().to_string();
+        let proxy    = tokio::spawn(async move {
+            let (mut sock, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 1024];
+            let n = sock.read(&mut buf).await.unwrap();
+            sock.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n").await.unwrap();
+            String::from_utf8_lossy(&buf[..n]).to_string()
+        });
+        let creds = ("user".to_string(), "pass".to_string());
+        http_tunnel(&addr, "stream.data.alpaca.markets", 443, Some(&creds)).await.unwrap();
+
+        let request = proxy.await.unwrap();
+        assert!(request.starts_with("CONNECT stream.data.alpaca.markets:443 HTTP/1.1\r\n"));
+        assert!(request.contains("Proxy-Authorization: Basic dXNlcjpwYXNz\r\n"));
+    }
+}